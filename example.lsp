@@ -1,6 +1,6 @@
-(
-	( "test add" ( = (+ 10 2 ) 12 ) )
-	( "test sub" ( = (- 10 2 ) 08 ) )
-	( "test mul" ( = (* 10 2 ) 20 ) )
-	( "test div" ( = (/ 10 2 ) 05 ) )
-)
+; checks basic arithmetic, failing with the form and its values if any is off
+(assert-eq (+ 10 2) 12)
+(assert-eq (- 10 2) 8)
+(assert-eq (* 10 2) 20)
+(assert-eq (/ 10 2) 5)
+(assert (< (- 10 2) (+ 10 2)))
@@ -0,0 +1,14 @@
+; the game's logic, run by examples/embed_game.rs once a frame: edit it while
+; the game runs and the next frame uses the new code, with the state kept
+
+; the first state: where the ball is and how fast it goes, in cells a second
+(defn init () {:x 0 :dx 12})
+
+(defn tick (state dt)
+  (let ((x (+ (dict-get state :x) (* (dict-get state :dx) dt)))
+        (dx (dict-get state :dx)))
+    ; bounce off the walls
+    (cond ((< x 0) (set! x (- x)) (set! dx (- dx)))
+          ((> x (- (width) 1)) (set! x (- (* 2 (- (width) 1)) x)) (set! dx (- dx))))
+    (draw (round x) "o")
+    {:x x :dx dx}))
@@ -0,0 +1,55 @@
+// a game loop running its logic from a script, with `ScriptHost`: the script
+// gets the time each frame took and returns the new state, calls the host's
+// functions to draw, can't hang the game, and is reloaded when it changes.
+// Run it with `cargo run --example embed_game`, and edit
+// examples/embed_game.lsp while it runs
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use sxprs::ScriptHost;
+
+const WIDTH: i64 = 40;
+const FRAME: Duration = Duration::from_millis(50);
+
+fn main() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/embed_game.lsp");
+    let mut host = match ScriptHost::load(path) {
+        Ok(host) => host,
+        Err(err) => {
+            eprintln!("{path}: {}", err.message());
+            std::process::exit(1);
+        }
+    };
+    // a frame's worth of steps: a script stuck in a loop loses the frame, not the game
+    host.set_fuel(10_000);
+    let screen = Rc::new(RefCell::new(vec![' '; WIDTH as usize]));
+    host.register("width", || WIDTH);
+    host.register("draw", {
+        let screen = screen.clone();
+        move |x: i64, text: String| {
+            let mut screen = screen.borrow_mut();
+            for (i, c) in text.chars().enumerate() {
+                if let Some(cell) = screen.get_mut(x as usize + i) {
+                    *cell = c;
+                }
+            }
+        }
+    });
+    let mut last = Instant::now();
+    // ten seconds' worth of frames
+    for _ in 0..200 {
+        std::thread::sleep(FRAME);
+        let dt = last.elapsed().as_secs_f64();
+        last = Instant::now();
+        screen.borrow_mut().fill(' ');
+        if let Err(err) = host.tick(dt) {
+            eprintln!("\n{path}: {}", err.message());
+        }
+        let line: String = screen.borrow().iter().collect();
+        print!("\r|{line}|");
+        use std::io::Write;
+        let _ = std::io::stdout().flush();
+    }
+    println!();
+}
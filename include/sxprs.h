@@ -0,0 +1,47 @@
+/* the sxprs interpreter's C API, in the library built with `cargo build --release --features ffi`
+ * (target/release/libsxprs.so, .dylib or .dll). Values cross as their printed form, which reads back
+ * as the same value for data: numbers, strings, symbols, lists, vectors and maps.
+ *
+ * Calls return SXPRS_OK, or the number of the kind of error, the one in its id:
+ * 101 for E0101, a type mismatch; `sxprs explain` lists them. Strings the library
+ * gives out are freed with sxprs_string_free. An interpreter is used from one thread at a time. */
+#ifndef SXPRS_H
+#define SXPRS_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define SXPRS_OK 0
+/* a null pointer or a string that isn't UTF-8 where one was needed */
+#define SXPRS_INVALID_ARGUMENT (-1)
+
+typedef struct sxprs sxprs;
+
+/* a C function lisp can call: it gets `data` as it was registered and the arguments' printed forms,
+ * and returns the printed form of its result, read back as a value and not evaluated, which has to
+ * stay valid until it's called again; or NULL to fail the call */
+typedef const char *(*sxprs_fn)(void *data, size_t argc, const char *const *argv);
+
+/* a new interpreter, with the prelude */
+sxprs *sxprs_new(void);
+
+void sxprs_free(sxprs *lisp);
+
+/* evaluates the program `source`; `*result`, unless `result` is NULL, is the printed value
+ * of its last form, or the error's message when it fails */
+int sxprs_eval(sxprs *lisp, const char *source, char **result);
+
+/* makes `f` callable from lisp as `name`, replacing any function of that name */
+int sxprs_register_fn(sxprs *lisp, const char *name, sxprs_fn f, void *data);
+
+/* frees a string the library gave out */
+void sxprs_string_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
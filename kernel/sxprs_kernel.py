@@ -0,0 +1,102 @@
+# the Jupyter kernel for sxprs: it speaks Jupyter's protocol to the notebook,
+# through ipykernel, and runs the cells in `sxprs kernel`, which it talks to
+# with one JSON line a request; see src/kernel.rs. Installed with
+#
+#   jupyter kernelspec install --user --name sxprs kernel/
+#
+# with this file where Python finds it, and `sxprs` on the PATH, or named by
+# the SXPRS environment variable
+import json
+import os
+import signal
+import subprocess
+
+from ipykernel.kernelbase import Kernel
+
+
+class Sxprs:
+    """`sxprs kernel` running, answering one request at a time"""
+
+    def __init__(self, command=None):
+        command = command or [os.environ.get("SXPRS", "sxprs"), "kernel"]
+        self.process = subprocess.Popen(command, stdin=subprocess.PIPE, stdout=subprocess.PIPE, text=True, encoding="utf-8")
+        self.busy = False
+
+    def request(self, op, **fields):
+        self.process.stdin.write(json.dumps({"op": op, **fields}) + "\n")
+        self.process.stdin.flush()
+        self.busy = True
+        try:
+            line = self.process.stdout.readline()
+        finally:
+            self.busy = False
+        if not line:
+            raise RuntimeError(f"sxprs kernel exited with {self.process.wait()}")
+        return json.loads(line)
+
+    # stops the cell that's running, like Ctrl-C in the REPL
+    def interrupt(self):
+        if self.busy:
+            self.process.send_signal(signal.SIGINT)
+
+    def close(self):
+        if self.process.poll() is None:
+            self.request("shutdown")
+        self.process.wait()
+
+
+class SxprsKernel(Kernel):
+    implementation = "sxprs"
+    implementation_version = "0.1"
+    language_info = {"name": "lisp", "mimetype": "text/x-lisp", "file_extension": ".lsp", "codemirror_mode": "commonlisp"}
+    banner = "sxprs"
+
+    def __init__(self, **kwargs):
+        super().__init__(**kwargs)
+        self.sxprs = Sxprs()
+        # ipykernel takes SIGINT for an interrupt; it's the running cell's to stop
+        signal.signal(signal.SIGINT, lambda signum, frame: self.sxprs.interrupt())
+
+    def stream(self, name, text):
+        if text:
+            self.send_response(self.iopub_socket, "stream", {"name": name, "text": text})
+
+    def do_execute(self, code, silent, store_history=True, user_expressions=None, allow_stdin=False):
+        reply = self.sxprs.request("execute", code=code)
+        if not silent:
+            self.stream("stdout", reply["stdout"])
+            self.stream("stderr", reply["stderr"])
+        if reply["status"] == "error":
+            error = {"ename": reply["ename"], "evalue": reply["evalue"], "traceback": reply.get("traceback", [])}
+            if not silent:
+                self.send_response(self.iopub_socket, "error", error)
+            return {"status": "error", "execution_count": self.execution_count, **error}
+        if not silent and reply["value"] is not None:
+            self.send_response(
+                self.iopub_socket,
+                "execute_result",
+                {"execution_count": self.execution_count, "data": {"text/plain": reply["value"]}, "metadata": {}},
+            )
+        return {"status": "ok", "execution_count": self.execution_count, "payload": [], "user_expressions": {}}
+
+    def do_complete(self, code, cursor_pos):
+        reply = self.sxprs.request("complete", code=code, cursor=cursor_pos)
+        return {**reply, "metadata": {}}
+
+    def do_is_complete(self, code):
+        reply = self.sxprs.request("is_complete", code=code)
+        if reply["status"] == "incomplete":
+            return {"status": "incomplete", "indent": "  "}
+        return reply
+
+    def do_shutdown(self, restart):
+        self.sxprs.close()
+        if restart:
+            self.sxprs = Sxprs()
+        return {"status": "ok", "restart": restart}
+
+
+if __name__ == "__main__":
+    from ipykernel.kernelapp import IPKernelApp
+
+    IPKernelApp.launch_instance(kernel_class=SxprsKernel)
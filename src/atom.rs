@@ -0,0 +1,198 @@
+// `atom`, `deref`, `reset!`, `swap!`, `lock` and `with-lock`: state shared
+// between the threads `spawn` and `pmap` start. Atoms and locks are structs
+// holding an id into a table every thread sees, so they're copied to workers
+// like any other value and still name the same cell. A cell is a lock a
+// thread can take more than once, which `swap!` holds while it calls its
+// function and `with-lock` while it runs its body; values go in and out of
+// it copied, the way they're sent to workers. Cells live as long as the
+// program
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread::ThreadId;
+
+use crate::builtins::{eval_body, record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::{LispExp, Struct};
+use crate::parallel::{pack, Job};
+use crate::symbol::{SymbolId, SymbolMap};
+
+struct State {
+    value: Job,
+    // the thread holding the cell and how many times it took it
+    owner: Option<ThreadId>,
+    depth: usize,
+}
+
+struct Cell {
+    state: Mutex<State>,
+    released: Condvar,
+}
+
+// every atom and lock made so far, by id
+fn cells() -> &'static Mutex<Vec<Arc<Cell>>> {
+    static CELLS: OnceLock<Mutex<Vec<Arc<Cell>>>> = OnceLock::new();
+    CELLS.get_or_init(Mutex::default)
+}
+
+// a thread that failed while holding a cell doesn't leave it unusable, as
+// `held` gives it back on the way out
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// `kind` with the id of a new cell holding `value`
+fn make(kind: &str, value: &LispExp) -> Result<LispExp, LispError> {
+    let cell = Cell { state: Mutex::new(State { value: pack(value)?, owner: None, depth: 0 }), released: Condvar::new() };
+    let mut cells = lock(cells());
+    let id = cells.len() as i64;
+    cells.push(Arc::new(cell));
+    Ok(LispExp::Struct(Rc::new(Struct {
+        name: SymbolId::new(kind),
+        fields: Rc::from([LispExp::Symbol(SymbolId::new("id"))]),
+        values: vec![LispExp::Int(id)],
+    })))
+}
+
+// the cell of an atom, or of a lock too when `locks` is set
+fn cell(vl: &LispExp, locks: bool) -> Result<Arc<Cell>, LispError> {
+    let id = match vl {
+        LispExp::Struct(record) if &*record.name.name() == "atom" || (locks && &*record.name.name() == "lock") => record.values.first(),
+        _ => None,
+    };
+    let expected = if locks { "atom or lock" } else { "atom" };
+    let Some(LispExp::Int(id)) = id else {
+        return Err(vl.mismatch(expected));
+    };
+    let cells = lock(cells());
+    usize::try_from(*id)
+        .ok()
+        .and_then(|id| cells.get(id))
+        .cloned()
+        .ok_or_else(|| LispError::Other(format!("{vl} isn't an {expected} that was made")))
+}
+
+impl Cell {
+    // runs `work` holding the cell, waiting for other threads to let go of
+    // it first; the thread holding it can take it again
+    fn held<T>(&self, work: impl FnOnce(&Cell) -> T) -> T {
+        let me = std::thread::current().id();
+        let mut state = lock(&self.state);
+        while state.owner.is_some_and(|owner| owner != me) {
+            state = self.released.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        state.owner = Some(me);
+        state.depth += 1;
+        drop(state);
+        let res = work(self);
+        let mut state = lock(&self.state);
+        state.depth -= 1;
+        if state.depth == 0 {
+            state.owner = None;
+            self.released.notify_one();
+        }
+        res
+    }
+    fn get(&self, env: &Interpreter) -> Result<LispExp, LispError> {
+        lock(&self.state).value.clone().unpack(env)
+    }
+    fn set(&self, value: &LispExp) -> Result<(), LispError> {
+        lock(&self.state).value = pack(value)?;
+        Ok(())
+    }
+}
+
+// (atom value) is a cell holding value, which every thread can read and change
+fn lisp_atom(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (value, _) = unpack(cont)?;
+    make("atom", value)
+}
+
+// (lock) is a lock for `with-lock`
+fn lisp_lock(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    make("lock", &LispExp::Nil)
+}
+
+// (deref a) is the value in an atom
+fn lisp_deref(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (atom, _) = unpack(cont)?;
+    cell(atom, false)?.held(|cell| cell.get(env))
+}
+
+// (reset! a value) puts value in an atom, and returns it
+fn lisp_reset(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (atom, rest) = unpack(cont)?;
+    let (value, _) = unpack(rest)?;
+    cell(atom, false)?.held(|cell| cell.set(value))?;
+    Ok(value.clone())
+}
+
+// (swap! a f args...) puts (f value args...) in an atom, and returns it; no
+// other thread changes the atom while f runs
+fn lisp_swap(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (atom, rest) = unpack(cont)?;
+    let (func, args) = unpack(rest)?;
+    cell(atom, false)?.held(|cell| {
+        let args = [vec![cell.get(env)?], args.to_vec()].concat();
+        let value = env.apply(func, args)?;
+        cell.set(&value)?;
+        Ok(value)
+    })
+}
+
+// (with-lock l body...) runs the body holding a lock, or an atom, which
+// other threads wait for
+fn lisp_with_lock(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (target, body) = unpack(cont)?;
+    let target = env.value(target)?;
+    cell(&target, true)?.held(|_| eval_body(env, body))
+}
+
+pub(crate) fn atom_forms() -> SymbolMap<Builtin> {
+    let mut specials: SymbolMap<Builtin> = SymbolMap::default();
+    record!(specials, "with-lock", lisp_with_lock, 1.., "(with-lock l body...)", "runs the body holding a lock or atom, which other threads wait for");
+    specials
+}
+
+pub(crate) fn atom_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "atom", lisp_atom, 1, "(atom value)", "a cell holding value that every thread can read and change");
+    record!(funcs, "deref", lisp_deref, 1, "(deref a)", "the value in an atom");
+    record!(funcs, "reset!", lisp_reset, 2, "(reset! a value)", "puts value in an atom");
+    record!(funcs, "swap!", lisp_swap, 2.., "(swap! a f args...)", "puts (f value args...) in an atom and returns it, with no other thread changing it meanwhile");
+    record!(funcs, "lock", lisp_lock, 0, "(lock)", "a lock for `with-lock`");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn atoms_hold_values() {
+        assert_eq!(run("(define a (atom '(1))) (swap! a append '(2) '(3)) (swap! a length) (deref a)"), "3");
+        assert_eq!(run("(define a (atom 1)) (list (reset! a 5) (deref a))"), "( 5 5 )");
+        assert!(run("(deref (lock))").ends_with(") is not a atom, it's a Struct"));
+        assert_eq!(run("(deref (make-struct 'atom '(id) -1))"), "error: ( atom :id -1 ) isn't an atom that was made");
+        assert_eq!(run("(deref 1)"), "error: 1 is not a atom, it's a Int");
+        // taking the atom again inside swap! doesn't wait for itself
+        assert_eq!(run("(define a (atom 1)) (swap! a (lambda (x) (+ x (deref a))))"), "2");
+    }
+
+    #[test]
+    fn threads_share_atoms() {
+        let source = "(define n (atom 0)) \
+                      (pmap (lambda (i) (dotimes (j 50) (swap! n + 1))) (range 0 8)) \
+                      (define l (lock)) (define seen (atom '())) \
+                      (pmap (lambda (i) (with-lock l (reset! seen (cons i (deref seen))))) (range 0 8)) \
+                      (list (deref n) (length (deref seen)))";
+        assert_eq!(run(source), "( 400 8 )");
+    }
+}
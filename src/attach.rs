@@ -0,0 +1,229 @@
+// debugging an interpreter while it runs: `listen` opens a socket that
+// `sxprs attach` connects to, speaking the line-JSON protocol of
+// `sxprs kernel`, so expressions can be evaluated and the environment looked
+// at in a live session, like a script inside a long-running host. The
+// requests are answered on the interpreter's own thread, between evaluation
+// steps, in the scope of the code that's running, so its locals can be read
+// and changed; a host that's idle answers them when it calls
+// `poll_attached`. Anyone who can connect can run code as the host, so it's
+// opt-in, and a TCP address is best kept on 127.0.0.1
+use std::cell::Cell;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use crate::eval::Interpreter;
+use crate::kernel::reply;
+
+// a request line from a connection, where its reply goes, and where
+// whether it ended the connection goes once it's answered. The reply is
+// written by the interpreter's thread, so it's sent even when the host
+// exits right after
+struct Request {
+    line: String,
+    writer: Arc<Mutex<dyn Write + Send>>,
+    done: Sender<bool>,
+}
+
+// how the connections' threads hand requests to the interpreter: through
+// the channel, with the flag set so its next step looks
+#[derive(Clone)]
+struct Queue {
+    requests: Sender<Request>,
+    pending: Arc<AtomicBool>,
+}
+
+// the interpreter's end of the queue
+pub(crate) struct Attached {
+    requests: Receiver<Request>,
+    pending: Arc<AtomicBool>,
+    // set while a request is answered, so the steps it takes don't answer more
+    answering: Cell<bool>,
+    // the unix socket's file, removed when the interpreter is dropped
+    socket: Option<PathBuf>,
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        if let Some(path) = &self.socket {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// answers the requests on the connection until it closes, a shutdown request
+// or the interpreter is dropped
+fn connection(reader: impl Read, writer: impl Write + Send + 'static, queue: Queue) {
+    let writer: Arc<Mutex<dyn Write + Send>> = Arc::new(Mutex::new(writer));
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (done, answered) = channel();
+        if queue.requests.send(Request { line, writer: writer.clone(), done }).is_err() {
+            return;
+        }
+        queue.pending.store(true, Ordering::Relaxed);
+        if answered.recv() != Ok(false) {
+            return;
+        }
+    }
+}
+
+impl Interpreter {
+    // lets `sxprs attach` connect at `addr`: a path with a `/` in it is a
+    // unix socket, which can't exist yet, anything else a TCP `host:port`,
+    // where port 0 picks a free one. Returns the address it's listening at
+    pub fn listen(&mut self, addr: &str) -> io::Result<String> {
+        let (requests, receiver) = channel();
+        let pending = Arc::new(AtomicBool::new(false));
+        let queue = Queue { requests, pending: pending.clone() };
+        let mut socket = None;
+        let bound = if addr.contains('/') {
+            #[cfg(unix)]
+            {
+                let listener = UnixListener::bind(addr)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let (Ok(reader), queue) = (stream.try_clone(), queue.clone()) else {
+                            continue;
+                        };
+                        thread::spawn(move || connection(reader, stream, queue));
+                    }
+                });
+                socket = Some(PathBuf::from(addr));
+                addr.to_owned()
+            }
+            #[cfg(not(unix))]
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "there are no unix sockets here"));
+        } else {
+            let listener = TcpListener::bind(addr)?;
+            let bound = listener.local_addr()?.to_string();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let (Ok(reader), queue) = (stream.try_clone(), queue.clone()) else {
+                        continue;
+                    };
+                    thread::spawn(move || connection(reader, stream, queue));
+                }
+            });
+            bound
+        };
+        self.attached = Some(Attached { requests: receiver, pending, answering: Cell::new(false), socket });
+        Ok(bound)
+    }
+    // answers the requests of attached clients that are waiting, for a host
+    // to call when it's between evaluations; evaluating calls it itself
+    pub fn poll_attached(&self) {
+        let Some(attached) = &self.attached else {
+            return;
+        };
+        if attached.answering.get() || !attached.pending.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        attached.answering.set(true);
+        while let Ok(request) = attached.requests.try_recv() {
+            let (reply, shutdown) = reply(self, &request.line);
+            let mut writer = request.writer.lock().unwrap_or_else(PoisonError::into_inner);
+            let written = writeln!(writer, "{reply}").and_then(|_| writer.flush());
+            let _ = request.done.send(shutdown || written.is_err());
+        }
+        attached.answering.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    // sends each request and reads its reply, while `lisp` answers
+    fn ask(lisp: &Interpreter, addr: &str, requests: &[&str]) -> Vec<String> {
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        requests
+            .iter()
+            .map(|request| {
+                writeln!(writer, "{request}").unwrap();
+                // nothing is evaluating, so it's for the host to answer
+                while !lisp.attached.as_ref().unwrap().pending.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                lisp.poll_attached();
+                let mut reply = String::new();
+                reader.read_line(&mut reply).unwrap();
+                reply.trim_end().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn answers_when_polled() {
+        let mut lisp = Interpreter::new();
+        let addr = lisp.listen("127.0.0.1:0").unwrap();
+        lisp.eval_str("(define hits 3)").unwrap();
+        let replies = ask(&lisp, &addr, &[r#"{"op":"execute","code":"(set! hits (+ hits 1)) (print hits)"}"#, r#"{"op":"env"}"#]);
+        assert_eq!(replies[0], r#"{"stdout":"4\n","stderr":"","status":"ok","value":null}"#);
+        assert_eq!(replies[1], r#"{"status":"ok","env":{"hits":"4"}}"#);
+        // the host sees what the client changed
+        assert_eq!(lisp.eval_str("hits").unwrap().to_string(), "4");
+    }
+
+    #[test]
+    fn answers_while_evaluating() {
+        let mut lisp = Interpreter::new();
+        let addr = lisp.listen("127.0.0.1:0").unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut ask = |request: &str| {
+                writeln!(writer, "{request}").unwrap();
+                let mut reply = String::new();
+                reader.read_line(&mut reply).unwrap();
+                reply.trim_end().to_owned()
+            };
+            let seen = ask(r#"{"op":"execute","code":"i"}"#);
+            let env = ask(r#"{"op":"env"}"#);
+            // stops the loop
+            ask(r#"{"op":"execute","code":"(set! running false)"}"#);
+            (seen, env)
+        });
+        // a loop that runs until the client says so, looking at its local `i`
+        lisp.eval_str("(define running true) (defn spin (i) (while running (set! i (+ i 1))) 'stopped) (spin 0)").unwrap();
+        let (seen, env) = client.join().unwrap();
+        assert!(seen.starts_with(r#"{"stdout":"","stderr":"","status":"ok","value":""#), "{seen}");
+        assert!(env.starts_with(r#"{"status":"ok","env":{"i":""#), "{env}");
+        assert!(env.contains(r#""running":"true","spin":"( lambda ( i )"#), "{env}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listens_on_unix_sockets() {
+        let path = std::env::temp_dir().join(format!("sxprs-attach-{}.sock", std::process::id()));
+        let mut lisp = Interpreter::new();
+        assert_eq!(lisp.listen(path.to_str().unwrap()).unwrap(), path.to_str().unwrap());
+        let stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        writeln!(&stream, r#"{{"op":"shutdown"}}"#).unwrap();
+        while !lisp.attached.as_ref().unwrap().pending.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        lisp.poll_attached();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+        assert_eq!(reply, "{\"status\":\"ok\"}\n");
+        drop(lisp);
+        assert!(!path.exists());
+    }
+}
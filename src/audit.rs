@@ -0,0 +1,264 @@
+// `sxprs audit`: what a program can do to the machine it runs on, found
+// without running it. Every place the program, or a file it loads or imports,
+// names a builtin that runs commands, writes or reads files, touches the
+// environment or evaluates code made while running is listed, whether it's
+// called there or passed along to be called later. Loads and imports of paths
+// that are only known when the program runs can't be followed, and are
+// reported. The interpreter has no network builtins: reaching the network
+// takes `exec` or `shell`
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::builtins::expand_path;
+use crate::bundle::split;
+use crate::error::LispError;
+use crate::exp::LispExp;
+use crate::fmt::flat;
+use crate::lexer::tokens;
+use crate::parser::parse_program;
+use crate::platform;
+use crate::span;
+
+// the builtins worth a reviewer's look, and what they can do
+const CAPABILITIES: &[(&str, &str)] = &[
+    ("exec", "exec"),
+    ("shell", "exec"),
+    ("write-file", "fs-write"),
+    ("append-file", "fs-write"),
+    ("save-image", "fs-write"),
+    ("checkpoint", "fs-write"),
+    ("heap-dump", "fs-write"),
+    ("render", "fs-write"),
+    ("read-file", "fs-read"),
+    ("load", "fs-read"),
+    ("watch-path", "fs-read"),
+    ("resume", "fs-read"),
+    ("getenv", "env"),
+    ("setenv", "env"),
+    ("eval", "eval"),
+];
+
+// the pipe stages that reach files, which are only names inside a `pipe`
+const PIPE_CAPABILITIES: &[(&str, &str)] = &[("write-lines", "fs-write"), ("lines", "fs-read")];
+
+// forms longer than this are cut short when they're shown
+const SHOWN: usize = 60;
+
+// a place the program reaches outside the interpreter
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    // the file, and where the form naming the builtin starts in it
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    pub capability: &'static str,
+    pub builtin: String,
+    // the form, on one line
+    pub form: String,
+}
+
+#[derive(Default)]
+struct Auditor {
+    // the files being read, innermost last, for loads relative to them
+    files: Vec<PathBuf>,
+    // every file read so far, which isn't read again
+    seen: HashSet<PathBuf>,
+    modules: HashSet<String>,
+    include: Vec<PathBuf>,
+    findings: Vec<Finding>,
+    warnings: Vec<String>,
+}
+
+impl Auditor {
+    fn file(&mut self, path: &Path) -> Result<(), LispError> {
+        let shown = path.display().to_string();
+        let canonical = platform::canonicalize(path).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        if !self.seen.insert(canonical.clone()) {
+            return Ok(());
+        }
+        let source = platform::read_file(&canonical).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        let program = tokens(&source)
+            .and_then(|tokens| parse_program(&tokens))
+            .map_err(|err| LispError::Other(format!("{shown}:{}", err.message())))?;
+        self.files.push(path.to_owned());
+        for form in &program {
+            self.form(form, &shown, None)?;
+        }
+        self.files.pop();
+        Ok(())
+    }
+    // a path as `load` reads it: relative to the file doing the loading
+    fn resolve(&self, path: &str) -> Result<PathBuf, LispError> {
+        let path = PathBuf::from(expand_path(path)?);
+        match self.files.last().and_then(|file| file.parent()) {
+            Some(dir) if path.is_relative() => Ok(dir.join(path)),
+            _ => Ok(path),
+        }
+    }
+    fn found(&mut self, capability: &'static str, builtin: &str, list: Option<&LispExp>, shown: &str) {
+        let pos = match list {
+            Some(LispExp::List(items)) => span::span(items),
+            _ => None,
+        };
+        let mut form = list.map(flat).unwrap_or_else(|| builtin.to_owned());
+        if form.chars().count() > SHOWN {
+            form = form.chars().take(SHOWN - 3).collect::<String>() + "...";
+        }
+        self.findings.push(Finding {
+            file: shown.to_owned(),
+            line: pos.map_or(0, |pos| pos.line),
+            col: pos.map_or(0, |pos| pos.col),
+            capability,
+            builtin: builtin.to_owned(),
+            form,
+        });
+    }
+    // the builtins named in `exp`, found in the innermost list holding them,
+    // and the files it loads and imports
+    fn form(&mut self, exp: &LispExp, shown: &str, within: Option<&LispExp>) -> Result<(), LispError> {
+        let items = match exp {
+            LispExp::Symbol(name) => {
+                let name = name.name();
+                if let Some((_, capability)) = CAPABILITIES.iter().find(|(builtin, _)| **builtin == *name) {
+                    self.found(capability, &name, within, shown);
+                }
+                return Ok(());
+            }
+            LispExp::List(items) => items,
+            LispExp::Vector(_) | LispExp::Map(_) => {
+                for item in exp.literal_items().unwrap_or_default() {
+                    self.form(&item, shown, within)?;
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let (head, args) = split(exp);
+        match (&*head, args) {
+            ("quote", _) => return Ok(()),
+            ("load", [LispExp::Str(path)]) => {
+                self.found("fs-read", "load", Some(exp), shown);
+                return self.file(&self.resolve(path)?);
+            }
+            ("load", _) => self.warnings.push(format!("{shown}: {} loads a file only known when the program runs", flat(exp))),
+            ("module", [LispExp::Symbol(name), ..]) => {
+                self.modules.insert(name.to_string());
+            }
+            ("import", [LispExp::Symbol(name), ..]) => {
+                let name = name.to_string();
+                if !self.modules.contains(&name) {
+                    let file = format!("{name}.lsp");
+                    let beside = self.resolve(&file)?;
+                    match Some(beside).into_iter().chain(self.include.iter().map(|dir| dir.join(&file))).find(|path| path.exists()) {
+                        Some(path) => {
+                            self.modules.insert(name);
+                            self.file(&path)?;
+                        }
+                        None => self.warnings.push(format!("{shown}: {} imports a module that wasn't found", flat(exp))),
+                    }
+                }
+                return Ok(());
+            }
+            ("pipe", stages) => {
+                for stage in stages {
+                    let (name, _) = split(stage);
+                    if let Some((builtin, capability)) = PIPE_CAPABILITIES.iter().find(|(builtin, _)| **builtin == *name) {
+                        self.found(capability, builtin, Some(stage), shown);
+                    }
+                }
+            }
+            _ => {}
+        }
+        for item in items.iter() {
+            self.form(item, shown, Some(exp))?;
+        }
+        Ok(())
+    }
+}
+
+// where the program at `path`, and the files it loads and imports, reach
+// outside the interpreter, in the order they're read, and a warning for each
+// load or import that couldn't be followed; `include` is where `import`
+// looks after the importing file's directory
+pub fn audit(path: &Path, include: &[PathBuf]) -> Result<(Vec<Finding>, Vec<String>), LispError> {
+    let mut auditor = Auditor { include: include.to_vec(), ..Auditor::default() };
+    auditor.file(path)?;
+    Ok((auditor.findings, auditor.warnings))
+}
+
+// the capabilities the findings need, each once
+pub fn capabilities(findings: &[Finding]) -> Vec<&'static str> {
+    findings.iter().map(|finding| finding.capability).collect::<BTreeSet<_>>().into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes `files` to a directory of their own and audits the first one,
+    // as `file:line: capability builtin` and the warnings
+    fn audited(dir: &str, files: &[(&str, &str)]) -> (Vec<String>, Vec<String>) {
+        let dir = std::env::temp_dir().join(format!("sxprs-audit-{}-{dir}", std::process::id()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let (findings, warnings) = audit(&dir.join(files[0].0), &[dir.join("lib")]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let prefix = format!("{}/", dir.display());
+        let findings = findings
+            .iter()
+            .map(|found| format!("{}:{}: {} {}", found.file.trim_start_matches(&prefix), found.line, found.capability, found.builtin))
+            .collect();
+        let warnings = warnings.iter().map(|warning| warning.trim_start_matches(&prefix).to_owned()).collect();
+        (findings, warnings)
+    }
+
+    #[test]
+    fn builtins_are_found_through_loads_and_imports() {
+        let files = [
+            ("main.lsp", "(load \"helpers.lsp\")\n(import util)\n(print (getenv \"HOME\"))\n'(exec \"quoted\")\n(map shell cmds)\n"),
+            ("helpers.lsp", "(defn save (x)\n  (write-file \"out\" x))\n(load \"helpers.lsp\")\n"),
+            ("lib/util.lsp", "(module util (export run)\n  (defn run (cmd) (exec cmd)))\n"),
+        ];
+        let (findings, warnings) = audited("found", &files);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(
+            findings,
+            [
+                "main.lsp:1: fs-read load",
+                "helpers.lsp:2: fs-write write-file",
+                "helpers.lsp:3: fs-read load",
+                "lib/util.lsp:2: exec exec",
+                "main.lsp:3: env getenv",
+                "main.lsp:5: exec shell",
+            ]
+        );
+    }
+
+    #[test]
+    fn what_cant_be_followed_is_reported() {
+        let files = [("main.lsp", "(defn later (name) (load name))\n(import nowhere)\n(pipe (lines \"in\") (map eval) (write-lines \"out\"))\n")];
+        let (findings, warnings) = audited("dynamic", &files);
+        assert_eq!(findings, ["main.lsp:1: fs-read load", "main.lsp:3: fs-read lines", "main.lsp:3: fs-write write-lines", "main.lsp:3: eval eval"]);
+        assert_eq!(
+            warnings,
+            [
+                "main.lsp: (load name) loads a file only known when the program runs",
+                "main.lsp: (import nowhere) imports a module that wasn't found"
+            ]
+        );
+    }
+
+    #[test]
+    fn long_forms_are_cut_short() {
+        let mut auditor = Auditor::default();
+        let long = format!("(shell \"{}\")", "x".repeat(100));
+        let program = parse_program(&tokens(&long).unwrap()).unwrap();
+        auditor.form(&program[0], "f", None).unwrap();
+        assert_eq!(auditor.findings[0].form.chars().count(), SHOWN);
+        assert!(auditor.findings[0].form.ends_with("xx..."));
+        assert_eq!(capabilities(&auditor.findings), ["exec"]);
+    }
+}
@@ -0,0 +1,1655 @@
+use std::cell::{OnceCell, RefCell};
+use std::cmp::Ordering;
+use std::path::Path;
+use std::rc::Rc;
+
+use num_rational::BigRational;
+
+use crate::atom::{atom_forms, atom_funcs};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter, Scope};
+use crate::exp::{LispExp, LispLambda, MapKey};
+use crate::json::{json_to_lisp, lisp_to_json};
+use crate::format::format_funcs;
+use crate::help::{help_forms, help_funcs};
+#[cfg(feature = "graphics")]
+use crate::graphics::graphics_funcs;
+use crate::heap::heap_funcs;
+use crate::image::image_funcs;
+use crate::math::math_funcs;
+use crate::num;
+use crate::pipe::pipe_forms;
+use crate::parallel::{lisp_spawn, lisp_with_tasks, parallel_funcs};
+use crate::schedule::schedule_funcs;
+use crate::parser::parse_number;
+use crate::structs::{struct_forms, struct_funcs};
+use crate::platform::{self, Timer};
+use crate::symbol::{SymbolId, SymbolMap, CATCH, ELSE, EXPORT, OPTIONAL, QUOTE, REST, UNQUOTE, UNQUOTE_SPLICING};
+
+// registers a builtin under `$symb` with its arity, and how it's called and
+// what it does for `help`; special forms that use their arguments as written
+// end with `unevaluated`
+macro_rules! record {
+  ($env: expr, $symb: expr, $check_fn:expr, $arity:expr, $usage:expr, $doc:expr) => {{
+      $crate::builtins::record!($env, $symb, $check_fn, $arity, $usage, $doc, false)
+  }};
+  ($env: expr, $symb: expr, $check_fn:expr, $arity:expr, $usage:expr, $doc:expr, unevaluated) => {{
+      $crate::builtins::record!($env, $symb, $check_fn, $arity, $usage, $doc, true)
+  }};
+  ($env: expr, $symb: expr, $check_fn:expr, $arity:expr, $usage:expr, $doc:expr, $unevaluated:literal) => {{
+      $env.insert(
+          SymbolId::new($symb),
+          $crate::eval::Builtin {
+              func: Box::new($check_fn),
+              arity: $crate::eval::Arity::from($arity),
+              doc: Some(($usage, $doc)),
+              unevaluated: $unevaluated,
+          },
+      )
+  }};
+}
+pub(crate) use record;
+
+// helper functions
+pub(crate) fn unpack(cont: &[LispExp]) -> Result<(&LispExp, &[LispExp]), LispError> {
+    cont
+        .split_first()
+        .ok_or(LispError::from("missing argument"))
+}
+
+pub(crate) fn eval_all(env: &Interpreter, r: &[LispExp]) -> Result<Vec<LispExp>, LispError> {
+    r.iter().map(|a|env.value(a)).collect()
+}
+
+pub(crate) fn eval_body(env: &Interpreter, body: &[LispExp]) -> Result<LispExp, LispError> {
+    let ev = eval_all(env, body)?;
+    ev.last().cloned().ok_or(LispError::from("body is empty"))
+}
+
+// folds the arguments left to right, staying exact while both sides are ints
+// or ratios and falling back to floats as soon as one side is a float
+fn fold_numbers(
+    cont: &[LispExp],
+    int_op: fn(i64, i64) -> Option<i64>,
+    exact_op: fn(BigRational, BigRational) -> Result<BigRational, LispError>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<LispExp, LispError> {
+    let (car, cdr) = unpack(cont)?;
+    car.get_number()?;
+    let mut acc = car.clone();
+    for item in cdr {
+        acc = num::arith(&acc, item, int_op, exact_op, float_op)?;
+    }
+    Ok(acc)
+}
+
+fn lisp_add(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    fold_numbers(cont, i64::checked_add, |a, b| Ok(a + b), |a, b| a + b)
+}
+// (- x) is x negated
+fn lisp_sub(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    if let [x] = cont {
+        return fold_numbers(&[LispExp::Int(0), x.clone()], i64::checked_sub, |a, b| Ok(a - b), |a, b| a - b);
+    }
+    fold_numbers(cont, i64::checked_sub, |a, b| Ok(a - b), |a, b| a - b)
+}
+fn lisp_mul(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    fold_numbers(cont, i64::checked_mul, |a, b| Ok(a * b), |a, b| a * b)
+}
+// ints only stay ints when they divide evenly, and make a ratio otherwise
+fn int_div(a: i64, b: i64) -> Option<i64> {
+    match a.checked_rem(b) {
+        Some(0) => a.checked_div(b),
+        _ => None,
+    }
+}
+fn lisp_div(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    fold_numbers(cont, int_div, num::divide, |a, b| a / b)
+}
+
+fn atom_eq(a: &LispExp, b: &LispExp) -> Result<bool, LispError> {
+    match (a, b) {
+        (LispExp::Int(a), LispExp::Int(b)) => Ok(a == b),
+        (
+            LispExp::Int(_) | LispExp::BigInt(_) | LispExp::Ratio(_) | LispExp::Number(_),
+            LispExp::Int(_) | LispExp::BigInt(_) | LispExp::Ratio(_) | LispExp::Number(_),
+        ) => Ok(num::compare(a, b)? == Some(Ordering::Equal)),
+        (LispExp::Symbol(a), LispExp::Symbol(b)) => Ok(a == b),
+        (LispExp::Str(a), LispExp::Str(b)) => Ok(a == b),
+        (LispExp::Bool(a), LispExp::Bool(b)) => Ok(a == b),
+        (LispExp::Char(a), LispExp::Char(b)) => Ok(a == b),
+        (LispExp::Nil, LispExp::Nil) => Ok(true),
+        (LispExp::Builtin(a), LispExp::Builtin(b)) => Ok(a == b),
+        (LispExp::List(_) | LispExp::Pair(_) | LispExp::Map(_) | LispExp::Vector(_) | LispExp::Struct(_) | LispExp::Lambda(_) | LispExp::Macro(_), _)
+        | (_, LispExp::List(_) | LispExp::Pair(_) | LispExp::Map(_) | LispExp::Vector(_) | LispExp::Struct(_) | LispExp::Lambda(_) | LispExp::Macro(_)) => {
+            Err(LispError::Other(format!("can't compare {} with {}", a.name(), b.name())))
+        }
+        _ => Ok(false),
+    }
+}
+
+fn lisp_eq(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, cdr) = unpack(cont)?;
+    for item in cdr {
+        if !atom_eq(car, item)? {
+            return Ok(false.into());
+        }
+    }
+    Ok(true.into())
+}
+
+// (equal? a b ...) when every value is structurally equal to the first
+fn lisp_equal(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, cdr) = unpack(cont)?;
+    Ok(cdr.iter().all(|item| item == car).into())
+}
+
+// (eq? a b ...) when every value is the first one itself, not just a copy of it
+fn lisp_identical(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, cdr) = unpack(cont)?;
+    Ok(cdr.iter().all(|item| item.is_identical(car)).into())
+}
+
+// true if every adjacent pair of numbers is ordered as `op` wants; NaN
+// isn't ordered at all, so comparing with it is always false
+fn compare(cont: &[LispExp], op: fn(Ordering) -> bool) -> Result<LispExp, LispError> {
+    for item in cont {
+        item.get_number()?;
+    }
+    for pair in cont.windows(2) {
+        if !num::compare(&pair[0], &pair[1])?.is_some_and(op) {
+            return Ok(false.into());
+        }
+    }
+    Ok(true.into())
+}
+fn lisp_lt(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    compare(cont, Ordering::is_lt)
+}
+fn lisp_gt(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    compare(cont, Ordering::is_gt)
+}
+fn lisp_le(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    compare(cont, Ordering::is_le)
+}
+fn lisp_ge(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    compare(cont, Ordering::is_ge)
+}
+
+// (time expr) evaluates `expr`, printing how long it took to stderr
+fn lisp_time(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (exp, _) = unpack(cont)?;
+    let timer = Timer::start();
+    let vl = env.value(exp)?;
+    platform::eprint(&format!("time: {:?}", timer.elapsed()));
+    Ok(vl)
+}
+
+// runs a process and returns a map of its exit code, nil if it was killed by a
+// signal, and what it wrote, under :code, :stdout and :stderr
+fn run_process(program: &str, args: &[String]) -> Result<LispExp, LispError> {
+    let output = platform::exec(program, args).map_err(|err| LispError::Other(format!("{program}: {err}")))?;
+    let code = output.code.map_or(LispExp::Nil, |code| LispExp::Int(code.into()));
+    let map = [(":code", code), (":stdout", output.stdout.into()), (":stderr", output.stderr.into())]
+        .into_iter()
+        .map(|(key, vl)| (MapKey::Symbol(key.to_owned()), vl))
+        .collect();
+    Ok(LispExp::Map(map))
+}
+
+// (exec program args...) runs the program directly, without a shell
+fn lisp_exec(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (program, args) = unpack(cont)?;
+    let args = args.iter().map(|arg| arg.get_string().map(str::to_owned)).collect::<Result<Vec<_>, _>>()?;
+    run_process(program.get_string()?, &args)
+}
+
+// (shell command) runs the command line with the system shell, like `exec`
+fn lisp_shell(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (command, _) = unpack(cont)?;
+    let command = command.get_string()?.to_owned();
+    if cfg!(windows) {
+        run_process("cmd", &["/C".to_owned(), command])
+    } else {
+        run_process("sh", &["-c".to_owned(), command])
+    }
+}
+
+// (now) is the wall-clock time in milliseconds since the unix epoch
+fn lisp_now(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(LispExp::Int(platform::epoch_millis()))
+}
+
+// (clock) is a monotonic time in milliseconds, for `elapsed`; unlike `now` it
+// never goes back when the system clock is changed
+fn lisp_clock(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(LispExp::Number(env.started.elapsed().as_secs_f64() * 1000.0))
+}
+
+// (elapsed start) is how many milliseconds passed since `(clock)` gave `start`
+fn lisp_elapsed(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (start, _) = unpack(cont)?;
+    let now = env.started.elapsed().as_secs_f64() * 1000.0;
+    Ok(LispExp::Number(now - start.get_number()?))
+}
+
+// (sleep ms) pauses the program
+fn lisp_sleep(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (ms, _) = unpack(cont)?;
+    let ms = ms.get_number()?;
+    if !(ms >= 0.0 && ms.is_finite()) {
+        return Err(LispError::Other(format!("can't sleep for {ms} milliseconds")));
+    }
+    platform::sleep(std::time::Duration::from_secs_f64(ms / 1000.0))
+        .map_err(|err| LispError::Other(format!("sleep: {err}")))?;
+    Ok(LispExp::Nil)
+}
+
+// how often `watch-path` looks for changes
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+// (watch-path path handler) calls `(handler event file)` with :created,
+// :modified or :removed for every change to the file at `path` or the files
+// under the directory, looking every 200ms, until the handler returns false
+fn lisp_watch_path(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (path, rest) = unpack(cont)?;
+    let (handler, _) = unpack(rest)?;
+    let path = expand_path(path.get_string()?)?;
+    let scan = || platform::scan(Path::new(&path)).map_err(|err| io_error(&path, err));
+    let mut files = scan()?;
+    loop {
+        // so Ctrl-C and the step limit can stop it
+        env.step()?;
+        platform::sleep(WATCH_INTERVAL).map_err(|err| LispError::Other(format!("watch-path: {err}")))?;
+        let now = scan()?;
+        let mut events = vec![];
+        for (file, modified, len) in &now {
+            match files.iter().find(|(old, ..)| old == file) {
+                None => events.push((":created", file)),
+                Some((_, old_modified, old_len)) if old_modified != modified || old_len != len => events.push((":modified", file)),
+                Some(_) => {}
+            }
+        }
+        for (file, ..) in &files {
+            if !now.iter().any(|(new, ..)| new == file) {
+                events.push((":removed", file));
+            }
+        }
+        for (event, file) in events {
+            let args = vec![LispExp::Symbol(SymbolId::new(event)), LispExp::from(file.display().to_string())];
+            if env.apply(handler, args)? == LispExp::Bool(false) {
+                return Ok(LispExp::Nil);
+            }
+        }
+        files = now;
+    }
+}
+
+fn lisp_debug(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    for item in cont {
+        platform::print(&env.show(item));
+    }
+    Ok(LispExp::Nil)
+}
+
+fn lisp_print(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    for item in cont {
+        platform::print(&env.show(item));
+    }
+    Ok(LispExp::Nil)
+}
+
+fn lisp_also(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let ev = cont.last().ok_or(LispError::ArityMismatch {
+        name: ",".to_owned(),
+        min: 1,
+        max: None,
+        found: 0,
+    })?;
+    Ok(ev.clone())
+}
+
+// expands a leading `~` to the home directory and `$VAR`/`${VAR}` to their values
+pub(crate) fn expand_path(path: &str) -> Result<String, LispError> {
+    let path = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(std::path::is_separator) => {
+            // windows has no $HOME by default
+            let home = platform::getenv("HOME")
+                .or_else(|| platform::getenv("USERPROFILE"))
+                .ok_or(LispError::from("can't expand `~`, $HOME is not set"))?;
+            format!("{home}{rest}")
+        }
+        _ => path.to_owned(),
+    };
+    let mut ret = String::new();
+    let mut chars = path.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr != '$' {
+            ret.push(chr);
+            continue;
+        }
+        let mut name = String::new();
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(format!("unclosed `${{` in path {path}").into()),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+        }
+        if name.is_empty() {
+            ret.push('$');
+            continue;
+        }
+        let value = platform::getenv(&name)
+            .ok_or_else(|| LispError::Other(format!("environment variable ${name} is not set")))?;
+        ret.push_str(&value);
+    }
+    Ok(ret)
+}
+
+fn lisp_expand_path(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, _) = unpack(cont)?;
+    Ok(expand_path(car.get_string()?)?.into())
+}
+
+// (getenv name) is the variable's value, or nil when it isn't set
+fn lisp_getenv(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, _) = unpack(cont)?;
+    Ok(platform::getenv(name.get_string()?)
+        .map(LispExp::from)
+        .unwrap_or(LispExp::Nil))
+}
+
+fn lisp_setenv(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (vl, _) = unpack(rest)?;
+    platform::setenv(name.get_string()?, vl.get_string()?);
+    Ok(vl.clone())
+}
+
+pub(crate) fn io_error(path: &str, err: std::io::Error) -> LispError {
+    LispError::Other(format!("{path}: {err}"))
+}
+
+fn lisp_read_file(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (path, _) = unpack(cont)?;
+    let path = expand_path(path.get_string()?)?;
+    platform::read_file(Path::new(&path))
+        .map(LispExp::from)
+        .map_err(|err| io_error(&path, err))
+}
+
+// (load "file.lsp") runs another file, defining what it defines in the current scope
+fn lisp_load(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (path, _) = unpack(cont)?;
+    env.load(Path::new(&expand_path(path.get_string()?)?))
+}
+
+// writes or appends `contents` to the file at `path`, creating it if needed
+fn write_to(cont: &[LispExp], append: bool) -> Result<LispExp, LispError> {
+    let (path, rest) = unpack(cont)?;
+    let (contents, _) = unpack(rest)?;
+    let path = expand_path(path.get_string()?)?;
+    let contents = contents.get_string()?;
+    platform::write_file(Path::new(&path), contents, append).map_err(|err| io_error(&path, err))?;
+    Ok(LispExp::Nil)
+}
+
+fn lisp_write_file(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    write_to(cont, false)
+}
+
+fn lisp_append_file(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    write_to(cont, true)
+}
+
+// reads a line from stdin without its line ending, or nil at the end of input
+fn lisp_read_line(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let Some(mut line) = platform::read_line().map_err(|err| io_error("<stdin>", err))? else {
+        return Ok(LispExp::Nil);
+    };
+    let trimmed = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(trimmed);
+    Ok(line.into())
+}
+
+// evaluates the condition of `(if cond then else)` and picks the branch to run,
+// None when it's false and there's no else
+fn if_branch<'a>(env: &Interpreter, cont: &'a [LispExp]) -> Result<Option<&'a LispExp>, LispError> {
+    let (cond, rest) = unpack(cont)?;
+    let (then, rest) = unpack(rest)?;
+    if env.value(cond)?.is_truthy() {
+        Ok(Some(then))
+    } else {
+        Ok(rest.first())
+    }
+}
+
+// finds the first `(test body...)` clause of a `cond` whose test is true,
+// returning the test's value and the body; `else` is always true
+fn cond_clause<'a>(env: &Interpreter, cont: &'a [LispExp]) -> Result<Option<(LispExp, &'a [LispExp])>, LispError> {
+    for clause in cont {
+        let Some((test, body)) = clause.get_list()?.split_first() else {
+            return Err(LispError::from("expected a (test body...) clause, found ()"));
+        };
+        let vl = match test {
+            LispExp::Symbol(ELSE) => LispExp::Bool(true),
+            test => env.value(test)?,
+        };
+        if vl.is_truthy() {
+            return Ok(Some((vl, body)));
+        }
+    }
+    Ok(None)
+}
+
+// (cond (test body...)...) runs the body of the first clause whose test is
+// true, returning the test's value if the body is empty, and nil if no test is
+fn lisp_cond(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    match cond_clause(env, cont)? {
+        Some((vl, [])) => Ok(vl),
+        Some((_, body)) => eval_body(env, body),
+        None => Ok(LispExp::Nil),
+    }
+}
+
+// builtins get their arguments unevaluated, so only the taken branch runs
+fn lisp_if(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    match if_branch(env, cont)? {
+        Some(branch) => env.value(branch),
+        None => Ok(LispExp::Nil),
+    }
+}
+
+// evaluates all but the last of `and`'s or `or`'s arguments, stopping at the
+// first whose truthiness is `stop_at`; None when it's up to the last one
+fn short_circuit(env: &Interpreter, cont: &[LispExp], stop_at: bool) -> Result<Option<LispExp>, LispError> {
+    for exp in &cont[..cont.len().saturating_sub(1)] {
+        let vl = env.value(exp)?;
+        if vl.is_truthy() == stop_at {
+            return Ok(Some(vl));
+        }
+    }
+    Ok(None)
+}
+
+// (and a b...) evaluates its arguments until one is false, returning that
+// one, or the last; true when there are none
+fn lisp_and(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    if let Some(vl) = short_circuit(env, cont, false)? {
+        return Ok(vl);
+    }
+    cont.last().map_or(Ok(LispExp::Bool(true)), |last| env.value(last))
+}
+
+// (or a b...) evaluates its arguments until one is true, returning that one,
+// or the last; false when there are none
+fn lisp_or(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    if let Some(vl) = short_circuit(env, cont, true)? {
+        return Ok(vl);
+    }
+    cont.last().map_or(Ok(LispExp::Bool(false)), |last| env.value(last))
+}
+
+fn lisp_not(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vl, _) = unpack(cont)?;
+    Ok(LispExp::Bool(!vl.is_truthy()))
+}
+
+// runs the body until the condition is falsy, returning the last body value
+// or nil if it never ran
+fn lisp_while(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (cond, body) = unpack(cont)?;
+    let mut last = LispExp::Nil;
+    while env.value(cond)?.is_truthy() {
+        for exp in body {
+            last = env.value(exp)?;
+        }
+    }
+    Ok(last)
+}
+
+// runs `body` with `name` bound to each of `items` in turn, in a fresh scope
+// every time, returning the last body value or nil if it never ran
+fn run_loop(env: &Interpreter, name: SymbolId, items: impl Iterator<Item = LispExp>, body: &[LispExp]) -> Result<LispExp, LispError> {
+    let mut last = LispExp::Nil;
+    for item in items {
+        let scope = Scope::child(&env.current_scope());
+        scope.insert(name, item);
+        last = env.with_scope(scope, || -> Result<_, LispError> {
+            let mut last = LispExp::Nil;
+            for exp in body {
+                last = env.value(exp)?;
+            }
+            Ok(last)
+        })?;
+    }
+    Ok(last)
+}
+
+// (for x in lst body...) runs the body for every item of the list
+fn lisp_for(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let [name, LispExp::Symbol(keyword), lst, body @ ..] = cont else {
+        return Err(LispError::from("expected (for name in list body...)"));
+    };
+    if &*keyword.name() != "in" {
+        return Err(LispError::Other(format!("expected `in` after the loop variable, found {keyword}")));
+    }
+    let lst = env.value(lst)?;
+    run_loop(env, name.get_symbol()?, lst.get_list()?.iter().cloned(), body)
+}
+
+// (dotimes (i n) body...) runs the body with `i` counting from 0 up to, but
+// not including, `n`
+fn lisp_dotimes(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (header, body) = unpack(cont)?;
+    let LispExp::List(header) = header else {
+        return Err(LispError::Other(format!("expected an (name count) header, found {header}")));
+    };
+    let [name, count] = &header[..] else {
+        return Err(LispError::from("expected an (name count) header"));
+    };
+    let count = env.value(count)?.get_int()?;
+    run_loop(env, name.get_symbol()?, (0..count).map(LispExp::Int), body)
+}
+
+fn lisp_let(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (bindings, body) = unpack(cont)?;
+    let LispExp::List(bindings) = bindings else {
+        return Err(LispError::Other(format!("expected a list of bindings, found {bindings}")));
+    };
+    // bindings are evaluated in the outer scope, like scheme's let
+    let scope = Scope::child(&env.current_scope());
+    for binding in bindings.iter() {
+        let LispExp::List(pair) = binding else {
+            return Err(LispError::Other(format!("expected a (name value) binding, found {binding}")));
+        };
+        let [name, vl] = &pair[..] else {
+            return Err(LispError::Other(format!("expected a (name value) binding, found {binding}")));
+        };
+        scope.insert(name.get_symbol()?, env.value(vl)?);
+    }
+    env.with_scope(scope, || eval_body(env, body))
+}
+
+// (begin exp...) evaluates the expressions in a scope of their own, so what
+// they define doesn't outlive the block, returning the last value or nil
+fn lisp_begin(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    if cont.is_empty() {
+        return Ok(LispExp::Nil);
+    }
+    let scope = Scope::child(&env.current_scope());
+    env.with_scope(scope, || eval_body(env, cont))
+}
+
+// whether `value` fits `pattern`, adding what it captures to `bindings`:
+// `_` matches anything, other symbols match anything and capture it, quoted
+// data and literals match equal values, and lists match lists item by item,
+// with `&rest name` capturing the items left over
+fn match_pattern(pattern: &LispExp, value: &LispExp, bindings: &mut Vec<(SymbolId, LispExp)>) -> bool {
+    match pattern {
+        LispExp::Symbol(name) if &*name.name() == "_" => true,
+        LispExp::Symbol(name) if name.is_keyword() => pattern == value,
+        LispExp::Symbol(name) => {
+            bindings.push((*name, value.clone()));
+            true
+        }
+        LispExp::List(items) => match &items[..] {
+            [LispExp::Symbol(QUOTE), datum] => datum == value,
+            _ => {
+                let LispExp::List(values) = value else {
+                    return false;
+                };
+                let (items, rest) = match &items[..] {
+                    [items @ .., LispExp::Symbol(REST), rest] => (items, Some(rest)),
+                    items => (items, None),
+                };
+                if values.len() < items.len() || (rest.is_none() && values.len() > items.len()) {
+                    return false;
+                }
+                let fits = items.iter().zip(values.iter()).all(|(item, vl)| match_pattern(item, vl, bindings));
+                match rest {
+                    Some(rest) => fits && match_pattern(rest, &LispExp::from(values[items.len()..].to_vec()), bindings),
+                    None => fits,
+                }
+            }
+        },
+        literal => literal == value,
+    }
+}
+
+// (match exp (pattern body...)...) runs the body of the first pattern the
+// value of `exp` fits, with what the pattern captured bound
+fn lisp_match(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (exp, clauses) = unpack(cont)?;
+    let value = env.value(exp)?;
+    for clause in clauses {
+        let Some((pattern, body)) = clause.get_list()?.split_first() else {
+            return Err(LispError::from("expected a (pattern body...) clause, found ()"));
+        };
+        let mut bindings = vec![];
+        if match_pattern(pattern, &value, &mut bindings) {
+            let scope = Scope::child(&env.current_scope());
+            for (name, vl) in bindings {
+                scope.insert(name, vl);
+            }
+            return env.with_scope(scope, || eval_body(env, body));
+        }
+    }
+    Err(LispError::Other(format!("no pattern matches {value}")))
+}
+
+// (exit [code]) ends the program with `code`, 0 by default
+fn lisp_exit(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let code = match cont.first() {
+        Some(code) => code.get_int()?,
+        None => 0,
+    };
+    let code = i32::try_from(code).map_err(|_| LispError::Other(format!("exit code {code} is out of range")))?;
+    Err(LispError::Exit(code))
+}
+
+// (error value) raises `value`, which `try` hands to its `catch` unchanged
+fn lisp_error(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (value, _) = unpack(cont)?;
+    Err(LispError::Raised(value.clone()))
+}
+
+// (try body... (catch e handler...)) runs the body, and if it fails runs the
+// handler with `e` bound to the raised value, or the message of any other error
+fn lisp_try(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let Some((LispExp::List(catch), body)) = cont.split_last() else {
+        return Err(LispError::from("try expects a (catch e handler) form last"));
+    };
+    let [LispExp::Symbol(head), name, handler @ ..] = &catch[..] else {
+        return Err(LispError::from("try expects a (catch e handler) form last"));
+    };
+    if *head != CATCH {
+        return Err(LispError::from("try expects a (catch e handler) form last"));
+    }
+    let err = match eval_body(env, body) {
+        Ok(vl) => return Ok(vl),
+        // errors that stop the whole program can't be caught
+        Err(err) if matches!(err.root(), LispError::Exit(_) | LispError::Interrupted | LispError::LimitExceeded { .. }) => {
+            return Err(err)
+        }
+        Err(err) => err,
+    };
+    let caught = match err.root() {
+        LispError::Raised(value) => value.clone(),
+        other => LispExp::Str(other.message()),
+    };
+    let scope = Scope::child(&env.current_scope());
+    scope.insert(name.get_symbol()?, caught);
+    env.with_scope(scope, || eval_body(env, handler))
+}
+
+// (deftest name body...) registers a test for `sxprs test` to run, instead of
+// running the body now
+fn lisp_deftest(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, body) = unpack(cont)?;
+    let name = match name {
+        LispExp::Str(name) => name.clone(),
+        LispExp::Symbol(name) => name.to_string(),
+        other => return Err(LispError::Other(format!("expected a test name, found {other}"))),
+    };
+    env.add_test(name, body.into());
+    Ok(LispExp::Nil)
+}
+
+// (assert exp) fails with the form and its value unless the value is true
+fn lisp_assert(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let [exp] = cont else {
+        return Err(LispError::ArityMismatch { name: "assert".to_owned(), min: 1, max: Some(1), found: cont.len() });
+    };
+    let vl = env.value(exp)?;
+    if !vl.is_truthy() {
+        return Err(LispError::AssertionFailed { form: exp.to_string(), found: format!("it is {vl}") });
+    }
+    Ok(vl)
+}
+
+// (assert-eq a b) fails with both forms and their values unless the values
+// are equal, like `equal?`
+fn lisp_assert_eq(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let [left, right] = cont else {
+        return Err(LispError::ArityMismatch { name: "assert-eq".to_owned(), min: 2, max: Some(2), found: cont.len() });
+    };
+    let (left_vl, right_vl) = (env.value(left)?, env.value(right)?);
+    if left_vl != right_vl {
+        return Err(LispError::AssertionFailed {
+            form: LispExp::from(vec![LispExp::Symbol(SymbolId::new("assert-eq")), left.clone(), right.clone()]).to_string(),
+            found: format!("{left} is {left_vl}, but {right} is {right_vl}"),
+        });
+    }
+    Ok(left_vl)
+}
+
+fn lisp_quote(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, _) = unpack(cont)?;
+    Ok(car.clone())
+}
+
+// (form x) when `exp` is a list headed by `form`
+fn unquoted(exp: &LispExp, form: SymbolId) -> Option<&LispExp> {
+    match exp {
+        LispExp::List(l) => match &l[..] {
+            [LispExp::Symbol(head), arg] if *head == form => Some(arg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// copies a quasiquote template, evaluating the unquoted parts, in vectors
+// and maps too
+fn fill_template(env: &Interpreter, exp: &LispExp) -> Result<LispExp, LispError> {
+    if let Some(arg) = unquoted(exp, UNQUOTE) {
+        return env.value(arg);
+    }
+    if let Some(items) = exp.literal_items() {
+        return exp.with_literal_items(fill_items(env, &items)?);
+    }
+    let LispExp::List(items) = exp else {
+        return Ok(exp.clone());
+    };
+    Ok(LispExp::List(fill_items(env, items)?.into()))
+}
+
+fn fill_items(env: &Interpreter, items: &[LispExp]) -> Result<Vec<LispExp>, LispError> {
+    let mut ret = vec![];
+    for item in items.iter() {
+        if let Some(arg) = unquoted(item, UNQUOTE_SPLICING) {
+            match env.value(arg)? {
+                LispExp::List(spliced) => ret.extend_from_slice(&spliced),
+                other => return Err(other.mismatch("List")),
+            }
+        } else {
+            ret.push(fill_template(env, item)?);
+        }
+    }
+    Ok(ret)
+}
+
+fn lisp_quasiquote(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, _) = unpack(cont)?;
+    fill_template(env, car)
+}
+
+// (define name [doc] value), where a function keeps the docstring for
+// `help` and any other global value has it kept by name
+fn lisp_define(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let name = name.get_symbol()?;
+    env.definable(name)?;
+    let (doc, vl) = match rest {
+        [doc, vl] => (Some(doc.get_string()?), vl),
+        [vl] => (None, vl),
+        _ => return Err(LispError::from("missing argument")),
+    };
+    let vl = env.value(vl)?;
+    env.define(name, vl.clone());
+    if let Some(doc) = doc {
+        match &vl {
+            LispExp::Lambda(func) | LispExp::Macro(func) => {
+                let _ = func.doc.set(doc.to_owned());
+            }
+            _ if env.current_scope().is_global() => env.set_doc(name, doc.to_owned()),
+            _ => {}
+        }
+    }
+    Ok(vl)
+}
+
+// (set! name value) changes an existing binding, wherever it was defined
+fn lisp_set(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (vl, _) = unpack(rest)?;
+    let name = name.get_symbol()?;
+    let vl = env.value(vl)?;
+    if !env.current_scope().set(name, vl.clone()) {
+        return Err(LispError::UnknownSymbol(name.to_string()));
+    }
+    Ok(vl)
+}
+
+// (module name (export a b) body...) runs the body in a scope of its own and
+// defines what it exports as name/a and name/b; without an export form
+// everything the body defines is exported
+fn lisp_module(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, body) = unpack(cont)?;
+    let name = name.get_symbol()?;
+    let scope = Scope::child(&env.current_scope());
+    let mut exports = None;
+    env.with_scope(scope.clone(), || {
+        for form in body {
+            if let LispExp::List(l) = form {
+                if let [LispExp::Symbol(EXPORT), names @ ..] = &l[..] {
+                    let names = names.iter().map(LispExp::get_symbol).collect::<Result<Vec<_>, _>>()?;
+                    exports.get_or_insert_with(Vec::new).extend(names);
+                    continue;
+                }
+            }
+            env.value(form)?;
+        }
+        Ok::<_, LispError>(())
+    })?;
+    let exports = match exports {
+        Some(names) => names
+            .into_iter()
+            .map(|export| {
+                scope
+                    .get(export)
+                    .map(|vl| (export, vl))
+                    .ok_or_else(|| LispError::Other(format!("module {name} exports {export}, which it doesn't define")))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => scope.bindings(),
+    };
+    for (export, vl) in &exports {
+        env.define(SymbolId::new(&format!("{name}/{export}")), vl.clone());
+    }
+    env.modules.borrow_mut().insert(name, exports);
+    Ok(LispExp::Symbol(name))
+}
+
+fn lisp_export(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Err(LispError::from("export is only allowed at the top of a module's body"))
+}
+
+// (import name names...) makes module `name` available as name/export,
+// loading name.lsp first if no such module was defined yet, and also
+// defines each of `names` it exports without the prefix. A plain name that's
+// already defined, other than by importing the same export, is an error
+// rather than replaced
+fn lisp_import(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, names) = unpack(cont)?;
+    let name = name.get_symbol()?;
+    if !env.modules.borrow().contains_key(&name) {
+        env.load(&env.module_path(name))?;
+    }
+    let exports = env
+        .modules
+        .borrow()
+        .get(&name)
+        .cloned()
+        .ok_or_else(|| LispError::Other(format!("no module {name}")))?;
+    for plain in names {
+        let plain = plain.get_symbol()?;
+        let Some((_, vl)) = exports.iter().find(|(export, _)| *export == plain) else {
+            return Err(LispError::Other(format!("module {name} doesn't export {plain}")));
+        };
+        match env.current_scope().lookup(plain) {
+            Some((defined, _)) if defined != *vl => {
+                return Err(LispError::Other(format!("`{plain}` is already defined, use {name}/{plain}")));
+            }
+            _ => env.define(plain, vl.clone()),
+        }
+    }
+    Ok(LispExp::Symbol(name))
+}
+
+// (gensym [prefix]) makes a symbol no code has used yet, for macros to bind
+// without capturing the caller's names
+fn lisp_gensym(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let prefix = match cont.first() {
+        Some(prefix) => prefix.get_string()?,
+        None => "g",
+    };
+    loop {
+        let n = env.gensyms.get() + 1;
+        env.gensyms.set(n);
+        let name = format!("{prefix}#{n}");
+        if !SymbolId::exists(&name) {
+            return Ok(LispExp::Symbol(SymbolId::new(&name)));
+        }
+    }
+}
+
+pub(crate) fn make_lambda(env: &Interpreter, params: &LispExp, body: &[LispExp]) -> Result<Rc<LispLambda>, LispError> {
+    let LispExp::List(params) = params else {
+        return Err(LispError::Other(format!("expected a parameter list, found {params}")));
+    };
+    // `(a &optional b (c 1) :d 2 &rest others)`, in that order; only one
+    // parameter can follow `&rest`, and it can't be mixed with keywords
+    let mut required = vec![];
+    let mut optional = vec![];
+    let mut keys = vec![];
+    let mut rest = None;
+    let mut in_optional = false;
+    let mut items = params.iter();
+    while let Some(item) = items.next() {
+        match item {
+            LispExp::Symbol(name) if *name == REST => {
+                let (Some(param), None) = (items.next(), items.next()) else {
+                    return Err(LispError::from("`&rest` must be followed by exactly one parameter"));
+                };
+                rest = Some(param.get_symbol()?);
+            }
+            LispExp::Symbol(name) if *name == OPTIONAL && !in_optional && keys.is_empty() => in_optional = true,
+            LispExp::Symbol(keyword) if keyword.is_keyword() => {
+                let default = items
+                    .next()
+                    .ok_or_else(|| LispError::Other(format!("keyword parameter {keyword} needs a default")))?;
+                let param = SymbolId::new(&keyword.name()[1..]);
+                keys.push((param, *keyword, default.clone()));
+            }
+            LispExp::Symbol(name) if !keys.is_empty() => {
+                return Err(LispError::Other(format!("parameter `{name}` comes after the keyword parameters")));
+            }
+            LispExp::Symbol(name) if in_optional => optional.push((*name, LispExp::Nil)),
+            LispExp::Symbol(name) => required.push(*name),
+            LispExp::List(pair) if in_optional && keys.is_empty() => match &pair[..] {
+                [LispExp::Symbol(name), default] => optional.push((*name, default.clone())),
+                _ => return Err(LispError::Other(format!("expected `(name default)`, found {item}"))),
+            },
+            other => return Err(other.mismatch("Symbol")),
+        }
+    }
+    if rest.is_some() && !keys.is_empty() {
+        return Err(LispError::from("a function can't take both keyword and `&rest` parameters"));
+    }
+    if body.is_empty() {
+        return Err(LispError::from("function body is empty"));
+    }
+    // a string before the rest of the body is its docstring, but a string
+    // that is the whole body is what it returns
+    let doc = OnceCell::new();
+    let body = match body {
+        [LispExp::Str(text), rest @ ..] if !rest.is_empty() => {
+            let _ = doc.set(text.clone());
+            rest
+        }
+        _ => body,
+    };
+    Ok(Rc::new(LispLambda {
+        params: required,
+        optional,
+        keys,
+        rest,
+        body: body.to_vec(),
+        env: env.current_scope(),
+        code: OnceCell::new(),
+        name: OnceCell::new(),
+        doc,
+    }))
+}
+
+fn lisp_lambda(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (params, body) = unpack(cont)?;
+    Ok(LispExp::Lambda(make_lambda(env, params, body)?))
+}
+
+fn lisp_defn(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (params, body) = unpack(rest)?;
+    let name = name.get_symbol()?;
+    env.definable(name)?;
+    let func = LispExp::Lambda(make_lambda(env, params, body)?);
+    env.define(name, func.clone());
+    Ok(func)
+}
+
+fn lisp_defmacro(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (params, body) = unpack(rest)?;
+    let name = name.get_symbol()?;
+    env.definable(name)?;
+    let mac = LispExp::Macro(make_lambda(env, params, body)?);
+    env.define(name, mac.clone());
+    Ok(mac)
+}
+
+fn lisp_car(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (lst, _) = unpack(cont)?;
+    if let LispExp::Pair(pair) = lst {
+        return Ok(pair.0.clone());
+    }
+    let (car, _) = lst.get_list()?.split_first().ok_or(LispError::from("car of empty list"))?;
+    Ok(car.clone())
+}
+
+fn lisp_cdr(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (lst, _) = unpack(cont)?;
+    if let LispExp::Pair(pair) = lst {
+        return Ok(pair.1.clone());
+    }
+    let (_, cdr) = lst.get_list()?.split_first().ok_or(LispError::from("cdr of empty list"))?;
+    Ok(cdr.to_vec().into())
+}
+
+// (cons a lst) is `lst` with `a` in front, and (cons a b) the pair (a . b)
+// when `b` isn't a list
+fn lisp_cons(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, rest) = unpack(cont)?;
+    let (cdr, _) = unpack(rest)?;
+    Ok(LispExp::cons(car.clone(), cdr.clone()))
+}
+
+fn lisp_list(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(cont.to_vec().into())
+}
+
+fn lisp_length(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (lst, _) = unpack(cont)?;
+    Ok((lst.get_list()?.len() as i64).into())
+}
+
+// (nth lst i), zero based
+fn lisp_nth(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (lst, rest) = unpack(cont)?;
+    let (idx, _) = unpack(rest)?;
+    let lst = lst.get_list()?;
+    let idx = idx.get_int()?;
+    usize::try_from(idx)
+        .ok()
+        .and_then(|i| lst.get(i))
+        .cloned()
+        .ok_or(LispError::IndexOutOfRange { index: idx, len: lst.len() })
+}
+
+fn lisp_append(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let mut ret = vec![];
+    for lst in cont {
+        ret.extend_from_slice(lst.get_list()?);
+    }
+    Ok(ret.into())
+}
+
+fn lisp_map(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (func, rest) = unpack(cont)?;
+    let (lst, _) = unpack(rest)?;
+    let ret = lst
+        .get_list()?
+        .iter()
+        .map(|item| env.apply(func, vec![item.clone()]))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ret.into())
+}
+
+fn lisp_filter(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (pred, rest) = unpack(cont)?;
+    let (lst, _) = unpack(rest)?;
+    let mut ret = vec![];
+    for item in lst.get_list()? {
+        if env.apply(pred, vec![item.clone()])?.is_truthy() {
+            ret.push(item.clone());
+        }
+    }
+    Ok(ret.into())
+}
+
+// (reduce f init lst) folds from the left, calling (f acc item)
+fn lisp_reduce(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let [func, init, lst] = cont else {
+        return Err(LispError::ArityMismatch {
+            name: "reduce".to_owned(),
+            min: 3,
+            max: Some(3),
+            found: cont.len(),
+        });
+    };
+    lst.get_list()?
+        .iter()
+        .try_fold(init.clone(), |acc, item| env.apply(func, vec![acc, item.clone()]))
+}
+
+// (eval expr) evaluates an already evaluated expression once more, in the current scope
+fn lisp_eval(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (exp, _) = unpack(cont)?;
+    env.value(exp)
+}
+
+// (read text) is the first form in the text as data, the way the parser reads
+// it but without evaluating it, or nil when the text has none
+fn lisp_read(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (text, _) = unpack(cont)?;
+    Ok(env.parse(text.get_string()?)?.into_iter().next().unwrap_or(LispExp::Nil))
+}
+
+// (read-all text) is every form in the text, as a list
+fn lisp_read_all(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (text, _) = unpack(cont)?;
+    Ok(env.parse(text.get_string()?)?.into())
+}
+
+// (apply f lst) calls `f` with the items of `lst` as its arguments
+fn lisp_apply(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let [func, args] = cont else {
+        return Err(LispError::ArityMismatch {
+            name: "apply".to_owned(),
+            min: 2,
+            max: Some(2),
+            found: cont.len(),
+        });
+    };
+    env.apply(func, args.get_list()?.to_vec())
+}
+
+// (dict k v ...) builds a map from key value pairs
+fn lisp_dict(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    if !cont.len().is_multiple_of(2) {
+        return Err(LispError::from("dict expects key value pairs"));
+    }
+    let map = cont
+        .chunks(2)
+        .map(|pair| Ok((MapKey::try_from(&pair[0])?, pair[1].clone())))
+        .collect::<Result<_, LispError>>()?;
+    Ok(LispExp::Map(map))
+}
+
+// (dict-get m k), or nil when `k` isn't in `m`
+fn lisp_dict_get(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (map, rest) = unpack(cont)?;
+    let (key, _) = unpack(rest)?;
+    Ok(map
+        .get_map()?
+        .get(&MapKey::try_from(key)?)
+        .cloned()
+        .unwrap_or(LispExp::Nil))
+}
+
+// maps are values like lists, so these return an updated copy
+fn lisp_dict_set(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (map, rest) = unpack(cont)?;
+    let (key, rest) = unpack(rest)?;
+    let (vl, _) = unpack(rest)?;
+    let mut map = map.get_map()?.clone();
+    map.insert(MapKey::try_from(key)?, vl.clone());
+    Ok(LispExp::Map(map))
+}
+
+fn lisp_dict_remove(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (map, rest) = unpack(cont)?;
+    let (key, _) = unpack(rest)?;
+    let mut map = map.get_map()?.clone();
+    map.remove(&MapKey::try_from(key)?);
+    Ok(LispExp::Map(map))
+}
+
+fn lisp_dict_keys(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (map, _) = unpack(cont)?;
+    Ok(map.get_map()?.keys().cloned().map(LispExp::from).collect::<Vec<_>>().into())
+}
+
+// `[a b c]` evaluates its items like (vector a b c)
+fn lisp_vector(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(LispExp::Vector(Rc::new(RefCell::new(cont.to_vec()))))
+}
+
+// checks `idx` against the vector's length
+fn vec_index(idx: &LispExp, len: usize) -> Result<usize, LispError> {
+    let index = idx.get_int()?;
+    usize::try_from(index)
+        .ok()
+        .filter(|&i| i < len)
+        .ok_or(LispError::IndexOutOfRange { index, len })
+}
+
+fn lisp_vec_get(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vector, rest) = unpack(cont)?;
+    let (idx, _) = unpack(rest)?;
+    let vector = vector.get_vector()?.borrow();
+    Ok(vector[vec_index(idx, vector.len())?].clone())
+}
+
+fn lisp_vec_set(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vector, rest) = unpack(cont)?;
+    let (idx, rest) = unpack(rest)?;
+    let (vl, _) = unpack(rest)?;
+    let mut vector = vector.get_vector()?.borrow_mut();
+    let idx = vec_index(idx, vector.len())?;
+    vector[idx] = vl.clone();
+    Ok(vl.clone())
+}
+
+fn lisp_vec_push(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vector, rest) = unpack(cont)?;
+    let (vl, _) = unpack(rest)?;
+    vector.get_vector()?.borrow_mut().push(vl.clone());
+    Ok(vector.clone())
+}
+
+fn lisp_vec_length(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vector, _) = unpack(cont)?;
+    let len = vector.get_vector()?.borrow().len();
+    Ok((len as i64).into())
+}
+
+// strings and chars are taken as-is, anything else as it prints
+fn lisp_str_concat(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let mut ret = String::new();
+    for item in cont {
+        match item {
+            LispExp::Str(s) => ret.push_str(s),
+            LispExp::Char(c) => ret.push(*c),
+            other => ret.push_str(&other.to_string()),
+        }
+    }
+    Ok(ret.into())
+}
+
+fn lisp_str_length(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, _) = unpack(cont)?;
+    Ok((string.get_string()?.chars().count() as i64).into())
+}
+
+// (substring s start [end]), indices count characters and end is exclusive
+fn lisp_substring(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, rest) = unpack(cont)?;
+    let (start, rest) = unpack(rest)?;
+    let chars: Vec<char> = string.get_string()?.chars().collect();
+    let start = start.get_int()?;
+    let end = match rest.first() {
+        Some(end) => end.get_int()?,
+        None => chars.len() as i64,
+    };
+    if start < 0 || start > end || end > chars.len() as i64 {
+        return Err(LispError::Other(format!(
+            "substring {start}..{end} out of range for string of length {}",
+            chars.len()
+        )));
+    }
+    Ok(chars[start as usize..end as usize].iter().collect::<String>().into())
+}
+
+// (str-split s [sep]), splits on whitespace when there's no separator
+fn lisp_str_split(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, rest) = unpack(cont)?;
+    let string = string.get_string()?;
+    let parts: Vec<LispExp> = match rest.first() {
+        Some(sep) => string.split(sep.get_string()?).map(LispExp::from).collect(),
+        None => string.split_whitespace().map(LispExp::from).collect(),
+    };
+    Ok(parts.into())
+}
+
+fn lisp_str_contains(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, rest) = unpack(cont)?;
+    let (needle, _) = unpack(rest)?;
+    Ok(string.get_string()?.contains(needle.get_string()?).into())
+}
+
+fn lisp_char_to_int(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (chr, _) = unpack(cont)?;
+    Ok(i64::from(u32::from(chr.get_char()?)).into())
+}
+
+fn lisp_int_to_char(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (code, _) = unpack(cont)?;
+    let code = code.get_int()?;
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(LispExp::Char)
+        .ok_or_else(|| LispError::Other(format!("{code} is not a unicode character")))
+}
+
+fn lisp_string_to_list(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, _) = unpack(cont)?;
+    Ok(string.get_string()?.chars().map(LispExp::Char).collect::<Vec<_>>().into())
+}
+
+fn lisp_list_to_string(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (chars, _) = unpack(cont)?;
+    let string = chars.get_list()?.iter().map(LispExp::get_char).collect::<Result<String, _>>()?;
+    Ok(string.into())
+}
+
+// (string->number text) reads any number literal the parser does, `0xFF`,
+// `1_000` and `1/3` included, or gives nil if the text isn't one, so input
+// can be checked before it's used
+fn lisp_string_to_number(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (text, _) = unpack(cont)?;
+    match parse_number(text.get_string()?.trim()) {
+        Some(Ok(num)) => Ok(num),
+        _ => Ok(LispExp::Nil),
+    }
+}
+
+fn lisp_number_to_string(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (num, _) = unpack(cont)?;
+    match num {
+        LispExp::Int(_) | LispExp::BigInt(_) | LispExp::Ratio(_) | LispExp::Number(_) => Ok(num.to_string().into()),
+        other => Err(other.mismatch("Number")),
+    }
+}
+
+fn lisp_symbol_to_string(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (sym, _) = unpack(cont)?;
+    Ok(sym.get_symbol()?.to_string().into())
+}
+
+fn lisp_string_to_symbol(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, _) = unpack(cont)?;
+    Ok(LispExp::Symbol(SymbolId::new(name.get_string()?)))
+}
+
+// (type-of v) is the name of the value's type as a symbol, like `Int` or
+// `List`, or the name a struct was defined with
+fn lisp_type_of(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vl, _) = unpack(cont)?;
+    if let LispExp::Struct(record) = vl {
+        return Ok(LispExp::Symbol(record.name));
+    }
+    Ok(LispExp::Symbol(SymbolId::new(vl.name())))
+}
+
+// (json->lisp text) reads a JSON document into lists, maps and atoms
+fn lisp_json_to_lisp(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (text, _) = unpack(cont)?;
+    json_to_lisp(text.get_string()?, env.max_depth())
+}
+
+// (lisp->json value) writes lisp data as a JSON document
+fn lisp_lisp_to_json(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (value, _) = unpack(cont)?;
+    lisp_to_json(value).map(LispExp::Str)
+}
+
+fn lisp_upcase(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, _) = unpack(cont)?;
+    Ok(string.get_string()?.to_uppercase().into())
+}
+
+fn lisp_downcase(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (string, _) = unpack(cont)?;
+    Ok(string.get_string()?.to_lowercase().into())
+}
+
+fn lisp_version(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(env!("CARGO_PKG_VERSION").into())
+}
+
+// (features) the cargo features the interpreter was built with, as strings
+fn lisp_features(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let mut features = vec![];
+    if cfg!(feature = "serde") {
+        features.push(LispExp::from("serde"));
+    }
+    if cfg!(feature = "graphics") {
+        features.push(LispExp::from("graphics"));
+    }
+    Ok(LispExp::from(features))
+}
+
+fn lisp_host_os(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(std::env::consts::OS.into())
+}
+
+fn lisp_host_arch(_env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(std::env::consts::ARCH.into())
+}
+
+// forms that get their arguments unevaluated and decide what to evaluate themselves
+pub(crate) fn special_forms() -> SymbolMap<Builtin> {
+    let mut specials: SymbolMap<Builtin> = SymbolMap::default();
+    record!(specials, "debug", lisp_debug, 0.., "(debug exp...)", "prints the expressions as they're written, without evaluating them", unevaluated);
+    record!(specials, "time", lisp_time, 1, "(time exp)", "evaluates exp, printing how long it took to stderr");
+    record!(specials, "if", lisp_if, 2..=3, "(if test then [else])", "evaluates then when test is true, else otherwise");
+    record!(specials, "cond", lisp_cond, 0.., "(cond (test body...)...)", "runs the body of the first clause whose test is true");
+    record!(specials, "and", lisp_and, 0.., "(and a b...)", "the first false argument, or the last; stops evaluating at the false one");
+    record!(specials, "or", lisp_or, 0.., "(or a b...)", "the first true argument, or the last; stops evaluating at the true one");
+    record!(specials, "try", lisp_try, 1.., "(try body... (catch e handler...))", "runs the body, and the handler with e bound to the error if it fails");
+    record!(specials, "assert", lisp_assert, 1, "(assert exp)", "fails with the form and its value unless the value is true", unevaluated);
+    record!(specials, "deftest", lisp_deftest, 1.., "(deftest name body...)", "registers a test for `sxprs test` to run");
+    record!(specials, "assert-eq", lisp_assert_eq, 2, "(assert-eq a b)", "fails with both forms and their values unless the values are equal", unevaluated);
+    record!(specials, "quote", lisp_quote, 1, "(quote exp)", "exp itself, unevaluated; 'exp for short", unevaluated);
+    record!(specials, "quasiquote", lisp_quasiquote, 1, "(quasiquote exp)", "exp unevaluated except for its unquoted parts; `exp for short", unevaluated);
+    record!(specials, "while", lisp_while, 1.., "(while test body...)", "runs the body while test is true, returning its last value");
+    record!(specials, "for", lisp_for, 3.., "(for x in lst body...)", "runs the body for every item of the list");
+    record!(specials, "dotimes", lisp_dotimes, 1.., "(dotimes (i n) body...)", "runs the body with i counting from 0 up to n");
+    record!(specials, "define", lisp_define, 2..=3, "(define name [doc] value)", "binds name to value in the current scope");
+    record!(specials, "set!", lisp_set, 2, "(set! name value)", "changes an existing binding, wherever it was defined");
+    record!(specials, "let", lisp_let, 2.., "(let ((name value)...) body...)", "runs the body with the names bound");
+    record!(specials, "spawn", lisp_spawn, 1, "(spawn exp)", "starts evaluating exp on another thread, returning a task for `await`");
+    record!(specials, "async", lisp_spawn, 1, "(async exp)", "`spawn` by another name: a task for `await` to get exp's value from");
+    record!(specials, "with-tasks", lisp_with_tasks, 1.., "(with-tasks (t) body...)", "runs the body with (t exp) spawning exp, then waits for the tasks it started, cancelling the rest once one fails");
+    record!(specials, "begin", lisp_begin, 0.., "(begin exp...)", "evaluates the expressions in a scope of their own, returning the last");
+    record!(specials, "do", lisp_begin, 0.., "(do exp...)", "the same as `begin`");
+    record!(specials, "match", lisp_match, 1.., "(match exp (pattern body...)...)", "runs the body of the first pattern the value fits");
+    record!(specials, "lambda", lisp_lambda, 1.., "(lambda (params...) [doc] body...)", "an anonymous function");
+    record!(specials, "defn", lisp_defn, 2.., "(defn name (params...) [doc] body...)", "defines a function");
+    record!(specials, "defmacro", lisp_defmacro, 2.., "(defmacro name (params...) [doc] body...)", "defines a macro, which gets its arguments unevaluated and returns the code to run");
+    record!(specials, "module", lisp_module, 1.., "(module name (export names...) body...)", "runs the body in a scope of its own and defines what it exports as name/export");
+    record!(specials, "export", lisp_export, 0.., "(export names...)", "lists what a module exports, at the top of its body", unevaluated);
+    record!(specials, "import", lisp_import, 1.., "(import name names...)", "makes module name's exports available as name/export, loading name.lsp if needed, and defines the listed ones without the prefix", unevaluated);
+    specials.extend(help_forms());
+    specials.extend(struct_forms());
+    specials.extend(atom_forms());
+    specials.extend(pipe_forms());
+    specials
+}
+
+pub(crate) fn builtin_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "+", lisp_add, 1.., "(+ x y...)", "the sum of the numbers");
+    record!(funcs, "-", lisp_sub, 1.., "(- x y...)", "x minus the other numbers, or x negated when it's alone");
+    record!(funcs, "*", lisp_mul, 1.., "(* x y...)", "the product of the numbers");
+    record!(funcs, "/", lisp_div, 2.., "(/ x y...)", "x divided by the other numbers, a ratio when ints don't divide evenly");
+    record!(funcs, "=", lisp_eq, 1.., "(= a b...)", "true when every atom is equal to the first, numbers by value");
+    record!(funcs, "equal?", lisp_equal, 2.., "(equal? a b...)", "true when every value is structurally equal to the first");
+    record!(funcs, "not", lisp_not, 1, "(not x)", "true for false values");
+    record!(funcs, "eq?", lisp_identical, 2.., "(eq? a b...)", "true when every value is the first one itself, not just a copy of it");
+    record!(funcs, "<", lisp_lt, 1.., "(< a b...)", "true when the numbers are increasing");
+    record!(funcs, ">", lisp_gt, 1.., "(> a b...)", "true when the numbers are decreasing");
+    record!(funcs, "<=", lisp_le, 1.., "(<= a b...)", "true when the numbers are non-decreasing");
+    record!(funcs, ">=", lisp_ge, 1.., "(>= a b...)", "true when the numbers are non-increasing");
+    record!(funcs, "print", lisp_print, 0.., "(print values...)", "prints each value on a line of its own");
+    record!(funcs, ",", lisp_also, 1.., "(, exp...)", "the last argument");
+    record!(funcs, "exec", lisp_exec, 1.., "(exec program args...)", "runs the program without a shell, returning a map of its :code, :stdout and :stderr");
+    record!(funcs, "shell", lisp_shell, 1, "(shell command)", "runs the command line with the system shell, like `exec`");
+    record!(funcs, "now", lisp_now, 0, "(now)", "the wall-clock time in milliseconds since the unix epoch");
+    record!(funcs, "clock", lisp_clock, 0, "(clock)", "a monotonic time in milliseconds, for `elapsed`");
+    record!(funcs, "elapsed", lisp_elapsed, 1, "(elapsed start)", "how many milliseconds passed since `(clock)` gave start");
+    record!(funcs, "sleep", lisp_sleep, 1, "(sleep ms)", "pauses the program");
+    record!(funcs, "error", lisp_error, 1, "(error value)", "raises value, which `try` hands to its catch unchanged");
+    record!(funcs, "exit", lisp_exit, 0..=1, "(exit [code])", "ends the program with code, 0 by default");
+    record!(funcs, "gensym", lisp_gensym, 0..=1, "(gensym [prefix])", "a symbol no code has used yet");
+    record!(funcs, "expand-path", lisp_expand_path, 1, "(expand-path path)", "path with a leading ~ and $VARS expanded");
+    record!(funcs, "getenv", lisp_getenv, 1, "(getenv name)", "the environment variable's value, or nil when it isn't set");
+    record!(funcs, "setenv", lisp_setenv, 2, "(setenv name value)", "sets the environment variable");
+    record!(funcs, "load", lisp_load, 1, "(load path)", "runs another file, defining what it defines in the current scope");
+    record!(funcs, "watch-path", lisp_watch_path, 2, "(watch-path path handler)", "calls (handler event file) with :created, :modified or :removed for each change under path, until it returns false");
+    record!(funcs, "read-file", lisp_read_file, 1, "(read-file path)", "the file's contents as a string");
+    record!(funcs, "write-file", lisp_write_file, 2, "(write-file path contents)", "writes the string to the file, replacing what was there");
+    record!(funcs, "append-file", lisp_append_file, 2, "(append-file path contents)", "adds the string to the end of the file");
+    record!(funcs, "read-line", lisp_read_line, 0, "(read-line)", "a line from stdin without its line ending, or nil at the end of input");
+    record!(funcs, "car", lisp_car, 1, "(car lst)", "the first item of a list or pair");
+    record!(funcs, "cdr", lisp_cdr, 1, "(cdr lst)", "the list without its first item, or the second half of a pair");
+    record!(funcs, "cons", lisp_cons, 2, "(cons a lst)", "lst with a in front, or the pair (a . b) when b isn't a list");
+    record!(funcs, "list", lisp_list, 0.., "(list items...)", "a list of the arguments");
+    record!(funcs, "length", lisp_length, 1, "(length lst)", "how many items the list has");
+    record!(funcs, "nth", lisp_nth, 2, "(nth lst i)", "the item at index i, counting from 0");
+    record!(funcs, "append", lisp_append, 0.., "(append lsts...)", "the lists joined into one");
+    record!(funcs, "map", lisp_map, 2, "(map f lst)", "the list of f called on every item");
+    record!(funcs, "filter", lisp_filter, 2, "(filter f lst)", "the items f is true for");
+    record!(funcs, "reduce", lisp_reduce, 3, "(reduce f init lst)", "folds the list from the left, calling (f acc item)");
+    record!(funcs, "eval", lisp_eval, 1, "(eval exp)", "evaluates the value once more, in the current scope");
+    record!(funcs, "read", lisp_read, 1, "(read text)", "the first form in the text, unevaluated, or nil when there's none");
+    record!(funcs, "read-all", lisp_read_all, 1, "(read-all text)", "every form in the text, unevaluated, as a list");
+    record!(funcs, "apply", lisp_apply, 2, "(apply f lst)", "calls f with the items of lst as its arguments");
+    record!(funcs, "dict", lisp_dict, 0.., "(dict k v...)", "a map of the key value pairs");
+    record!(funcs, "dict-get", lisp_dict_get, 2, "(dict-get m k)", "the value of k in m, or nil when it's missing");
+    record!(funcs, "dict-set", lisp_dict_set, 3, "(dict-set m k v)", "a copy of m with k set to v");
+    record!(funcs, "dict-remove", lisp_dict_remove, 2, "(dict-remove m k)", "a copy of m without k");
+    record!(funcs, "dict-keys", lisp_dict_keys, 1, "(dict-keys m)", "the keys of m");
+    record!(funcs, "vector", lisp_vector, 0.., "(vector items...)", "a vector of the arguments; [items...] for short");
+    record!(funcs, "vec-get", lisp_vec_get, 2, "(vec-get v i)", "the item at index i");
+    record!(funcs, "vec-set!", lisp_vec_set, 3, "(vec-set! v i x)", "replaces the item at index i");
+    record!(funcs, "vec-push!", lisp_vec_push, 2, "(vec-push! v x)", "adds x to the end of the vector");
+    record!(funcs, "vec-length", lisp_vec_length, 1, "(vec-length v)", "how many items the vector has");
+    record!(funcs, "str-concat", lisp_str_concat, 0.., "(str-concat values...)", "the values joined into a string, strings as they are and anything else as it prints");
+    record!(funcs, "str-length", lisp_str_length, 1, "(str-length s)", "how many characters the string has");
+    record!(funcs, "substring", lisp_substring, 2..=3, "(substring s start [end])", "the characters from start up to end");
+    record!(funcs, "str-split", lisp_str_split, 1..=2, "(str-split s [sep])", "the parts of s between the separators, or between whitespace");
+    record!(funcs, "str-contains?", lisp_str_contains, 2, "(str-contains? s needle)", "true when needle is in s");
+    record!(funcs, "char->int", lisp_char_to_int, 1, "(char->int c)", "the character's unicode code point");
+    record!(funcs, "int->char", lisp_int_to_char, 1, "(int->char n)", "the character with the unicode code point");
+    record!(funcs, "string->list", lisp_string_to_list, 1, "(string->list s)", "the string's characters");
+    record!(funcs, "list->string", lisp_list_to_string, 1, "(list->string chars)", "the characters joined into a string");
+    record!(funcs, "string->number", lisp_string_to_number, 1, "(string->number s)", "the number the text is, or nil if it isn't one");
+    record!(funcs, "number->string", lisp_number_to_string, 1, "(number->string n)", "the number as text");
+    record!(funcs, "symbol->string", lisp_symbol_to_string, 1, "(symbol->string sym)", "the symbol's name");
+    record!(funcs, "string->symbol", lisp_string_to_symbol, 1, "(string->symbol s)", "the symbol with the name");
+    record!(funcs, "type-of", lisp_type_of, 1, "(type-of v)", "the name of the value's type as a symbol, like Int or List, or a struct's name");
+    record!(funcs, "json->lisp", lisp_json_to_lisp, 1, "(json->lisp text)", "the JSON document as lists, maps and atoms");
+    record!(funcs, "lisp->json", lisp_lisp_to_json, 1, "(lisp->json value)", "the value as a JSON document");
+    record!(funcs, "upcase", lisp_upcase, 1, "(upcase s)", "the string in upper case");
+    record!(funcs, "downcase", lisp_downcase, 1, "(downcase s)", "the string in lower case");
+    record!(funcs, "version", lisp_version, 0, "(version)", "the interpreter's version");
+    record!(funcs, "features", lisp_features, 0, "(features)", "the optional features the interpreter was built with");
+    record!(funcs, "host-os", lisp_host_os, 0, "(host-os)", "the operating system, like \"linux\"");
+    record!(funcs, "host-arch", lisp_host_arch, 0, "(host-arch)", "the CPU architecture, like \"x86_64\"");
+    funcs.extend(format_funcs());
+    funcs.extend(parallel_funcs());
+    funcs.extend(math_funcs());
+    funcs.extend(help_funcs());
+    funcs.extend(image_funcs());
+    funcs.extend(heap_funcs());
+    funcs.extend(schedule_funcs());
+    funcs.extend(struct_funcs());
+    funcs.extend(atom_funcs());
+    #[cfg(feature = "graphics")]
+    funcs.extend(graphics_funcs());
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn features() {
+        let features: Vec<&str> = [("serde", cfg!(feature = "serde")), ("graphics", cfg!(feature = "graphics"))]
+            .into_iter()
+            .filter_map(|(name, on)| on.then_some(name))
+            .collect();
+        let expected = features.iter().map(|name| format!("\"{name}\"")).collect::<Vec<_>>().join(" ");
+        assert_eq!(run("(features)"), format!("( {expected} )"));
+    }
+
+    #[test]
+    fn str_concat_and_dict() {
+        assert_eq!(run("(str-concat \"a\" #\\b 1 '(c))"), "\"ab1( c )\"");
+        assert_eq!(run("(dict-get (dict :a 1 \"b\" 2) \"b\")"), "2");
+        assert_eq!(run("(dict :a)"), "error: dict expects key value pairs");
+    }
+
+    #[test]
+    fn string_to_number() {
+        for (text, num) in [("42", "42"), (" -2.5 ", "-2.5"), ("0x10", "16"), ("-0b101", "-5"), ("1_000", "1000"), (".5", "0.5"), ("1e3", "1000.0"), ("2/4", "1/2")] {
+            assert_eq!(run(&format!("(string->number \"{text}\")")), num, "{text}");
+        }
+        for text in ["", "abc", "1/0", "inf", "NaN", "0x", "12ab"] {
+            assert_eq!(run(&format!("(string->number \"{text}\")")), "nil", "{text}");
+        }
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(run("(- 5)"), "-5");
+        assert_eq!(run("(- 2.5)"), "-2.5");
+        assert_eq!(run("(- 1/3)"), "-1/3");
+        assert_eq!(run("(- -9223372036854775808)"), "9223372036854775808");
+        assert_eq!(run("(- 10 3 2)"), "5");
+    }
+
+    #[test]
+    fn try_and_error() {
+        assert_eq!(run("(try (error \"boom\") (catch e e))"), "\"boom\"");
+        assert_eq!(run("(try (car 1) (catch e e))"), "\"1 is not a List, it's a Int\"");
+        assert_eq!(run("(try 1 (catch e 2))"), "1");
+    }
+
+    #[test]
+    fn match_and_quasiquote() {
+        assert_eq!(run("(match '(1 2) ((a b) (+ a b)) (_ 0))"), "3");
+        assert_eq!(run("(match 5 ((a b) 1) (x x))"), "5");
+        assert_eq!(run("`(a ~(+ 1 2) ~@(list 3 4))"), "( a 3 3 4 )");
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(run("(for x in (list 1 2 3) x)"), "3");
+        assert_eq!(run("(dotimes (i 3) i)"), "2");
+        assert_eq!(run("(begin (define n 0) (while (< n 5) (set! n (+ n 1))) n)"), "5");
+    }
+
+    #[test]
+    fn modules_export_what_they_list() {
+        let source = "(module m (export f) (defn f (x) (* x 2)) (defn g () 1))";
+        assert_eq!(run(&format!("{source} (m/f 4)")), "8");
+        assert_eq!(run(&format!("{source} (m/g)")), "error: symbol m/g is not defined");
+    }
+
+    #[test]
+    fn lists_and_strings() {
+        assert_eq!(run("(reduce + 0 (filter (lambda (x) (> x 1)) '(1 2 3)))"), "5");
+        assert_eq!(run("(apply + (map (lambda (x) (* x x)) '(1 2)))"), "5");
+        assert_eq!(run("(str-split \"a,b\" \",\")"), "( \"a\" \"b\" )");
+        assert_eq!(run("(substring \"hello\" 1 3)"), "\"el\"");
+        assert_eq!(run("(list->string (string->list \"abc\"))"), "\"abc\"");
+    }
+
+    #[test]
+    fn watch_path_reports_changes() {
+        let dir = std::env::temp_dir().join(format!("sxprs-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("f.txt");
+        let changes = {
+            let file = file.clone();
+            std::thread::spawn(move || {
+                let pause = || std::thread::sleep(std::time::Duration::from_millis(500));
+                pause();
+                std::fs::write(&file, "a").unwrap();
+                pause();
+                std::fs::write(&file, "bb").unwrap();
+                pause();
+                std::fs::remove_file(&file).unwrap();
+            })
+        };
+        let source = format!(
+            "(define seen '()) \
+             (watch-path {:?} (lambda (event file) (set! seen (cons (list event (= file {:?})) seen)) (not (eq? event :removed)))) \
+             seen",
+            dir.display().to_string(),
+            file.display().to_string()
+        );
+        let res = run(&source);
+        changes.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res, "( ( :removed true ) ( :modified true ) ( :created true ) )");
+    }
+
+    #[test]
+    fn deftest_registers_tests() {
+        let lisp = Interpreter::new();
+        let source = "(define x 1) (deftest passes (assert-eq (+ x 1) 2)) (deftest \"fails\" (assert (= x 2)))";
+        assert_eq!(lisp.eval_str(source).unwrap().to_string(), "nil");
+        let results = lisp.run_tests();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, "passes");
+        assert_eq!(results[0].1.as_ref().unwrap().to_string(), "2");
+        assert_eq!(results[1].0, "fails");
+        assert!(results[1].1.as_ref().unwrap_err().summary().contains("( = x 2 )"));
+    }
+}
@@ -0,0 +1,180 @@
+// `sxprs bundle`: a program and the files it loads, as one file that runs
+// without them. Top-level `(load "file")` forms are replaced by the forms of
+// the file, and `(import name)` gets the forms of `name.lsp` before it, the
+// first time the module is imported; loads and imports anywhere else, or of
+// paths that are only known when the program runs, are left as they are and
+// reported
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::builtins::expand_path;
+use crate::error::LispError;
+use crate::exp::LispExp;
+use crate::fmt::{flat, format_forms};
+use crate::lexer::tokens;
+use crate::parser::parse_program;
+use crate::platform;
+
+#[derive(Default)]
+struct Bundler {
+    // the files being bundled, innermost last, to catch ones that load each other
+    files: Vec<PathBuf>,
+    // the modules defined so far, which importing doesn't load again
+    modules: HashSet<String>,
+    // where `import` looks when a module's file isn't beside the importing one
+    include: Vec<PathBuf>,
+    out: String,
+    warnings: Vec<String>,
+}
+
+// the head and arguments of `exp`, or no head when it isn't a call
+pub(crate) fn split(exp: &LispExp) -> (Rc<str>, &[LispExp]) {
+    match exp {
+        LispExp::List(items) => match items.first() {
+            Some(LispExp::Symbol(head)) => (head.name(), &items[1..]),
+            _ => (Rc::from(""), &[]),
+        },
+        _ => (Rc::from(""), &[]),
+    }
+}
+
+impl Bundler {
+    fn file(&mut self, path: &Path) -> Result<(), LispError> {
+        let shown = path.display().to_string();
+        let path = platform::canonicalize(path).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        if self.files.contains(&path) {
+            return Err(LispError::Other(format!("{shown} loads itself")));
+        }
+        let source = platform::read_file(&path).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        let program = tokens(&source)
+            .and_then(|tokens| parse_program(&tokens))
+            .map_err(|err| LispError::Other(format!("{shown}:{}", err.message())))?;
+        self.files.push(path);
+        for form in &program {
+            self.form(form, &shown)?;
+        }
+        self.files.pop();
+        Ok(())
+    }
+    // a path as `load` reads it: relative to the file doing the loading
+    fn resolve(&self, path: &str) -> Result<PathBuf, LispError> {
+        let path = PathBuf::from(expand_path(path)?);
+        match self.files.last().and_then(|file| file.parent()) {
+            Some(dir) if path.is_relative() => Ok(dir.join(path)),
+            _ => Ok(path),
+        }
+    }
+    fn form(&mut self, form: &LispExp, shown: &str) -> Result<(), LispError> {
+        let (head, args) = split(form);
+        match (&*head, args) {
+            ("load", [LispExp::Str(path)]) => {
+                self.out.push_str(&format!("; {path}\n"));
+                return self.file(&self.resolve(path)?);
+            }
+            ("module", [LispExp::Symbol(name), ..]) => {
+                self.modules.insert(name.to_string());
+            }
+            ("import", [LispExp::Symbol(name), ..]) => {
+                let name = name.to_string();
+                let file = format!("{name}.lsp");
+                let beside = self.resolve(&file)?;
+                let path = Some(beside).into_iter().chain(self.include.iter().map(|dir| dir.join(&file))).find(|path| path.exists());
+                if let Some(path) = path.filter(|_| !self.modules.contains(&name)) {
+                    self.modules.insert(name.clone());
+                    self.out.push_str(&format!("; {name}.lsp\n"));
+                    self.file(&path)?;
+                }
+                self.out.push_str(&format_forms(std::slice::from_ref(form)));
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.nested(form, shown);
+        self.out.push_str(&format_forms(std::slice::from_ref(form)));
+        Ok(())
+    }
+    // reports the loads and imports that are left for when the program runs
+    fn nested(&mut self, exp: &LispExp, shown: &str) {
+        if let Some(items) = exp.literal_items() {
+            return items.iter().for_each(|item| self.nested(item, shown));
+        }
+        let LispExp::List(items) = exp else { return };
+        let (head, args) = split(exp);
+        match (&*head, args) {
+            ("quote", _) => return,
+            ("load", _) => self.warnings.push(format!("{shown}: {} is left for when the program runs", flat(exp))),
+            ("import", [LispExp::Symbol(name), ..]) if !self.modules.contains(&name.to_string()) => {
+                self.warnings.push(format!("{shown}: {} is left for when the program runs", flat(exp)))
+            }
+            _ => {}
+        }
+        items.iter().for_each(|item| self.nested(item, shown));
+    }
+}
+
+// the program at `path` with the files it loads bundled into it, and a
+// warning for each load that's left as it is; `include` is where `import`
+// looks after the importing file's directory
+pub fn bundle(path: &Path, include: &[PathBuf]) -> Result<(String, Vec<String>), LispError> {
+    let mut bundler = Bundler { include: include.to_vec(), ..Bundler::default() };
+    let source = platform::read_file(path).map_err(|err| LispError::Other(format!("{}: {err}", path.display())))?;
+    // the lexer skips a script's `#!` line, so it's kept as it is
+    if let Some(shebang) = source.lines().next().filter(|line| line.starts_with("#!")) {
+        bundler.out.push_str(&format!("{shebang}\n"));
+    }
+    bundler.file(path)?;
+    Ok((bundler.out, bundler.warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    // writes `files` to a directory of their own and bundles the first one
+    fn bundled(dir: &str, files: &[(&str, &str)]) -> Result<(String, Vec<String>), LispError> {
+        let dir = std::env::temp_dir().join(format!("sxprs-bundle-{}-{dir}", std::process::id()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let res = bundle(&dir.join(files[0].0), &[dir.join("lib")]);
+        std::fs::remove_dir_all(dir).unwrap();
+        res
+    }
+
+    #[test]
+    fn loads_and_imports_are_bundled() {
+        let files = [
+            ("main.lsp", "#!/usr/bin/env sxprs\n(load \"lib/h.lsp\")\n(import util twice)\n(import util twice)\n(import inc one)\n(twice (helper (+ more one)))\n"),
+            ("lib/h.lsp", "(defn helper (x) (+ x 1))\n(load \"more.lsp\")\n"),
+            ("lib/more.lsp", "(define more 7)\n"),
+            ("util.lsp", "(module util (export twice) (defn twice (x) (* 2 x)))\n"),
+            ("lib/inc.lsp", "(module inc (export one) (define one 1))\n"),
+        ];
+        let (source, warnings) = bundled("bundles", &files).unwrap();
+        assert!(warnings.is_empty());
+        assert!(source.starts_with("#!/usr/bin/env sxprs\n; lib/h.lsp\n(defn helper (x) (+ x 1))\n; more.lsp\n(define more 7)\n; util.lsp\n"), "{source}");
+        assert_eq!(source.matches("(module util").count(), 1);
+        assert!(source.contains("; inc.lsp\n(module inc"), "{source}");
+        assert_eq!(Interpreter::new().eval_str(&source).unwrap().to_string(), "18");
+    }
+
+    #[test]
+    fn dynamic_loads_are_reported() {
+        let files = [("main.lsp", "(defn later (name) (load name))\n(import nowhere)\n'(load \"quoted\")\n")];
+        let (source, warnings) = bundled("dynamic", &files).unwrap();
+        assert_eq!(source, "(defn later (name) (load name))\n(import nowhere)\n'(load \"quoted\")\n");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].ends_with("main.lsp: (load name) is left for when the program runs"), "{warnings:?}");
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let files = [("a.lsp", "(load \"b.lsp\")\n"), ("b.lsp", "(load \"a.lsp\")\n")];
+        let err = bundled("cycle", &files).unwrap_err();
+        assert!(err.message().ends_with("a.lsp loads itself"), "{}", err.message());
+    }
+}
@@ -0,0 +1,355 @@
+// a static pass over a parsed program that flags obvious mistakes without
+// running any of it: calls to names nothing defines or to literals, builtins
+// called with the wrong number of arguments and literals of the wrong type
+// given to arithmetic
+use std::collections::HashSet;
+
+use crate::eval::{Arity, Interpreter};
+use crate::exp::LispExp;
+use crate::parser::parse_error;
+use crate::symbol::SymbolId;
+
+// builtins that only take numbers
+const NUMERIC: &[&str] = &["+", "-", "*", "/", "<", ">", "<=", ">="];
+
+// the forms that don't evaluate some of their arguments, so the pass has to
+// know which names they bind
+fn binds(head: &str) -> bool {
+    matches!(head, "quote" | "quasiquote" | "lambda" | "defn" | "defmacro" | "let" | "for" | "dotimes" | "match" | "cond" | "try" | "with-tasks" | "pipe" | "module" | "export" | "import")
+}
+
+struct Checker<'a> {
+    lisp: &'a Interpreter,
+    // every name the program defines anywhere, so functions can be called
+    // before the form that defines them
+    defined: HashSet<SymbolId>,
+    macros: HashSet<SymbolId>,
+    // code the program loads, evaluates or expands can define anything, so
+    // undefined names aren't reported at all
+    dynamic: bool,
+    // parameters and let bindings in scope
+    locals: Vec<SymbolId>,
+    // the top-level definition being checked, for messages
+    within: Option<SymbolId>,
+    problems: Vec<String>,
+}
+
+// checks `program` against what `lisp` defines, returning a message for every
+// problem found
+pub fn check_types(lisp: &Interpreter, program: &[LispExp]) -> Vec<String> {
+    let mut checker = Checker {
+        lisp,
+        defined: HashSet::new(),
+        macros: HashSet::new(),
+        dynamic: false,
+        locals: vec![],
+        within: None,
+        problems: vec![],
+    };
+    let modules: HashSet<SymbolId> = program.iter().filter_map(|form| definition(form, "module")).collect();
+    for form in program {
+        checker.collect(form, None, &modules);
+    }
+    for form in program {
+        checker.within = definition(form, "defn").or_else(|| definition(form, "defmacro"));
+        checker.walk(form);
+    }
+    checker.problems
+}
+
+// the name `form` defines, if it's a `(head name ...)` form
+fn definition(form: &LispExp, head: &str) -> Option<SymbolId> {
+    match form {
+        LispExp::List(items) => match &items[..] {
+            [LispExp::Symbol(form_head), LispExp::Symbol(name), ..] if *form_head.name() == *head => Some(*name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// the names a `match` pattern captures
+fn captures(pattern: &LispExp, names: &mut Vec<SymbolId>) {
+    match pattern {
+        LispExp::Symbol(name) if !name.is_keyword() => names.push(*name),
+        LispExp::List(items) => match &items[..] {
+            [LispExp::Symbol(head), _] if &*head.name() == "quote" => {}
+            items => items.iter().for_each(|item| captures(item, names)),
+        },
+        _ => {}
+    }
+}
+
+// the names a parameter list binds, and the default values in it
+fn params(list: &LispExp) -> (Vec<SymbolId>, Vec<&LispExp>) {
+    let mut names = vec![];
+    let mut defaults = vec![];
+    let Ok(items) = list.get_list() else {
+        return (names, defaults);
+    };
+    let mut items = items.iter();
+    while let Some(item) = items.next() {
+        match item {
+            LispExp::Symbol(name) if name.is_keyword() => {
+                names.push(SymbolId::new(&name.name()[1..]));
+                defaults.extend(items.next());
+            }
+            LispExp::Symbol(name) => names.push(*name),
+            LispExp::List(pair) => {
+                if let [LispExp::Symbol(name), default] = &pair[..] {
+                    names.push(*name);
+                    defaults.push(default);
+                }
+            }
+            _ => {}
+        }
+    }
+    (names, defaults)
+}
+
+impl Checker<'_> {
+    fn collect(&mut self, form: &LispExp, module: Option<SymbolId>, modules: &HashSet<SymbolId>) {
+        if let Some(items) = form.literal_items() {
+            return items.iter().for_each(|item| self.collect(item, module, modules));
+        }
+        let LispExp::List(items) = form else {
+            return;
+        };
+        let Some((LispExp::Symbol(head), args)) = items.split_first() else {
+            return items.iter().for_each(|item| self.collect(item, module, modules));
+        };
+        match (&*head.name(), args) {
+            ("quote" | "quasiquote", _) => return,
+            ("load" | "eval", _) => self.dynamic = true,
+            ("defmacro", [LispExp::Symbol(name), ..]) => {
+                self.macros.insert(*name);
+                self.dynamic = true;
+            }
+            ("import", [LispExp::Symbol(name), ..]) if !modules.contains(name) => self.dynamic = true,
+            ("module", [LispExp::Symbol(name), body @ ..]) => {
+                return body.iter().for_each(|form| self.collect(form, Some(*name), modules));
+            }
+            _ => {}
+        }
+        let mut names = vec![];
+        match (&*head.name(), args) {
+            ("define" | "defn", [LispExp::Symbol(name), ..]) => names.push(name.to_string()),
+            // the constructor, the predicate and an accessor per field
+            ("defstruct", [LispExp::Symbol(name), fields @ ..]) => {
+                names.push(name.to_string());
+                names.push(format!("{name}?"));
+                names.extend(fields.iter().map(|field| format!("{name}-{field}")));
+            }
+            _ => {}
+        }
+        for name in names {
+            if let Some(module) = module {
+                self.defined.insert(SymbolId::new(&format!("{module}/{name}")));
+            }
+            self.defined.insert(SymbolId::new(&name));
+        }
+        args.iter().for_each(|arg| self.collect(arg, module, modules));
+    }
+    fn problem(&mut self, message: String) {
+        match self.within {
+            Some(name) => self.problems.push(format!("in `{name}`: {message}")),
+            None => self.problems.push(message),
+        }
+    }
+    fn is_defined(&self, name: SymbolId) -> bool {
+        self.dynamic
+            || name.is_keyword()
+            || self.locals.contains(&name)
+            || self.defined.contains(&name)
+            || self.lisp.is_special(name)
+            || self.lisp.resolve(name).is_ok()
+    }
+    // walks `body` with `names` bound
+    fn walk_with(&mut self, names: Vec<SymbolId>, body: &[LispExp]) {
+        let outer = self.locals.len();
+        self.locals.extend(names);
+        body.iter().for_each(|form| self.walk(form));
+        self.locals.truncate(outer);
+    }
+    fn walk(&mut self, form: &LispExp) {
+        if let Some(items) = form.literal_items() {
+            return items.iter().for_each(|item| self.walk(item));
+        }
+        let LispExp::List(items) = form else {
+            return;
+        };
+        // what couldn't be read was reported already
+        if parse_error(form).is_some() {
+            return;
+        }
+        let Some((head, args)) = items.split_first() else {
+            return;
+        };
+        let LispExp::Symbol(name) = head else {
+            if let LispExp::Str(_) | LispExp::Char(_) | LispExp::Int(_) | LispExp::BigInt(_) | LispExp::Ratio(_) | LispExp::Number(_) | LispExp::Bool(_) | LispExp::Nil = head {
+                self.problem(format!("the {} {head} is called, but it isn't a function", head.name()));
+            }
+            return items.iter().for_each(|item| self.walk(item));
+        };
+        if self.locals.contains(name) {
+            return args.iter().for_each(|arg| self.walk(arg));
+        }
+        self.check_call(*name, args);
+        let head = name.name();
+        if !binds(&head) {
+            // a macro's arguments are code it rewrites, not values
+            if !self.macros.contains(name) {
+                args.iter().for_each(|arg| self.walk(arg));
+            }
+            return;
+        }
+        match (&*head, args) {
+            ("quasiquote", [template]) => self.walk_template(template),
+            ("lambda", [list, body @ ..]) | ("defn" | "defmacro", [_, list, body @ ..]) => {
+                let (names, defaults) = params(list);
+                let outer = self.locals.len();
+                self.locals.extend(names);
+                defaults.into_iter().for_each(|default| self.walk(default));
+                body.iter().for_each(|form| self.walk(form));
+                self.locals.truncate(outer);
+            }
+            ("let", [LispExp::List(bindings), body @ ..]) => {
+                let mut names = vec![];
+                for binding in bindings.iter() {
+                    if let LispExp::List(pair) = binding {
+                        if let [LispExp::Symbol(name), vl] = &pair[..] {
+                            self.walk(vl);
+                            names.push(*name);
+                        }
+                    }
+                }
+                self.walk_with(names, body);
+            }
+            ("for", [LispExp::Symbol(name), _, lst, body @ ..]) => {
+                self.walk(lst);
+                self.walk_with(vec![*name], body);
+            }
+            ("dotimes", [LispExp::List(header), body @ ..]) => {
+                if let [LispExp::Symbol(name), count] = &header[..] {
+                    self.walk(count);
+                    self.walk_with(vec![*name], body);
+                }
+            }
+            // a clause is a test and a body, not a call
+            ("cond", clauses) => {
+                for clause in clauses {
+                    if let LispExp::List(clause) = clause {
+                        for exp in clause.iter() {
+                            if !matches!(exp, LispExp::Symbol(name) if &*name.name() == "else") {
+                                self.walk(exp);
+                            }
+                        }
+                    }
+                }
+            }
+            ("match", [exp, clauses @ ..]) => {
+                self.walk(exp);
+                for clause in clauses {
+                    if let LispExp::List(clause) = clause {
+                        if let [pattern, body @ ..] = &clause[..] {
+                            let mut names = vec![];
+                            captures(pattern, &mut names);
+                            self.walk_with(names, body);
+                        }
+                    }
+                }
+            }
+            ("try", [body @ .., LispExp::List(catch)]) => {
+                body.iter().for_each(|form| self.walk(form));
+                if let [_, LispExp::Symbol(err), handler @ ..] = &catch[..] {
+                    self.walk_with(vec![*err], handler);
+                }
+            }
+            ("with-tasks", [LispExp::List(names), body @ ..]) => {
+                let names = names.iter().filter_map(|name| if let LispExp::Symbol(name) = name { Some(*name) } else { None }).collect();
+                self.walk_with(names, body);
+            }
+            // the stages are named by their heads, which aren't calls
+            ("pipe", stages) => {
+                for stage in stages {
+                    if let LispExp::List(stage) = stage {
+                        stage.iter().skip(1).for_each(|arg| self.walk(arg));
+                    }
+                }
+            }
+            ("module", [_, body @ ..]) => body.iter().for_each(|form| self.walk(form)),
+            _ => {}
+        }
+    }
+    // only what's unquoted in a quasiquote template is evaluated
+    fn walk_template(&mut self, template: &LispExp) {
+        let LispExp::List(items) = template else {
+            return;
+        };
+        match &items[..] {
+            [LispExp::Symbol(head), exp] if matches!(&*head.name(), "unquote" | "unquote-splicing") => self.walk(exp),
+            items => items.iter().for_each(|item| self.walk_template(item)),
+        }
+    }
+    fn check_call(&mut self, name: SymbolId, args: &[LispExp]) {
+        if !self.is_defined(name) {
+            self.problem(format!("`{name}` is called, but it isn't defined"));
+            return;
+        }
+        // the program can shadow builtins with functions and parameters of its own
+        if self.defined.contains(&name) || self.locals.contains(&name) {
+            return;
+        }
+        let head = name.name();
+        if let Some(Arity { min, max }) = self.lisp.arity(name) {
+            if args.len() < min || max.is_some_and(|max| args.len() > max) {
+                let expected = match max {
+                    Some(max) if max == min => format!("{min}"),
+                    Some(max) => format!("{min} to {max}"),
+                    None => format!("at least {min}"),
+                };
+                self.problem(format!("`{name}` takes {expected} arguments, but is given {}", args.len()));
+            }
+        }
+        if NUMERIC.contains(&&*head) {
+            for arg in args {
+                if let LispExp::Str(_) | LispExp::Char(_) | LispExp::Bool(_) | LispExp::Nil = arg {
+                    self.problem(format!("`{name}` takes numbers, but is given the {} {arg}", arg.name()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(source: &str) -> Vec<String> {
+        let lisp = Interpreter::new();
+        let program = lisp.parse(source).unwrap();
+        check_types(&lisp, &program)
+    }
+
+    #[test]
+    fn finds_mistakes() {
+        assert_eq!(
+            problems("(defn f (x) (+ x \"a\")) (g 1) (car 1 2) (1 2)"),
+            [
+                "in `f`: `+` takes numbers, but is given the String \"a\"",
+                "`g` is called, but it isn't defined",
+                "`car` takes 1 arguments, but is given 2",
+                "the Int 1 is called, but it isn't a function",
+            ]
+        );
+    }
+
+    #[test]
+    fn knows_what_forms_bind() {
+        let source = "(defn f (a &optional (b a) &rest r) (list a b r)) (let ((y 1)) (+ y 1)) \
+                      (match '(1 2) ((a b) (+ a b))) (for x in '(1) (+ x 1)) (try (error 1) (catch e e)) \
+                      (with-tasks (t) (t (+ 1 2))) (pipe (from '(1)) (map car) (collect)) (defmacro m (x) x) (m (+ 1 2)) (f (later)) (defn later () 1) \
+                      (defn g (car) (car 1 2))";
+        assert_eq!(problems(source), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,415 @@
+// the concrete syntax tree: a program as it was written, down to its spaces
+// and comments, for tools that rewrite part of a file and have to leave the
+// rest of it alone. Every node owns the whitespace and comments before it, and
+// a list the ones before its closing delimiter, so writing the tree out gives
+// back the source byte for byte
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+
+use crate::error::LispError;
+use crate::exp::LispExp;
+use crate::fmt::{pieces, Piece};
+use crate::lexer::{lex, tokens, Token};
+use crate::parser::{parse, parse_atom, parse_program, DEFAULT_MAX_DEPTH};
+
+// what's between two tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trivia {
+    Space(String),
+    // a `;` or `#| |#` comment, or a script's `#!` line
+    Comment(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CstKind {
+    // an atom, string or character, as written
+    Atom(String),
+    // a reader prefix as written, `'`, `` ` ``, `~` or `~@`, and its form
+    Prefix(String, Box<CstNode>),
+    List {
+        open: &'static str,
+        close: &'static str,
+        items: Vec<CstNode>,
+        // the spaces and comments after the last item
+        trailing: Vec<Trivia>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CstNode {
+    // the spaces and comments since the token before it
+    pub leading: Vec<Trivia>,
+    // where the node is in the source, in bytes, not counting `leading`
+    pub span: Range<usize>,
+    pub kind: CstKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cst {
+    pub forms: Vec<CstNode>,
+    // the spaces and comments after the last form
+    pub trailing: Vec<Trivia>,
+}
+
+impl Display for Trivia {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Trivia::Space(text) | Trivia::Comment(text) => f.write_str(text),
+        }
+    }
+}
+
+fn write_trivia(f: &mut Formatter<'_>, trivia: &[Trivia]) -> fmt::Result {
+    trivia.iter().try_for_each(|trivia| trivia.fmt(f))
+}
+
+// the node with the spaces and comments before it
+impl Display for CstNode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_trivia(f, &self.leading)?;
+        match &self.kind {
+            CstKind::Atom(text) => f.write_str(text),
+            CstKind::Prefix(prefix, form) => write!(f, "{prefix}{form}"),
+            CstKind::List { open, close, items, trailing } => {
+                f.write_str(open)?;
+                items.iter().try_for_each(|item| item.fmt(f))?;
+                write_trivia(f, trailing)?;
+                f.write_str(close)
+            }
+        }
+    }
+}
+
+// the program exactly as it was read
+impl Display for Cst {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.forms.iter().try_for_each(|form| form.fmt(f))?;
+        write_trivia(f, &self.trailing)
+    }
+}
+
+impl CstNode {
+    // the node as written, without the spaces and comments before it
+    pub fn text(&self) -> String {
+        let leading: usize = self.leading.iter().map(|trivia| trivia.to_string().len()).sum();
+        self.to_string().split_off(leading)
+    }
+    // what the parser reads the node as
+    pub fn exp(&self) -> Result<LispExp, LispError> {
+        parse(&tokens(&self.text())?, DEFAULT_MAX_DEPTH).map(|(exp, _)| exp)
+    }
+    // the name, if the node is a symbol
+    pub fn symbol(&self) -> Option<&str> {
+        match &self.kind {
+            CstKind::Atom(text) if matches!(parse_atom(text), Ok(LispExp::Symbol(_))) => Some(text),
+            _ => None,
+        }
+    }
+    // the nodes right inside this one
+    pub fn children(&self) -> &[CstNode] {
+        match &self.kind {
+            CstKind::Atom(_) => &[],
+            CstKind::Prefix(_, form) => std::slice::from_ref(form),
+            CstKind::List { items, .. } => items,
+        }
+    }
+    // where the spaces and comments before the node start
+    fn outer_start(&self) -> usize {
+        self.span.start - self.leading.iter().map(|trivia| trivia.to_string().len()).sum::<usize>()
+    }
+    // the comments before the node
+    pub fn comments(&self) -> impl Iterator<Item = &str> {
+        self.leading.iter().filter_map(|trivia| match trivia {
+            Trivia::Comment(text) => Some(&text[..]),
+            Trivia::Space(_) => None,
+        })
+    }
+}
+
+impl Cst {
+    // the node spanning exactly `span`, with the nodes next to it and where
+    // it is among them
+    fn find(&self, span: &Range<usize>) -> Option<(&[CstNode], usize)> {
+        let mut siblings = &self.forms[..];
+        loop {
+            let at = siblings.iter().position(|node| node.span.start <= span.start && span.end <= node.span.end)?;
+            if siblings[at].span == *span {
+                return Some((siblings, at));
+            }
+            siblings = siblings[at].children();
+        }
+    }
+    // the node spanning exactly `span`
+    pub fn node(&self, span: &Range<usize>) -> Option<&CstNode> {
+        self.find(span).map(|(siblings, at)| &siblings[at])
+    }
+    // the innermost node the byte at `offset` is in
+    pub fn node_at(&self, offset: usize) -> Option<&CstNode> {
+        let mut found = None;
+        let mut siblings = &self.forms[..];
+        while let Some(node) = siblings.iter().find(|node| node.span.contains(&offset)) {
+            found = Some(node);
+            siblings = node.children();
+        }
+        found
+    }
+    fn expect(&self, span: &Range<usize>) -> Result<(&[CstNode], usize), LispError> {
+        self.find(span).ok_or_else(|| LispError::Other(format!("no expression spans bytes {}..{}", span.start, span.end)))
+    }
+}
+
+// a change to the source: the bytes in `span` become `text`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Range<usize>,
+    pub text: String,
+}
+
+impl Edit {
+    // the node spanning `span` written as `text`, which has to be one
+    // expression; the comments before the node stay where they are
+    pub fn replace(cst: &Cst, span: Range<usize>, text: &str) -> Result<Edit, LispError> {
+        cst.expect(&span)?;
+        match parse_program(&tokens(text)?)?.len() {
+            1 => Ok(Edit { span, text: text.to_owned() }),
+            found => Err(LispError::Other(format!("expected one expression to replace with, found {found}"))),
+        }
+    }
+    // the node spanning `span` taken out, with the spaces and comments
+    // before it, or the spaces after it when nothing is before it
+    pub fn remove(cst: &Cst, span: Range<usize>) -> Result<Edit, LispError> {
+        let (siblings, at) = cst.expect(&span)?;
+        let node = &siblings[at];
+        let start = node.outer_start();
+        let end = match siblings.get(at + 1) {
+            Some(next) if start == node.span.start && next.comments().next().is_none() => next.span.start,
+            _ => node.span.end,
+        };
+        Ok(Edit { span: start..end, text: String::new() })
+    }
+    // `text` put in front of the node spanning `span`, and of the comments
+    // right before it, on a line of its own at the same indentation when the
+    // node starts a line, as top-level forms always do
+    pub fn insert_before(cst: &Cst, span: Range<usize>, text: &str) -> Result<Edit, LispError> {
+        let (siblings, at) = cst.expect(&span)?;
+        let node = &siblings[at];
+        // a comment on the line of the code before the node is that code's
+        let own_line = match at {
+            0 => 0,
+            _ => node.leading.iter().position(|trivia| matches!(trivia, Trivia::Space(space) if space.contains('\n'))).unwrap_or(node.leading.len()),
+        };
+        let comments = (own_line..node.leading.len()).find(|&i| matches!(&node.leading[i], Trivia::Comment(text) if !text.starts_with("#!")));
+        let (before, start) = match comments {
+            Some(first) => (&node.leading[..first], span.start - node.leading[first..].iter().map(|trivia| trivia.to_string().len()).sum::<usize>()),
+            None => (&node.leading[..], span.start),
+        };
+        let separator = match before.last() {
+            // a blank line before the node goes between the two as well
+            Some(Trivia::Space(space)) if space.contains('\n') => {
+                let indent = &space[space.rfind('\n').unwrap_or_default()..];
+                if space.matches('\n').count() > 1 { format!("\n{indent}") } else { indent.to_owned() }
+            }
+            _ if std::ptr::eq(siblings, &cst.forms[..]) => "\n".to_owned(),
+            _ => " ".to_owned(),
+        };
+        Ok(Edit { span: start..start, text: format!("{text}{separator}") })
+    }
+}
+
+// the source with `edits` made, which mustn't overlap; fails if the result
+// doesn't parse
+pub fn apply_edits(cst: &Cst, edits: &[Edit]) -> Result<String, LispError> {
+    let mut source = cst.to_string();
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|edit| (edit.span.start, edit.span.end));
+    for pair in edits.windows(2) {
+        if pair[0].span.end > pair[1].span.start {
+            return Err(LispError::Other(format!("edits at {}..{} and {}..{} overlap", pair[0].span.start, pair[0].span.end, pair[1].span.start, pair[1].span.end)));
+        }
+    }
+    for edit in edits.iter().rev() {
+        if edit.span.end > source.len() || !source.is_char_boundary(edit.span.start) || !source.is_char_boundary(edit.span.end) {
+            return Err(LispError::Other(format!("bytes {}..{} aren't in the source", edit.span.start, edit.span.end)));
+        }
+        source.replace_range(edit.span.clone(), &edit.text);
+    }
+    parse_program(&tokens(&source)?)?;
+    Ok(source)
+}
+
+// the source with the node spanning `span` written as `new_expr` instead
+pub fn replace_node(cst: &Cst, span: Range<usize>, new_expr: &str) -> Result<String, LispError> {
+    apply_edits(cst, &[Edit::replace(cst, span, new_expr)?])
+}
+
+// reads the pieces of `source` into nodes
+struct Reader<'a> {
+    source: &'a str,
+    pieces: Vec<Piece<'a>>,
+    next: usize,
+    // where what hasn't been read yet starts
+    at: usize,
+}
+
+impl Reader<'_> {
+    // the spaces and comments up to the next token
+    fn trivia(&mut self) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        loop {
+            let piece = self.pieces.get(self.next);
+            let mut gap = &self.source[self.at..piece.map_or(self.source.len(), |piece| piece.start)];
+            // the lexer skips a script's `#!` line, so it's only found here
+            if self.at == 0 && gap.starts_with("#!") {
+                let line = gap.find('\n').map_or(gap.len(), |end| end + 1);
+                trivia.push(Trivia::Comment(gap[..line].to_owned()));
+                gap = &gap[line..];
+            }
+            if !gap.is_empty() {
+                trivia.push(Trivia::Space(gap.to_owned()));
+            }
+            match piece {
+                Some(piece) if piece.token.is_none() => {
+                    trivia.push(Trivia::Comment(piece.text.to_owned()));
+                    self.at = piece.start + piece.text.len();
+                    self.next += 1;
+                }
+                Some(piece) => {
+                    self.at = piece.start;
+                    return trivia;
+                }
+                None => {
+                    self.at = self.source.len();
+                    return trivia;
+                }
+            }
+        }
+    }
+    // the items up to the end of the list, or of the program, and the
+    // spaces and comments after the last one
+    fn items(&mut self) -> (Vec<CstNode>, Vec<Trivia>) {
+        let mut items = vec![];
+        loop {
+            let leading = self.trivia();
+            match self.pieces.get(self.next).and_then(|piece| piece.token) {
+                None | Some(Token::Close | Token::CloseBracket | Token::CloseBrace) => return (items, leading),
+                Some(_) => items.push(self.node(leading)),
+            }
+        }
+    }
+    fn node(&mut self, leading: Vec<Trivia>) -> CstNode {
+        let (token, text, start) = {
+            let piece = &self.pieces[self.next];
+            (piece.token, piece.text, piece.start)
+        };
+        self.next += 1;
+        self.at = start + text.len();
+        let (open, close) = match token {
+            Some(Token::Open) => ("(", ")"),
+            Some(Token::OpenBracket) => ("[", "]"),
+            Some(Token::OpenBrace) => ("{", "}"),
+            Some(Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing) => {
+                let leading_form = self.trivia();
+                let form = self.node(leading_form);
+                let end = form.span.end;
+                return CstNode { leading, span: start..end, kind: CstKind::Prefix(text.to_owned(), Box::new(form)) };
+            }
+            _ => return CstNode { leading, span: start..self.at, kind: CstKind::Atom(text.to_owned()) },
+        };
+        let (items, trailing) = self.items();
+        // the parser has checked the list is closed
+        let end = &self.pieces[self.next];
+        self.at = end.start + end.text.len();
+        self.next += 1;
+        CstNode { leading, span: start..self.at, kind: CstKind::List { open, close, items, trailing } }
+    }
+}
+
+// the concrete syntax tree of `source`, which has to parse
+pub fn parse_cst(source: &str) -> Result<Cst, LispError> {
+    let (tokens, comments) = lex(source)?;
+    parse_program(&tokens)?;
+    let mut reader = Reader { source, pieces: pieces(source, &tokens, &comments), next: 0, at: 0 };
+    let (forms, trailing) = reader.items();
+    Ok(Cst { forms, trailing })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_as_written() {
+        let sources = [
+            "",
+            "   \n",
+            "#!/usr/bin/env sxprs\n(print 1)",
+            "; about x\n(define x 0xFF)   ; hex\n\n#| a #| nested |# block |# (define c #\\ ) [1  2]\n{:a 1 ; one\n }",
+            "(list 'a ` (b ~c ~@ d) ' ; why\n e (1 . 2) \"a \\\"string\\\"\\n\")  \r\n",
+        ];
+        for source in sources {
+            assert_eq!(parse_cst(source).unwrap().to_string(), source);
+        }
+        assert!(parse_cst("(list 1").is_err());
+        // and so do the spec's programs
+        let spec = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec");
+        for entry in std::fs::read_dir(spec).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "lsp") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(parse_cst(&source).unwrap().to_string(), source, "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn nodes_keep_their_comments_and_spans() {
+        let source = "; adds one\n(defn inc (x)\n  ; the sum\n  (+ x 1))  ; trailing\n";
+        let cst = parse_cst(source).unwrap();
+        let defn = &cst.forms[0];
+        assert_eq!(defn.comments().collect::<Vec<_>>(), ["; adds one"]);
+        assert_eq!(&source[defn.span.clone()], defn.text());
+        assert_eq!(defn.exp().unwrap().to_string(), "( defn inc ( x ) ( + x 1 ) )");
+        let CstKind::List { items, .. } = &defn.kind else { panic!("{defn:?}") };
+        assert_eq!(items.iter().map(CstNode::symbol).collect::<Vec<_>>(), [Some("defn"), Some("inc"), None, None]);
+        assert_eq!(items[3].comments().collect::<Vec<_>>(), ["; the sum"]);
+        assert_eq!(&source[items[3].span.clone()], "(+ x 1)");
+        assert_eq!(cst.trailing, [Trivia::Space("  ".to_owned()), Trivia::Comment("; trailing".to_owned()), Trivia::Space("\n".to_owned())]);
+    }
+
+    #[test]
+    fn finds_nodes() {
+        let source = "(defn f (x)\n  (+ x 1))\n";
+        let cst = parse_cst(source).unwrap();
+        assert_eq!(cst.node(&(14..21)).unwrap().text(), "(+ x 1)");
+        assert_eq!(cst.node(&(14..20)), None);
+        assert_eq!(cst.node_at(17).unwrap().text(), "x");
+        assert_eq!(cst.node_at(13).unwrap().text(), source.trim_end());
+        assert_eq!(cst.node_at(22), None);
+    }
+
+    #[test]
+    fn edits_leave_the_rest_alone() {
+        let source = "; doubles\n(defn f (x)\n  ; the work\n  (* x 2))  ; done\n\n(f 1)\n";
+        let cst = parse_cst(source).unwrap();
+        let body = cst.forms[0].children()[3].span.clone();
+        assert_eq!(replace_node(&cst, body.clone(), "(+ x x)").unwrap(), "; doubles\n(defn f (x)\n  ; the work\n  (+ x x))  ; done\n\n(f 1)\n");
+        assert!(replace_node(&cst, body.clone(), "(+ x").is_err());
+        assert!(replace_node(&cst, body.clone(), "1 2").is_err());
+        assert!(replace_node(&cst, 0..3, "x").is_err());
+        // the body goes with its comment, and the first form with the space after it
+        assert_eq!(apply_edits(&cst, &[Edit::remove(&cst, body.clone()).unwrap()]).unwrap(), "; doubles\n(defn f (x))  ; done\n\n(f 1)\n");
+        let call = cst.forms[1].span.clone();
+        let name = cst.forms[1].children()[0].span.clone();
+        let edits = [Edit::insert_before(&cst, call.clone(), "(define y 2)").unwrap(), Edit::replace(&cst, name, "g").unwrap()];
+        assert_eq!(apply_edits(&cst, &edits).unwrap(), "; doubles\n(defn f (x)\n  ; the work\n  (* x 2))  ; done\n\n(define y 2)\n\n(g 1)\n");
+        let edits = [Edit::insert_before(&cst, body.clone(), "(print x)").unwrap()];
+        assert_eq!(apply_edits(&cst, &edits).unwrap(), "; doubles\n(defn f (x)\n  (print x)\n  ; the work\n  (* x 2))  ; done\n\n(f 1)\n");
+        let edits = [Edit::insert_before(&cst, cst.forms[0].span.clone(), "(define y 2)").unwrap()];
+        assert!(apply_edits(&cst, &edits).unwrap().starts_with("(define y 2)\n; doubles\n(defn f (x)\n"));
+        let edits = [Edit::replace(&cst, call.clone(), "(f 2)").unwrap(), Edit::remove(&cst, call).unwrap()];
+        assert!(apply_edits(&cst, &edits).is_err());
+        let cst = parse_cst("(a b)").unwrap();
+        let a = cst.forms[0].children()[0].span.clone();
+        assert_eq!(apply_edits(&cst, &[Edit::remove(&cst, a).unwrap()]).unwrap(), "(b)");
+    }
+}
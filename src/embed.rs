@@ -0,0 +1,81 @@
+// `sxprs embed`: a Rust `main` with a lisp program embedded in it, for
+// shipping a script as a standalone binary. The program, with the files it
+// loads bundled into it, is kept as its source in a string, and interpreted
+// on the bytecode VM of the sxprs crate the binary is built with. Nothing is
+// translated to Rust: the binary starts without reading any files or needing
+// sxprs installed, and runs as fast as `sxprs --vm` runs it, not faster
+use std::path::{Path, PathBuf};
+
+use crate::bundle::bundle;
+use crate::error::LispError;
+
+// a raw string literal holding `text`, with enough `#`s that nothing in it ends it
+fn raw_string(text: &str) -> String {
+    let mut hashes = 1;
+    while text.contains(&format!("\"{}", "#".repeat(hashes))) {
+        hashes += 1;
+    }
+    let hashes = "#".repeat(hashes);
+    format!("r{hashes}\"{text}\"{hashes}")
+}
+
+// the Rust source of a `main` running the program at `path`, and the bundler's
+// warnings about loads left for when it runs
+pub fn embed(path: &Path, include: &[PathBuf]) -> Result<(String, Vec<String>), LispError> {
+    let (source, warnings) = bundle(path, include)?;
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let rust = format!(
+        "// generated by `sxprs embed {name}`, with sxprs {version}: runs {name}, and the files it loads,
+// on the sxprs crate's bytecode VM. Build it in a crate that depends on sxprs {version}
+use sxprs::{{Interpreter, LispError}};
+
+const SOURCE: &str = {source};
+
+fn main() {{
+    let mut lisp = Interpreter::new();
+    lisp.set_vm(true);
+    lisp.set_args(std::env::args().skip(1).collect());
+    match lisp.eval_str(SOURCE) {{
+        Ok(_) => {{}}
+        Err(err) => match err.root() {{
+            LispError::Exit(code) => std::process::exit(*code),
+            _ => {{
+                eprintln!(\"{{err}}\");
+                std::process::exit(1);
+            }}
+        }},
+    }}
+}}
+",
+        version = env!("CARGO_PKG_VERSION"),
+        source = raw_string(&source),
+    );
+    Ok((rust, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_strings_hold_anything() {
+        assert_eq!(raw_string("(print \"hi\")"), "r#\"(print \"hi\")\"#");
+        assert_eq!(raw_string("\"#\" \"##"), "r###\"\"#\" \"##\"###");
+    }
+
+    #[test]
+    fn embeds_the_bundled_program() {
+        let dir = std::env::temp_dir().join(format!("sxprs-embed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lib.lsp"), "(defn greet (name) (format \"hi {}\" name))\n").unwrap();
+        std::fs::write(dir.join("app.lsp"), "(load \"lib.lsp\")\n(print (greet \"#\\\"\"))\n").unwrap();
+        let (rust, warnings) = embed(&dir.join("app.lsp"), &[]).unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(rust.starts_with("// generated by `sxprs embed app.lsp`, with sxprs "), "{rust}");
+        assert!(rust.contains("const SOURCE: &str = r##\""), "{rust}");
+        assert!(rust.contains("(defn greet (name)"), "{rust}");
+        assert!(rust.contains("lisp.set_vm(true);"));
+        std::fs::write(dir.join("bad.lsp"), "(print 1").unwrap();
+        assert!(embed(&dir.join("bad.lsp"), &[]).unwrap_err().message().contains("could not find closing `)`"));
+    }
+}
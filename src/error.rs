@@ -0,0 +1,256 @@
+use std::fmt::Display;
+
+use crate::exp::LispExp;
+use crate::lexer::Pos;
+
+#[derive(Debug, Clone)]
+pub enum LispError {
+    // a token that can't appear where it was found, like a stray `)`
+    UnexpectedToken { pos: Pos, token: String },
+    // a `(` that is never closed
+    UnclosedList(Pos),
+    // the source ends inside a string, comment, vector or quote, so more
+    // input could still complete it
+    Incomplete { pos: Pos, msg: String },
+    // any other problem reading the source, like a bad escape
+    Syntax { pos: Pos, msg: String },
+    TypeMismatch { expected: &'static str, found: &'static str, value: String },
+    UnknownSymbol(String),
+    // `max` is None for variadic functions
+    ArityMismatch { name: String, min: usize, max: Option<usize>, found: usize },
+    IndexOutOfRange { index: i64, len: usize },
+    DivisionByZero,
+    // evaluation nested deeper than the limit
+    TooDeep(usize),
+    // a value thrown by `(error value)`, handed to `catch` as-is
+    Raised(LispExp),
+    // an `assert` or `assert-eq` that didn't hold: the form as written, and
+    // the values it found
+    AssertionFailed { form: String, found: String },
+    Other(String),
+    // a budget from Interpreter::with_limits ran out: the steps or memory
+    // the program may use; `try` doesn't catch it
+    LimitExceeded { limit: &'static str, max: u64 },
+    // evaluation stopped by the host, like on Ctrl-C in the REPL; `try`
+    // doesn't catch it
+    Interrupted,
+    // `(exit code)`, unwinding to whoever runs the program; `try` doesn't catch it
+    Exit(i32),
+    // `error` with the functions that were being called when it happened,
+    // innermost first, the file the top-level form came from and where in
+    // the source the innermost list that failed is
+    Backtrace { error: Box<LispError>, calls: Vec<String>, file: Option<String>, pos: Option<Pos> },
+}
+
+impl Display for LispError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "Lisp Processing Error: {}", self.message())
+    }
+}
+
+impl std::error::Error for LispError {}
+
+impl LispError {
+    pub fn at(pos: Pos, msg: impl Display) -> LispError {
+        LispError::Syntax { pos, msg: msg.to_string() }
+    }
+    // where in the source the error is, for errors found while reading it
+    // and ones raised evaluating a list the parser read
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            LispError::UnexpectedToken { pos, .. }
+            | LispError::UnclosedList(pos)
+            | LispError::Incomplete { pos, .. }
+            | LispError::Syntax { pos, .. } => Some(*pos),
+            LispError::Backtrace { pos, .. } => *pos,
+            _ => None,
+        }
+    }
+    // whether the source was fine as far as it went, but ended too soon, like
+    // a REPL line with an open `(`
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, LispError::UnclosedList(_) | LispError::Incomplete { .. })
+    }
+    // the error itself, without the backtrace around it
+    pub fn root(&self) -> &LispError {
+        match self {
+            LispError::Backtrace { error, .. } => error.root(),
+            other => other,
+        }
+    }
+    // a stable name for the kind of error, for tools that match on it
+    pub fn code(&self) -> &'static str {
+        match self.root() {
+            LispError::UnexpectedToken { .. } => "unexpected-token",
+            LispError::UnclosedList(_) => "unclosed-list",
+            LispError::Incomplete { .. } => "incomplete",
+            LispError::Syntax { .. } => "syntax",
+            LispError::TypeMismatch { .. } => "type-mismatch",
+            LispError::UnknownSymbol(_) => "unknown-symbol",
+            LispError::ArityMismatch { .. } => "arity-mismatch",
+            LispError::IndexOutOfRange { .. } => "index-out-of-range",
+            LispError::DivisionByZero => "division-by-zero",
+            LispError::TooDeep(_) => "too-deep",
+            LispError::Raised(_) => "raised",
+            LispError::AssertionFailed { .. } => "assertion-failed",
+            LispError::Other(_) => "other",
+            LispError::LimitExceeded { .. } => "limit-exceeded",
+            LispError::Interrupted => "interrupted",
+            LispError::Exit(_) => "exit",
+            LispError::Backtrace { .. } => unreachable!("root is never a backtrace"),
+        }
+    }
+    // the root error's message, without its position or the backtrace
+    pub fn summary(&self) -> String {
+        match self.root() {
+            LispError::Incomplete { msg, .. } | LispError::Syntax { msg, .. } => msg.clone(),
+            LispError::UnexpectedToken { token, .. } => format!("unexpected `{token}`"),
+            LispError::UnclosedList(_) => "could not find closing `)`".to_owned(),
+            root => root.message(),
+        }
+    }
+    // the error without the "Lisp Processing Error" prefix
+    pub fn message(&self) -> String {
+        match self {
+            LispError::UnexpectedToken { pos, .. }
+            | LispError::UnclosedList(pos)
+            | LispError::Incomplete { pos, .. }
+            | LispError::Syntax { pos, .. } => format!("{pos}: {}", self.summary()),
+            LispError::TypeMismatch { expected, found, value } => {
+                format!("{value} is not a {expected}, it's a {found}")
+            }
+            LispError::UnknownSymbol(name) => format!("symbol {name} is not defined"),
+            LispError::ArityMismatch { name, min, max, found } => {
+                let expected = match max {
+                    Some(max) if max == min => format!("{min}"),
+                    Some(max) => format!("between {min} and {max}"),
+                    None => format!("at least {min}"),
+                };
+                format!("`{name}` expects {expected} arguments, got {found}")
+            }
+            LispError::IndexOutOfRange { index, len } => {
+                format!("index {index} out of range for length {len}")
+            }
+            LispError::DivisionByZero => "division by zero".to_owned(),
+            LispError::TooDeep(limit) => format!("evaluation nested deeper than {limit} levels"),
+            LispError::Raised(LispExp::Str(msg)) => msg.clone(),
+            LispError::Raised(value) => value.to_string(),
+            LispError::AssertionFailed { form, found } => format!("assertion {form} failed: {found}"),
+            LispError::Other(msg) => msg.clone(),
+            LispError::LimitExceeded { limit, max } => format!("exceeded the {limit} limit of {max}"),
+            LispError::Interrupted => "interrupted".to_owned(),
+            LispError::Exit(code) => format!("exited with code {code}"),
+            LispError::Backtrace { error, calls, file, pos } => {
+                let mut msg = error.message();
+                match (pos, file) {
+                    (Some(pos), Some(file)) => msg.push_str(&format!("\n  at {file}:{pos}")),
+                    (Some(pos), None) => msg.push_str(&format!("\n  at {pos}")),
+                    (None, _) => {}
+                }
+                for line in call_lines(calls) {
+                    msg.push_str("\n  ");
+                    msg.push_str(&line);
+                }
+                msg.push_str("\n  called from top-level");
+                if let Some(file) = file {
+                    msg.push_str(&format!(" in {file}"));
+                }
+                msg
+            }
+        }
+    }
+}
+
+// how many lines of calls a backtrace shows from each end when it's longer
+// than twice that
+const SHOWN_CALLS: usize = 10;
+
+// a backtrace's calls, innermost first, with runs of the same call in one
+// line, so deep recursion doesn't print a line per level
+fn call_lines(calls: &[String]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut i = 0;
+    while i < calls.len() {
+        let call = &calls[i];
+        if i == 0 {
+            lines.push(format!("in `{call}`"));
+            i += 1;
+            continue;
+        }
+        let run = calls[i..].iter().take_while(|other| *other == call).count();
+        match run {
+            1 => lines.push(format!("called from `{call}`")),
+            _ => lines.push(format!("called from `{call}` (×{run})")),
+        }
+        i += run;
+    }
+    if lines.len() > 2 * SHOWN_CALLS {
+        let hidden = lines.len() - 2 * SHOWN_CALLS;
+        lines.splice(SHOWN_CALLS..lines.len() - SHOWN_CALLS, [format!("... {hidden} more lines of calls")]);
+    }
+    lines
+}
+
+impl From<String> for LispError {
+    fn from(value: String) -> LispError {
+        LispError::Other(value)
+    }
+}
+impl From<&str> for LispError {
+    fn from(value: &str) -> LispError {
+        LispError::Other(value.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn error(source: &str) -> super::LispError {
+        Interpreter::new().eval_str(source).unwrap_err()
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(error("(car 1)").code(), "type-mismatch");
+        assert_eq!(error("(defn f () (nope)) (f)").code(), "unknown-symbol");
+        assert_eq!(error("(car)").code(), "arity-mismatch");
+        assert_eq!(error("(/ 1 0)").code(), "division-by-zero");
+        assert_eq!(error("(error '(a 1))").code(), "raised");
+        assert_eq!(error("(exit 3)").code(), "exit");
+        assert_eq!(error("(car 1").code(), "unclosed-list");
+        assert!(error("(car 1").is_incomplete());
+    }
+
+    #[test]
+    fn backtraces() {
+        // `g` calls `f` in tail position, so `f` replaces it
+        let err = error("(defn f (x) (car x))\n(defn g () (f 1))\n(g)");
+        assert_eq!(err.summary(), "1 is not a List, it's a Int");
+        assert_eq!(err.pos().map(|pos| pos.to_string()).as_deref(), Some("1:13"));
+        assert_eq!(err.message(), "1 is not a List, it's a Int\n  at 1:13\n  in `car`\n  called from `f`\n  called from top-level");
+        assert_eq!(error("(error '(a 1))").summary(), "( a 1 )");
+        // a bare symbol has no list to point at, but the reader saw where it was
+        assert_eq!(error("(define a 1)\n  nope").message(), "symbol nope is not defined\n  at 2:3\n  called from top-level");
+    }
+
+    #[test]
+    fn deep_backtraces() {
+        // a level per call, so the limit is hit in the recursion
+        let err = error("(defn count (n) (+ 1 (count (- n 1))))\n(count 100000)");
+        let message = err.message();
+        assert!(message.starts_with("evaluation nested deeper than"), "{message}");
+        let lines: Vec<_> = message.lines().skip(2).collect();
+        assert_eq!(lines.len(), 3, "{message}");
+        assert_eq!(lines[0], "  in `count`");
+        assert!(lines[1].starts_with("  called from `count` (×"), "{message}");
+        assert_eq!(lines[2], "  called from top-level");
+        // calls that take turns don't make runs, so only the ends are shown
+        let err = error("(defn ping (n) (+ 1 (pong n))) (defn pong (n) (+ 1 (ping n))) (ping 1)");
+        let message = err.message();
+        let lines: Vec<_> = message.lines().skip(2).collect();
+        assert_eq!(lines.len(), 22, "{message}");
+        assert!(lines[10].starts_with("  ... ") && lines[10].ends_with(" more lines of calls"), "{message}");
+        assert_eq!(lines[21], "  called from top-level");
+    }
+}
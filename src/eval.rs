@@ -0,0 +1,1470 @@
+use std::cell::{Cell, RefCell};
+use std::ops::{RangeFrom, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::attach::Attached;
+use crate::builtins::{builtin_funcs, special_forms};
+use crate::error::LispError;
+use crate::exp::{LispExp, LispLambda, MapKey};
+use crate::image;
+use crate::lexer::{tokens, Pos};
+use crate::math::{math_funcs, random_seed};
+use crate::num;
+use crate::parser::{parse_program_spanned, parse_program_with_depth, DEFAULT_MAX_DEPTH};
+use crate::platform::{self, Timer};
+use crate::pretty;
+use crate::profile::{ProfileEntry, Profiler};
+use crate::span;
+use crate::symbol::{SymbolId, SymbolMap, AND, BEGIN, COND, ELSE, IF, LET, OR};
+use crate::opt;
+use crate::parallel::Tasks;
+use crate::schedule::Job;
+#[cfg(feature = "tracing")]
+use crate::telemetry::Spans;
+#[cfg(feature = "graphics")]
+use crate::graphics::Turtle;
+use crate::vm;
+
+#[derive(Default)]
+pub(crate) struct Scope {
+    vars: RefCell<SymbolMap<LispExp>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub(crate) fn child(parent: &Rc<Scope>) -> Rc<Scope> {
+        Rc::new(Scope {
+            vars: RefCell::new(SymbolMap::default()),
+            parent: Some(parent.clone()),
+        })
+    }
+    pub(crate) fn get(&self, name: SymbolId) -> Option<LispExp> {
+        if let Some(vl) = self.vars.borrow().get(&name) {
+            return Some(vl.clone());
+        }
+        self.parent.as_ref().and_then(|p| p.get(name))
+    }
+    pub(crate) fn insert(&self, name: SymbolId, vl: LispExp) {
+        self.vars.borrow_mut().insert(name, vl);
+    }
+    // rebinds `name` in the nearest scope that has it, false if none does
+    pub(crate) fn set(&self, name: SymbolId, vl: LispExp) -> bool {
+        if let Some(slot) = self.vars.borrow_mut().get_mut(&name) {
+            *slot = vl;
+            return true;
+        }
+        self.parent.as_ref().is_some_and(|p| p.set(name, vl))
+    }
+    // the value of `name`, and whether it's bound in the outermost, global scope
+    pub(crate) fn lookup(&self, name: SymbolId) -> Option<(LispExp, bool)> {
+        if let Some(vl) = self.vars.borrow().get(&name) {
+            return Some((vl.clone(), self.parent.is_none()));
+        }
+        self.parent.as_ref().and_then(|p| p.lookup(name))
+    }
+    // whether `name` is bound here or in a parent
+    pub(crate) fn binds(&self, name: SymbolId) -> bool {
+        self.vars.borrow().contains_key(&name) || self.parent.as_ref().is_some_and(|p| p.binds(name))
+    }
+    // what this scope itself defines, without its parents'
+    pub(crate) fn bindings(&self) -> Vec<(SymbolId, LispExp)> {
+        self.vars.borrow().iter().map(|(name, vl)| (*name, vl.clone())).collect()
+    }
+    pub(crate) fn is_global(&self) -> bool {
+        self.parent.is_none()
+    }
+    // the outermost scope this one is in
+    pub(crate) fn global(self: &Rc<Scope>) -> Rc<Scope> {
+        match &self.parent {
+            Some(parent) => parent.global(),
+            None => self.clone(),
+        }
+    }
+    // what's bound here and in the parents, innermost first, without what an
+    // inner scope shadows
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn visible(&self) -> Vec<(SymbolId, LispExp)> {
+        let mut bindings = self.bindings();
+        bindings.sort_by_cached_key(|(name, _)| name.to_string());
+        if let Some(parent) = &self.parent {
+            let outer = parent.visible();
+            bindings.extend(outer.into_iter().filter(|(name, _)| !self.vars.borrow().contains_key(name)));
+        }
+        bindings
+    }
+    // every name bound here or in a parent
+    pub(crate) fn names(&self) -> Vec<SymbolId> {
+        let mut names: Vec<SymbolId> = self.vars.borrow().keys().copied().collect();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.names());
+        }
+        names
+    }
+}
+
+// scopes can hold lambdas that point back at them, so only list the names
+impl std::fmt::Debug for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_set().entries(self.vars.borrow().keys()).finish()
+    }
+}
+
+// builtin functions get their arguments evaluated, special forms get them as
+// written and evaluate what they need themselves
+pub(crate) type LispFN = Box<dyn Fn(&Interpreter, &[LispExp]) -> Result<LispExp, LispError>>;
+
+// a builtin function or special form, and how many arguments it takes, which
+// is checked before it's called
+pub(crate) struct Builtin {
+    pub(crate) func: LispFN,
+    pub(crate) arity: Arity,
+    // how it's called and what it does, for `help`; None for the host's
+    pub(crate) doc: Option<(&'static str, &'static str)>,
+    // a special form that uses its arguments as written, like `quote` and
+    // `debug`, so the optimizer has to leave them alone
+    pub(crate) unevaluated: bool,
+}
+
+// how many arguments a function takes, at least and at most; builtins declare
+// theirs as a count, like `1`, or a range, like `1..` or `2..=3`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Arity {
+    pub(crate) min: usize,
+    pub(crate) max: Option<usize>,
+}
+
+impl Arity {
+    pub(crate) fn check(self, name: SymbolId, found: usize) -> Result<(), LispError> {
+        if found < self.min || self.max.is_some_and(|max| found > max) {
+            return Err(LispError::ArityMismatch { name: name.to_string(), min: self.min, max: self.max, found });
+        }
+        Ok(())
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(count: usize) -> Arity {
+        Arity { min: count, max: Some(count) }
+    }
+}
+impl From<RangeFrom<usize>> for Arity {
+    fn from(range: RangeFrom<usize>) -> Arity {
+        Arity { min: range.start, max: None }
+    }
+}
+impl From<RangeInclusive<usize>> for Arity {
+    fn from(range: RangeInclusive<usize>) -> Arity {
+        Arity { min: *range.start(), max: Some(*range.end()) }
+    }
+}
+// what the evaluator does next; a call in tail position takes the place of
+// the call it's the last thing of instead of nesting in it
+enum Step {
+    Eval(LispExp, bool),
+    // hand a value to the frame on top of the stack
+    Return(LispExp),
+    Call(Rc<LispLambda>, Vec<LispExp>, bool),
+}
+
+// expressions run one after the other: a form's items from some index on, or
+// a lambda's body
+enum Exprs {
+    Form(Rc<[LispExp]>),
+    Lambda(Rc<LispLambda>),
+}
+
+impl Exprs {
+    fn items(&self) -> &[LispExp] {
+        match self {
+            Exprs::Form(form) => form,
+            Exprs::Lambda(func) => &func.body,
+        }
+    }
+}
+
+// what a call's arguments are evaluated for
+enum Callee {
+    Builtin(SymbolId),
+    Lambda(Rc<LispLambda>),
+}
+
+// what's left to do with the value being computed, kept on the heap so
+// nesting doesn't grow the native stack; the flags say whether the form the
+// frame is for is in tail position
+enum Frame {
+    // a list being evaluated, which errors inside it point at, and the depth
+    // to go back to once it's done
+    Form(Rc<[LispExp]>, usize),
+    // a call whose head isn't a builtin's name, waiting on the head's value
+    Head(Rc<[LispExp]>, bool),
+    // a call waiting on its arguments, with the values so far
+    Args { form: Rc<[LispExp]>, callee: Callee, values: Vec<LispExp>, tail: bool },
+    // an `if` waiting on its test
+    If(Rc<[LispExp]>, bool),
+    // a `cond` waiting on the test of the clause before `next`
+    Cond { form: Rc<[LispExp]>, next: usize, tail: bool },
+    // an `and` or `or` waiting on the argument before `next`
+    Junction { form: Rc<[LispExp]>, next: usize, or: bool, tail: bool },
+    // a `let` waiting on the value of the binding of `name`
+    Let { form: Rc<[LispExp]>, scope: Rc<Scope>, name: SymbolId, next: usize, tail: bool },
+    // a body waiting on the expression before `next`
+    Body { exprs: Exprs, next: usize, tail: bool },
+    // the scope to go back to after a `let` or `begin`
+    Scope(Rc<Scope>),
+    // a lambda being called, and the scope to go back to after it
+    Called(Rc<Scope>),
+    // a macro call waiting on its expansion
+    Expand(bool),
+}
+
+pub struct Interpreter {
+    functions: SymbolMap<Builtin>,
+    specials: SymbolMap<Builtin>,
+    // innermost scope of the code being evaluated, the global one at top-level
+    scope: RefCell<Rc<Scope>>,
+    metrics: Option<Box<dyn Metrics>>,
+    tracer: Option<Box<dyn Tracer>>,
+    // times calls when profiling, see `set_profiling`
+    profiler: Option<RefCell<Profiler>>,
+    // the `tracing` spans of the calls being made
+    #[cfg(feature = "tracing")]
+    spans: RefCell<Spans>,
+    // how many evaluations are nested right now, and how many are allowed
+    depth: Cell<usize>,
+    max_depth: usize,
+    // state of the generator behind `random`, reset by `seed`
+    pub(crate) rng: Cell<u64>,
+    // how many symbols `gensym` has made
+    pub(crate) gensyms: Cell<u64>,
+    // run top-level forms on the bytecode VM instead of the tree-walker
+    vm: bool,
+    // fold constant calls in top-level forms before running them
+    optimize: bool,
+    // how `print` shows values: broken over lines when they're wide, and colored
+    pretty: bool,
+    color: bool,
+    // the program's file and the files `load` is in the middle of, innermost last
+    files: RefCell<Vec<PathBuf>>,
+    // every file `load` read, in the order it first did
+    loaded: RefCell<Vec<PathBuf>>,
+    // where `import` looks for a module's file when it isn't beside the
+    // file importing it
+    include: Vec<PathBuf>,
+    // the functions being called, innermost last, None for anonymous lambdas
+    calls: RefCell<Vec<Option<SymbolId>>>,
+    // what each `module` exports, for `import`
+    pub(crate) modules: RefCell<SymbolMap<Vec<(SymbolId, LispExp)>>>,
+    // what the values `define` was given a docstring for do, when they
+    // aren't functions, which keep their own
+    docs: RefCell<SymbolMap<String>>,
+    // the emptied frame stacks of evaluations that finished, to reuse
+    stacks: RefCell<Vec<Vec<Frame>>>,
+    // what the prelude defined, which images leave out unless it was redefined
+    prelude: SymbolMap<LispExp>,
+    // the tests `deftest` registered, in order, with the scope they were defined in
+    tests: RefCell<Vec<Test>>,
+    // set from another thread, like a signal handler, to stop the evaluation
+    pub(crate) interrupt: Option<Arc<AtomicBool>>,
+    // set by the `with-tasks` forms this interpreter's task was started in
+    // to cancel it, once another of their tasks failed
+    pub(crate) cancel: RefCell<Vec<Arc<AtomicBool>>>,
+    // how many evaluation steps were taken, and how many bytes of lists,
+    // strings and the like builtins built, against their limits
+    steps: Cell<u64>,
+    max_steps: Option<u64>,
+    bytes: Cell<usize>,
+    max_bytes: Option<usize>,
+    // the threads `spawn` started
+    pub(crate) tasks: RefCell<Tasks>,
+    // what every file `load` and `import` read has to pass before it runs
+    load_check: Option<LoadCheck>,
+    // the clients `listen` lets attach, whose requests steps answer
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) attached: Option<Attached>,
+    // the handlers `schedule` registered, for `run_schedule`
+    pub(crate) jobs: RefCell<Vec<Job>>,
+    // what the turtle builtins drew
+    #[cfg(feature = "graphics")]
+    pub(crate) turtle: RefCell<Turtle>,
+    // whether `resume` gives back what was saved with `checkpoint`
+    resume: bool,
+    // when the interpreter was made, what `clock` counts from
+    pub(crate) started: Timer,
+}
+
+// a test `deftest` registered, run by `run_tests`
+#[derive(Clone)]
+struct Test {
+    name: String,
+    body: Rc<[LispExp]>,
+    scope: Rc<Scope>,
+}
+
+// what an interpreter for a worker thread takes from the one that starts it
+pub(crate) struct Settings {
+    max_depth: usize,
+    vm: bool,
+    max_steps: Option<u64>,
+    max_bytes: Option<usize>,
+    interrupt: Option<Arc<AtomicBool>>,
+    cancel: Vec<Arc<AtomicBool>>,
+    load_check: Option<LoadCheck>,
+    #[cfg(not(target_arch = "wasm32"))]
+    capture: Option<platform::Capture>,
+}
+
+// hooks for hosts that want to collect interpreter metrics, all no-ops by default
+pub trait Metrics {
+    fn form_evaluated(&self) {}
+    fn builtin_called(&self, _name: &str) {}
+    fn error(&self, _err: &LispError) {}
+    fn eval_duration(&self, _elapsed: std::time::Duration) {}
+}
+
+// hooks for watching the tree-walker evaluate, `depth` is how many evaluations
+// enclose this one; a call in tail position reports its value from the body's
+// last expression instead of from the call itself
+pub trait Tracer {
+    fn enter(&self, _exp: &LispExp, _depth: usize) {}
+    fn value(&self, _value: &LispExp, _depth: usize) {}
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+// the builtins that build a new list, vector, map or string, which counts
+// towards the memory limit
+const ALLOCATING: &[&str] = &[
+    "cons", "list", "append", "map", "filter", "dict", "dict-set", "dict-remove", "dict-keys", "vector",
+    "str-concat", "substring", "str-split", "string->list", "list->string", "upcase", "downcase",
+    "number->string", "symbol->string", "read", "read-all", "json->lisp", "lisp->json", "format", "read-file", "read-line",
+    "exec", "shell", "make-struct",
+];
+
+// the builtins whose result can be a new big int or ratio, which counts
+// towards the memory limit by the size of its digits
+const EXACT: &[&str] = &["+", "-", "*", "/", "pow", "mod", "abs", "floor", "ceil", "round", "string->number"];
+
+// roughly how many bytes the builtin `name` allocated to build `value`: the
+// value itself, not what it shares with others, like the items of a list
+fn allocated(name: &str, value: &LispExp) -> usize {
+    let slot = std::mem::size_of::<LispExp>();
+    if name == "vec-push!" {
+        return slot;
+    }
+    if EXACT.contains(&name) {
+        return num::size(value);
+    }
+    if !ALLOCATING.contains(&name) {
+        return 0;
+    }
+    match value {
+        LispExp::List(items) => items.len() * slot,
+        LispExp::Vector(items) => items.borrow().len() * slot,
+        LispExp::Map(map) => map.len() * (slot + std::mem::size_of::<MapKey>()),
+        LispExp::Str(string) => string.len(),
+        LispExp::Struct(record) => record.values.len() * slot,
+        // `exec`'s output is in a map of strings
+        _ => slot,
+    }
+}
+
+// what `set_load_check` was given, shared with worker threads
+type LoadCheck = Arc<dyn Fn(&Path, &str) -> Result<(), LispError> + Send + Sync>;
+
+// lisp-side library functions, see prelude.lsp
+const PRELUDE: &str = include_str!("prelude.lsp");
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut lisp = Interpreter::bare();
+        lisp.eval_str(PRELUDE).expect("the prelude is valid");
+        lisp.prelude = lisp.current_scope().bindings().into_iter().collect();
+        lisp
+    }
+    // an interpreter for code that isn't trusted: it fails with LimitExceeded
+    // after `steps` evaluation steps, or once builtins have built `bytes` of
+    // lists, vectors, maps, strings and big numbers, and with TooDeep when
+    // evaluation nests deeper than `depth`; the prelude doesn't count against
+    // the limits
+    pub fn with_limits(steps: u64, depth: usize, bytes: usize) -> Interpreter {
+        let mut lisp = Interpreter::new();
+        lisp.max_steps = Some(steps);
+        lisp.max_depth = depth;
+        lisp.max_bytes = Some(bytes);
+        lisp
+    }
+    // a worker thread's interpreter: the builtins, without the prelude, since
+    // the functions it's given bring what they use along
+    pub(crate) fn with_settings(settings: Settings) -> Interpreter {
+        let mut lisp = Interpreter::bare();
+        lisp.max_depth = settings.max_depth;
+        lisp.vm = settings.vm;
+        lisp.max_steps = settings.max_steps;
+        lisp.max_bytes = settings.max_bytes;
+        lisp.interrupt = settings.interrupt;
+        lisp.cancel = RefCell::new(settings.cancel);
+        lisp.load_check = settings.load_check;
+        #[cfg(not(target_arch = "wasm32"))]
+        platform::set_capture(settings.capture);
+        lisp
+    }
+    pub(crate) fn settings(&self) -> Settings {
+        Settings {
+            max_depth: self.max_depth,
+            vm: self.vm,
+            max_steps: self.max_steps,
+            max_bytes: self.max_bytes,
+            interrupt: self.interrupt.clone(),
+            cancel: self.cancel.borrow().clone(),
+            load_check: self.load_check.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            capture: platform::capture(),
+        }
+    }
+    // an interpreter with only the builtins, without the prelude's functions
+    pub fn bare() -> Interpreter {
+        let mut lisp = Interpreter {
+            functions: builtin_funcs(),
+            specials: special_forms(),
+            scope: RefCell::new(Rc::new(Scope::default())),
+            metrics: None,
+            tracer: None,
+            profiler: None,
+            #[cfg(feature = "tracing")]
+            spans: RefCell::default(),
+            depth: Cell::new(0),
+            max_depth: DEFAULT_MAX_DEPTH,
+            rng: Cell::new(random_seed()),
+            gensyms: Cell::new(0),
+            vm: false,
+            optimize: false,
+            pretty: false,
+            color: false,
+            files: RefCell::new(vec![]),
+            loaded: RefCell::new(vec![]),
+            include: vec![],
+            calls: RefCell::new(vec![]),
+            modules: RefCell::new(SymbolMap::default()),
+            docs: RefCell::new(SymbolMap::default()),
+            stacks: RefCell::new(vec![]),
+            prelude: SymbolMap::default(),
+            tests: RefCell::new(vec![]),
+            interrupt: None,
+            cancel: RefCell::new(vec![]),
+            steps: Cell::new(0),
+            max_steps: None,
+            bytes: Cell::new(0),
+            max_bytes: None,
+            tasks: RefCell::new(Tasks::default()),
+            jobs: RefCell::new(vec![]),
+            load_check: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            attached: None,
+            #[cfg(feature = "graphics")]
+            turtle: RefCell::default(),
+            resume: false,
+            started: Timer::start(),
+        };
+        lisp.set_args(vec![]);
+        lisp
+    }
+
+    pub(crate) fn value(&self, vl: &LispExp) -> Result<LispExp, LispError> {
+        match vl {
+            LispExp::List(form) if !form.is_empty() => self.evaluate(Step::Eval(vl.clone(), false)),
+            _ => self.atom(vl),
+        }
+    }
+    pub(crate) fn call(&self, func: &Rc<LispLambda>, args: Vec<LispExp>) -> Result<LispExp, LispError> {
+        self.evaluate(Step::Call(func.clone(), args, false))
+    }
+    // runs steps until the first one's value is known, keeping what's left to
+    // do in `frames` instead of recursing, so only special forms that evaluate
+    // their arguments themselves nest on the native stack
+    fn evaluate(&self, first: Step) -> Result<LispExp, LispError> {
+        let mut frames = self.stacks.borrow_mut().pop().unwrap_or_default();
+        let mut step = first;
+        let res = loop {
+            let next = match step {
+                Step::Eval(exp, tail) => self.start(exp, tail, &mut frames),
+                Step::Return(vl) => match frames.pop() {
+                    Some(frame) => self.resume(frame, vl, &mut frames),
+                    None => break Ok(vl),
+                },
+                Step::Call(func, args, tail) => self.enter(func, args, tail, &mut frames),
+            };
+            step = match next {
+                Ok(next) => next,
+                Err(err) => break Err(self.unwind(err, &mut frames)),
+            };
+        };
+        self.stacks.borrow_mut().push(frames);
+        res
+    }
+    // leaves every frame an error passed through, like returning from the
+    // nested evaluations they stand for would
+    fn unwind(&self, mut err: LispError, frames: &mut Vec<Frame>) -> LispError {
+        while let Some(frame) = frames.pop() {
+            match frame {
+                Frame::Form(form, depth) => {
+                    self.depth.set(depth);
+                    err = self.locate_list(err, &form);
+                }
+                Frame::Called(scope) => {
+                    err = self.backtrace(err);
+                    self.exit_call();
+                    self.scope.replace(scope);
+                }
+                Frame::Scope(scope) => {
+                    self.scope.replace(scope);
+                }
+                _ => {}
+            }
+        }
+        err
+    }
+    // counts the evaluation of `exp` starting, returning how deep it is
+    fn enter_form(&self, exp: &LispExp) -> Result<usize, LispError> {
+        // bounds how far evaluations nest, the frames and the native stack both
+        if self.depth.get() >= self.max_depth {
+            return Err(LispError::TooDeep(self.max_depth));
+        }
+        self.step()?;
+        let depth = self.depth.get();
+        if let Some(tracer) = &self.tracer {
+            tracer.enter(exp, depth);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.form_evaluated();
+        }
+        Ok(depth)
+    }
+    // the value of an expression that isn't a call, which needs no frame
+    fn atom(&self, exp: &LispExp) -> Result<LispExp, LispError> {
+        // vector and map literals evaluate their items, which `start` does
+        if matches!(exp, LispExp::Vector(_) | LispExp::Map(_)) {
+            return self.evaluate(Step::Eval(exp.clone(), false));
+        }
+        let depth = self.enter_form(exp)?;
+        let vl = match exp {
+            LispExp::Symbol(name) => self.resolve(*name)?,
+            vl => vl.clone(),
+        };
+        if let Some(tracer) = &self.tracer {
+            tracer.value(&vl, depth);
+        }
+        Ok(vl)
+    }
+    fn start(&self, exp: LispExp, tail: bool, frames: &mut Vec<Frame>) -> Result<Step, LispError> {
+        // `()` is the empty list, not a call
+        let literal = exp.literal_call();
+        if literal.is_none() && !matches!(&exp, LispExp::List(form) if !form.is_empty()) {
+            return self.atom(&exp).map(Step::Return);
+        }
+        let depth = self.enter_form(&exp)?;
+        let form = match (literal, exp) {
+            (Some(form), _) | (None, LispExp::List(form)) => form,
+            _ => unreachable!("it's a list"),
+        };
+        self.depth.set(depth + 1);
+        frames.push(Frame::Form(form.clone(), depth));
+        // a vector or map literal is built by the builtin itself
+        if let LispExp::Builtin(name) = form[0] {
+            let values = Vec::with_capacity(form.len() - 1);
+            return self.argument(form, Callee::Builtin(name), values, tail, frames);
+        }
+        let LispExp::Symbol(car) = form[0] else {
+            frames.push(Frame::Head(form.clone(), tail));
+            return Ok(Step::Eval(form[0].clone(), false));
+        };
+        let special = self.specials.get(&car);
+        if let Some(special) = special {
+            special.arity.check(car, form.len() - 1)?;
+        }
+        match car {
+            // the taken branch of an `if` is still in tail position, and so
+            // are the last expressions of `cond`'s clauses, `and`, `or`, and
+            // the bodies of `let` and `begin`
+            IF => {
+                frames.push(Frame::If(form.clone(), tail));
+                Ok(Step::Eval(form[1].clone(), false))
+            }
+            COND => self.clause(form, 1, tail, frames),
+            AND | OR if form.len() == 1 => Ok(Step::Return(LispExp::Bool(car == AND))),
+            AND | OR => Ok(self.junction(form, 1, car == OR, tail, frames)),
+            LET => {
+                let LispExp::List(bindings) = &form[1] else {
+                    return Err(LispError::Other(format!("expected a list of bindings, found {}", form[1])));
+                };
+                // bindings are evaluated in the outer scope, like scheme's let
+                let scope = Scope::child(&self.current_scope());
+                let bindings = bindings.clone();
+                self.binding(form, &bindings, scope, 0, tail, frames)
+            }
+            BEGIN if form.len() == 1 => Ok(Step::Return(LispExp::Nil)),
+            // what the block defines doesn't outlive it
+            BEGIN => {
+                frames.push(Frame::Scope(self.scope.replace(Scope::child(&self.current_scope()))));
+                Ok(self.body(Exprs::Form(form), 1, tail, frames))
+            }
+            _ => match special {
+                Some(special) => {
+                    if let Some(metrics) = &self.metrics {
+                        metrics.builtin_called(&car.name());
+                    }
+                    (special.func)(self, &form[1..]).map(Step::Return)
+                }
+                // what the program bound to the name comes before the builtin, like in `resolve`
+                None if self.functions.contains_key(&car) && !self.current_scope().binds(car) => {
+                    let values = Vec::with_capacity(form.len() - 1);
+                    self.argument(form, Callee::Builtin(car), values, tail, frames)
+                }
+                None => {
+                    frames.push(Frame::Head(form.clone(), tail));
+                    Ok(Step::Eval(form[0].clone(), false))
+                }
+            },
+        }
+    }
+    fn resume(&self, frame: Frame, vl: LispExp, frames: &mut Vec<Frame>) -> Result<Step, LispError> {
+        match frame {
+            Frame::Form(_, depth) => {
+                self.depth.set(depth);
+                if let Some(tracer) = &self.tracer {
+                    tracer.value(&vl, depth);
+                }
+                Ok(Step::Return(vl))
+            }
+            Frame::Head(form, tail) => match vl {
+                LispExp::Lambda(func) => {
+                    let values = Vec::with_capacity(form.len() - 1);
+                    self.argument(form, Callee::Lambda(func), values, tail, frames)
+                }
+                LispExp::Builtin(name) => {
+                    let values = Vec::with_capacity(form.len() - 1);
+                    self.argument(form, Callee::Builtin(name), values, tail, frames)
+                }
+                LispExp::Macro(mac) => {
+                    frames.push(Frame::Expand(tail));
+                    Ok(Step::Call(mac, form[1..].to_vec(), false))
+                }
+                head if form.len() == 1 => Ok(Step::Return(head)),
+                other => Err(other.mismatch("function")),
+            },
+            Frame::Args { form, callee, mut values, tail } => {
+                values.push(vl);
+                self.argument(form, callee, values, tail, frames)
+            }
+            Frame::If(form, tail) => match form.get(if vl.is_truthy() { 2 } else { 3 }) {
+                Some(branch) => Ok(Step::Eval(branch.clone(), tail)),
+                None => Ok(Step::Return(LispExp::Nil)),
+            },
+            Frame::Cond { form, next, tail } if vl.is_truthy() => {
+                let LispExp::List(clause) = &form[next - 1] else {
+                    unreachable!("clauses are checked before their test runs");
+                };
+                match clause.len() {
+                    1 => Ok(Step::Return(vl)),
+                    _ => Ok(self.body(Exprs::Form(clause.clone()), 1, tail, frames)),
+                }
+            }
+            Frame::Cond { form, next, tail } => self.clause(form, next, tail, frames),
+            Frame::Junction { or, .. } if vl.is_truthy() == or => Ok(Step::Return(vl)),
+            Frame::Junction { form, next, or, tail } => Ok(self.junction(form, next, or, tail, frames)),
+            Frame::Let { form, scope, name, next, tail } => {
+                scope.insert(name, vl);
+                let LispExp::List(bindings) = &form[1] else {
+                    unreachable!("the bindings are checked before the first one runs");
+                };
+                let bindings = bindings.clone();
+                self.binding(form, &bindings, scope, next, tail, frames)
+            }
+            Frame::Body { exprs, next, tail } => Ok(self.body(exprs, next, tail, frames)),
+            Frame::Scope(scope) => {
+                self.scope.replace(scope);
+                Ok(Step::Return(vl))
+            }
+            Frame::Called(scope) => {
+                self.exit_call();
+                self.scope.replace(scope);
+                Ok(Step::Return(vl))
+            }
+            Frame::Expand(tail) => Ok(Step::Eval(vl, tail)),
+        }
+    }
+    // calls a lambda; in tail position the frames left of the call it's the
+    // last thing of are only forms and scopes, so they're dropped and the
+    // call's place taken
+    fn enter(&self, func: Rc<LispLambda>, args: Vec<LispExp>, tail: bool, frames: &mut Vec<Frame>) -> Result<Step, LispError> {
+        let name = func.name.get().copied();
+        if tail {
+            loop {
+                match frames.last() {
+                    Some(Frame::Form(_, depth)) => self.depth.set(*depth),
+                    Some(Frame::Scope(_)) => {}
+                    _ => break,
+                }
+                frames.pop();
+            }
+            self.replace_call(name, Some(&func));
+        } else {
+            self.enter_call(name, Some(&func));
+            frames.push(Frame::Called(self.current_scope()));
+        }
+        let scope = func.bind(self, args)?;
+        self.scope.replace(scope);
+        if func.body.is_empty() {
+            return Err(LispError::from("body is empty"));
+        }
+        Ok(self.body(Exprs::Lambda(func), 0, true, frames))
+    }
+    // evaluates the expressions from `next` on, the last in `tail` position
+    fn body(&self, exprs: Exprs, next: usize, tail: bool, frames: &mut Vec<Frame>) -> Step {
+        let exp = exprs.items()[next].clone();
+        if next + 1 == exprs.items().len() {
+            return Step::Eval(exp, tail);
+        }
+        frames.push(Frame::Body { exprs, next: next + 1, tail });
+        Step::Eval(exp, false)
+    }
+    // evaluates the next argument of a call, or makes the call once they all are
+    fn argument(&self, form: Rc<[LispExp]>, callee: Callee, mut values: Vec<LispExp>, tail: bool, frames: &mut Vec<Frame>) -> Result<Step, LispError> {
+        while let Some(arg) = form.get(values.len() + 1) {
+            if matches!(arg, LispExp::List(items) if !items.is_empty()) {
+                let arg = arg.clone();
+                frames.push(Frame::Args { form, callee, values, tail });
+                return Ok(Step::Eval(arg, false));
+            }
+            values.push(self.atom(arg)?);
+        }
+        match callee {
+            Callee::Builtin(name) => self.exec(name, &values).map(Step::Return),
+            Callee::Lambda(func) => Ok(Step::Call(func, values, tail)),
+        }
+    }
+    // tests the `cond` clauses from `next` on; `else` is always true
+    fn clause(&self, form: Rc<[LispExp]>, next: usize, tail: bool, frames: &mut Vec<Frame>) -> Result<Step, LispError> {
+        let Some(clause) = form.get(next) else {
+            return Ok(Step::Return(LispExp::Nil));
+        };
+        let Some(test) = clause.get_list()?.first().cloned() else {
+            return Err(LispError::from("expected a (test body...) clause, found ()"));
+        };
+        frames.push(Frame::Cond { form, next: next + 1, tail });
+        match test {
+            LispExp::Symbol(ELSE) => Ok(Step::Return(LispExp::Bool(true))),
+            test => Ok(Step::Eval(test, false)),
+        }
+    }
+    // evaluates the argument of `and` or `or` at `next`, the last one in tail
+    // position
+    fn junction(&self, form: Rc<[LispExp]>, next: usize, or: bool, tail: bool, frames: &mut Vec<Frame>) -> Step {
+        let exp = form[next].clone();
+        if next + 1 == form.len() {
+            return Step::Eval(exp, tail);
+        }
+        frames.push(Frame::Junction { form, next: next + 1, or, tail });
+        Step::Eval(exp, false)
+    }
+    // evaluates the value of the `let` binding at `next`, or runs the body in
+    // `scope` once they're all bound
+    fn binding(&self, form: Rc<[LispExp]>, bindings: &[LispExp], scope: Rc<Scope>, next: usize, tail: bool, frames: &mut Vec<Frame>) -> Result<Step, LispError> {
+        let Some(binding) = bindings.get(next) else {
+            frames.push(Frame::Scope(self.scope.replace(scope)));
+            return Ok(self.body(Exprs::Form(form), 2, tail, frames));
+        };
+        let LispExp::List(pair) = binding else {
+            return Err(LispError::Other(format!("expected a (name value) binding, found {binding}")));
+        };
+        let [name, vl] = &pair[..] else {
+            return Err(LispError::Other(format!("expected a (name value) binding, found {binding}")));
+        };
+        frames.push(Frame::Let { form: form.clone(), scope, name: name.get_symbol()?, next: next + 1, tail });
+        Ok(Step::Eval(vl.clone(), false))
+    }
+    // calls a lambda or builtin with arguments that are already evaluated
+    pub(crate) fn apply(&self, func: &LispExp, args: Vec<LispExp>) -> Result<LispExp, LispError> {
+        match func {
+            LispExp::Lambda(func) => self.call(func, args),
+            LispExp::Builtin(name) => self.apply_builtin(*name, args),
+            other => Err(other.mismatch("function")),
+        }
+    }
+    pub(crate) fn apply_builtin(&self, name: SymbolId, args: Vec<LispExp>) -> Result<LispExp, LispError> {
+        self.exec(name, &args)
+    }
+    // evaluates `f` with `scope` as the current scope, restoring the previous one after
+    pub(crate) fn with_scope<T>(&self, scope: Rc<Scope>, f: impl FnOnce() -> T) -> T {
+        let old = self.scope.replace(scope);
+        let res = f();
+        self.scope.replace(old);
+        res
+    }
+    pub(crate) fn current_scope(&self) -> Rc<Scope> {
+        self.scope.borrow().clone()
+    }
+    // makes `scope` current without restoring the old one, for the VM's frames
+    pub(crate) fn swap_scope(&self, scope: Rc<Scope>) -> Rc<Scope> {
+        self.scope.replace(scope)
+    }
+    // the value of a variable, or the builtin of that name
+    pub(crate) fn resolve(&self, name: SymbolId) -> Result<LispExp, LispError> {
+        // builtins are values too, so they can be passed to map and friends
+        self.current_scope()
+            .get(name)
+            .or_else(|| self.is_builtin(name).then_some(LispExp::Builtin(name)))
+            .or_else(|| name.is_keyword().then_some(LispExp::Symbol(name)))
+            .ok_or_else(|| LispError::UnknownSymbol(name.to_string()))
+    }
+    pub(crate) fn is_builtin(&self, name: SymbolId) -> bool {
+        self.functions.contains_key(&name)
+    }
+    pub(crate) fn is_special(&self, name: SymbolId) -> bool {
+        self.specials.contains_key(&name)
+    }
+    pub(crate) fn is_unevaluated(&self, name: SymbolId) -> bool {
+        self.specials.get(&name).is_some_and(|special| special.unevaluated)
+    }
+    // keeps the docstring `define` gave a global value that isn't a function
+    pub(crate) fn set_doc(&self, name: SymbolId, doc: String) {
+        self.docs.borrow_mut().insert(name, doc);
+    }
+    // the docstring `define` gave a value that isn't a function
+    pub(crate) fn value_doc(&self, name: SymbolId) -> Option<String> {
+        self.docs.borrow().get(&name).cloned()
+    }
+    // how `name` is called and what it does, None when it isn't bound
+    pub(crate) fn doc(&self, name: SymbolId) -> Option<(String, Option<String>)> {
+        if let Some(builtin) = self.functions.get(&name).or_else(|| self.specials.get(&name)) {
+            return Some(match builtin.doc {
+                Some((usage, doc)) => (usage.to_owned(), Some(doc.to_owned())),
+                None => (format!("({name} ...)"), None),
+            });
+        }
+        match self.current_scope().get(name)? {
+            LispExp::Lambda(func) | LispExp::Macro(func) => Some((func.usage(name), func.doc.get().cloned())),
+            _ => Some((name.to_string(), self.value_doc(name))),
+        }
+    }
+    // whether `name` is still bound to what the prelude defined it as
+    pub(crate) fn is_prelude(&self, name: SymbolId, vl: &LispExp) -> bool {
+        self.prelude.get(&name).is_some_and(|defined| defined.is_identical(vl))
+    }
+    // every name that's bound where the code is, builtins included, sorted
+    pub fn names(&self) -> Vec<SymbolId> {
+        let mut names: Vec<SymbolId> = self.functions.keys().chain(self.specials.keys()).copied().collect();
+        names.extend(self.current_scope().names());
+        names.sort_by_cached_key(SymbolId::to_string);
+        names.dedup();
+        names
+    }
+    // how many arguments the builtin, special form or function `name` takes
+    pub(crate) fn arity(&self, name: SymbolId) -> Option<Arity> {
+        if let Some(builtin) = self.functions.get(&name).or_else(|| self.specials.get(&name)) {
+            return Some(builtin.arity);
+        }
+        match self.current_scope().get(name) {
+            Some(LispExp::Lambda(func)) => Some(func.arity()),
+            _ => None,
+        }
+    }
+    // counts an evaluation step against the limit, failing with Interrupted
+    // once the host sets the interrupt flag, and clearing it so the next
+    // evaluation can run, or once a `with-tasks` cancels the task it's running;
+    // attached clients' requests are answered first
+    pub(crate) fn step(&self) -> Result<(), LispError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.poll_attached();
+        if let Some(flag) = &self.interrupt {
+            if flag.swap(false, Ordering::Relaxed) {
+                return Err(LispError::Interrupted);
+            }
+        }
+        if self.cancel.borrow().iter().any(|flag| flag.load(Ordering::Relaxed)) {
+            return Err(LispError::Interrupted);
+        }
+        if let Some(max) = self.max_steps {
+            let steps = self.steps.get() + 1;
+            if steps > max {
+                return Err(LispError::LimitExceeded { limit: "step", max });
+            }
+            self.steps.set(steps);
+        }
+        Ok(())
+    }
+    // fails when `bytes` more would go past the memory limit, for builtins
+    // that shouldn't spend the time building a value only to have it refused
+    pub(crate) fn can_allocate(&self, bytes: usize) -> Result<(), LispError> {
+        match self.max_bytes {
+            Some(max) if self.bytes.get().saturating_add(bytes) > max => {
+                Err(LispError::LimitExceeded { limit: "memory", max: max as u64 })
+            }
+            _ => Ok(()),
+        }
+    }
+    // counts what the builtin `name` allocated to build `value` against the
+    // memory limit
+    fn charge(&self, name: SymbolId, value: &LispExp) -> Result<(), LispError> {
+        let Some(max) = self.max_bytes else {
+            return Ok(());
+        };
+        let bytes = self.bytes.get() + allocated(&name.name(), value);
+        if bytes > max {
+            return Err(LispError::LimitExceeded { limit: "memory", max: max as u64 });
+        }
+        self.bytes.set(bytes);
+        Ok(())
+    }
+    pub(crate) fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+    // special forms are looked up before the scope, so a definition of one
+    // would never be called
+    pub(crate) fn definable(&self, name: SymbolId) -> Result<(), LispError> {
+        match self.is_special(name) {
+            true => Err(LispError::Other(format!("`{name}` is a special form, which can't be redefined"))),
+            false => Ok(()),
+        }
+    }
+    pub(crate) fn define(&self, name: SymbolId, vl: LispExp) {
+        if let LispExp::Lambda(func) | LispExp::Macro(func) = &vl {
+            let _ = func.name.set(name);
+        }
+        let scope = self.current_scope();
+        // the docstring was about the value being replaced
+        if scope.is_global() {
+            self.docs.borrow_mut().remove(&name);
+        }
+        scope.insert(name, vl);
+    }
+    // a call to `func`, or to the builtin `name` when there's no `func`
+    pub(crate) fn enter_call(&self, name: Option<SymbolId>, func: Option<&LispLambda>) {
+        self.calls.borrow_mut().push(name);
+        if let Some(profiler) = &self.profiler {
+            profiler.borrow_mut().enter(name);
+        }
+        #[cfg(feature = "tracing")]
+        self.spans.borrow_mut().enter(name, func, &self.files.borrow());
+        #[cfg(not(feature = "tracing"))]
+        let _ = func;
+    }
+    // for tail calls, which take the place of their caller
+    pub(crate) fn replace_call(&self, name: Option<SymbolId>, func: Option<&LispLambda>) {
+        if let Some(call) = self.calls.borrow_mut().last_mut() {
+            *call = name;
+            if let Some(profiler) = &self.profiler {
+                let mut profiler = profiler.borrow_mut();
+                profiler.leave();
+                profiler.enter(name);
+            }
+            #[cfg(feature = "tracing")]
+            {
+                let mut spans = self.spans.borrow_mut();
+                spans.leave();
+                spans.enter(name, func, &self.files.borrow());
+            }
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = func;
+    }
+    pub(crate) fn exit_call(&self) {
+        self.calls.borrow_mut().pop();
+        if let Some(profiler) = &self.profiler {
+            profiler.borrow_mut().leave();
+        }
+        #[cfg(feature = "tracing")]
+        self.spans.borrow_mut().leave();
+    }
+    // exits the innermost call, adding the backtrace to an error leaving it
+    pub(crate) fn leave_call<T>(&self, res: Result<T, LispError>) -> Result<T, LispError> {
+        let res = res.map_err(|err| self.backtrace(err));
+        self.exit_call();
+        res
+    }
+    pub(crate) fn call_depth(&self) -> usize {
+        self.calls.borrow().len()
+    }
+    // drops the calls an error unwound past without leaving them
+    pub(crate) fn unwind_calls(&self, depth: usize) {
+        self.calls.borrow_mut().truncate(depth);
+        if let Some(profiler) = &self.profiler {
+            profiler.borrow_mut().unwind(depth);
+        }
+        #[cfg(feature = "tracing")]
+        self.spans.borrow_mut().unwind(depth);
+    }
+    // wraps `err` with the calls being made, unless it already has them
+    pub(crate) fn backtrace(&self, err: LispError) -> LispError {
+        if matches!(err, LispError::Backtrace { .. } | LispError::Exit(_) | LispError::Interrupted) || self.calls.borrow().is_empty() {
+            return err;
+        }
+        self.wrap(err, None)
+    }
+    fn wrap(&self, err: LispError, pos: Option<Pos>) -> LispError {
+        let calls = self.calls
+            .borrow()
+            .iter()
+            .rev()
+            .map(|call| call.map_or("lambda".to_owned(), |name| name.to_string()))
+            .collect();
+        let file = self.files.borrow().last().map(|file| file.display().to_string());
+        LispError::Backtrace { error: Box::new(err), calls, file, pos }
+    }
+    // points an error raised evaluating `vl`, a list the parser read, at
+    // where it is in the source, unless a list inside it already did
+    fn locate(&self, err: LispError, vl: &LispExp) -> LispError {
+        if matches!(err, LispError::Backtrace { pos: Some(_), .. } | LispError::Exit(_) | LispError::Interrupted) {
+            return err;
+        }
+        match vl {
+            LispExp::List(items) => self.locate_list(err, items),
+            _ => err,
+        }
+    }
+    fn locate_list(&self, err: LispError, items: &Rc<[LispExp]>) -> LispError {
+        match span::span(items) {
+            Some(pos) => self.place(err, pos),
+            None => err,
+        }
+    }
+    // points `err` at `pos`, unless it already points somewhere
+    fn place(&self, err: LispError, pos: Pos) -> LispError {
+        if matches!(err, LispError::Backtrace { pos: Some(_), .. } | LispError::Exit(_) | LispError::Interrupted) {
+            return err;
+        }
+        match err {
+            LispError::Backtrace { error, calls, file, .. } => LispError::Backtrace { error, calls, file, pos: Some(pos) },
+            err => self.wrap(err, Some(pos)),
+        }
+    }
+    // calls a builtin function with its evaluated arguments
+    fn exec(&self, car: SymbolId, args: &[LispExp]) -> Result<LispExp, LispError> {
+        let builtin = self.functions
+            .get(&car)
+            .ok_or_else(|| LispError::UnknownSymbol(car.to_string()))?;
+        if let Some(metrics) = &self.metrics {
+            metrics.builtin_called(&car.name());
+        }
+        self.enter_call(Some(car), None);
+        let res = builtin
+            .arity
+            .check(car, args.len())
+            .and_then(|()| (builtin.func)(self, args))
+            .and_then(|vl| self.charge(car, &vl).map(|()| vl));
+        self.leave_call(res)
+    }
+    // a pure builtin's value for constant `args`, without counting it as a
+    // call; None if it fails
+    pub(crate) fn call_pure(&self, name: SymbolId, args: &[LispExp]) -> Option<LispExp> {
+        let builtin = self.functions.get(&name)?;
+        builtin.arity.check(name, args.len()).ok()?;
+        (builtin.func)(self, args).ok()
+    }
+    // evaluates a top-level form, with the VM if it's enabled
+    pub fn run(&self, root: &LispExp) -> Result<LispExp, LispError> {
+        let folded;
+        let root = if self.optimize {
+            folded = opt::fold(self, root);
+            &folded
+        } else {
+            root
+        };
+        // the VM doesn't keep spans, so its errors point at the whole form
+        let eval = |root| if self.vm { vm::run(self, root).map_err(|err| self.locate(err, root)) } else { self.value(root) };
+        let Some(metrics) = &self.metrics else {
+            return eval(root);
+        };
+        let timer = Timer::start();
+        let res = eval(root);
+        metrics.eval_duration(timer.elapsed());
+        if let Err(err) = &res {
+            metrics.error(err);
+        }
+        res
+    }
+    // parses and runs a whole program
+    pub fn eval_str(&self, source: &str) -> Result<LispExp, LispError> {
+        let mut last = LispExp::Nil;
+        for (exp, pos) in parse_program_spanned(&tokens(source)?, self.max_depth)? {
+            last = self.run_at(&exp, pos)?;
+        }
+        Ok(last)
+    }
+    // runs `root`, a top-level form read at `pos`, pointing errors that
+    // nothing inside it placed, like an unbound bare symbol's, at `pos`
+    pub fn run_at(&self, root: &LispExp, pos: Pos) -> Result<LispExp, LispError> {
+        self.run(root).map_err(|err| self.place(err, pos))
+    }
+    // the forms in `source`, nested no deeper than evaluation may go
+    pub(crate) fn parse(&self, source: &str) -> Result<Vec<LispExp>, LispError> {
+        parse_program_with_depth(&tokens(source)?, self.max_depth)
+    }
+    // limits how deep lists may nest in eval_str and how deep evaluation may recurse
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+    // folds calls to pure builtins with constant arguments, like `(* 2 (+ 3 4))`,
+    // into their value before each top-level form runs
+    pub fn optimize(&mut self) {
+        self.optimize = true;
+    }
+    // removes the math builtins (sqrt, pow, sin, random, ...), leaving the basic arithmetic
+    pub fn disable_math(&mut self) {
+        for name in math_funcs().keys() {
+            self.functions.remove(name);
+        }
+    }
+    // the optional builtins the interpreter has: `math` unless it was
+    // disabled, and the cargo features it was built with
+    pub fn capabilities(&self) -> Vec<String> {
+        let mut capabilities = vec![];
+        if math_funcs().keys().all(|name| self.functions.contains_key(name)) {
+            capabilities.push("math".to_owned());
+        }
+        if cfg!(feature = "serde") {
+            capabilities.push("serde".to_owned());
+        }
+        if cfg!(feature = "graphics") {
+            capabilities.push("graphics".to_owned());
+        }
+        capabilities
+    }
+    // the state of the random number generator; read before a program runs,
+    // it's the seed that makes `set_seed` repeat the program's random numbers
+    pub fn seed(&self) -> u64 {
+        self.rng.get()
+    }
+    // allows `steps` more evaluation steps from now, or any number with
+    // None, for hosts that give each call into a script a budget of its own
+    pub fn set_fuel(&mut self, steps: Option<u64>) {
+        self.max_steps = steps;
+        self.steps.set(0);
+    }
+    pub fn set_seed(&self, seed: u64) {
+        self.rng.set(seed);
+    }
+    // makes `f` callable from lisp as `name`, replacing any builtin of that name;
+    // `f` gets its arguments already evaluated
+    pub fn register_fn(
+        &mut self,
+        name: impl Into<String>,
+        f: impl Fn(&Interpreter, &[LispExp]) -> Result<LispExp, LispError> + 'static,
+    ) {
+        let name = SymbolId::new(&name.into());
+        self.specials.remove(&name);
+        self.functions.insert(name, Builtin { func: Box::new(f), arity: Arity::from(0..), doc: None, unevaluated: false });
+    }
+    // like `register_fn`, but `f` gets its arguments unevaluated, so it can
+    // decide what to evaluate, like `if` and `quote` do
+    pub fn register_special(
+        &mut self,
+        name: impl Into<String>,
+        f: impl Fn(&Interpreter, &[LispExp]) -> Result<LispExp, LispError> + 'static,
+    ) {
+        let name = SymbolId::new(&name.into());
+        self.functions.remove(&name);
+        // what the host does with its arguments isn't known, so they're never folded
+        self.specials.insert(name, Builtin { func: Box::new(f), arity: Arity::from(0..), doc: None, unevaluated: true });
+    }
+    // evaluates `exp` in the current scope, for special forms registered from outside the crate
+    pub fn eval(&self, exp: &LispExp) -> Result<LispExp, LispError> {
+        self.value(exp)
+    }
+    // like `register_fn`, for functions that keep mutable state between calls
+    pub fn register_fn_mut(
+        &mut self,
+        name: impl Into<String>,
+        f: impl FnMut(&Interpreter, &[LispExp]) -> Result<LispExp, LispError> + 'static,
+    ) {
+        let name = name.into();
+        let f = RefCell::new(f);
+        let func: LispFN = Box::new({
+            let name = name.clone();
+            move |env, args| {
+                let mut f = f
+                    .try_borrow_mut()
+                    .map_err(|_| LispError::Other(format!("{name} can't call itself")))?;
+                f(env, args)
+            }
+        });
+        let name = SymbolId::new(&name);
+        self.specials.remove(&name);
+        self.functions.insert(name, Builtin { func, arity: Arity::from(0..), doc: None, unevaluated: false });
+    }
+    // binds `argv` to the script's command-line arguments
+    pub fn set_args(&mut self, args: Vec<String>) {
+        let args = args.into_iter().map(LispExp::from).collect::<Vec<_>>();
+        self.define(SymbolId::new("argv"), args.into());
+    }
+    // writes what the program defined globally to `path` as source that
+    // defines it again, see `image::image`
+    pub fn save_image(&self, path: impl AsRef<Path>) -> Result<(), LispError> {
+        let path = path.as_ref();
+        let source = image::image(self)?;
+        platform::write_file(path, &source, false).map_err(|err| LispError::Other(format!("{}: {err}", path.display())))
+    }
+    // has `(resume name default)` give back what `(checkpoint name state)`
+    // saved in an earlier run, instead of the default
+    pub fn set_resume(&mut self, resume: bool) {
+        self.resume = resume;
+    }
+    pub(crate) fn resuming(&self) -> bool {
+        self.resume
+    }
+    // defines what `save_image` saved to `path`
+    pub fn load_image(&self, path: impl AsRef<Path>) -> Result<(), LispError> {
+        let path = path.as_ref();
+        let source = platform::read_file(path).map_err(|err| LispError::Other(format!("{}: {err}", path.display())))?;
+        self.eval_str(&source)?;
+        Ok(())
+    }
+    // reads and runs the file at `path` in the global scope, like `load`
+    pub fn load_file(&self, path: impl AsRef<Path>) -> Result<LispExp, LispError> {
+        self.load(path.as_ref())
+    }
+    // the files `load` and `import` read so far, for `--watch`
+    pub fn loaded_files(&self) -> Vec<PathBuf> {
+        self.loaded.borrow().clone()
+    }
+    // the file the program was read from, so `load` resolves paths relative to it
+    pub fn set_file(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let path = platform::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        self.files.replace(vec![path]);
+    }
+    // the file being run or loaded, if there's one
+    pub(crate) fn current_file(&self) -> Option<PathBuf> {
+        self.files.borrow().last().cloned()
+    }
+    // adds a directory for `import` to look for modules in, after the one
+    // the importing file is in
+    pub fn add_include_path(&mut self, dir: impl AsRef<Path>) {
+        self.include.push(dir.as_ref().to_owned());
+    }
+    // relative paths start from the directory of the file doing the loading
+    fn resolve_path(&self, path: &Path) -> PathBuf {
+        match self.files.borrow().last().and_then(|file| file.parent()) {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_owned(),
+        }
+    }
+    // the file `import` reads a module from: `name.lsp` beside the importing
+    // file, or else in the first include directory that has it
+    pub(crate) fn module_path(&self, name: SymbolId) -> PathBuf {
+        let file = format!("{name}.lsp");
+        let beside = self.resolve_path(Path::new(&file));
+        if beside.exists() {
+            return beside;
+        }
+        self.include.iter().map(|dir| dir.join(&file)).find(|path| path.exists()).unwrap_or(beside)
+    }
+    // reads and runs the file at `path` in the current scope; relative paths
+    // start from the directory of the file doing the loading
+    pub(crate) fn load(&self, path: &Path) -> Result<LispExp, LispError> {
+        let path = self.resolve_path(path);
+        let shown = path.display().to_string();
+        let path = platform::canonicalize(&path)
+            .map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        if self.files.borrow().contains(&path) {
+            return Err(LispError::Other(format!("{shown} is already being loaded")));
+        }
+        if !self.loaded.borrow().contains(&path) {
+            self.loaded.borrow_mut().push(path.clone());
+        }
+        let source = platform::read_file(&path).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        if let Some(check) = &self.load_check {
+            check(&path, &source).map_err(|err| LispError::Other(format!("{shown}: {}", err.message())))?;
+        }
+        self.files.borrow_mut().push(path);
+        let res = self.eval_str(&source);
+        self.files.borrow_mut().pop();
+        res
+    }
+    // has `load` and `import` refuse a file unless `check` passes it, given
+    // its path and the source that would run, like to require a signature
+    pub fn set_load_check(&mut self, check: impl Fn(&Path, &str) -> Result<(), LispError> + Send + Sync + 'static) {
+        self.load_check = Some(Arc::new(check));
+    }
+    pub fn set_vm(&mut self, vm: bool) {
+        self.vm = vm;
+    }
+    // makes `print` break values wider than a line into an item per line
+    pub fn set_pretty(&mut self, pretty: bool) {
+        self.pretty = pretty;
+    }
+    // makes `print` color values by type with ANSI escapes, for terminals
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+    // `value` the way `print` shows it
+    pub fn show(&self, value: &LispExp) -> String {
+        pretty::render(value, self.pretty, self.color)
+    }
+    // stops whatever is being evaluated with LispError::Interrupted when `flag`
+    // is set, like from a Ctrl-C handler; the flag is cleared when it does
+    pub fn set_interrupt(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = Some(flag);
+    }
+    pub fn set_metrics(&mut self, metrics: Box<dyn Metrics>) {
+        self.metrics = Some(metrics);
+    }
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+    // counts the calls to every function and times them from now on, for `profile`
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.profiler = profiling.then(RefCell::default);
+    }
+    // what was measured since profiling was turned on, the slowest functions first
+    pub fn profile(&self) -> Vec<ProfileEntry> {
+        self.profiler.as_ref().map_or(vec![], |profiler| profiler.borrow().entries())
+    }
+    pub(crate) fn add_test(&self, name: String, body: Rc<[LispExp]>) {
+        self.tests.borrow_mut().push(Test { name, body, scope: self.current_scope() });
+    }
+    // runs every test `deftest` registered, each in a scope of its own, and
+    // returns their names with how they went
+    pub fn run_tests(&self) -> Vec<(String, Result<LispExp, LispError>)> {
+        let tests = self.tests.borrow().clone();
+        tests
+            .into_iter()
+            .map(|Test { name, body, scope }| {
+                let depth = self.call_depth();
+                let res = self.with_scope(Scope::child(&scope), || {
+                    let mut last = LispExp::Nil;
+                    for exp in body.iter() {
+                        last = self.value(exp)?;
+                    }
+                    Ok(last)
+                });
+                self.unwind_calls(depth);
+                (name, res)
+            })
+            .collect()
+    }
+    // runs top-level forms in order, returning the last value or nil for an empty program
+    pub fn run_program(&self, program: &[LispExp]) -> Result<LispExp, LispError> {
+        let mut last = LispExp::Nil;
+        for exp in program {
+            last = self.run(exp)?;
+        }
+        Ok(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(source: &str) -> Result<LispExp, LispError> {
+        Interpreter::with_limits(1_000_000, 200, 1 << 16).eval_str(source)
+    }
+
+    fn exceeded(res: Result<LispExp, LispError>, which: &str) -> bool {
+        matches!(res.map_err(|err| err.root().clone()), Err(LispError::LimitExceeded { limit, .. }) if limit == which)
+    }
+
+    #[test]
+    fn step_limit() {
+        assert!(exceeded(limited("(while true nil)"), "step"));
+    }
+
+    #[test]
+    fn memory_limit() {
+        assert!(exceeded(limited("(define l '()) (while true (set! l (cons 1 l)))"), "memory"));
+        // refused before it's computed
+        assert!(exceeded(limited("(pow 10 2000000000)"), "memory"));
+        assert!(exceeded(limited("(define n 2) (while true (set! n (* n n)))"), "memory"));
+        assert!(limited("(pow 1 2000000000)").is_ok());
+        assert!(limited("(pow 2 1000)").is_ok());
+    }
+
+    #[test]
+    fn load_checks_reach_worker_threads() {
+        let dir = std::env::temp_dir().join(format!("sxprs-load-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ok.lsp"), "(define ok 1)").unwrap();
+        std::fs::write(dir.join("bad.lsp"), "(define bad 1)").unwrap();
+        let mut lisp = Interpreter::new();
+        lisp.set_file(dir.join("main.lsp"));
+        lisp.set_load_check(|path, _| match path.ends_with("bad.lsp") {
+            true => Err(LispError::Other("refused".to_owned())),
+            false => Ok(()),
+        });
+        assert_eq!(lisp.eval_str("(load \"ok.lsp\") ok").unwrap().to_string(), "1");
+        let shown = dir.join("bad.lsp").canonicalize().unwrap().display().to_string();
+        assert_eq!(lisp.eval_str("(load \"bad.lsp\")").unwrap_err().summary(), format!("{shown}: refused"));
+        let path = dir.join("bad.lsp").display().to_string();
+        let err = lisp.eval_str(&format!("(pmap (lambda (x) (load {path:?})) '(1))")).unwrap_err();
+        assert!(err.message().contains(&format!("{shown}: refused")), "{}", err.message());
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_depth() {
+        let lisp = Interpreter::new();
+        let res = lisp.eval_str("(defn count (n) (if (= n 0) 'done (count (- n 1)))) (count 100000)");
+        assert_eq!(res.unwrap().to_string(), "done");
+        // and so do the bodies of `let`, `begin` and `cond`
+        let res = lisp.eval_str("(defn down (n) (cond ((= n 0) 'done) (else (let ((m (- n 1))) (begin (down m)))))) (down 100000)");
+        assert_eq!(res.unwrap().to_string(), "done");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let source = "(defn sum (n) (if (= n 0) 0 (+ n (sum (- n 1)))))";
+        let res = limited(&format!("{source} (sum 1000)"));
+        assert!(matches!(res.map_err(|err| err.root().clone()), Err(LispError::TooDeep(200))));
+        // deep recursion doesn't grow the native stack, which is small on a test thread
+        let mut lisp = Interpreter::new();
+        lisp.set_max_depth(1_000_000);
+        assert_eq!(lisp.eval_str(&format!("{source} (sum 100000)")).unwrap().to_string(), "5000050000");
+    }
+
+    #[test]
+    fn errors_point_at_the_call() {
+        let res = Interpreter::new().eval_str("(defn f (n) (if (= n 0) (car 5) (+ 1 (f (- n 1)))))\n(f 2)");
+        let LispError::Backtrace { calls, pos, .. } = res.unwrap_err() else {
+            panic!("expected a backtrace");
+        };
+        assert_eq!(calls, ["car", "f", "f", "f"]);
+        assert_eq!(pos, Some(Pos { line: 1, col: 25 }));
+    }
+
+    #[test]
+    fn prelude_retries() {
+        use std::time::{Duration, Instant};
+        let lisp = Interpreter::new();
+        let flaky = "(define tries 0) (defn flaky () (set! tries (+ tries 1)) (if (< tries 3) (error \"flaky\") tries))";
+        lisp.eval_str(flaky).unwrap();
+        assert_eq!(lisp.eval_str("(retry {:delay 1} flaky)").unwrap().to_string(), "3");
+        let start = Instant::now();
+        let res = lisp.eval_str("(retry {:times 4 :delay 20} (lambda () (car 1)))");
+        assert_eq!(res.unwrap_err().root().message(), "1 is not a List, it's a Int");
+        // 20, 40 and 80ms between the four tries
+        assert!(start.elapsed() >= Duration::from_millis(140));
+        let res = lisp.eval_str("(retry {:backoff :sometimes} flaky)");
+        assert_eq!(res.unwrap_err().root().message(), "retry: :backoff should be :exp, :linear or :fixed, not :sometimes");
+    }
+
+    #[test]
+    fn prelude_rate_limits_and_debounces() {
+        use std::time::{Duration, Instant};
+
+        let lisp = Interpreter::new();
+        let start = Instant::now();
+        let calls = lisp.eval_str("(define f (rate-limit 2 50 list)) (list (f 1) (f 2 3) (f 4) (f 5) (f))").unwrap();
+        assert_eq!(calls.to_string(), "( ( 1 ) ( 2 3 ) ( 4 ) ( 5 ) (  ) )");
+        // the third and fifth calls wait for the window to pass
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        let calls = lisp.eval_str("(define g (debounce 30 list)) (list (g 1) (g 2) (begin (sleep 40) (g 3)))").unwrap();
+        assert_eq!(calls.to_string(), "( ( 1 ) nil ( 3 ) )");
+    }
+}
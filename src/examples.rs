@@ -0,0 +1,61 @@
+// `sxprs examples`: small programs built into the binary, to run and read
+// while finding out what the language can do
+pub struct Example {
+    pub name: &'static str,
+    // what the example is about: math, strings, files, http or graphics
+    pub category: &'static str,
+    pub about: &'static str,
+    pub source: &'static str,
+}
+
+pub const EXAMPLES: &[Example] = &[
+    Example { name: "fib", category: "math", about: "fibonacci numbers, past 64 bits", source: include_str!("examples/fib.lsp") },
+    Example { name: "primes", category: "math", about: "the primes below 100", source: include_str!("examples/primes.lsp") },
+    Example { name: "stats", category: "math", about: "mean and standard deviation", source: include_str!("examples/stats.lsp") },
+    Example { name: "words", category: "strings", about: "counting the words in a sentence", source: include_str!("examples/words.lsp") },
+    Example { name: "caesar", category: "strings", about: "the caesar cipher", source: include_str!("examples/caesar.lsp") },
+    Example { name: "notes", category: "files", about: "writing, appending to and reading a file", source: include_str!("examples/notes.lsp") },
+    Example { name: "fetch", category: "http", about: "fetching a page with curl", source: include_str!("examples/fetch.lsp") },
+    #[cfg(feature = "graphics")]
+    Example { name: "life", category: "graphics", about: "the game of life, drawn as an svg", source: include_str!("examples/life.lsp") },
+];
+
+pub fn example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+// the categories, in the order their first example is in
+pub fn categories() -> Vec<&'static str> {
+    let mut categories = vec![];
+    for example in EXAMPLES {
+        if !categories.contains(&example.category) {
+            categories.push(example.category);
+        }
+    }
+    categories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    #[test]
+    fn examples_read_and_check() {
+        let lisp = Interpreter::new();
+        for example in EXAMPLES {
+            let program = lisp.parse(example.source).unwrap_or_else(|err| panic!("{}: {}", example.name, err.message()));
+            let problems = crate::check::check_types(&lisp, &program);
+            assert!(problems.is_empty(), "{}: {problems:?}", example.name);
+            assert!(example.source.starts_with("; "), "{} says what it does first", example.name);
+        }
+    }
+
+    #[test]
+    fn finds_examples() {
+        assert_eq!(example("caesar").map(|example| example.category), Some("strings"));
+        assert!(example("nope").is_none());
+        assert_eq!(categories()[..4], ["math", "strings", "files", "http"]);
+        assert_eq!(categories().contains(&"graphics"), cfg!(feature = "graphics"));
+    }
+}
@@ -0,0 +1,14 @@
+; the caesar cipher: every letter moved along the alphabet, wrapping around
+(defn shift-char (c by)
+  (let ((code (char->int c)))
+    (cond
+      ((and (>= code 97) (<= code 122)) (int->char (+ 97 (mod (+ (- code 97) by) 26))))
+      ((and (>= code 65) (<= code 90)) (int->char (+ 65 (mod (+ (- code 65) by) 26))))
+      (else c))))
+
+(defn caesar (text by)
+  (list->string (map (lambda (c) (shift-char c by)) (string->list text))))
+
+(define secret (caesar "Hello, World!" 3))
+(printf "{}\n" secret)
+(printf "{}\n" (caesar secret -3))
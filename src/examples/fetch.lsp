@@ -0,0 +1,10 @@
+; fetches a page over HTTP. There are no network builtins, so it runs curl,
+; which has to be installed; the URL can be given after the file
+(define url (if argv (car argv) "https://example.com"))
+
+(define response (exec "curl" "--silent" "--show-error" "--location" "--write-out" "\n%{http_code}" url))
+
+(if (= (dict-get response :code) 0)
+  (let ((lines (str-split (dict-get response :stdout) "\n")))
+    (printf "{} answered {}, {} lines\n" url (last lines) (- (length lines) 1)))
+  (printf "couldn't fetch {}: {}" url (dict-get response :stderr)))
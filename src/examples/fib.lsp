@@ -0,0 +1,11 @@
+; fibonacci numbers, which outgrow 64 bits and keep going as big integers
+(defn fib (n)
+  (let ((a 0) (b 1))
+    (dotimes (i n)
+      (let ((next (+ a b)))
+        (set! a b)
+        (set! b next)))
+    a))
+
+(for n in (list 10 50 90 100 150)
+  (printf "fib {} = {}\n" n (fib n)))
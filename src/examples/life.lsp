@@ -0,0 +1,43 @@
+; a glider in the game of life, drawing four generations side by side as an svg
+(define size 6)
+; how big a cell is drawn
+(define cell 10)
+
+(defn alive? (cells x y)
+  (filter (lambda (cell) (equal? cell (list x y))) cells))
+
+(defn neighbours (cells x y)
+  (let ((count 0))
+    (for dx in '(-1 0 1)
+      (for dy in '(-1 0 1)
+        (if (and (not (and (= dx 0) (= dy 0)))
+                 (alive? cells (mod (+ x dx) size) (mod (+ y dy) size)))
+          (set! count (+ count 1)))))
+    count))
+
+(defn step (cells)
+  (let ((next '()))
+    (dotimes (x size)
+      (dotimes (y size)
+        (let ((n (neighbours cells x y)))
+          (if (or (= n 3) (and (= n 2) (alive? cells x y)))
+            (set! next (cons (list x y) next))))))
+    next))
+
+(defn draw (cells left)
+  (pen-color "#dddddd")
+  (rect left 0 (* size cell) (* size cell))
+  (pen-color "black")
+  (for c in cells
+    (rect (+ left (* cell (car c))) (* cell (second c)) cell cell)))
+
+(define cells '((1 3) (2 3) (3 3) (3 4) (2 5)))
+(dotimes (generation 4)
+  (draw cells (* generation cell (+ size 1)))
+  (printf "generation {}: {} cells\n" generation (length cells))
+  (set! cells (step cells)))
+
+(define dir (or (getenv "TMPDIR") (getenv "TEMP") "/tmp"))
+(define path (str-concat dir "/sxprs-life.svg"))
+(render path)
+(printf "drawn to {}\n" path)
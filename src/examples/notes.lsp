@@ -0,0 +1,12 @@
+; writes a file, adds to it and reads it back, in the system's temporary directory
+(define dir (or (getenv "TMPDIR") (getenv "TEMP") "/tmp"))
+(define path (str-concat dir "/sxprs-notes.txt"))
+
+(write-file path "buy milk\n")
+(append-file path "water the plants\n")
+
+(define notes (filter (lambda (line) (> (str-length line) 0))
+                      (str-split (read-file path) "\n")))
+(printf "{} notes in {}:\n" (length notes) path)
+(for note in notes
+  (printf "- {}\n" note))
@@ -0,0 +1,13 @@
+; the primes below 100, by trial division, and how many there are
+(defn prime? (n)
+  (if (< n 2)
+    false
+    (let ((d 2) (found false))
+      (while (and (not found) (<= (* d d) n))
+        (if (= (mod n d) 0) (set! found true))
+        (set! d (+ d 1)))
+      (not found))))
+
+(define primes (filter prime? (range 0 100)))
+(printf "{}\n" primes)
+(printf "{} primes below 100, the largest {}\n" (length primes) (last primes))
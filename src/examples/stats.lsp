@@ -0,0 +1,11 @@
+; the mean and standard deviation of some measurements
+(define samples (list 12.5 14.1 13.8 15.2 12.9 14.6))
+
+(defn mean (xs) (/ (reduce + 0 xs) (length xs)))
+
+(defn stddev (xs)
+  (let ((m (mean xs)))
+    (sqrt (mean (map (lambda (x) (pow (- x m) 2)) xs)))))
+
+(printf "mean {}\n" (/ (round (* 100 (mean samples))) 100))
+(printf "stddev {}\n" (/ (round (* 100 (stddev samples))) 100))
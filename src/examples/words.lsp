@@ -0,0 +1,12 @@
+; counts the words in a sentence, ignoring case and extra spaces
+(define text "the quick brown fox jumps over the lazy dog  The End")
+
+(define words (filter (lambda (word) (> (str-length word) 0))
+                      (map downcase (str-split text " "))))
+
+(define counts (dict))
+(for word in words
+  (set! counts (dict-set counts word (+ 1 (or (dict-get counts word) 0)))))
+
+(printf "{} words, {} different\n" (length words) (length (dict-keys counts)))
+(printf "\"the\" is there {} times\n" (dict-get counts "the"))
@@ -0,0 +1,511 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::error::LispError;
+use crate::eval::{Arity, Interpreter, Scope};
+use crate::lexer::char_name;
+use crate::num;
+use crate::symbol::{SymbolId, DICT, OPTIONAL, REST, VECTOR};
+use crate::vm::Chunk;
+
+#[derive(Debug, Clone)]
+pub enum LispExp {
+    Symbol(SymbolId),
+    Str(String),
+    Char(char),
+    Int(i64),
+    // an int that doesn't fit in an i64
+    BigInt(Rc<BigInt>),
+    // an exact fraction, what `/` makes of ints that don't divide evenly
+    Ratio(Rc<BigRational>),
+    Number(f64),
+    Bool(bool),
+    // the absence of a value, what `print`, an `if` without an else and the like return
+    Nil,
+    // shared, so evaluating or passing a list around doesn't copy it
+    List(Rc<[LispExp]>),
+    // a dotted pair, `(a . b)`, what `cons` makes of a value and a non-list
+    Pair(Rc<(LispExp, LispExp)>),
+    Map(BTreeMap<MapKey, LispExp>),
+    // unlike lists, vectors are shared, so `vec-set!` is seen by every copy
+    Vector(Rc<RefCell<Vec<LispExp>>>),
+    Lambda(Rc<LispLambda>),
+    // a builtin function used as a value, by name
+    Builtin(SymbolId),
+    // like a lambda, but gets its arguments unevaluated and its result is evaluated
+    Macro(Rc<LispLambda>),
+    // a value made by a `defstruct` constructor
+    Struct(Rc<Struct>),
+}
+
+// the values that can key a map, kept ordered so maps print deterministically
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Symbol(String),
+}
+
+#[derive(Debug)]
+pub struct LispLambda {
+    pub(crate) params: Vec<SymbolId>,
+    // the `&optional` parameters, which take their default when left out
+    pub(crate) optional: Vec<(SymbolId, LispExp)>,
+    // parameters passed as `:name value` after the positional ones, with
+    // their keyword and default
+    pub(crate) keys: Vec<(SymbolId, SymbolId, LispExp)>,
+    // the `&rest` parameter, bound to a list of the arguments past the others
+    pub(crate) rest: Option<SymbolId>,
+    pub(crate) body: Vec<LispExp>,
+    // scope the lambda was created in, so free variables resolve lexically
+    pub(crate) env: Rc<Scope>,
+    // the body as bytecode, once the VM has called it
+    pub(crate) code: OnceCell<Rc<Chunk>>,
+    // the name it was first defined as, for backtraces
+    pub(crate) name: OnceCell<SymbolId>,
+    // what it does, for `help`, from a string starting its body
+    pub(crate) doc: OnceCell<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Struct {
+    pub(crate) name: SymbolId,
+    // the field names, symbols, shared by every value the constructor makes
+    pub(crate) fields: Rc<[LispExp]>,
+    pub(crate) values: Vec<LispExp>,
+}
+
+impl Struct {
+    pub(crate) fn get(&self, field: SymbolId) -> Option<&LispExp> {
+        let at = self.fields.iter().position(|name| matches!(name, LispExp::Symbol(name) if *name == field))?;
+        self.values.get(at)
+    }
+}
+
+impl LispExp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LispExp::Int(_)=>"Int",
+            LispExp::BigInt(_)=>"BigInt",
+            LispExp::Ratio(_)=>"Ratio",
+            LispExp::Number(_)=>"Number",
+            LispExp::Symbol(_)=>"Symbol",
+            LispExp::Str(_)=>"String",
+            LispExp::Char(_)=>"Char",
+            LispExp::Bool(_)=>"Bool",
+            LispExp::Nil=>"Nil",
+            LispExp::List(_)=>"List",
+            LispExp::Pair(_)=>"Pair",
+            LispExp::Map(_)=>"Map",
+            LispExp::Vector(_)=>"Vector",
+            LispExp::Lambda(_)=>"Lambda",
+            LispExp::Builtin(_)=>"Builtin",
+            LispExp::Macro(_)=>"Macro",
+            LispExp::Struct(_)=>"Struct",
+        }
+    }
+    // the error for finding this value where an `expected` was needed
+    pub fn mismatch(&self, expected: &'static str) -> LispError {
+        LispError::TypeMismatch {
+            expected,
+            found: self.name(),
+            value: self.to_string(),
+        }
+    }
+    pub fn get_symbol(&self) -> Result<SymbolId, LispError> {
+        if let LispExp::Symbol(n) = self {
+            Ok(*n)
+        } else {
+            Err(self.mismatch("Symbol"))
+        }
+    }
+    // false, nil, 0, 0.0 and the empty list are false, everything else is true
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            LispExp::Int(n) => *n != 0,
+            LispExp::Number(n) => *n != 0.0,
+            LispExp::Bool(b) => *b,
+            LispExp::Nil => false,
+            LispExp::List(l) => !l.is_empty(),
+            _ => true,
+        }
+    }
+    pub fn get_string(&self) -> Result<&str, LispError> {
+        if let LispExp::Str(s) = self {
+            Ok(s)
+        } else {
+            Err(self.mismatch("String"))
+        }
+    }
+    pub fn get_char(&self) -> Result<char, LispError> {
+        if let LispExp::Char(c) = self {
+            Ok(*c)
+        } else {
+            Err(self.mismatch("Char"))
+        }
+    }
+    pub fn get_list(&self) -> Result<&[LispExp], LispError> {
+        if let LispExp::List(l) = self {
+            Ok(l)
+        } else {
+            Err(self.mismatch("List"))
+        }
+    }
+    pub fn get_map(&self) -> Result<&BTreeMap<MapKey, LispExp>, LispError> {
+        if let LispExp::Map(m) = self {
+            Ok(m)
+        } else {
+            Err(self.mismatch("Map"))
+        }
+    }
+    pub fn get_vector(&self) -> Result<&Rc<RefCell<Vec<LispExp>>>, LispError> {
+        if let LispExp::Vector(v) = self {
+            Ok(v)
+        } else {
+            Err(self.mismatch("Vector"))
+        }
+    }
+    // `car` put in front of `cdr`: a list when `cdr` is one, a dotted pair otherwise
+    pub fn cons(car: LispExp, cdr: LispExp) -> LispExp {
+        match cdr {
+            LispExp::List(items) => LispExp::from([vec![car], items.to_vec()].concat()),
+            cdr => LispExp::Pair(Rc::new((car, cdr))),
+        }
+    }
+    // the items of a chain of pairs, `(a b . c)`, and what the last one ends in
+    pub(crate) fn pair_items(pair: &(LispExp, LispExp)) -> (Vec<LispExp>, LispExp) {
+        let mut items = vec![pair.0.clone()];
+        let mut tail = &pair.1;
+        while let LispExp::Pair(next) = tail {
+            items.push(next.0.clone());
+            tail = &next.1;
+        }
+        (items, tail.clone())
+    }
+    // the items of a vector, or the keys and values of a map in turn, which
+    // are what evaluating it evaluates
+    pub(crate) fn literal_items(&self) -> Option<Vec<LispExp>> {
+        match self {
+            LispExp::Vector(items) => Some(items.borrow().clone()),
+            LispExp::Map(map) => Some(map.iter().flat_map(|(key, vl)| [LispExp::from(key.clone()), vl.clone()]).collect()),
+            _ => None,
+        }
+    }
+    // a vector or map like this one with `items`, listed like `literal_items` does
+    pub(crate) fn with_literal_items(&self, items: Vec<LispExp>) -> Result<LispExp, LispError> {
+        match self {
+            LispExp::Map(_) => {
+                let pairs = items.chunks_exact(2).map(|pair| Ok((MapKey::try_from(&pair[0])?, pair[1].clone())));
+                Ok(LispExp::Map(pairs.collect::<Result<_, LispError>>()?))
+            }
+            _ => Ok(LispExp::Vector(Rc::new(RefCell::new(items)))),
+        }
+    }
+    // the call evaluating a vector or map literal comes to, `[1 x]` to
+    // `(vector 1 x)`, with the builtin itself at the head, whatever the
+    // program binds `vector` and `dict` to
+    pub(crate) fn literal_call(&self) -> Option<Rc<[LispExp]>> {
+        let builtin = match self {
+            LispExp::Vector(_) => VECTOR,
+            LispExp::Map(_) => DICT,
+            _ => return None,
+        };
+        Some([vec![LispExp::Builtin(builtin)], self.literal_items()?].concat().into())
+    }
+    // ints and ratios are widened, so any numeric value can be read as a float
+    pub fn get_number(&self) -> Result<f64, LispError> {
+        match self {
+            LispExp::Number(n) => Ok(*n),
+            LispExp::Int(n) => Ok(*n as f64),
+            LispExp::BigInt(_) | LispExp::Ratio(_) => Ok(num::exact(self).map_or(f64::NAN, |n| num::to_float(&n))),
+            _ => Err(self.mismatch("Number")),
+        }
+    }
+    pub fn get_int(&self) -> Result<i64, LispError> {
+        if let LispExp::Int(n) = self {
+            Ok(*n)
+        } else {
+            Err(self.mismatch("Int"))
+        }
+    }
+}
+
+fn is_keyword(exp: &LispExp) -> bool {
+    matches!(exp, LispExp::Symbol(name) if name.is_keyword())
+}
+
+impl Display for LispExp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            LispExp::Symbol(symb) => write!(f, "{symb}"),
+            LispExp::Str(string) => write!(f, "{string:?}"),
+            LispExp::Char(chr) => match char_name(*chr) {
+                Some(name) => write!(f, "#\\{name}"),
+                None => write!(f, "#\\{chr}"),
+            },
+            LispExp::Int(num) => write!(f, "{}", num),
+            LispExp::BigInt(num) => write!(f, "{num}"),
+            LispExp::Ratio(num) => write!(f, "{num}"),
+            LispExp::Number(num) => write!(f, "{num:?}"),
+            LispExp::Bool(b) => write!(f, "{}", b),
+            LispExp::Nil => write!(f, "nil"),
+            LispExp::List(cdr) => {
+                let cont: Vec<String> = cdr.iter().map(LispExp::to_string).collect();
+                write!(f, "( {} )", cont.join(" "))
+            }
+            LispExp::Pair(pair) => {
+                let (items, tail) = LispExp::pair_items(pair);
+                let cont: Vec<String> = items.iter().map(LispExp::to_string).collect();
+                write!(f, "( {} . {tail} )", cont.join(" "))
+            }
+            LispExp::Map(map) => {
+                let cont: Vec<String> = map.iter().map(|(k, v)| format!("{} {v}", LispExp::from(k.clone()))).collect();
+                write!(f, "{{ {} }}", cont.join(" "))
+            }
+            LispExp::Vector(items) => {
+                let cont: Vec<String> = items.borrow().iter().map(LispExp::to_string).collect();
+                write!(f, "[ {} ]", cont.join(" "))
+            }
+            LispExp::Lambda(func) => write!(f, "( lambda {func} )"),
+            LispExp::Builtin(name) => write!(f, "#<builtin {name}>"),
+            LispExp::Macro(func) => write!(f, "( macro {func} )"),
+            // the fields by name, `( point :x 1 :y 2 )`
+            LispExp::Struct(record) => {
+                let cont: Vec<String> = record.fields.iter().zip(&record.values).map(|(field, vl)| format!(":{field} {vl}")).collect();
+                write!(f, "( {} {} )", record.name, cont.join(" "))
+            }
+        }
+    }
+}
+
+// structural equality, what `equal?` checks: values of different types are
+// never equal, so neither are 1 and 1.0; numbers compare like f64 does, so
+// NaN isn't equal to itself; lambdas and macros are only equal to themselves
+impl PartialEq for LispExp {
+    fn eq(&self, other: &LispExp) -> bool {
+        match (self, other) {
+            (LispExp::Symbol(a), LispExp::Symbol(b)) => a == b,
+            (LispExp::Str(a), LispExp::Str(b)) => a == b,
+            (LispExp::Char(a), LispExp::Char(b)) => a == b,
+            (LispExp::Int(a), LispExp::Int(b)) => a == b,
+            (LispExp::BigInt(a), LispExp::BigInt(b)) => a == b,
+            (LispExp::Ratio(a), LispExp::Ratio(b)) => a == b,
+            (LispExp::Number(a), LispExp::Number(b)) => a == b,
+            (LispExp::Bool(a), LispExp::Bool(b)) => a == b,
+            (LispExp::Nil, LispExp::Nil) => true,
+            (LispExp::List(a), LispExp::List(b)) => a == b,
+            (LispExp::Pair(a), LispExp::Pair(b)) => a == b,
+            (LispExp::Map(a), LispExp::Map(b)) => a == b,
+            (LispExp::Vector(a), LispExp::Vector(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (LispExp::Lambda(a), LispExp::Lambda(b)) | (LispExp::Macro(a), LispExp::Macro(b)) => Rc::ptr_eq(a, b),
+            (LispExp::Builtin(a), LispExp::Builtin(b)) => a == b,
+            (LispExp::Struct(a), LispExp::Struct(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl LispExp {
+    // identity, what `eq?` checks: lists, pairs, vectors, structs, lambdas and macros are only
+    // identical to the same value, not to an equal copy; everything else is
+    // compared like `==`
+    pub fn is_identical(&self, other: &LispExp) -> bool {
+        match (self, other) {
+            (LispExp::List(a), LispExp::List(b)) => Rc::ptr_eq(a, b) || (a.is_empty() && b.is_empty()),
+            (LispExp::Pair(a), LispExp::Pair(b)) => Rc::ptr_eq(a, b),
+            (LispExp::Vector(a), LispExp::Vector(b)) => Rc::ptr_eq(a, b),
+            (LispExp::Struct(a), LispExp::Struct(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl TryFrom<&LispExp> for MapKey {
+    type Error = LispError;
+    fn try_from(value: &LispExp) -> Result<MapKey, LispError> {
+        match value {
+            LispExp::Bool(b) => Ok(MapKey::Bool(*b)),
+            LispExp::Int(n) => Ok(MapKey::Int(*n)),
+            LispExp::Str(s) => Ok(MapKey::Str(s.clone())),
+            LispExp::Symbol(s) => Ok(MapKey::Symbol(s.name().to_string())),
+            other => Err(other.mismatch("map key")),
+        }
+    }
+}
+impl From<MapKey> for LispExp {
+    fn from(value: MapKey) -> LispExp {
+        match value {
+            MapKey::Bool(b) => LispExp::Bool(b),
+            MapKey::Int(n) => LispExp::Int(n),
+            MapKey::Str(s) => LispExp::Str(s),
+            MapKey::Symbol(s) => LispExp::Symbol(SymbolId::new(&s)),
+        }
+    }
+}
+
+impl LispLambda {
+    fn display_name(&self) -> String {
+        self.name.get().map_or("lambda".to_owned(), SymbolId::to_string)
+    }
+    // the parameter list as it's written, `(a &optional (b 1) :c 2 &rest d)`
+    pub(crate) fn param_items(&self) -> Vec<LispExp> {
+        let mut params: Vec<LispExp> = self.params.iter().copied().map(LispExp::Symbol).collect();
+        if !self.optional.is_empty() {
+            params.push(LispExp::Symbol(OPTIONAL));
+            params.extend(self.optional.iter().map(|(param, default)| LispExp::from(vec![LispExp::Symbol(*param), default.clone()])));
+        }
+        for (_, keyword, default) in &self.keys {
+            params.extend([LispExp::Symbol(*keyword), default.clone()]);
+        }
+        if let Some(rest) = self.rest {
+            params.extend([LispExp::Symbol(REST), LispExp::Symbol(rest)]);
+        }
+        params
+    }
+    // how it's called, like `(name a &rest b)`
+    pub(crate) fn usage(&self, name: SymbolId) -> String {
+        let mut usage = vec![name.to_string()];
+        usage.extend(self.param_items().iter().map(LispExp::to_string));
+        format!("({})", usage.join(" "))
+    }
+    // a keyword argument is two, the keyword and its value
+    pub(crate) fn arity(&self) -> Arity {
+        let max = self.params.len() + self.optional.len() + 2 * self.keys.len();
+        Arity { min: self.params.len(), max: self.rest.is_none().then_some(max) }
+    }
+    // a scope for a call with `args`, bound to the parameters; defaults are
+    // evaluated in it, so they can refer to the parameters before them
+    pub(crate) fn bind(&self, env: &Interpreter, args: Vec<LispExp>) -> Result<Rc<Scope>, LispError> {
+        let required = self.params.len();
+        // with keyword parameters, the positional arguments end at the first keyword
+        let positional = if self.keys.is_empty() {
+            args.len()
+        } else {
+            let optional = args.get(required..).unwrap_or_default();
+            required + optional.iter().take_while(|arg| !is_keyword(arg)).count()
+        };
+        let variadic = self.rest.is_some() || !self.keys.is_empty();
+        if args.len() < required || (!variadic && args.len() > required + self.optional.len()) {
+            return Err(LispError::ArityMismatch {
+                name: self.display_name(),
+                min: required,
+                max: (!variadic).then_some(required + self.optional.len()),
+                found: args.len(),
+            });
+        }
+        let positional = positional.min(required + self.optional.len());
+        let scope = Scope::child(&self.env);
+        let mut args = args.into_iter();
+        for (param, arg) in self.params.iter().zip(&mut args) {
+            scope.insert(*param, arg);
+        }
+        let mut given = args.by_ref().take(positional - required);
+        for (param, default) in &self.optional {
+            let vl = match given.next() {
+                Some(arg) => arg,
+                None => env.with_scope(scope.clone(), || env.eval(default))?,
+            };
+            scope.insert(*param, vl);
+        }
+        if let Some(rest) = self.rest {
+            scope.insert(rest, LispExp::from(args.collect::<Vec<_>>()));
+            return Ok(scope);
+        }
+        let mut passed = vec![false; self.keys.len()];
+        while let Some(key) = args.next() {
+            let found = match &key {
+                LispExp::Symbol(key) => self.keys.iter().position(|(_, keyword, _)| keyword == key),
+                _ => None,
+            };
+            let Some(at) = found else {
+                return Err(LispError::Other(format!("`{}` got an unexpected argument {key}", self.display_name())));
+            };
+            let vl = args
+                .next()
+                .ok_or_else(|| LispError::Other(format!("keyword argument {key} is missing its value")))?;
+            scope.insert(self.keys[at].0, vl);
+            passed[at] = true;
+        }
+        for ((param, _, default), passed) in self.keys.iter().zip(passed) {
+            if !passed {
+                let vl = env.with_scope(scope.clone(), || env.eval(default))?;
+                scope.insert(*param, vl);
+            }
+        }
+        Ok(scope)
+    }
+}
+
+impl Display for LispLambda {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let body: Vec<String> = self.body.iter().map(LispExp::to_string).collect();
+        let params: Vec<String> = self.param_items().iter().map(LispExp::to_string).collect();
+        write!(f, "( {} ) {}", params.join(" "), body.join(" "))
+    }
+}
+
+impl From<f64> for LispExp {
+    fn from(value: f64) -> LispExp {
+        LispExp::Number(value)
+    }
+}
+impl From<i64> for LispExp {
+    fn from(value: i64) -> LispExp {
+        LispExp::Int(value)
+    }
+}
+impl From<bool> for LispExp {
+    fn from(value: bool) -> LispExp {
+        LispExp::Bool(value)
+    }
+}
+impl From<&str> for LispExp {
+    fn from(value: &str) -> LispExp {
+        LispExp::Str(value.to_owned())
+    }
+}
+impl From<String> for LispExp {
+    fn from(value: String) -> LispExp {
+        LispExp::Str(value)
+    }
+}
+impl From<Vec<LispExp>> for LispExp {
+    fn from(value: Vec<LispExp>) -> LispExp {
+        LispExp::List(value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn equality_and_identity() {
+        assert_eq!(run("(list (equal? '(1 2) '(1 2)) (eq? '(1 2) '(1 2)) (eq? '() '()) (eq? 'a 'a))"), "( true false true true )");
+        assert_eq!(run("(let ((v [1])) (list (eq? v v) (eq? v [1]) (equal? v [1])))"), "( true false true )");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(run("(list \"a\\nb\" #\\a 1.5 nil true (cons 1 2) [1] {:a 1} 'sym)"), "( \"a\\nb\" #\\a 1.5 nil true ( 1 . 2 ) [ 1 ] { :a 1 } sym )");
+        assert_eq!(run("(defn f (a &optional (b 1) &rest c) a)"), "( lambda ( a &optional ( b 1 ) &rest c ) a )");
+    }
+
+    #[test]
+    fn truthiness() {
+        assert_eq!(run("(list (if nil 1 2) (if false 1 2) (if 0 1 2) (if 0.0 1 2) (if '() 1 2) (if \"\" 1 2) (if '(0) 1 2))"), "( 2 2 2 2 2 1 1 )");
+    }
+}
@@ -0,0 +1,340 @@
+// `sxprs expand`: the program as it runs, with every macro call replaced by
+// what the macro gives, and optionally with constants folded like `--opt`
+// does. Macros are only expanded when a call runs, so the expander defines
+// the program's macros and functions first, without running anything else,
+// then applies each macro to its call as written. Each top-level form is
+// printed after a comment with where it was in the source and which macros
+// it expanded
+use crate::error::LispError;
+use crate::eval::Interpreter;
+use crate::exp::LispExp;
+use crate::fmt::format_forms;
+use crate::lexer::Pos;
+use crate::opt;
+use crate::span;
+use crate::symbol::SymbolId;
+
+// how many times a call may expand to another macro call before it's taken
+// to be a macro that never stops
+const MAX_EXPANSIONS: usize = 1000;
+
+// the top-level forms that are run before the ones after them are expanded,
+// so the macros and the functions macros call are there
+fn defines(form: &LispExp) -> bool {
+    let LispExp::List(items) = form else {
+        return false;
+    };
+    matches!(items.first(), Some(LispExp::Symbol(head)) if matches!(&*head.name(), "defmacro" | "defn" | "import"))
+}
+
+struct Expander<'a> {
+    lisp: &'a Interpreter,
+    // the names bound where the form being expanded is, which hide the
+    // macros of the same name
+    locals: Vec<SymbolId>,
+    // the macros expanded, and where their call was when the parser read it
+    expanded: Vec<(SymbolId, Option<Pos>)>,
+}
+
+impl Expander<'_> {
+    fn all(&mut self, items: &[LispExp]) -> Result<Vec<LispExp>, LispError> {
+        items.iter().map(|item| self.expand(item)).collect()
+    }
+    // expands `items` with `names` bound
+    fn scoped(&mut self, names: Vec<SymbolId>, items: &[LispExp]) -> Result<Vec<LispExp>, LispError> {
+        let before = self.locals.len();
+        self.locals.extend(names);
+        let res = self.all(items);
+        self.locals.truncate(before);
+        res
+    }
+    fn macro_named(&self, name: SymbolId) -> Option<LispExp> {
+        if self.locals.contains(&name) {
+            return None;
+        }
+        match self.lisp.resolve(name) {
+            Ok(LispExp::Macro(mac)) => Some(LispExp::Lambda(mac)),
+            _ => None,
+        }
+    }
+    // rebuilt lists keep the span of the one they were rebuilt from
+    fn expand(&mut self, form: &LispExp) -> Result<LispExp, LispError> {
+        let expanded = self.expand_form(form)?;
+        span::copy(form, &expanded);
+        Ok(expanded)
+    }
+    fn expand_form(&mut self, form: &LispExp) -> Result<LispExp, LispError> {
+        if let Some(items) = form.literal_items() {
+            return form.with_literal_items(self.all(&items)?);
+        }
+        let LispExp::List(items) = form else {
+            return Ok(form.clone());
+        };
+        let Some((LispExp::Symbol(head), args)) = items.split_first() else {
+            return Ok(LispExp::from(self.all(items)?));
+        };
+        let head = *head;
+        if let Some(mac) = self.macro_named(head) {
+            return self.call(form, mac);
+        }
+        let rebuilt = |args: Vec<LispExp>| LispExp::from([vec![LispExp::Symbol(head)], args].concat());
+        if self.locals.contains(&head) {
+            return Ok(rebuilt(self.all(args)?));
+        }
+        match (&*head.name(), args) {
+            ("quasiquote", [template]) => Ok(rebuilt(vec![self.template(template, 1)?])),
+            // quote, and the forms that show their arguments as they were written
+            _ if self.lisp.is_unevaluated(head) => Ok(form.clone()),
+            // a macro's body runs when it's called, on code that isn't known yet
+            ("defmacro", _) => Ok(form.clone()),
+            ("lambda", [params, body @ ..]) => {
+                let (params, names) = self.params(params)?;
+                Ok(rebuilt([vec![params], self.scoped(names, body)?].concat()))
+            }
+            ("defn", [name, params, body @ ..]) => {
+                let (params, names) = self.params(params)?;
+                Ok(rebuilt([vec![name.clone(), params], self.scoped(names, body)?].concat()))
+            }
+            ("define" | "set!", [name, rest @ ..]) => Ok(rebuilt([vec![name.clone()], self.all(rest)?].concat())),
+            ("let", [LispExp::List(bindings), body @ ..]) => {
+                let mut names = vec![];
+                let mut pairs = vec![];
+                for binding in bindings.iter() {
+                    match binding {
+                        LispExp::List(pair) if matches!(&pair[..], [LispExp::Symbol(_), _]) => {
+                            let vl = self.scoped(names.clone(), &pair[1..])?.remove(0);
+                            names.push(pair[0].get_symbol()?);
+                            pairs.push(LispExp::from(vec![pair[0].clone(), vl]));
+                        }
+                        _ => pairs.push(binding.clone()),
+                    }
+                }
+                Ok(rebuilt([vec![LispExp::from(pairs)], self.scoped(names, body)?].concat()))
+            }
+            ("for", [LispExp::Symbol(name), keyword, lst, body @ ..]) => {
+                let lst = self.expand(lst)?;
+                Ok(rebuilt([vec![LispExp::Symbol(*name), keyword.clone(), lst], self.scoped(vec![*name], body)?].concat()))
+            }
+            ("dotimes", [LispExp::List(header), body @ ..]) => match &header[..] {
+                [LispExp::Symbol(name), count] => {
+                    let header = LispExp::from(vec![LispExp::Symbol(*name), self.expand(count)?]);
+                    Ok(rebuilt([vec![header], self.scoped(vec![*name], body)?].concat()))
+                }
+                _ => Ok(rebuilt([vec![LispExp::List(header.clone())], self.all(body)?].concat())),
+            },
+            // patterns aren't evaluated, and bind the names in them
+            ("match", [exp, clauses @ ..]) => {
+                let mut expanded = vec![self.expand(exp)?];
+                for clause in clauses {
+                    match clause {
+                        LispExp::List(clause) if !clause.is_empty() => {
+                            let mut names = vec![];
+                            symbols(&clause[0], &mut names);
+                            let body = self.scoped(names, &clause[1..])?;
+                            expanded.push(LispExp::from([vec![clause[0].clone()], body].concat()));
+                        }
+                        _ => expanded.push(clause.clone()),
+                    }
+                }
+                Ok(rebuilt(expanded))
+            }
+            ("try", [body @ .., LispExp::List(catch)]) if matches!(&catch[..], [LispExp::Symbol(word), LispExp::Symbol(_), ..] if &*word.name() == "catch") => {
+                let name = catch[1].get_symbol()?;
+                let handler = LispExp::from([vec![catch[0].clone(), catch[1].clone()], self.scoped(vec![name], &catch[2..])?].concat());
+                span::copy(&LispExp::List(catch.clone()), &handler);
+                Ok(rebuilt([self.all(body)?, vec![handler]].concat()))
+            }
+            _ => Ok(rebuilt(self.all(args)?)),
+        }
+    }
+    // a lambda's parameters, with their defaults expanded, and the names they bind
+    fn params(&mut self, params: &LispExp) -> Result<(LispExp, Vec<SymbolId>), LispError> {
+        let LispExp::List(items) = params else {
+            let mut names = vec![];
+            symbols(params, &mut names);
+            return Ok((params.clone(), names));
+        };
+        let mut names = vec![];
+        let mut expanded = vec![];
+        for param in items.iter() {
+            match param {
+                LispExp::List(pair) if matches!(&pair[..], [LispExp::Symbol(_), _]) => {
+                    let default = self.scoped(names.clone(), &pair[1..])?.remove(0);
+                    names.push(pair[0].get_symbol()?);
+                    expanded.push(LispExp::from(vec![pair[0].clone(), default]));
+                }
+                other => {
+                    symbols(other, &mut names);
+                    expanded.push(other.clone());
+                }
+            }
+        }
+        Ok((LispExp::from(expanded), names))
+    }
+    // a quasiquote's template: only what's unquoted at the outermost level runs
+    fn template(&mut self, template: &LispExp, depth: usize) -> Result<LispExp, LispError> {
+        let LispExp::List(items) = template else {
+            return Ok(template.clone());
+        };
+        let depth = match items.first() {
+            Some(LispExp::Symbol(head)) if matches!(&*head.name(), "unquote" | "unquote-splicing") && items.len() == 2 => {
+                if depth == 1 {
+                    let expanded = LispExp::from(vec![items[0].clone(), self.expand(&items[1])?]);
+                    span::copy(template, &expanded);
+                    return Ok(expanded);
+                }
+                depth - 1
+            }
+            Some(LispExp::Symbol(head)) if &*head.name() == "quasiquote" => depth + 1,
+            _ => depth,
+        };
+        let expanded = LispExp::from(items.iter().map(|item| self.template(item, depth)).collect::<Result<Vec<_>, _>>()?);
+        span::copy(template, &expanded);
+        Ok(expanded)
+    }
+    // applies the macro to the call's arguments as written, then expands
+    // what it gave, which may be another call to a macro
+    fn call(&mut self, form: &LispExp, mac: LispExp) -> Result<LispExp, LispError> {
+        let pos = match form {
+            LispExp::List(items) => span::span(items),
+            _ => None,
+        };
+        let mut form = form.clone();
+        let mut mac = Some(mac);
+        for _ in 0..MAX_EXPANSIONS {
+            let Some(current) = mac.take() else {
+                return self.expand(&form);
+            };
+            let LispExp::List(items) = &form else {
+                unreachable!("only a list calls a macro");
+            };
+            let name = items[0].get_symbol()?;
+            self.expanded.push((name, span::span(items)));
+            form = self.lisp.apply(&current, items[1..].to_vec()).map_err(|err| {
+                let message = format!("expanding {name}: {}", err.summary());
+                match pos {
+                    Some(pos) => LispError::at(pos, message),
+                    None => LispError::Other(message),
+                }
+            })?;
+            if let LispExp::List(items) = &form {
+                if let Some(LispExp::Symbol(head)) = items.first() {
+                    mac = self.macro_named(*head);
+                }
+            }
+        }
+        let message = format!("expanding {}: it still calls a macro after {MAX_EXPANSIONS} expansions", self.expanded[0].0);
+        Err(match pos {
+            Some(pos) => LispError::at(pos, message),
+            None => LispError::Other(message),
+        })
+    }
+}
+
+// the symbols in a pattern or parameter list, which it binds
+fn symbols(exp: &LispExp, names: &mut Vec<SymbolId>) {
+    match exp {
+        LispExp::Symbol(name) => names.push(*name),
+        LispExp::List(items) => items.iter().for_each(|item| symbols(item, names)),
+        _ => {}
+    }
+}
+
+// `source` with its macros expanded, and its constants folded when `fold`
+// is set, each top-level form after a comment saying where it's from in
+// `file` and what it expanded
+pub fn expand_program(lisp: &Interpreter, source: &str, file: &str, fold: bool) -> Result<String, LispError> {
+    let mut out = String::new();
+    for form in lisp.parse(source)? {
+        let mut expander = Expander { lisp, locals: vec![], expanded: vec![] };
+        let mut expanded = expander.expand(&form)?;
+        if fold {
+            expanded = opt::fold(lisp, &expanded);
+        }
+        if defines(&form) {
+            lisp.run(&form)?;
+        }
+        let pos = match &form {
+            LispExp::List(items) => span::span(items),
+            _ => None,
+        };
+        let mut comment = match pos {
+            Some(pos) => format!("; {file}:{pos}"),
+            None => format!("; {file}"),
+        };
+        let macros: Vec<String> = expander
+            .expanded
+            .iter()
+            .map(|(name, at)| match at {
+                Some(at) => format!("{name} at {at}"),
+                None => name.to_string(),
+            })
+            .collect();
+        if !macros.is_empty() {
+            comment.push_str(&format!(", expands {}", macros.join(", ")));
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&comment);
+        out.push('\n');
+        out.push_str(&format_forms(&[expanded]));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(source: &str) -> String {
+        expand_program(&Interpreter::new(), source, "f.lsp", false).unwrap()
+    }
+
+    #[test]
+    fn expands_macro_calls() {
+        let source = "(defmacro unless (test &rest body) `(if ~test nil (begin ~@body)))\n(unless (> 1 2) (print 1) (print 2))\n";
+        assert_eq!(
+            expand(source),
+            "; f.lsp:1:1\n(defmacro unless (test &rest body) `(if ~test nil (begin ~@body)))\n\n; f.lsp:2:1, expands unless at 2:1\n(if (> 1 2) nil (begin (print 1) (print 2)))\n"
+        );
+    }
+
+    #[test]
+    fn expands_what_a_macro_gives_and_its_arguments() {
+        let source = "(defmacro my-when (test &rest body) `(if ~test (begin ~@body) nil))\n(defmacro my-unless (test &rest body) `(my-when (not ~test) ~@body))\n(defn f (x) (my-unless x (my-when x 1)))";
+        let expanded = expand(source);
+        assert!(expanded.contains("; f.lsp:3:1, expands my-unless at 3:13, my-when, my-when at 3:26\n(defn f (x) (if (not x) (begin (if x (begin 1) nil)) nil))\n"), "{expanded}");
+    }
+
+    #[test]
+    fn leaves_what_does_not_run() {
+        let source = "(defmacro m () 1)\n(list '(m) `(m ~(m)) (let ((m (lambda () 2))) (m)) (lambda (m) (m)))";
+        let expanded = expand(source);
+        assert!(expanded.ends_with("(list '(m) `(m ~1) (let ((m (lambda () 2))) (m)) (lambda (m) (m)))\n"), "{expanded}");
+    }
+
+    #[test]
+    fn runs_only_definitions() {
+        let lisp = Interpreter::new();
+        let expanded = expand_program(&lisp, "(define x 1) (defn twice (v) (* 2 v)) (set! x 2) (print x)", "f.lsp", false).unwrap();
+        assert!(expanded.contains("(print x)"));
+        assert!(lisp.eval_str("x").is_err());
+        assert_eq!(lisp.eval_str("(twice 3)").unwrap().to_string(), "6");
+    }
+
+    #[test]
+    fn folds_constants() {
+        let source = "(defmacro square (x) `(* ~x ~x))\n(print (square (+ 1 2)))";
+        let expanded = expand_program(&Interpreter::new(), source, "f.lsp", true).unwrap();
+        assert!(expanded.ends_with("(print 9)\n"), "{expanded}");
+    }
+
+    #[test]
+    fn macro_errors_point_at_the_call() {
+        let err = expand_program(&Interpreter::new(), "(defmacro bad (x) (error \"no\"))\n\n  (bad 1)", "f.lsp", false).unwrap_err();
+        assert_eq!(err.message(), "3:3: expanding bad: no");
+        let err = expand_program(&Interpreter::new(), "(defmacro forever () '(forever))\n(forever)", "f.lsp", false).unwrap_err();
+        assert!(err.message().ends_with("still calls a macro after 1000 expansions"), "{}", err.message());
+    }
+}
@@ -0,0 +1,249 @@
+// stable numbers for every kind of diagnostic, and `sxprs explain`, which
+// says more about one than its message does. The numbers are grouped: E00xx
+// for reading the source, E01xx for values and calls that don't work, E02xx
+// for what the program raised itself, E03xx for what stops a program from
+// outside it and E04xx for what `sxprs check --types` finds. A number is
+// never reused for another kind, so it can be searched for
+use crate::error::LispError;
+
+pub struct Explanation {
+    // like `E0101`
+    pub id: &'static str,
+    // the name `LispError::code` and the JSON diagnostics give
+    pub code: &'static str,
+    pub title: &'static str,
+    // what it means and how it's usually fixed, with an example
+    pub text: &'static str,
+}
+
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        id: "E0001",
+        code: "unexpected-token",
+        title: "a token where it can't be",
+        text: "The source has a closing bracket that doesn't close anything.
+
+    (+ 1 2))        ; the second `)` has no `(`
+
+Remove the extra bracket, or add the one it was meant to close.",
+    },
+    Explanation {
+        id: "E0002",
+        code: "unclosed-list",
+        title: "a list that's never closed",
+        text: "The source ends while a `(` is still open. The position is where the list starts.
+
+    (defn f (x)
+      (* x 2)
+
+Add the missing `)`; `sxprs fmt` indents the file so where a list ends is easy to see.",
+    },
+    Explanation {
+        id: "E0003",
+        code: "incomplete",
+        title: "the source ends too soon",
+        text: "The source ends inside a string, a block comment, a vector or map, or right after a quote,
+so more of it could still make sense of what was read.
+
+    (print \"hello)
+    '
+
+Close the string or comment, or give the quote something to quote.",
+    },
+    Explanation {
+        id: "E0004",
+        code: "syntax",
+        title: "source that can't be read",
+        text: "Anything else the reader can't make sense of: a bad escape in a string, a bracket that closes a different
+kind of bracket than the one that's open, a `.` that isn't in a pair, or lists nested deeper than the reader allows.
+
+    \"\\q\"            ; there's no \\q escape
+    [1 2)           ; a vector is closed with `]`
+    '(a . b . c)    ; a pair has one `.`
+
+The message says what was wrong at the position.",
+    },
+    Explanation {
+        id: "E0101",
+        code: "type-mismatch",
+        title: "a value of the wrong type",
+        text: "A function was given a value it can't work with, like a number where it takes a list.
+
+    (car 1)         ; 1 is not a List, it's a Int
+    (+ 1 \"2\")
+
+Convert the value first, like with `string->number`, or check what the variable holds with `type-of`.",
+    },
+    Explanation {
+        id: "E0102",
+        code: "arity-mismatch",
+        title: "the wrong number of arguments",
+        text: "A function was called with more or fewer arguments than it takes.
+
+    (defn area (w h) (* w h))
+    (area 3)        ; `area` expects 2 arguments, got 1
+
+`(help name)` shows how a function is called. Parameters after `&optional` can be left out,
+like `(defn f (x &optional (y 1)) ...)`, and `&rest` takes any number of arguments.",
+    },
+    Explanation {
+        id: "E0103",
+        code: "unknown-symbol",
+        title: "a name that isn't defined",
+        text: "A variable or function is used where no definition of it can be seen: it's misspelled, defined after
+it's used, local to another function, or in a module that isn't imported.
+
+    (defn f () (prnt 1))
+    (f)             ; symbol prnt is not defined
+
+Names from a module are `module/name`, or plain when listed in `(import module name)`. `sxprs check --types`
+finds calls to undefined names without running the program.",
+    },
+    Explanation {
+        id: "E0104",
+        code: "index-out-of-range",
+        title: "an index past the end",
+        text: "A list, vector or string was indexed at a position it doesn't have. Indexes start at 0.
+
+    (nth '(a b c) 3)
+
+Check the index against `(length xs)` first.",
+    },
+    Explanation {
+        id: "E0105",
+        code: "division-by-zero",
+        title: "division by zero",
+        text: "An integer or ratio was divided by zero, with `/` or `mod`. Floats give infinity or NaN instead.
+
+    (/ 1 0)
+
+Check the divisor first, or catch the error with `try`.",
+    },
+    Explanation {
+        id: "E0106",
+        code: "too-deep",
+        title: "evaluation nested too deep",
+        text: "Calls nested deeper than the interpreter allows, usually from recursion that never stops
+or that isn't in tail position.
+
+    (defn count (n) (+ 1 (count (- n 1))))
+
+Give the recursion a case where it stops, or move the recursive call to tail position with an accumulator;
+tail calls don't nest.",
+    },
+    Explanation {
+        id: "E0201",
+        code: "raised",
+        title: "an error the program raised",
+        text: "The program called `(error value)` and nothing caught it; the message is the value.
+
+    (error \"config not found\")
+
+Catch it with `(try ... (catch err ...))` where it can be handled.",
+    },
+    Explanation {
+        id: "E0202",
+        code: "assertion-failed",
+        title: "an assertion that didn't hold",
+        text: "An `assert` or `assert-eq` found its condition false; the message has the form as written and the values.
+
+    (assert-eq (+ 1 1) 3)
+
+Either the code or the assertion is wrong; `sxprs test` runs a file's `deftest`s.",
+    },
+    Explanation {
+        id: "E0203",
+        code: "other",
+        title: "a builtin that failed",
+        text: "A builtin couldn't do what it was asked, for a reason the message says, like a file that can't be read
+or a command that can't be run.
+
+    (read-file \"missing.txt\")
+
+These can be caught with `try`.",
+    },
+    Explanation {
+        id: "E0301",
+        code: "limit-exceeded",
+        title: "a limit the host set ran out",
+        text: "The program took more steps or memory than the program embedding the interpreter allowed it,
+with `Interpreter::with_limits`. `try` doesn't catch it.
+
+Make the program do less, or ask for a bigger limit.",
+    },
+    Explanation {
+        id: "E0302",
+        code: "interrupted",
+        title: "stopped from outside",
+        text: "The program was stopped while it ran, like with Ctrl-C in the REPL. `try` doesn't catch it.",
+    },
+    Explanation {
+        id: "E0303",
+        code: "exit",
+        title: "the program exited",
+        text: "The program called `(exit code)`. It's only reported when a host runs the program and gets it as an error;
+`sxprs` exits with the code.",
+    },
+    Explanation {
+        id: "E0401",
+        code: "check",
+        title: "a call that can't work",
+        text: "`sxprs check --types` found a call that would fail when it runs, without running the program:
+a name that isn't defined, a builtin given the wrong number of arguments, a number builtin given
+something that isn't a number, or a value called as a function.
+
+    (defn f (x) (+ x \"1\"))
+    (car)
+
+Fix the call; the message says which.",
+    },
+];
+
+// the explanation for an id like `E0101`, in any case, or for a code like `type-mismatch`
+pub fn explain(name: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS.iter().find(|explanation| explanation.id.eq_ignore_ascii_case(name) || explanation.code == name)
+}
+
+// the id for the code `LispError::code` or a diagnostic gives
+pub fn id(code: &str) -> Option<&'static str> {
+    EXPLANATIONS.iter().find(|explanation| explanation.code == code).map(|explanation| explanation.id)
+}
+
+impl LispError {
+    // the stable number of the kind of error, like `E0101`
+    pub fn id(&self) -> &'static str {
+        id(self.code()).expect("every code is explained")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    #[test]
+    fn every_error_has_an_id() {
+        let lisp = Interpreter::new();
+        for (source, id) in [("(car 1)", "E0101"), ("(car", "E0002"), (")", "E0001"), ("(nope)", "E0103"), ("(/ 1 0)", "E0105"), ("(error 1)", "E0201"), ("(exit 2)", "E0303")] {
+            assert_eq!(lisp.eval_str(source).unwrap_err().id(), id, "{source}");
+        }
+        assert_eq!(LispError::Interrupted.id(), "E0302");
+        assert_eq!(LispError::TooDeep(1).id(), "E0106");
+    }
+
+    #[test]
+    fn ids_are_unique_and_ordered() {
+        for pair in EXPLANATIONS.windows(2) {
+            assert!(pair[0].id < pair[1].id, "{} before {}", pair[0].id, pair[1].id);
+            assert!(EXPLANATIONS.iter().filter(|explanation| explanation.code == pair[0].code).count() == 1);
+        }
+        assert!(EXPLANATIONS.iter().all(|explanation| explanation.id.len() == 5 && explanation.id.starts_with('E')));
+    }
+
+    #[test]
+    fn finds_by_id_or_code() {
+        assert_eq!(explain("e0102").map(|explanation| explanation.code), Some("arity-mismatch"));
+        assert_eq!(explain("check").map(|explanation| explanation.id), Some("E0401"));
+        assert!(explain("E9999").is_none());
+    }
+}
@@ -0,0 +1,183 @@
+// the C API, with the `ffi` feature, declared in include/sxprs.h: an
+// interpreter behind an opaque handle, programs evaluated from C strings, and
+// C functions callable from lisp. Values cross as their printed form, which
+// reads back as the same value for data: numbers, strings, symbols, lists,
+// vectors and maps. Calls return 0, or the number of the kind of error, the
+// one in its `E0101`-like id (see `sxprs explain`), or a negative number for
+// arguments that can't be used, like a null handle or a string that isn't
+// UTF-8. Strings the library gives out are freed with `sxprs_string_free`
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+
+use crate::error::LispError;
+use crate::eval::Interpreter;
+
+// a null pointer or a string that isn't UTF-8 where one was needed
+pub const SXPRS_INVALID_ARGUMENT: c_int = -1;
+
+// a C function lisp can call: it gets `data` as it was registered and the
+// arguments' printed forms, and returns the printed form of its result, which
+// has to stay valid until it's called again, or null to fail the call
+pub type SxprsFn = extern "C" fn(data: *mut c_void, argc: usize, argv: *const *const c_char) -> *const c_char;
+
+// the status for `err`: the number in its id, E0101 being 101
+fn status(err: &LispError) -> c_int {
+    err.id()[1..].parse().expect("ids are E and a number")
+}
+
+// hands `text` to C through `out`, when it isn't null
+unsafe fn give(out: *mut *mut c_char, text: String) {
+    if !out.is_null() {
+        // a NUL inside a string can't go through C
+        let text = CString::new(text.replace('\0', "\\0")).expect("no NULs left");
+        *out = text.into_raw();
+    }
+}
+
+unsafe fn text<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        return None;
+    }
+    CStr::from_ptr(ptr).to_str().ok()
+}
+
+// a new interpreter, with the prelude; freed with `sxprs_free`
+#[no_mangle]
+pub extern "C" fn sxprs_new() -> *mut Interpreter {
+    Box::into_raw(Box::new(Interpreter::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sxprs_free(lisp: *mut Interpreter) {
+    if !lisp.is_null() {
+        drop(Box::from_raw(lisp));
+    }
+}
+
+// evaluates the program `source`; `*result`, unless `result` is null, is
+// the printed value of its last form, or the error's message when it fails
+#[no_mangle]
+pub unsafe extern "C" fn sxprs_eval(lisp: *mut Interpreter, source: *const c_char, result: *mut *mut c_char) -> c_int {
+    let (Some(lisp), Some(source)) = (lisp.as_ref(), text(source)) else {
+        return SXPRS_INVALID_ARGUMENT;
+    };
+    match lisp.eval_str(source) {
+        Ok(value) => {
+            give(result, value.to_string());
+            0
+        }
+        Err(err) => {
+            give(result, err.message());
+            status(&err)
+        }
+    }
+}
+
+// makes `f` callable from lisp as `name`, replacing any function of that
+// name; its result is read back as a value, not evaluated
+#[no_mangle]
+pub unsafe extern "C" fn sxprs_register_fn(lisp: *mut Interpreter, name: *const c_char, f: SxprsFn, data: *mut c_void) -> c_int {
+    let (Some(lisp), Some(name)) = (lisp.as_mut(), text(name)) else {
+        return SXPRS_INVALID_ARGUMENT;
+    };
+    let shown = name.to_owned();
+    lisp.register_fn(name, move |lisp, args| {
+        let args = args.iter().map(|arg| CString::new(arg.to_string().replace('\0', "\\0")).expect("no NULs left")).collect::<Vec<_>>();
+        let argv = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+        let res = f(data, argv.len(), argv.as_ptr());
+        let res = text(res).ok_or_else(|| LispError::Other(format!("{shown} failed")))?;
+        match &lisp.parse(res)?[..] {
+            [value] => Ok(value.clone()),
+            _ => Err(LispError::Other(format!("{shown} should return one value, not {res:?}"))),
+        }
+    });
+    0
+}
+
+// frees a string the library gave out
+#[no_mangle]
+pub unsafe extern "C" fn sxprs_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::{null, null_mut};
+
+    unsafe fn eval(lisp: *mut Interpreter, source: &str) -> (c_int, String) {
+        let source = CString::new(source).unwrap();
+        let mut result = null_mut();
+        let status = sxprs_eval(lisp, source.as_ptr(), &mut result);
+        let text = CStr::from_ptr(result).to_str().unwrap().to_owned();
+        sxprs_string_free(result);
+        (status, text)
+    }
+
+    extern "C" fn sum(data: *mut c_void, argc: usize, argv: *const *const c_char) -> *const c_char {
+        let out = unsafe { &mut *(data as *mut CString) };
+        let args = unsafe { std::slice::from_raw_parts(argv, argc) };
+        let total: i64 = args.iter().map(|arg| unsafe { CStr::from_ptr(*arg) }.to_str().unwrap().parse::<i64>().unwrap_or(0)).sum();
+        *out = CString::new(format!("( sum {total} )")).unwrap();
+        out.as_ptr()
+    }
+
+    extern "C" fn broken(_data: *mut c_void, _argc: usize, _argv: *const *const c_char) -> *const c_char {
+        null()
+    }
+
+    #[test]
+    fn evaluates_and_maps_errors() {
+        unsafe {
+            let lisp = sxprs_new();
+            assert_eq!(eval(lisp, "(define x 2) (list x \"two\")"), (0, "( 2 \"two\" )".to_owned()));
+            assert_eq!(eval(lisp, "(car 1)"), (101, "1 is not a List, it's a Int\n  at 1:1\n  in `car`\n  called from top-level".to_owned()));
+            assert_eq!(eval(lisp, "(car").0, 2);
+            assert_eq!(eval(lisp, "(exit 4)").0, 303);
+            assert_eq!(sxprs_eval(lisp, null(), null_mut()), SXPRS_INVALID_ARGUMENT);
+            assert_eq!(sxprs_eval(null_mut(), c"1".as_ptr(), null_mut()), SXPRS_INVALID_ARGUMENT);
+            sxprs_free(lisp);
+        }
+    }
+
+    #[test]
+    fn calls_c_functions() {
+        unsafe {
+            let lisp = sxprs_new();
+            let mut out = CString::default();
+            assert_eq!(sxprs_register_fn(lisp, c"sum".as_ptr(), sum, &mut out as *mut CString as *mut c_void), 0);
+            assert_eq!(sxprs_register_fn(lisp, c"broken".as_ptr(), broken, null_mut()), 0);
+            // the result is data, not a call
+            assert_eq!(eval(lisp, "(sum 1 2 (+ 3 4))"), (0, "( sum 10 )".to_owned()));
+            assert_eq!(eval(lisp, "(broken)"), (203, "broken failed\n  at 1:1\n  in `broken`\n  called from top-level".to_owned()));
+            sxprs_free(lisp);
+        }
+    }
+
+    // the names of the functions declared or defined on the lines of `source` `is_signature` picks
+    fn functions(source: &str, is_signature: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut names: Vec<String> = source
+            .lines()
+            .filter(|line| is_signature(line))
+            .filter_map(|line| {
+                let name: String = line[line.find("sxprs_")?..].chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+                line.contains(&format!("{name}(")).then_some(name)
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn the_header_declares_every_function() {
+        let header = include_str!("../include/sxprs.h");
+        let declared = functions(header, |line| !line.starts_with([' ', '/', '#', 't']));
+        let defined = functions(include_str!("ffi.rs"), |line| line.starts_with("pub ") && line.contains("extern \"C\" fn "));
+        assert_eq!(defined, ["sxprs_eval", "sxprs_free", "sxprs_new", "sxprs_register_fn", "sxprs_string_free"]);
+        assert_eq!(declared, defined);
+        assert!(header.contains(&format!("#define SXPRS_INVALID_ARGUMENT ({SXPRS_INVALID_ARGUMENT})")));
+    }
+}
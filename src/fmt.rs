@@ -0,0 +1,370 @@
+use crate::error::LispError;
+use crate::exp::LispExp;
+use crate::lexer::{lex, Pos, SpannedToken, Token};
+use crate::parser::parse_program;
+
+// lines longer than this are broken up
+const WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+// how many arguments stay on the line of the form's head when it's broken up
+fn header_args(head: &str) -> usize {
+    match head {
+        "for" => 3,
+        "defn" | "defmacro" => 2,
+        "define" | "set!" | "lambda" | "let" | "if" | "while" | "try" | "match" | "dotimes" | "deftest" => 1,
+        _ => 0,
+    }
+}
+
+// the reader prefix `(form x)` was written with
+fn prefix(form: &str) -> Option<&'static str> {
+    match form {
+        "quote" => Some("'"),
+        "quasiquote" => Some("`"),
+        "unquote" => Some("~"),
+        "unquote-splicing" => Some("~@"),
+        _ => None,
+    }
+}
+
+// only the escapes the lexer understands, so the output always reads back the same
+fn escape(string: &str) -> String {
+    let mut ret = String::from("\"");
+    for chr in string.chars() {
+        match chr {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            other => ret.push(other),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn atom(exp: &LispExp) -> String {
+    match exp {
+        LispExp::Str(string) => escape(string),
+        // `{:?}` keeps the `.0`, so floats don't read back as ints
+        LispExp::Number(num) => format!("{num:?}"),
+        other => other.to_string(),
+    }
+}
+
+// what gets laid out: the source's own text for atoms and comments, so `0xFF`
+// and `#t` stay as they were written
+enum Node {
+    Text(String),
+    // `trailing` when it's on the line of the code before it
+    Comment { text: String, trailing: bool },
+    // an empty line between two items, kept when they go on lines of their own
+    Blank,
+    // a reader prefix, and the form it's on
+    Prefix(String, Box<Node>),
+    List { open: &'static str, close: &'static str, items: Vec<Node> },
+}
+
+impl Node {
+    fn list(open: &'static str, close: &'static str, items: &[LispExp]) -> Node {
+        Node::List { open, close, items: items.iter().map(Node::from).collect() }
+    }
+    fn has_comment(&self) -> bool {
+        match self {
+            Node::Comment { .. } => true,
+            Node::Prefix(_, form) => form.has_comment(),
+            Node::List { items, .. } => items.iter().any(Node::has_comment),
+            Node::Text(_) | Node::Blank => false,
+        }
+    }
+    // the node on a single line, which has to have no comments
+    fn flat(&self) -> String {
+        match self {
+            Node::Text(text) | Node::Comment { text, .. } => text.clone(),
+            Node::Blank => String::new(),
+            Node::Prefix(prefix, form) => format!("{prefix}{}", form.flat()),
+            Node::List { open, close, items } => {
+                let items = items.iter().filter(|item| !matches!(item, Node::Blank)).map(Node::flat);
+                format!("{open}{}{close}", items.collect::<Vec<_>>().join(" "))
+            }
+        }
+    }
+}
+
+impl From<&LispExp> for Node {
+    fn from(exp: &LispExp) -> Node {
+        if let LispExp::Pair(pair) = exp {
+            let (items, tail) = LispExp::pair_items(pair);
+            let mut items: Vec<Node> = items.iter().map(Node::from).collect();
+            items.extend([Node::Text(".".to_owned()), Node::from(&tail)]);
+            return Node::List { open: "(", close: ")", items };
+        }
+        match exp {
+            LispExp::Vector(_) => return Node::list("[", "]", &exp.literal_items().unwrap_or_default()),
+            LispExp::Map(_) => return Node::list("{", "}", &exp.literal_items().unwrap_or_default()),
+            _ => {}
+        }
+        let LispExp::List(items) = exp else {
+            return Node::Text(atom(exp));
+        };
+        match &items[..] {
+            [LispExp::Symbol(form), arg] if prefix(&form.name()).is_some() => {
+                Node::Prefix(prefix(&form.name()).unwrap_or_default().to_owned(), Box::new(Node::from(arg)))
+            }
+            _ => Node::list("(", ")", items),
+        }
+    }
+}
+
+// a token or comment, with the text it was written as
+pub(crate) struct Piece<'a> {
+    // `None` for a comment
+    pub(crate) token: Option<&'a Token>,
+    pub(crate) text: &'a str,
+    // the byte offset it starts at
+    pub(crate) start: usize,
+    line: usize,
+    // the line it ends on, later than `line` for multi-line strings and comments
+    end_line: usize,
+}
+
+// the byte offset of each position in `source`
+pub(crate) fn offsets(source: &str, positions: &[Pos]) -> Vec<usize> {
+    let mut lines = vec![0];
+    lines.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    let offset = |pos: &Pos| {
+        let line = lines[pos.line - 1];
+        line + source[line..].char_indices().nth(pos.col - 1).map_or(source.len() - line, |(i, _)| i)
+    };
+    positions.iter().map(offset).collect()
+}
+
+// the tokens and comments in source order, each running up to where the next
+// one starts, less the whitespace in between
+pub(crate) fn pieces<'a>(source: &'a str, tokens: &'a [SpannedToken], comments: &[Pos]) -> Vec<Piece<'a>> {
+    let mut starts: Vec<(Pos, Option<&Token>)> = tokens.iter().map(|token| (token.pos, Some(&token.token))).collect();
+    starts.extend(comments.iter().map(|pos| (*pos, None)));
+    starts.sort_by_key(|(pos, _)| (pos.line, pos.col));
+    let positions: Vec<Pos> = starts.iter().map(|(pos, _)| *pos).collect();
+    let mut offsets = offsets(source, &positions);
+    offsets.push(source.len());
+    let mut ret = vec![];
+    for (i, (pos, token)) in starts.into_iter().enumerate() {
+        let written = &source[offsets[i]..offsets[i + 1]];
+        let mut text = written.trim_end();
+        // `#\ ` is the space character, which trimming would take away
+        if let (Some(Token::Char(chr)), Some(stripped)) = (token, written.strip_prefix("#\\")) {
+            if text.len() <= 2 {
+                text = &written[..2 + stripped.chars().next().map_or(chr.len_utf8(), char::len_utf8)];
+            }
+        }
+        ret.push(Piece { token, text, start: offsets[i], line: pos.line, end_line: pos.line + text.matches('\n').count() });
+    }
+    ret
+}
+
+// builds the nodes of a program the parser has already checked
+struct Builder<'a> {
+    pieces: Vec<Piece<'a>>,
+    next: usize,
+    // where the piece read last ends
+    last_line: Option<usize>,
+    // comments met between a reader prefix and its form, which go before the prefix
+    hoisted: Vec<Node>,
+}
+
+impl Builder<'_> {
+    fn take(&mut self) -> &Piece<'_> {
+        let piece = &self.pieces[self.next];
+        self.next += 1;
+        self.last_line = Some(piece.end_line);
+        piece
+    }
+    fn comment(&mut self) -> Node {
+        let last_line = self.last_line;
+        let piece = self.take();
+        Node::Comment { text: piece.text.to_owned(), trailing: last_line == Some(piece.line) }
+    }
+    // the items up to the end of the list, or of the program
+    fn items(&mut self) -> Vec<Node> {
+        let mut items = vec![];
+        while let Some(piece) = self.pieces.get(self.next) {
+            if matches!(piece.token, Some(Token::Close | Token::CloseBracket | Token::CloseBrace)) {
+                break;
+            }
+            if self.last_line.is_some_and(|line| piece.line > line + 1) {
+                items.push(Node::Blank);
+            }
+            let item = match piece.token {
+                None => self.comment(),
+                Some(_) => self.form(),
+            };
+            items.append(&mut self.hoisted);
+            items.push(item);
+        }
+        items
+    }
+    fn form(&mut self) -> Node {
+        let piece = self.take();
+        let text = piece.text.to_owned();
+        let (open, close) = match piece.token {
+            Some(Token::Open) => ("(", ")"),
+            Some(Token::OpenBracket) => ("[", "]"),
+            Some(Token::OpenBrace) => ("{", "}"),
+            Some(Token::Quote | Token::Quasiquote | Token::Unquote | Token::UnquoteSplicing) => {
+                while self.pieces[self.next].token.is_none() {
+                    let comment = self.comment();
+                    self.hoisted.push(comment);
+                }
+                return Node::Prefix(text, Box::new(self.form()));
+            }
+            _ => return Node::Text(text),
+        };
+        let items = self.items();
+        self.take();
+        Node::List { open, close, items }
+    }
+}
+
+// the form on a single line
+pub fn flat(exp: &LispExp) -> String {
+    Node::from(exp).flat()
+}
+
+// writes `items` from column `at`, the first `on_first_line` of them there and
+// each of the rest on a line of its own at `column`, two to a line when they're
+// `pairs`; a comment ends its line, and blank lines between items are kept as
+// one; returns whether it ended on a comment
+fn lay_out(items: &[Node], column: usize, mut at: usize, on_first_line: usize, pairs: bool, out: &mut String) -> bool {
+    let (mut code, mut first, mut after_comment, mut blank) = (0, true, false, false);
+    for item in items {
+        let new_line = match item {
+            Node::Blank => {
+                blank = true;
+                continue;
+            }
+            Node::Comment { trailing, .. } => !trailing,
+            _ => after_comment || (code >= on_first_line && (!pairs || code % 2 == 0)),
+        };
+        if !first && new_line {
+            out.push_str(if blank { "\n\n" } else { "\n" });
+            out.push_str(&" ".repeat(column));
+            at = column;
+        } else if !first {
+            out.push(' ');
+            at += 1;
+        }
+        let start = out.len();
+        pretty(item, at, out);
+        let written = &out[start..];
+        at = match written.rfind('\n') {
+            Some(newline) => written[newline + 1..].chars().count(),
+            None => at + written.chars().count(),
+        };
+        after_comment = matches!(item, Node::Comment { .. });
+        code += usize::from(!after_comment);
+        (first, blank) = (false, false);
+    }
+    after_comment
+}
+
+// writes `node` starting at column `indent`, breaking lists that don't fit or
+// have comments
+fn pretty(node: &Node, indent: usize, out: &mut String) {
+    let (open, close, items) = match node {
+        Node::Prefix(prefix, form) => {
+            out.push_str(prefix);
+            return pretty(form, indent + prefix.chars().count(), out);
+        }
+        Node::List { open, close, items } => (*open, *close, items),
+        _ => return out.push_str(&node.flat()),
+    };
+    if !node.has_comment() {
+        let line = node.flat();
+        if indent + line.chars().count() <= WIDTH || items.len() < 2 {
+            return out.push_str(&line);
+        }
+    }
+    out.push_str(open);
+    // vectors have no head, every item goes on its own line, and maps put a
+    // key and its value on each
+    let on_first_line = match (open, items.iter().find(|item| !matches!(item, Node::Comment { .. } | Node::Blank))) {
+        ("(", Some(Node::Text(head))) => 1 + header_args(head),
+        ("{", _) => 2,
+        _ => 1,
+    };
+    let column = indent + if open == "(" { INDENT } else { open.len() };
+    if lay_out(items, column, indent + open.len(), on_first_line, open == "{", out) {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+    }
+    out.push_str(close);
+}
+
+// reformats a whole program, one top-level form per line, keeping its comments
+// and the way each atom was written
+pub fn format_program(source: &str) -> Result<String, LispError> {
+    let (tokens, comments) = lex(source)?;
+    parse_program(&tokens)?;
+    let mut builder = Builder { pieces: pieces(source, &tokens, &comments), next: 0, last_line: None, hoisted: vec![] };
+    let mut forms = String::new();
+    lay_out(&builder.items(), 0, 0, 0, false, &mut forms);
+    if !forms.is_empty() {
+        forms.push('\n');
+    }
+    // the lexer skips a script's `#!` line, so it's kept as it is
+    match source.lines().next().filter(|line| line.starts_with("#!")) {
+        Some(shebang) => Ok(format!("{shebang}\n{forms}")),
+        None => Ok(forms),
+    }
+}
+
+// the forms, each broken up like `format_program` does
+pub(crate) fn format_forms(forms: &[LispExp]) -> String {
+    let mut out = String::new();
+    for exp in forms {
+        pretty(&Node::from(exp), 0, &mut out);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn keeps_comments_and_literals() {
+        let source = "; about x\n(define x 0xFF)   ; hex\n\n\n#| block |# (define t #t) (define c #\\ )\n";
+        assert_eq!(format_program(source).unwrap(), "; about x\n(define x 0xFF) ; hex\n\n#| block |#\n(define t #t)\n(define c #\\ )\n");
+    }
+
+    #[test]
+    fn breaks_long_forms() {
+        let source = "(defn f (a b) (if (> a b) (list a b \"a rather long string to push it past the width\") (begin (g a) ; why\n nil)))";
+        let formatted = "(defn f (a b)\n  (if (> a b)\n    (list a b \"a rather long string to push it past the width\")\n    (begin\n      (g a) ; why\n      nil)))\n";
+        assert_eq!(format_program(source).unwrap(), formatted);
+        assert_eq!(format_program(formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn comment_before_a_close() {
+        assert_eq!(format_program("(list 1 ; one\n)").unwrap(), "(list\n  1 ; one\n)\n");
+        assert_eq!(format_program("#!/bin/sxprs\n[1 2]").unwrap(), "#!/bin/sxprs\n[1 2]\n");
+    }
+
+    #[test]
+    fn rejects_what_doesnt_parse() {
+        assert!(format_program("(list 1").is_err());
+    }
+
+    #[test]
+    fn values_read_back() {
+        let source = "(list 1.0 \"a\\\"b\" [1 {:k 'v}] (quote x) (1 . 2))";
+        let forms = parse_program(&crate::lexer::tokens(source).unwrap()).unwrap();
+        let formatted = format_forms(&forms);
+        assert_eq!(formatted, "(list 1.0 \"a\\\"b\" [1 {:k 'v}] 'x (1 . 2))\n");
+        assert_eq!(parse_program(&crate::lexer::tokens(&formatted).unwrap()).unwrap(), forms);
+    }
+}
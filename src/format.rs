@@ -0,0 +1,150 @@
+// `format` and `printf`: strings built from a template, where `{}` is the
+// next argument as `str-concat` shows it and `{:?}` as `print` does, with
+// an optional alignment, width and precision like rust's, as in `{:>8.2}`
+use crate::builtins::{record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::LispExp;
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// what's between the braces of a directive
+#[derive(Default)]
+struct Spec {
+    align: Option<char>,
+    width: usize,
+    precision: Option<usize>,
+    // `?`, the value as it's written in source, with strings quoted
+    written: bool,
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, LispError> {
+    let bad = || LispError::Other(format!("bad format directive {{{spec}}}"));
+    let mut ret = Spec::default();
+    let Some(mut rest) = spec.strip_prefix(':') else {
+        return if spec.is_empty() { Ok(ret) } else { Err(bad()) };
+    };
+    if let Some(stripped) = rest.strip_suffix('?') {
+        ret.written = true;
+        rest = stripped;
+    }
+    if let Some(align @ ('<' | '>' | '^')) = rest.chars().next() {
+        ret.align = Some(align);
+        rest = &rest[1..];
+    }
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision)),
+        None => (rest, None),
+    };
+    if !width.is_empty() {
+        ret.width = width.parse().map_err(|_| bad())?;
+    }
+    if let Some(precision) = precision {
+        ret.precision = Some(precision.parse().map_err(|_| bad())?);
+    }
+    Ok(ret)
+}
+
+// `value` written out for `spec`, before it's padded
+fn show(value: &LispExp, spec: &Spec) -> String {
+    match (value, spec.precision) {
+        (LispExp::Int(num), Some(precision)) => format!("{:.precision$}", *num as f64),
+        (LispExp::Number(num), Some(precision)) => format!("{num:.precision$}"),
+        (LispExp::BigInt(_) | LispExp::Ratio(_), Some(precision)) => {
+            format!("{:.precision$}", value.get_number().unwrap_or(f64::NAN))
+        }
+        (LispExp::Str(string), _) if !spec.written => string.clone(),
+        (LispExp::Char(chr), _) if !spec.written => chr.to_string(),
+        (other, _) => other.to_string(),
+    }
+}
+
+fn pad(text: String, spec: &Spec) -> String {
+    let width = spec.width;
+    match spec.align {
+        Some('<') => format!("{text:<width$}"),
+        Some('^') => format!("{text:^width$}"),
+        Some(_) => format!("{text:>width$}"),
+        // numbers line up on the right, like rust's
+        None if text.parse::<f64>().is_ok() => format!("{text:>width$}"),
+        None => format!("{text:<width$}"),
+    }
+}
+
+// fills in `template`'s directives with `args`, in order; `{{` and `}}` are
+// literal braces
+pub(crate) fn format(template: &str, args: &[LispExp]) -> Result<String, LispError> {
+    let mut ret = String::new();
+    let mut args = args.iter();
+    let mut chars = template.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                ret.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                ret.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest.find('}').ok_or(LispError::from("unclosed `{` in format string"))?;
+                let spec = parse_spec(&rest[..end])?;
+                chars = rest[end + 1..].chars();
+                let value = args
+                    .next()
+                    .ok_or_else(|| LispError::Other(format!("format string {template:?} needs more arguments")))?;
+                ret.push_str(&pad(show(value, &spec), &spec));
+            }
+            '}' => return Err(LispError::from("unmatched `}` in format string")),
+            chr => ret.push(chr),
+        }
+    }
+    if args.next().is_some() {
+        return Err(LispError::Other(format!("format string {template:?} takes fewer arguments")));
+    }
+    Ok(ret)
+}
+
+// (format template args...) is the filled in template
+fn lisp_format(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (template, args) = unpack(cont)?;
+    Ok(format(template.get_string()?, args)?.into())
+}
+
+// (printf template args...) writes the filled in template, without adding a newline
+fn lisp_printf(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (template, args) = unpack(cont)?;
+    platform::write(&format(template.get_string()?, args)?);
+    Ok(LispExp::Nil)
+}
+
+pub(crate) fn format_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "format", lisp_format, 1.., "(format template args...)", "the template with every {} replaced by the next argument");
+    record!(funcs, "printf", lisp_printf, 1.., "(printf template args...)", "writes the filled in template, without a newline");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        let args = [LispExp::Str("ana".to_owned()), LispExp::Number(9.25), LispExp::Int(42), LispExp::Char('x')];
+        assert_eq!(format("{} scored {:.1}, {{{}}} {:?}", &args).unwrap(), "ana scored 9.2, {42} #\\x");
+        assert_eq!(format("[{:<5}|{:>5}|{:^5}]", &args[..3]).unwrap(), "[ana  | 9.25| 42  ]");
+        assert_eq!(format("{:?} {:>6.2}", &[LispExp::Str("a\"b".to_owned()), LispExp::Int(3)]).unwrap(), "\"a\\\"b\"   3.00");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(format("{}", &[]).is_err());
+        assert!(format("{}", &[LispExp::Int(1), LispExp::Int(2)]).is_err());
+        assert!(format("{:x}", &[LispExp::Int(1)]).is_err());
+        assert!(format("{", &[]).is_err());
+        assert!(format("}", &[]).is_err());
+    }
+}
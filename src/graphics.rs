@@ -0,0 +1,270 @@
+// turtle graphics, with the `graphics` feature: a turtle starts at the origin
+// facing right, and draws a line wherever it moves with its pen down. Angles
+// are in degrees, counterclockwise, and y grows upwards, like in maths.
+// `render` writes what was drawn as an SVG, sized to fit it; `rect` fills
+// squares too, for grids like the game of life's. Each interpreter has a
+// canvas of its own
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::builtins::{expand_path, io_error, record};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::LispExp;
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// the space left around the drawing in the SVG
+const MARGIN: f64 = 10.0;
+
+enum Shape {
+    Line { from: (f64, f64), to: (f64, f64), color: String, width: f64 },
+    Rect { at: (f64, f64), size: (f64, f64), color: String },
+}
+
+pub(crate) struct Turtle {
+    x: f64,
+    y: f64,
+    heading: f64,
+    pen_down: bool,
+    color: String,
+    width: f64,
+    shapes: Vec<Shape>,
+}
+
+impl Default for Turtle {
+    fn default() -> Turtle {
+        Turtle { x: 0.0, y: 0.0, heading: 0.0, pen_down: true, color: "black".to_owned(), width: 1.0, shapes: vec![] }
+    }
+}
+
+// a number short enough for an SVG, without a trailing `.0`
+fn number(n: f64) -> String {
+    let rounded = (n * 100.0).round() / 100.0;
+    // no `-0`
+    format!("{}", rounded + 0.0)
+}
+
+// what can't be in an attribute
+fn attribute(text: &str) -> String {
+    text.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;")
+}
+
+impl Turtle {
+    fn move_to(&mut self, x: f64, y: f64) {
+        if self.pen_down {
+            self.shapes.push(Shape::Line { from: (self.x, self.y), to: (x, y), color: self.color.clone(), width: self.width });
+        }
+        (self.x, self.y) = (x, y);
+    }
+    fn forward(&mut self, distance: f64) {
+        let radians = self.heading.to_radians();
+        self.move_to(self.x + distance * radians.cos(), self.y + distance * radians.sin());
+    }
+    // the smallest box holding everything drawn and the turtle, as x, y, width, height in SVG coordinates
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        let mut points = vec![(self.x, self.y)];
+        for shape in &self.shapes {
+            match shape {
+                Shape::Line { from, to, .. } => points.extend([*from, *to]),
+                Shape::Rect { at, size, .. } => points.extend([*at, (at.0 + size.0, at.1 + size.1)]),
+            }
+        }
+        let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+        let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+        let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        // SVG's y grows downwards
+        (min_x - MARGIN, -max_y - MARGIN, max_x - min_x + 2.0 * MARGIN, max_y - min_y + 2.0 * MARGIN)
+    }
+    pub(crate) fn svg(&self) -> String {
+        let (x, y, width, height) = self.bounds();
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"{}\" height=\"{}\">\n",
+            number(x),
+            number(y),
+            number(width),
+            number(height),
+            number(width),
+            number(height)
+        );
+        for shape in &self.shapes {
+            let _ = match shape {
+                Shape::Line { from, to, color, width } => writeln!(
+                    svg,
+                    "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\" stroke-linecap=\"round\"/>",
+                    number(from.0),
+                    number(-from.1),
+                    number(to.0),
+                    number(-to.1),
+                    attribute(color),
+                    number(*width)
+                ),
+                Shape::Rect { at, size, color } => writeln!(
+                    svg,
+                    "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    number(at.0),
+                    number(-(at.1 + size.1)),
+                    number(size.0),
+                    number(size.1),
+                    attribute(color)
+                ),
+            };
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn turtle<T>(env: &Interpreter, f: impl FnOnce(&mut Turtle) -> T) -> T {
+    f(&mut env.turtle.borrow_mut())
+}
+
+// (forward distance) moves the turtle ahead, drawing a line with the pen down
+fn lisp_forward(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let distance = cont[0].get_number()?;
+    turtle(env, |turtle| turtle.forward(distance));
+    Ok(LispExp::Nil)
+}
+
+// (back distance) moves the turtle backwards
+fn lisp_back(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let distance = cont[0].get_number()?;
+    turtle(env, |turtle| turtle.forward(-distance));
+    Ok(LispExp::Nil)
+}
+
+// (turn degrees) turns the turtle counterclockwise, or clockwise for negative degrees
+fn lisp_turn(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let degrees = cont[0].get_number()?;
+    turtle(env, |turtle| turtle.heading = (turtle.heading + degrees).rem_euclid(360.0));
+    Ok(LispExp::Nil)
+}
+
+// (goto x y) moves the turtle to x, y without turning it
+fn lisp_goto(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (x, y) = (cont[0].get_number()?, cont[1].get_number()?);
+    turtle(env, |turtle| turtle.move_to(x, y));
+    Ok(LispExp::Nil)
+}
+
+// (pen-up) and (pen-down) stop and start drawing as the turtle moves
+fn lisp_pen_up(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    turtle(env, |turtle| turtle.pen_down = false);
+    Ok(LispExp::Nil)
+}
+
+fn lisp_pen_down(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    turtle(env, |turtle| turtle.pen_down = true);
+    Ok(LispExp::Nil)
+}
+
+// (pen-color color) draws from now on in an SVG color, like "red" or "#336699"
+fn lisp_pen_color(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let color = cont[0].get_string()?.to_owned();
+    turtle(env, |turtle| turtle.color = color);
+    Ok(LispExp::Nil)
+}
+
+// (pen-width width) draws lines this wide from now on
+fn lisp_pen_width(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let width = cont[0].get_number()?;
+    if width <= 0.0 {
+        return Err(LispError::Other(format!("pen-width should be more than 0, not {}", cont[0])));
+    }
+    turtle(env, |turtle| turtle.width = width);
+    Ok(LispExp::Nil)
+}
+
+// (position) is where the turtle is, as (x y heading)
+fn lisp_position(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(turtle(env, |turtle| LispExp::from(vec![LispExp::Number(turtle.x), LispExp::Number(turtle.y), LispExp::Number(turtle.heading)])))
+}
+
+// (rect x y width height) fills a rectangle with the pen's color, from its lower left corner
+fn lisp_rect(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let numbers = cont.iter().map(LispExp::get_number).collect::<Result<Vec<f64>, _>>()?;
+    turtle(env, |turtle| {
+        let color = turtle.color.clone();
+        turtle.shapes.push(Shape::Rect { at: (numbers[0], numbers[1]), size: (numbers[2], numbers[3]), color });
+    });
+    Ok(LispExp::Nil)
+}
+
+// (clear-canvas) rubs out the drawing and puts the turtle back at the start
+fn lisp_clear_canvas(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    turtle(env, |turtle| *turtle = Turtle::default());
+    Ok(LispExp::Nil)
+}
+
+// (svg) is the drawing as an SVG
+fn lisp_svg(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    Ok(LispExp::Str(turtle(env, |turtle| turtle.svg())))
+}
+
+// (render path) writes the drawing to path as an SVG
+fn lisp_render(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let path = expand_path(cont[0].get_string()?)?;
+    let svg = turtle(env, |turtle| turtle.svg());
+    platform::write_file(Path::new(&path), &svg, false).map_err(|err| io_error(&path, err))?;
+    Ok(LispExp::Nil)
+}
+
+pub(crate) fn graphics_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "forward", lisp_forward, 1, "(forward distance)", "moves the turtle ahead, drawing a line when the pen is down");
+    record!(funcs, "back", lisp_back, 1, "(back distance)", "moves the turtle backwards, drawing a line when the pen is down");
+    record!(funcs, "turn", lisp_turn, 1, "(turn degrees)", "turns the turtle counterclockwise, or clockwise for negative degrees");
+    record!(funcs, "goto", lisp_goto, 2, "(goto x y)", "moves the turtle to x, y without turning it, drawing a line when the pen is down");
+    record!(funcs, "pen-up", lisp_pen_up, 0, "(pen-up)", "stops drawing as the turtle moves");
+    record!(funcs, "pen-down", lisp_pen_down, 0, "(pen-down)", "draws as the turtle moves again");
+    record!(funcs, "pen-color", lisp_pen_color, 1, "(pen-color color)", "draws from now on in an SVG color, like \"red\" or \"#336699\"");
+    record!(funcs, "pen-width", lisp_pen_width, 1, "(pen-width width)", "draws lines this wide from now on");
+    record!(funcs, "position", lisp_position, 0, "(position)", "where the turtle is and which way it faces, as (x y degrees)");
+    record!(funcs, "rect", lisp_rect, 4, "(rect x y width height)", "fills a rectangle with the pen's color, from its lower left corner");
+    record!(funcs, "clear-canvas", lisp_clear_canvas, 0, "(clear-canvas)", "rubs out the drawing and puts the turtle back at the origin, facing right");
+    record!(funcs, "svg", lisp_svg, 0, "(svg)", "the drawing as an SVG");
+    record!(funcs, "render", lisp_render, 1, "(render path)", "writes the drawing to path as an SVG, sized to fit it");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svg(source: &str) -> String {
+        let lisp = Interpreter::new();
+        lisp.eval_str(source).unwrap();
+        lisp.eval_str("(svg)").unwrap().get_string().unwrap().to_owned()
+    }
+
+    #[test]
+    fn draws_lines_where_the_turtle_goes() {
+        let square = svg("(dotimes (i 4) (forward 10) (turn 90))");
+        assert!(square.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-10 -20 30 30\" width=\"30\" height=\"30\">\n"), "{square}");
+        assert!(square.contains("<line x1=\"0\" y1=\"0\" x2=\"10\" y2=\"0\" stroke=\"black\" stroke-width=\"1\""), "{square}");
+        // up is -y in the SVG
+        assert!(square.contains("<line x1=\"10\" y1=\"0\" x2=\"10\" y2=\"-10\""), "{square}");
+        assert_eq!(square.matches("<line").count(), 4);
+    }
+
+    #[test]
+    fn pen_and_rects() {
+        let drawn = svg("(pen-up) (forward 5) (pen-down) (pen-color \"red\") (pen-width 2) (back 5) (rect 1 1 2 3)");
+        assert_eq!(drawn.matches("<line").count(), 1);
+        assert!(drawn.contains("<line x1=\"5\" y1=\"0\" x2=\"0\" y2=\"0\" stroke=\"red\" stroke-width=\"2\""), "{drawn}");
+        assert!(drawn.contains("<rect x=\"1\" y=\"-4\" width=\"2\" height=\"3\" fill=\"red\"/>"), "{drawn}");
+    }
+
+    #[test]
+    fn position_and_clearing() {
+        let lisp = Interpreter::new();
+        assert_eq!(lisp.eval_str("(goto 3 4) (turn 450) (position)").unwrap().to_string(), "( 3.0 4.0 90.0 )");
+        assert_eq!(lisp.eval_str("(clear-canvas) (position)").unwrap().to_string(), "( 0.0 0.0 0.0 )");
+        assert_eq!(lisp.eval_str("(svg)").unwrap().get_string().unwrap().matches("<line").count(), 0);
+        assert!(lisp.eval_str("(pen-width 0)").is_err());
+        // every interpreter has a canvas of its own
+        lisp.eval_str("(forward 1)").unwrap();
+        assert!(!svg("1").contains("<line"));
+    }
+}
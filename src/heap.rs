@@ -0,0 +1,223 @@
+// `heap-dump` and `--heap-at-exit`: what the program's global variables hold
+// onto, as JSON, for finding what grows in a long session. Every value
+// reachable from a global, through lists, maps, vectors, structs and the
+// variables functions close over, is counted once by its type, with an
+// estimate of the bytes it takes; then the globals that reach the most bytes,
+// each counted as if it were the only one. The prelude's definitions are the
+// same in every session, so they're left out
+use std::collections::{BTreeMap, HashSet};
+use std::mem::size_of;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::builtins::{expand_path, io_error, record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter, Scope};
+use crate::exp::{LispExp, LispLambda, MapKey, Struct};
+use crate::json::{escape, object};
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// how many globals `largest` lists
+const LARGEST: usize = 10;
+
+// the reference counts an `Rc` keeps beside what it holds
+const RC: usize = 2 * size_of::<usize>();
+
+// the values reached so far and how many bytes they take, by type
+#[derive(Default)]
+struct Walk {
+    // what's shared is counted once, by address
+    seen: HashSet<*const ()>,
+    types: BTreeMap<&'static str, (usize, usize)>,
+    values: usize,
+    bytes: usize,
+}
+
+// what's left to walk: values, and the scopes functions close over, which
+// are counted as `Scope`
+enum Node {
+    Value(LispExp),
+    Scope(Rc<Scope>),
+}
+
+fn lambda(func: &LispLambda, stack: &mut Vec<Node>) -> usize {
+    stack.extend(func.body.iter().cloned().map(Node::Value));
+    if !func.env.is_global() {
+        stack.push(Node::Scope(func.env.clone()));
+    }
+    RC + size_of::<LispLambda>() + func.params.capacity() * size_of::<SymbolId>()
+}
+
+impl Walk {
+    fn value(&mut self, root: LispExp) {
+        let mut stack = vec![Node::Value(root)];
+        while let Some(node) = stack.pop() {
+            let vl = match node {
+                Node::Scope(scope) => {
+                    if self.seen.insert(Rc::as_ptr(&scope).cast()) {
+                        let bindings = scope.bindings();
+                        self.count("Scope", RC + size_of::<Scope>() + bindings.len() * size_of::<(SymbolId, LispExp)>());
+                        stack.extend(bindings.into_iter().map(|(_, vl)| Node::Value(vl)));
+                    }
+                    continue;
+                }
+                Node::Value(vl) => vl,
+            };
+            let shared: Option<*const ()> = match &vl {
+                LispExp::List(items) => Some(items.as_ptr().cast()),
+                LispExp::Pair(pair) => Some(Rc::as_ptr(pair).cast()),
+                LispExp::Vector(items) => Some(Rc::as_ptr(items).cast()),
+                LispExp::Lambda(func) | LispExp::Macro(func) => Some(Rc::as_ptr(func).cast()),
+                LispExp::Struct(record) => Some(Rc::as_ptr(record).cast()),
+                LispExp::BigInt(int) => Some(Rc::as_ptr(int).cast()),
+                LispExp::Ratio(ratio) => Some(Rc::as_ptr(ratio).cast()),
+                _ => None,
+            };
+            // a value already counted is only the slot pointing at it
+            if shared.is_some_and(|ptr| !self.seen.insert(ptr)) {
+                continue;
+            }
+            // what the value has on the heap besides the values in it, which
+            // are counted as they're reached
+            let heap = match &vl {
+                LispExp::Str(text) => text.capacity(),
+                LispExp::BigInt(int) => RC + int.bits().div_ceil(8) as usize,
+                LispExp::Ratio(ratio) => RC + (ratio.numer().bits() + ratio.denom().bits()).div_ceil(8) as usize,
+                LispExp::List(items) => {
+                    stack.extend(items.iter().cloned().map(Node::Value));
+                    RC
+                }
+                LispExp::Pair(pair) => {
+                    stack.push(Node::Value(pair.0.clone()));
+                    stack.push(Node::Value(pair.1.clone()));
+                    RC
+                }
+                LispExp::Map(map) => {
+                    stack.extend(map.values().cloned().map(Node::Value));
+                    map.keys()
+                        .map(|key| match key {
+                            MapKey::Str(text) | MapKey::Symbol(text) => size_of::<MapKey>() + text.capacity(),
+                            _ => size_of::<MapKey>(),
+                        })
+                        .sum()
+                }
+                LispExp::Vector(items) => {
+                    let items = items.borrow();
+                    stack.extend(items.iter().cloned().map(Node::Value));
+                    RC + size_of::<std::cell::RefCell<Vec<LispExp>>>() + (items.capacity() - items.len()) * size_of::<LispExp>()
+                }
+                LispExp::Lambda(func) | LispExp::Macro(func) => lambda(func, &mut stack),
+                LispExp::Struct(record) => {
+                    stack.extend(record.values.iter().cloned().map(Node::Value));
+                    RC + size_of::<Struct>()
+                }
+                _ => 0,
+            };
+            self.count(vl.name(), size_of::<LispExp>() + heap);
+        }
+    }
+    fn count(&mut self, kind: &'static str, bytes: usize) {
+        let counts = self.types.entry(kind).or_default();
+        counts.0 += 1;
+        counts.1 += bytes;
+        self.values += 1;
+        self.bytes += bytes;
+    }
+}
+
+impl Interpreter {
+    // the heap dump of the global variables, as JSON: `values` and `bytes`
+    // in all, the same `by_type`, and the globals reaching the most bytes
+    pub fn heap_dump(&self) -> String {
+        let bindings: Vec<(SymbolId, LispExp)> = self
+            .current_scope()
+            .global()
+            .bindings()
+            .into_iter()
+            .filter(|(name, vl)| !self.is_prelude(*name, vl))
+            .collect();
+        let mut all = Walk::default();
+        let mut largest = vec![];
+        for (name, vl) in &bindings {
+            all.value(vl.clone());
+            let mut alone = Walk::default();
+            alone.value(vl.clone());
+            largest.push((name.to_string(), alone.values, alone.bytes));
+        }
+        largest.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        let types: Vec<(&str, String)> = all
+            .types
+            .iter()
+            .map(|(kind, (count, bytes))| (*kind, object(&[("count", count.to_string()), ("bytes", bytes.to_string())])))
+            .collect();
+        let largest: Vec<String> = largest
+            .iter()
+            .take(LARGEST)
+            .map(|(name, values, bytes)| object(&[("name", escape(name)), ("values", values.to_string()), ("bytes", bytes.to_string())]))
+            .collect();
+        object(&[
+            ("globals", bindings.len().to_string()),
+            ("values", all.values.to_string()),
+            ("bytes", all.bytes.to_string()),
+            ("by_type", object(&types)),
+            ("largest", format!("[{}]", largest.join(","))),
+        ])
+    }
+}
+
+// (heap-dump path) writes what the global variables hold onto to path, as JSON
+fn lisp_heap_dump(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (path, _) = unpack(cont)?;
+    let path = expand_path(path.get_string()?)?;
+    platform::write_file(Path::new(&path), &(env.heap_dump() + "\n"), false).map_err(|err| io_error(&path, err))?;
+    Ok(LispExp::Nil)
+}
+
+pub(crate) fn heap_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "heap-dump", lisp_heap_dump, 1, "(heap-dump path)", "writes what the global variables hold onto to path as JSON: counts and bytes by type, and the largest globals");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the dump of the globals `source` defines
+    fn dump(source: &str) -> String {
+        let lisp = Interpreter::new();
+        lisp.eval_str(source).unwrap();
+        lisp.heap_dump()
+    }
+
+    fn field<'a>(json: &'a str, key: &str) -> &'a str {
+        let at = json.find(&format!("\"{key}\":")).unwrap_or_else(|| panic!("no {key} in {json}")) + key.len() + 3;
+        let end = json[at..].find([',', '}']).unwrap();
+        &json[at..at + end]
+    }
+
+    #[test]
+    fn values_are_counted_once() {
+        let json = dump("(define xs '(1 2 3)) (define same xs) (define name \"sxprs\")");
+        assert_eq!(field(&json, "globals"), "3");
+        // one list of three ints, shared by two globals, and a string
+        assert!(json.contains("\"by_type\":{\"Int\":{\"count\":3,"), "{json}");
+        assert!(json.contains(",\"List\":{\"count\":1,"), "{json}");
+        assert!(json.contains(",\"String\":{\"count\":1,"), "{json}");
+        let ints = 3 * size_of::<LispExp>();
+        let list = size_of::<LispExp>() + RC;
+        assert!(json.contains(&format!("{{\"name\":\"same\",\"values\":4,\"bytes\":{}}}", ints + list)), "{json}");
+    }
+
+    #[test]
+    fn largest_globals_and_closures() {
+        let json = dump("(define big (range 0 1000)) (define small 1) (define count (let ((seen (range 0 100))) (lambda () (length seen))))");
+        assert!(json.contains("\"largest\":[{\"name\":\"big\",\"values\":1001,"), "{json}");
+        assert!(json.contains("{\"name\":\"count\",\"values\":"), "{json}");
+        assert!(json.ends_with(&format!("{{\"name\":\"small\",\"values\":1,\"bytes\":{}}}]}}", size_of::<LispExp>())), "{json}");
+        assert!(json.contains("\"Scope\":{\"count\":1,"), "{json}");
+        // the prelude isn't in it
+        assert!(!json.contains("\"name\":\"second\""));
+    }
+}
@@ -0,0 +1,86 @@
+// `help` and `apropos`: what the builtins and the functions with a docstring
+// do, from the REPL
+use crate::builtins::{record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::LispExp;
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// (help name) prints how `name` is called and what it does; the name isn't
+// evaluated, so it works for special forms and variables too
+fn lisp_help(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, _) = unpack(cont)?;
+    let name = name.get_symbol()?;
+    let (usage, doc) = env.doc(name).ok_or_else(|| LispError::UnknownSymbol(name.to_string()))?;
+    platform::print(&usage);
+    platform::print(&format!("  {}", doc.as_deref().unwrap_or("no documentation")));
+    Ok(LispExp::Nil)
+}
+
+// (apropos "text") prints every bound name with `text` in it, with what it does
+fn lisp_apropos(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (text, _) = unpack(cont)?;
+    let text = text.get_string()?;
+    for name in env.names() {
+        if !name.name().contains(text) {
+            continue;
+        }
+        match env.doc(name) {
+            Some((usage, Some(doc))) => platform::print(&format!("{usage}  {doc}")),
+            Some((usage, None)) => platform::print(&usage),
+            None => {}
+        }
+    }
+    Ok(LispExp::Nil)
+}
+
+pub(crate) fn help_forms() -> SymbolMap<Builtin> {
+    let mut specials: SymbolMap<Builtin> = SymbolMap::default();
+    record!(specials, "help", lisp_help, 1, "(help name)", "prints how name is called and what it does", unevaluated);
+    record!(specials, "doc", lisp_help, 1, "(doc name)", "the same as `help`", unevaluated);
+    specials
+}
+
+pub(crate) fn help_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "apropos", lisp_apropos, 1, "(apropos text)", "prints every bound name with text in it");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+    use crate::symbol::SymbolId;
+
+    fn doc(lisp: &Interpreter, name: &str) -> Option<(String, Option<String>)> {
+        lisp.doc(SymbolId::new(name))
+    }
+
+    #[test]
+    fn builtins_are_documented() {
+        let lisp = Interpreter::new();
+        let (usage, doc) = doc(&lisp, "car").unwrap();
+        assert_eq!(usage, "(car lst)");
+        assert!(doc.is_some());
+        assert!(lisp.names().iter().all(|name| lisp.doc(*name).is_some()));
+    }
+
+    #[test]
+    fn docstrings() {
+        let lisp = Interpreter::new();
+        lisp.eval_str("(defn add (a b) \"adds them\" (+ a b)) (define limit \"the most\" 10)").unwrap();
+        assert_eq!(doc(&lisp, "add"), Some(("(add a b)".to_owned(), Some("adds them".to_owned()))));
+        assert_eq!(doc(&lisp, "limit"), Some(("limit".to_owned(), Some("the most".to_owned()))));
+        assert_eq!(doc(&lisp, "nothing"), None);
+    }
+
+    #[test]
+    fn redefining_drops_the_docstring() {
+        let lisp = Interpreter::new();
+        lisp.eval_str("(define limit \"the most\" 10) (define limit 20)").unwrap();
+        assert_eq!(doc(&lisp, "limit"), Some(("limit".to_owned(), None)));
+        lisp.eval_str("(define limit \"the most\" 10) (let ((x 1)) (define limit \"local\" 5))").unwrap();
+        assert_eq!(doc(&lisp, "limit"), Some(("limit".to_owned(), Some("the most".to_owned()))));
+    }
+}
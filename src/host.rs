@@ -0,0 +1,311 @@
+// `ScriptHost`: the interpreter packaged for hosts that call a script over
+// and over, like a game calling it once a frame. The script defines
+// `(tick state dt)`, and optionally `(init)` for the first state; the host
+// keeps the state between calls, so it survives when the script is changed
+// and reloaded. Every call gets the same budget of evaluation steps, so a
+// script stuck in a loop costs a frame, not the game. Host functions are
+// registered as plain Rust functions, with their arguments converted from
+// lisp values and checked, and their results converted back
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::LispError;
+use crate::eval::Interpreter;
+use crate::exp::LispExp;
+use crate::platform;
+use crate::symbol::SymbolId;
+
+// how many evaluation steps a call gets unless `set_fuel` says otherwise
+pub const DEFAULT_FUEL: u64 = 100_000;
+
+// a lisp value a host function can take as an argument
+pub trait FromLisp: Sized {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError>;
+}
+
+// what a host function can return to lisp
+pub trait IntoLisp {
+    fn into_lisp(self) -> Result<LispExp, LispError>;
+}
+
+impl FromLisp for LispExp {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError> {
+        Ok(value.clone())
+    }
+}
+
+// a float that's a whole number converts too, like what `round` gives
+impl FromLisp for i64 {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError> {
+        match value {
+            LispExp::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(*n as i64),
+            _ => value.get_int(),
+        }
+    }
+}
+
+impl FromLisp for f64 {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError> {
+        value.get_number()
+    }
+}
+
+impl FromLisp for bool {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError> {
+        match value {
+            LispExp::Bool(b) => Ok(*b),
+            _ => Err(value.mismatch("Bool")),
+        }
+    }
+}
+
+impl FromLisp for String {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError> {
+        value.get_string().map(str::to_owned)
+    }
+}
+
+impl<T: FromLisp> FromLisp for Vec<T> {
+    fn from_lisp(value: &LispExp) -> Result<Self, LispError> {
+        value.get_list()?.iter().map(T::from_lisp).collect()
+    }
+}
+
+impl IntoLisp for () {
+    fn into_lisp(self) -> Result<LispExp, LispError> {
+        Ok(LispExp::Nil)
+    }
+}
+
+macro_rules! into_lisp {
+    ($($t:ty),*) => {
+        $(impl IntoLisp for $t {
+            fn into_lisp(self) -> Result<LispExp, LispError> {
+                Ok(LispExp::from(self))
+            }
+        })*
+    };
+}
+
+into_lisp!(LispExp, i64, f64, bool, String, &str);
+
+impl<T: IntoLisp> IntoLisp for Vec<T> {
+    fn into_lisp(self) -> Result<LispExp, LispError> {
+        Ok(LispExp::from(self.into_iter().map(T::into_lisp).collect::<Result<Vec<_>, _>>()?))
+    }
+}
+
+impl<T: IntoLisp> IntoLisp for Result<T, LispError> {
+    fn into_lisp(self) -> Result<LispExp, LispError> {
+        self?.into_lisp()
+    }
+}
+
+// a Rust function `ScriptHost::register` can make callable from lisp, with
+// `Args` the tuple of its argument types
+pub trait HostFn<Args> {
+    fn call(&self, name: &str, args: &[LispExp]) -> Result<LispExp, LispError>;
+}
+
+macro_rules! host_fn {
+    ($count:literal $(, $arg:ident)*) => {
+        impl<F, R, $($arg),*> HostFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: IntoLisp,
+            $($arg: FromLisp),*
+        {
+            #[allow(non_snake_case, unused_variables, unused_mut)]
+            fn call(&self, name: &str, args: &[LispExp]) -> Result<LispExp, LispError> {
+                if args.len() != $count {
+                    return Err(LispError::ArityMismatch { name: name.to_owned(), min: $count, max: Some($count), found: args.len() });
+                }
+                let mut args = args.iter();
+                $(let $arg = $arg::from_lisp(args.next().expect("counted"))?;)*
+                self($($arg),*).into_lisp()
+            }
+        }
+    };
+}
+
+host_fn!(0);
+host_fn!(1, A);
+host_fn!(2, A, B);
+host_fn!(3, A, B, C);
+host_fn!(4, A, B, C, D);
+
+pub struct ScriptHost {
+    lisp: Interpreter,
+    path: PathBuf,
+    // the files the script loaded, itself included, and when they were last
+    // changed, to know when to reload
+    seen: Vec<(PathBuf, Option<SystemTime>)>,
+    state: LispExp,
+    fuel: u64,
+}
+
+// when the file at `path` was last changed, None when it can't be read
+fn modified(path: &Path) -> Option<SystemTime> {
+    platform::scan(path).ok()?.first().map(|(_, modified, _)| *modified)
+}
+
+impl ScriptHost {
+    // loads the script at `path` in a new interpreter, and runs its `init`,
+    // when it has one, for the first state; the state is nil otherwise
+    pub fn load(path: impl AsRef<Path>) -> Result<ScriptHost, LispError> {
+        let mut host = ScriptHost { lisp: Interpreter::new(), path: path.as_ref().to_owned(), seen: vec![], state: LispExp::Nil, fuel: DEFAULT_FUEL };
+        host.reload()?;
+        if host.defines("init") {
+            host.state = host.call("init", vec![])?;
+        }
+        Ok(host)
+    }
+    // how many evaluation steps each call into the script may take
+    pub fn set_fuel(&mut self, steps: u64) {
+        self.fuel = steps;
+    }
+    // makes the Rust function `f` callable from the script as `name`; calls
+    // with the wrong number of arguments, or arguments that don't convert
+    // to `f`'s types, fail before it runs
+    pub fn register<Args>(&mut self, name: &str, f: impl HostFn<Args> + 'static) {
+        let shown = name.to_owned();
+        self.lisp.register_fn(name, move |_, args| f.call(&shown, args));
+    }
+    // reloads the script when it or a file it loaded changed since it was
+    // last loaded, and calls `(tick state dt)`, keeping what it returns as the
+    // new state. When it fails, including by running out of fuel, the state
+    // stays what it was; when the script fails to reload, the definitions it
+    // got to replace stay replaced. Clients attached with `listen` are
+    // answered first, between frames
+    pub fn tick(&mut self, dt: f64) -> Result<&LispExp, LispError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.lisp.poll_attached();
+        if self.changed() {
+            self.reload()?;
+        }
+        self.state = self.call("tick", vec![self.state.clone(), LispExp::Number(dt)])?;
+        Ok(&self.state)
+    }
+    // calls the script's function `name` with `args`, on a budget of fuel
+    pub fn call(&mut self, name: &str, args: Vec<LispExp>) -> Result<LispExp, LispError> {
+        self.lisp.set_fuel(Some(self.fuel));
+        let func = self.lisp.resolve(SymbolId::new(name))?;
+        let res = self.lisp.apply(&func, args);
+        self.lisp.set_fuel(None);
+        res
+    }
+    // whether the script or a file it loaded changed since it was loaded
+    pub fn changed(&self) -> bool {
+        self.seen.iter().any(|(path, seen)| modified(path) != *seen)
+    }
+    // runs the script again in the same interpreter, keeping the state;
+    // what it defines replaces what it defined before. A script that fails
+    // to reload isn't tried again until it changes again
+    pub fn reload(&mut self) -> Result<(), LispError> {
+        self.lisp.set_fuel(None);
+        // the times from before loading, so a change made while it loads is seen next time
+        let before: Vec<_> = self.lisp.loaded_files().into_iter().map(|path| (modified(&path), path)).collect();
+        let res = self.lisp.load_file(&self.path).map(drop);
+        self.seen = self
+            .lisp
+            .loaded_files()
+            .into_iter()
+            .map(|path| match before.iter().find(|(_, seen)| *seen == path) {
+                Some((time, _)) => (path, *time),
+                None => {
+                    let time = modified(&path);
+                    (path, time)
+                }
+            })
+            .collect();
+        res
+    }
+    fn defines(&self, name: &str) -> bool {
+        self.lisp.resolve(SymbolId::new(name)).is_ok()
+    }
+    pub fn state(&self) -> &LispExp {
+        &self.state
+    }
+    pub fn set_state(&mut self, state: LispExp) {
+        self.state = state;
+    }
+    // the interpreter the script runs in, to evaluate code in it or change its settings
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.lisp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn script(name: &str, source: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sxprs-host-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    // rewrites the script with a later time, so the change is seen however coarse the file system's clock is
+    fn rewrite(path: &Path, source: &str) {
+        let before = modified(path).unwrap();
+        std::fs::write(path, source).unwrap();
+        std::fs::File::options().write(true).open(path).unwrap().set_modified(before + Duration::from_secs(1)).unwrap();
+    }
+
+    #[test]
+    fn ticks_keep_the_state() {
+        let path = script("count.lsp", "(defn init () 0)\n(defn tick (n dt) (+ n dt))\n");
+        let mut host = ScriptHost::load(&path).unwrap();
+        assert_eq!(host.state().to_string(), "0");
+        host.tick(0.5).unwrap();
+        assert_eq!(host.tick(0.25).unwrap().to_string(), "0.75");
+        assert!(!host.changed());
+    }
+
+    #[test]
+    fn typed_host_functions() {
+        let path = script("typed.lsp", "(defn tick (state dt) (list (spawn \"orc\" 3) (distance 0 0 3 4)))\n");
+        let mut host = ScriptHost::load(&path).unwrap();
+        host.register("spawn", |kind: String, count: i64| vec![kind; count as usize]);
+        host.register("distance", |x1: f64, y1: f64, x2: f64, y2: f64| ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt());
+        host.register("quit", || -> Result<(), LispError> { Err(LispError::Other("bye".to_owned())) });
+        assert_eq!(host.tick(0.0).unwrap().to_string(), "( ( \"orc\" \"orc\" \"orc\" ) 5.0 )");
+        let lisp = host.interpreter();
+        assert_eq!(lisp.eval_str("(spawn \"orc\")").unwrap_err().summary(), "`spawn` expects 2 arguments, got 1");
+        assert_eq!(lisp.eval_str("(spawn 1 2)").unwrap_err().code(), "type-mismatch");
+        assert_eq!(lisp.eval_str("(spawn \"elf\" (round 1.2))").unwrap().to_string(), "( \"elf\" )");
+        assert_eq!(lisp.eval_str("(spawn \"elf\" 1.5)").unwrap_err().code(), "type-mismatch");
+        assert_eq!(lisp.eval_str("(quit)").unwrap_err().summary(), "bye");
+    }
+
+    #[test]
+    fn fuel_runs_out_each_call() {
+        let path = script("loop.lsp", "(defn init () 0)\n(defn tick (n dt) (if (> dt 0) (while true nil) (+ n 1)))\n");
+        let mut host = ScriptHost::load(&path).unwrap();
+        host.set_fuel(1000);
+        assert!(matches!(host.tick(1.0).unwrap_err().root(), LispError::LimitExceeded { limit: "step", .. }));
+        // the state stays, and the next call gets a budget of its own
+        assert_eq!(host.state().to_string(), "0");
+        for _ in 0..10 {
+            host.tick(0.0).unwrap();
+        }
+        assert_eq!(host.state().to_string(), "10");
+    }
+
+    #[test]
+    fn reloads_what_changed() {
+        let path = script("reload.lsp", "(defn init () 0)\n(defn tick (n dt) (+ n 1))\n");
+        let mut host = ScriptHost::load(&path).unwrap();
+        host.tick(0.0).unwrap();
+        rewrite(&path, "(defn init () 0)\n(defn tick (n dt) (+ n 100))\n");
+        assert!(host.changed());
+        assert_eq!(host.tick(0.0).unwrap().to_string(), "101");
+        // a broken script is reported once, and the old definitions keep running
+        rewrite(&path, "(defn tick (n dt)\n");
+        assert_eq!(host.tick(0.0).unwrap_err().code(), "unclosed-list");
+        assert_eq!(host.tick(0.0).unwrap().to_string(), "201");
+    }
+}
@@ -0,0 +1,258 @@
+// images: what a program defined globally, written out as source that defines
+// it all again, so a long session can be picked up later with `--load-image`
+// or `load`; builtins and the prelude's functions aren't saved, a function
+// that closes over local variables is saved inside a `let` that binds a copy
+// of them, and anything that still can't be written out is left out with a
+// warning.
+//
+// checkpoints use the same writer for a single value: `(checkpoint name
+// state)` saves the state of a long job as the expression that evaluates to
+// it, and after a restart with `--resume`, `(resume name default)` gives it
+// back instead of the default
+use std::path::PathBuf;
+
+use crate::builtins::{expand_path, record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::{LispExp, LispLambda};
+use crate::fmt::format_forms;
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// where checkpoints are kept, in the current directory
+const CHECKPOINTS: &str = ".sxprs-checkpoints";
+
+const HEADER: &str = "; an sxprs image, written by save-image; loading it defines again what was\n; defined when it was saved\n";
+
+// the global definitions as source, sorted by name
+pub(crate) fn image(env: &Interpreter) -> Result<String, LispError> {
+    let mut bindings = env.current_scope().global().bindings();
+    bindings.sort_by_cached_key(|(name, _)| name.to_string());
+    let mut forms = vec![];
+    for (name, vl) in bindings {
+        // the host sets the arguments anew for every run
+        if &*name.name() == "argv" || env.is_prelude(name, &vl) {
+            continue;
+        }
+        match Imager::default().definition(env, name, &vl) {
+            Ok(form) => forms.push(form),
+            Err(err) => platform::eprint(&format!("warning: `{name}` isn't saved: {}", err.summary())),
+        }
+    }
+    Ok(format!("{HEADER}{}", format_forms(&forms)))
+}
+
+fn call(head: &str, args: Vec<LispExp>) -> LispExp {
+    LispExp::from([vec![LispExp::Symbol(SymbolId::new(head))], args].concat())
+}
+
+// every symbol in `exp`, which includes the free variables of code
+fn symbols(exp: &LispExp, names: &mut Vec<SymbolId>) {
+    match exp {
+        LispExp::Symbol(name) if !name.is_keyword() && !names.contains(name) => names.push(*name),
+        LispExp::List(items) => items.iter().for_each(|item| symbols(item, names)),
+        LispExp::Vector(_) | LispExp::Map(_) => exp.literal_items().unwrap_or_default().iter().for_each(|item| symbols(item, names)),
+        _ => {}
+    }
+}
+
+#[derive(Default)]
+struct Imager {
+    // the functions being written, to catch local ones that refer to themselves
+    writing: Vec<*const LispLambda>,
+}
+
+impl Imager {
+    // the form that binds `name` to `vl` again
+    fn definition(&mut self, env: &Interpreter, name: SymbolId, vl: &LispExp) -> Result<LispExp, LispError> {
+        if let LispExp::Macro(mac) = vl {
+            if !mac.env.is_global() {
+                return Err(LispError::from("it's a macro that closes over local variables"));
+            }
+            return Ok(call("defmacro", [vec![LispExp::Symbol(name)], self.function(mac)?].concat()));
+        }
+        let mut args = vec![LispExp::Symbol(name)];
+        args.extend(env.value_doc(name).map(LispExp::from));
+        args.push(self.expression(vl)?);
+        Ok(call("define", args))
+    }
+    // a function's parameters, docstring and body, as `lambda` takes them
+    fn function(&mut self, func: &LispLambda) -> Result<Vec<LispExp>, LispError> {
+        let mut ret = vec![LispExp::from(func.param_items())];
+        ret.extend(func.doc.get().map(|doc| LispExp::from(doc.as_str())));
+        ret.extend(func.body.iter().cloned());
+        Ok(ret)
+    }
+    // `(lambda ...)`, in a `let` binding the local variables it uses
+    fn lambda(&mut self, func: &LispLambda) -> Result<LispExp, LispError> {
+        let lambda = call("lambda", self.function(func)?);
+        if func.env.is_global() {
+            return Ok(lambda);
+        }
+        if self.writing.contains(&(func as *const _)) {
+            return Err(LispError::from("it's a local function that refers to itself"));
+        }
+        self.writing.push(func);
+        let mut names = vec![];
+        symbols(&LispExp::from(func.param_items()), &mut names);
+        func.body.iter().for_each(|exp| symbols(exp, &mut names));
+        let mut bindings = vec![];
+        for name in names {
+            if let Some((vl, false)) = func.env.lookup(name) {
+                bindings.push(LispExp::from(vec![LispExp::Symbol(name), self.expression(&vl)?]));
+            }
+        }
+        self.writing.pop();
+        Ok(call("let", vec![LispExp::from(bindings), lambda]))
+    }
+    fn expressions<'a>(&mut self, values: impl IntoIterator<Item = &'a LispExp>) -> Result<Vec<LispExp>, LispError> {
+        values.into_iter().map(|vl| self.expression(vl)).collect()
+    }
+    // an expression that evaluates to `vl`
+    fn expression(&mut self, vl: &LispExp) -> Result<LispExp, LispError> {
+        Ok(match vl {
+            LispExp::Symbol(_) => call("quote", vec![vl.clone()]),
+            LispExp::List(items) if items.is_empty() => call("quote", vec![vl.clone()]),
+            LispExp::List(items) => call("list", self.expressions(items.iter())?),
+            LispExp::Pair(pair) => call("cons", vec![self.expression(&pair.0)?, self.expression(&pair.1)?]),
+            LispExp::Vector(items) => call("vector", self.expressions(items.borrow().iter())?),
+            LispExp::Map(map) => {
+                let mut args = vec![];
+                for (key, vl) in map.iter() {
+                    args.push(self.expression(&LispExp::from(key.clone()))?);
+                    args.push(self.expression(vl)?);
+                }
+                call("dict", args)
+            }
+            LispExp::Struct(record) => {
+                let fields = call("quote", vec![LispExp::List(record.fields.clone())]);
+                call("make-struct", [vec![call("quote", vec![LispExp::Symbol(record.name)]), fields], self.expressions(&record.values)?].concat())
+            }
+            // floats that have no literal
+            LispExp::Number(num) if num.is_nan() => call("/", vec![0.0.into(), 0.0.into()]),
+            LispExp::Number(num) if num.is_infinite() => call("/", vec![num.signum().into(), 0.0.into()]),
+            LispExp::Lambda(func) => self.lambda(func)?,
+            LispExp::Builtin(name) => LispExp::Symbol(*name),
+            LispExp::Macro(_) => return Err(LispError::from("a macro can only be saved as a definition of its own")),
+            atom => atom.clone(),
+        })
+    }
+}
+
+// the file checkpoint `name` is kept in
+fn checkpoint_path(name: &LispExp) -> Result<PathBuf, LispError> {
+    let name = name.get_string()?;
+    if name.is_empty() || !name.chars().all(|chr| chr.is_alphanumeric() || matches!(chr, '-' | '_' | '.')) || name.starts_with('.') {
+        return Err(LispError::Other(format!("{name:?} can't name a checkpoint, which is letters, digits, `-`, `_` and `.`")));
+    }
+    Ok(PathBuf::from(CHECKPOINTS).join(format!("{name}.lsp")))
+}
+
+// (checkpoint name state) saves state for `resume` to give back after a
+// restart, and returns it
+fn lisp_checkpoint(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (state, _) = unpack(rest)?;
+    let path = checkpoint_path(name)?;
+    let source = format_forms(&[Imager::default().expression(state)?]);
+    platform::replace_file(&path, &source).map_err(|err| LispError::Other(format!("{}: {err}", path.display())))?;
+    Ok(state.clone())
+}
+
+// (resume name default) is the state last saved as checkpoint name when the
+// program runs with --resume and there is one, and default otherwise
+fn lisp_resume(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (default, _) = unpack(rest)?;
+    let path = checkpoint_path(name)?;
+    if !env.resuming() || !path.exists() {
+        return Ok(default.clone());
+    }
+    let source = platform::read_file(&path).map_err(|err| LispError::Other(format!("{}: {err}", path.display())))?;
+    env.eval_str(&source).map_err(|err| LispError::Other(format!("{}: {}", path.display(), err.message())))
+}
+
+// (save-image path) writes the global definitions to `path`
+fn lisp_save_image(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (path, _) = unpack(cont)?;
+    env.save_image(expand_path(path.get_string()?)?)?;
+    Ok(LispExp::Nil)
+}
+
+pub(crate) fn image_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "save-image", lisp_save_image, 1, "(save-image path)", "writes the global definitions to path, for --load-image to restore");
+    record!(funcs, "checkpoint", lisp_checkpoint, 2, "(checkpoint name state)", "saves state for (resume name default) to give back when the program is run again with --resume");
+    record!(funcs, "resume", lisp_resume, 2, "(resume name default)", "the state last saved as checkpoint name under --resume, or default");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_of(source: &str) -> String {
+        let lisp = Interpreter::new();
+        lisp.eval_str(source).unwrap();
+        image(&lisp).unwrap()
+    }
+
+    // what `check` evaluates to after loading the image `source` makes
+    fn restored(source: &str, check: &str) -> String {
+        let lisp = Interpreter::new();
+        lisp.eval_str(&image_of(source)).unwrap();
+        lisp.eval_str(check).unwrap().to_string()
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let source = "(define n 12345678901234567890123) (define r 1/3) (define l '(a \"b\" #\\c (1.5))) \
+                      (define p (cons 1 2)) (define v [1 [2]]) (define m {:a 1 \"b\" '(x)}) (define nan (/ 0.0 0.0)) \
+                      (defstruct point x y) (define o (point 1 2))";
+        assert_eq!(restored(source, "(list n r l p v (dict-get m :a) (dict-get m \"b\") (point-x o) (point? o))"), "( 12345678901234567890123 1/3 ( a \"b\" #\\c ( 1.5 ) ) ( 1 . 2 ) [ 1 [ 2 ] ] 1 ( x ) 1 true )");
+        assert_eq!(restored(source, "(equal? nan nan)"), "false");
+    }
+
+    #[test]
+    fn functions_and_docstrings() {
+        let source = "(defn add (a &optional (b 1)) \"adds them\" (+ a b)) (define limit \"the most\" 10) \
+                      (defmacro twice (x) `(begin ~x ~x))";
+        assert_eq!(restored(source, "(list (add 1) (add 1 2) limit (twice 3))"), "( 2 3 10 3 )");
+        let lisp = Interpreter::new();
+        lisp.eval_str(&image_of(source)).unwrap();
+        assert_eq!(lisp.doc(SymbolId::new("limit")).unwrap().1.as_deref(), Some("the most"));
+        assert_eq!(lisp.doc(SymbolId::new("add")).unwrap().1.as_deref(), Some("adds them"));
+    }
+
+    #[test]
+    fn closures_keep_their_locals() {
+        let source = "(define f (let ((n 1)) (lambda (x) (+ x n)))) \
+                      (module counter (export step) (define by 5) (defn step (x) (+ x by)))";
+        assert_eq!(restored(source, "(list (f 1) (counter/step 1))"), "( 2 6 )");
+    }
+
+    #[test]
+    fn leaves_out_what_it_cant_save() {
+        let image = image_of("(define ok 1) (define loop (let () (defn again (n) (again n)) again))");
+        assert!(image.contains("(define ok 1)"));
+        assert!(!image.contains("loop"));
+    }
+
+    #[test]
+    fn checkpoint_names() {
+        assert_eq!(checkpoint_path(&LispExp::from("batch-2.v1")).unwrap(), PathBuf::from(".sxprs-checkpoints/batch-2.v1.lsp"));
+        for name in ["", "../x", "a/b", ".hidden"] {
+            assert!(checkpoint_path(&LispExp::from(name)).is_err(), "{name}");
+        }
+        // without --resume the default is used
+        let lisp = Interpreter::new();
+        assert_eq!(lisp.eval_str("(resume \"anything\" 7)").unwrap().to_string(), "7");
+    }
+
+    #[test]
+    fn leaves_out_the_prelude() {
+        let image = image_of("(define x 1)");
+        assert!(!image.contains("(define second"));
+        assert!(image_of("(defn second (l) (car (cdr l)))").contains("(define second"));
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+
+use crate::error::LispError;
+use crate::explain;
+use crate::exp::{LispExp, MapKey};
+use crate::lexer::Pos;
+
+// a JSON string literal
+pub fn escape(string: &str) -> String {
+    let mut ret = String::from("\"");
+    for chr in string.chars() {
+        match chr {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if c.is_control() => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            other => ret.push(other),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn node(kind: &str, field: &str, value: String) -> String {
+    format!("{{\"type\":\"{kind}\",\"{field}\":{value}}}")
+}
+
+fn array<'a>(items: impl Iterator<Item = &'a LispExp>) -> String {
+    format!("[{}]", items.map(to_json).collect::<Vec<_>>().join(","))
+}
+
+// every node is an object with its `type`, atoms keep their value in `value`
+// and lists and vectors their children in `items`
+pub fn to_json(exp: &LispExp) -> String {
+    match exp {
+        LispExp::Symbol(symb) => node("Symbol", "value", escape(&symb.name())),
+        LispExp::Str(string) => node("String", "value", escape(string)),
+        LispExp::Int(num) => node("Int", "value", num.to_string()),
+        // as strings, as most JSON readers would round them to a float
+        LispExp::BigInt(num) => node("BigInt", "value", escape(&num.to_string())),
+        LispExp::Ratio(num) => node("Ratio", "value", escape(&num.to_string())),
+        // JSON has no infinity or NaN
+        LispExp::Number(num) if !num.is_finite() => node("Number", "value", escape(&num.to_string())),
+        LispExp::Number(num) => node("Number", "value", format!("{num:?}")),
+        LispExp::Bool(b) => node("Bool", "value", b.to_string()),
+        LispExp::Nil => "{\"type\":\"Nil\"}".to_owned(),
+        LispExp::Char(c) => node("Char", "value", escape(&c.to_string())),
+        LispExp::List(items) => node("List", "items", array(items.iter())),
+        LispExp::Pair(pair) => node("Pair", "items", array([&pair.0, &pair.1].into_iter())),
+        LispExp::Vector(items) => node("Vector", "items", array(items.borrow().iter())),
+        LispExp::Map(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(k, v)| format!("[{},{}]", to_json(&k.clone().into()), to_json(v)))
+                .collect();
+            node("Map", "entries", format!("[{}]", entries.join(",")))
+        }
+        other => node(other.name(), "value", escape(&other.to_string())),
+    }
+}
+
+// `exp` as plain JSON data, what `lisp->json` returns: lists and vectors are
+// arrays, maps and structs are objects, and symbols and chars are strings
+pub fn lisp_to_json(exp: &LispExp) -> Result<String, LispError> {
+    Ok(match exp {
+        LispExp::Nil => "null".to_owned(),
+        LispExp::Bool(b) => b.to_string(),
+        LispExp::Int(num) => num.to_string(),
+        LispExp::BigInt(num) => num.to_string(),
+        LispExp::Number(num) if !num.is_finite() => return Err(LispError::Other(format!("JSON has no {num}"))),
+        LispExp::Number(num) => format!("{num:?}"),
+        LispExp::Str(string) => escape(string),
+        LispExp::Char(chr) => escape(&chr.to_string()),
+        LispExp::Symbol(name) => escape(&name.name()),
+        LispExp::List(items) => json_array(items.iter())?,
+        LispExp::Vector(items) => json_array(items.borrow().iter())?,
+        LispExp::Map(map) => {
+            let entries = map
+                .iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        MapKey::Str(key) | MapKey::Symbol(key) => key.clone(),
+                        MapKey::Int(key) => key.to_string(),
+                        MapKey::Bool(key) => key.to_string(),
+                    };
+                    Ok(format!("{}:{}", escape(&key), lisp_to_json(v)?))
+                })
+                .collect::<Result<Vec<_>, LispError>>()?;
+            format!("{{{}}}", entries.join(","))
+        }
+        // an object of its fields; the struct's name is left out
+        LispExp::Struct(record) => {
+            let entries = record
+                .fields
+                .iter()
+                .zip(&record.values)
+                .map(|(field, vl)| Ok(format!("{}:{}", escape(&field.to_string()), lisp_to_json(vl)?)))
+                .collect::<Result<Vec<_>, LispError>>()?;
+            format!("{{{}}}", entries.join(","))
+        }
+        other => return Err(LispError::Other(format!("can't convert a {} to JSON", other.name()))),
+    })
+}
+
+fn json_array<'a>(items: impl Iterator<Item = &'a LispExp>) -> Result<String, LispError> {
+    let items = items.map(lisp_to_json).collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("[{}]", items.join(",")))
+}
+
+// reads JSON text into lisp data, what `json->lisp` returns: null is nil,
+// arrays are lists, objects are maps with string keys, and numbers are ints
+// when they're whole and fit
+pub fn json_to_lisp(source: &str, max_depth: usize) -> Result<LispExp, LispError> {
+    let mut reader = JsonReader { chars: source.char_indices().peekable(), max_depth };
+    let value = reader.value(0)?;
+    reader.skip_whitespace();
+    match reader.chars.next() {
+        None => Ok(value),
+        Some((at, _)) => Err(reader.error(at, "unexpected text after the value")),
+    }
+}
+
+struct JsonReader<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    max_depth: usize,
+}
+
+impl JsonReader<'_> {
+    fn error(&self, at: usize, msg: &str) -> LispError {
+        LispError::Other(format!("invalid JSON at byte {at}: {msg}"))
+    }
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, chr)| matches!(chr, ' ' | '\t' | '\n' | '\r')).is_some() {}
+    }
+    fn next(&mut self) -> Result<(usize, char), LispError> {
+        self.chars.next().ok_or_else(|| LispError::from("invalid JSON: unexpected end of input"))
+    }
+    fn expect_word(&mut self, word: &str, value: LispExp) -> Result<LispExp, LispError> {
+        for expected in word.chars() {
+            let (at, chr) = self.next()?;
+            if chr != expected {
+                return Err(self.error(at, &format!("expected `{word}`")));
+            }
+        }
+        Ok(value)
+    }
+    fn value(&mut self, depth: usize) -> Result<LispExp, LispError> {
+        self.skip_whitespace();
+        let &(at, chr) = self.chars.peek().ok_or_else(|| LispError::from("invalid JSON: unexpected end of input"))?;
+        if depth > self.max_depth {
+            return Err(self.error(at, &format!("nesting deeper than {} levels", self.max_depth)));
+        }
+        match chr {
+            'n' => self.expect_word("null", LispExp::Nil),
+            't' => self.expect_word("true", LispExp::Bool(true)),
+            'f' => self.expect_word("false", LispExp::Bool(false)),
+            '"' => self.string().map(LispExp::Str),
+            '[' => {
+                self.next()?;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.chars.next_if(|(_, chr)| *chr == ']').is_some() {
+                    return Ok(LispExp::from(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        (_, ',') => {}
+                        (_, ']') => return Ok(LispExp::from(items)),
+                        (at, _) => return Err(self.error(at, "expected `,` or `]`")),
+                    }
+                }
+            }
+            '{' => {
+                self.next()?;
+                let mut map = BTreeMap::new();
+                self.skip_whitespace();
+                if self.chars.next_if(|(_, chr)| *chr == '}').is_some() {
+                    return Ok(LispExp::Map(map));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if !matches!(self.chars.peek(), Some((_, '"'))) {
+                        let (at, _) = self.next()?;
+                        return Err(self.error(at, "expected a string key"));
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    match self.next()? {
+                        (_, ':') => {}
+                        (at, _) => return Err(self.error(at, "expected `:`")),
+                    }
+                    map.insert(MapKey::Str(key), self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        (_, ',') => {}
+                        (_, '}') => return Ok(LispExp::Map(map)),
+                        (at, _) => return Err(self.error(at, "expected `,` or `}`")),
+                    }
+                }
+            }
+            '-' | '0'..='9' => self.number(at),
+            _ => Err(self.error(at, &format!("unexpected `{chr}`"))),
+        }
+    }
+    fn number(&mut self, start: usize) -> Result<LispExp, LispError> {
+        let mut text = String::new();
+        while let Some((_, chr)) = self.chars.next_if(|(_, chr)| matches!(chr, '-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            text.push(chr);
+        }
+        if let Ok(num) = text.parse::<i64>() {
+            return Ok(LispExp::Int(num));
+        }
+        text.parse::<f64>()
+            .map(LispExp::Number)
+            .map_err(|_| self.error(start, &format!("invalid number `{text}`")))
+    }
+    fn string(&mut self) -> Result<String, LispError> {
+        self.next()?;
+        let mut ret = String::new();
+        loop {
+            match self.next()? {
+                (_, '"') => return Ok(ret),
+                (at, '\\') => match self.next()? {
+                    (_, '"') => ret.push('"'),
+                    (_, '\\') => ret.push('\\'),
+                    (_, '/') => ret.push('/'),
+                    (_, 'b') => ret.push('\u{8}'),
+                    (_, 'f') => ret.push('\u{c}'),
+                    (_, 'n') => ret.push('\n'),
+                    (_, 'r') => ret.push('\r'),
+                    (_, 't') => ret.push('\t'),
+                    (_, 'u') => {
+                        let high = self.hex4(at)?;
+                        // characters outside the BMP come as a surrogate pair
+                        let code = if (0xd800..0xdc00).contains(&high) {
+                            match (self.next()?, self.next()?) {
+                                ((_, '\\'), (_, 'u')) => {}
+                                _ => return Err(self.error(at, "unpaired surrogate")),
+                            }
+                            let low = self.hex4(at)?;
+                            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                        } else {
+                            high
+                        };
+                        ret.push(char::from_u32(code).ok_or_else(|| self.error(at, "invalid \\u escape"))?);
+                    }
+                    (_, _) => return Err(self.error(at, "unknown escape")),
+                },
+                (_, chr) => ret.push(chr),
+            }
+        }
+    }
+    fn hex4(&mut self, at: usize) -> Result<u32, LispError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let (_, chr) = self.next()?;
+            let digit = chr.to_digit(16).ok_or_else(|| self.error(at, "invalid \\u escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+}
+
+pub(crate) fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields.iter().map(|(key, value)| format!("{}:{value}", escape(key))).collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn diagnostic_fields(severity: &str, code: &str, message: &str, file: Option<&str>, pos: Option<Pos>) -> Vec<(&'static str, String)> {
+    let span = pos.map_or("null".to_owned(), |pos| {
+        object(&[("line", pos.line.to_string()), ("col", pos.col.to_string())])
+    });
+    vec![
+        ("severity", escape(severity)),
+        ("code", escape(code)),
+        ("id", explain::id(code).map_or("null".to_owned(), escape)),
+        ("message", escape(message)),
+        ("file", file.map_or("null".to_owned(), escape)),
+        ("span", span),
+    ]
+}
+
+// a diagnostic for editors and other tools, as one JSON object:
+// `{"severity":...,"code":...,"id":...,"message":...,"file":...,"span":{"line":...,"col":...}}`,
+// where `id` is the code's number for `sxprs explain`, and the id, file and
+// span are null when they aren't known
+pub fn diagnostic(severity: &str, code: &str, message: &str, file: Option<&str>, pos: Option<Pos>) -> String {
+    object(&diagnostic_fields(severity, code, message, file, pos))
+}
+
+// `err` as an error diagnostic, with the functions it happened in, innermost
+// first, as `backtrace`
+pub fn error_diagnostic(err: &LispError, file: Option<&str>) -> String {
+    let (calls, file): (Vec<String>, _) = match err {
+        LispError::Backtrace { calls, file: from, .. } => (calls.iter().map(|call| escape(call)).collect(), file.or(from.as_deref())),
+        _ => (vec![], file),
+    };
+    let mut fields = diagnostic_fields("error", err.code(), &err.summary(), file, err.pos());
+    fields.push(("backtrace", format!("[{}]", calls.join(","))));
+    object(&fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    fn read(source: &str) -> String {
+        match json_to_lisp(source, 10) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn reads_json() {
+        assert_eq!(read(r#"{"a": [1, 2.5, -3e2], "b": {"c": null}, "d": "x\u00e9\n", "e": true}"#), r#"{ "a" ( 1 2.5 -300.0 ) "b" { "c" nil } "d" "xé\n" "e" true }"#);
+        // whole numbers too big for an int are floats
+        assert_eq!(json_to_lisp("123456789012345678901234567890", 10).unwrap().name(), "Number");
+        assert_eq!(read("  []  "), "(  )");
+    }
+
+    #[test]
+    fn rejects_bad_json() {
+        assert!(read("[1,]").starts_with("error"));
+        assert!(read("{\"a\" 1}").starts_with("error"));
+        assert!(read("1 2").contains("unexpected text after the value"), "{}", read("1 2"));
+        assert!(read("\"\\x\"").starts_with("error"));
+        assert!(read(&"[".repeat(20)).contains("nesting deeper than 10 levels"));
+    }
+
+    #[test]
+    fn writes_json() {
+        let lisp = Interpreter::new();
+        let value = lisp.eval_str("(defstruct point x y) (list 1 2.0 \"a\\\"b\" 'sym #\\c nil true [1] {:k 1 \"s\" 2} (point 1 2))").unwrap();
+        assert_eq!(lisp_to_json(&value).unwrap(), r#"[1,2.0,"a\"b","sym","c",null,true,[1],{"s":2,":k":1},{"x":1,"y":2}]"#);
+        assert!(lisp_to_json(&LispExp::Number(f64::NAN)).is_err());
+        assert!(lisp_to_json(&lisp.eval_str("(lambda (x) x)").unwrap()).is_err());
+        // and back
+        assert_eq!(lisp.eval_str("(json->lisp (lisp->json '(1 (2 \"three\"))))").unwrap().to_string(), "( 1 ( 2 \"three\" ) )");
+    }
+
+    #[test]
+    fn ast_and_diagnostics() {
+        assert_eq!(to_json(&LispExp::from(vec![LispExp::Int(1), LispExp::Nil])), r#"{"type":"List","items":[{"type":"Int","value":1},{"type":"Nil"}]}"#);
+        let pos = Pos { line: 2, col: 3 };
+        assert_eq!(
+            diagnostic("warning", "unused", "x \"quoted\"", Some("f.lsp"), Some(pos)),
+            r#"{"severity":"warning","code":"unused","id":null,"message":"x \"quoted\"","file":"f.lsp","span":{"line":2,"col":3}}"#
+        );
+        let err = Interpreter::new().eval_str("(defn f (x) (car x))\n(f 1)").unwrap_err();
+        assert_eq!(
+            error_diagnostic(&err, Some("f.lsp")),
+            r#"{"severity":"error","code":"type-mismatch","id":"E0101","message":"1 is not a List, it's a Int","file":"f.lsp","span":{"line":1,"col":13},"backtrace":["car","f"]}"#
+        );
+    }
+}
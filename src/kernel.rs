@@ -0,0 +1,226 @@
+// `sxprs kernel`: the interpreter behind a notebook. It reads one JSON
+// request a line from stdin and writes one JSON reply a line to stdout, for
+// `kernel/sxprs_kernel.py`, which speaks Jupyter's protocol to the notebook
+// and this one to sxprs. Cells run one after the other in the same
+// interpreter, so what one defines the next can use. The requests, by `op`:
+//
+//   execute      {"op":"execute","code":"(+ 1 2)"}, runs the cell; the reply
+//                has what it printed in `stdout` and `stderr`, and its value
+//                as the REPL shows it in `value`, null for nil, or the
+//                error's `ename`, `id`, `evalue` and `traceback`
+//   complete     {"op":"complete","code":"(pri","cursor":4}, the names
+//                bound in the interpreter that complete the symbol before
+//                the cursor, counted in characters, in `matches`
+//   is_complete  {"op":"is_complete","code":"(+ 1"}, whether the code is a
+//                whole cell: `complete`, `incomplete` or `invalid`
+//   env          {"op":"env"}, the variables the code that's running can
+//                see, innermost first, without the builtins and what the
+//                prelude defined, with their values as the REPL shows them
+//                in the object `env`
+//   shutdown     {"op":"shutdown"}, replies and stops
+//
+// `sxprs attach` speaks it too, to an interpreter the host made debuggable
+// with `listen`
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::Ordering;
+
+use crate::eval::Interpreter;
+use crate::exp::{LispExp, MapKey};
+use crate::json::{escape, json_to_lisp, object};
+use crate::platform;
+
+// what can't be part of a symbol, as in the REPL
+fn is_delimiter(chr: char) -> bool {
+    chr.is_whitespace() || "()[]{}'`,\"".contains(chr)
+}
+
+fn strings<'a>(items: impl Iterator<Item = &'a str>) -> String {
+    format!("[{}]", items.map(escape).collect::<Vec<_>>().join(","))
+}
+
+fn failure(message: &str) -> String {
+    object(&[("status", escape("error")), ("ename", escape("request")), ("evalue", escape(message))])
+}
+
+fn field<'a>(request: &'a LispExp, name: &str) -> Option<&'a LispExp> {
+    request.get_map().ok()?.get(&MapKey::Str(name.to_owned()))
+}
+
+fn execute(lisp: &Interpreter, code: &str) -> String {
+    // what was being captured before, when the cell runs in the middle of another
+    let outer = platform::capture();
+    let capture = platform::Capture::default();
+    platform::set_capture(Some(capture.clone()));
+    let res = lisp.eval_str(code);
+    platform::set_capture(outer);
+    let (stdout, stderr) = std::mem::take(&mut *capture.lock().unwrap_or_else(std::sync::PoisonError::into_inner));
+    let mut fields = vec![("stdout", escape(&stdout)), ("stderr", escape(&stderr))];
+    match res {
+        Ok(value) => {
+            let shown = match value {
+                LispExp::Nil => "null".to_owned(),
+                value => escape(&lisp.show(&value)),
+            };
+            fields.extend([("status", escape("ok")), ("value", shown)]);
+        }
+        Err(err) => fields.extend([
+            ("status", escape("error")),
+            ("ename", escape(err.code())),
+            ("id", escape(err.id())),
+            ("evalue", escape(&err.summary())),
+            ("traceback", strings(err.message().lines())),
+        ]),
+    }
+    object(&fields)
+}
+
+// the names that complete the symbol ending at the `cursor`th character of `code`
+fn complete(lisp: &Interpreter, code: &str, cursor: usize) -> String {
+    let end = code.char_indices().nth(cursor).map_or(code.len(), |(at, _)| at);
+    let start = code[..end].char_indices().rfind(|&(_, chr)| is_delimiter(chr)).map_or(0, |(at, chr)| at + chr.len_utf8());
+    let prefix = &code[start..end];
+    let names: Vec<String> = if prefix.is_empty() {
+        vec![]
+    } else {
+        lisp.names().iter().map(ToString::to_string).filter(|name| name.starts_with(prefix)).collect()
+    };
+    object(&[
+        ("status", escape("ok")),
+        ("matches", strings(names.iter().map(String::as_str))),
+        ("cursor_start", code[..start].chars().count().to_string()),
+        ("cursor_end", cursor.to_string()),
+    ])
+}
+
+fn env(lisp: &Interpreter) -> String {
+    let env: Vec<(String, String)> = lisp
+        .current_scope()
+        .visible()
+        .into_iter()
+        .filter(|(name, vl)| !lisp.is_prelude(*name, vl))
+        .map(|(name, vl)| (name.to_string(), escape(&lisp.show(&vl))))
+        .collect();
+    let env: Vec<(&str, String)> = env.iter().map(|(name, vl)| (name.as_str(), vl.clone())).collect();
+    object(&[("status", escape("ok")), ("env", object(&env))])
+}
+
+fn is_complete(lisp: &Interpreter, code: &str) -> String {
+    let status = match lisp.parse(code) {
+        Ok(_) => "complete",
+        Err(err) if err.is_incomplete() => "incomplete",
+        Err(_) => "invalid",
+    };
+    object(&[("status", escape(status))])
+}
+
+// the reply to the `request` line, and whether it was to shut down
+pub(crate) fn reply(lisp: &Interpreter, request: &str) -> (String, bool) {
+    let request = match json_to_lisp(request, lisp.max_depth()) {
+        Ok(request) => request,
+        Err(err) => return (failure(&format!("the request isn't JSON: {}", err.summary())), false),
+    };
+    let code = field(&request, "code").and_then(|code| code.get_string().ok());
+    let op = field(&request, "op").and_then(|op| op.get_string().ok());
+    match (op, code) {
+        (Some("execute"), Some(code)) => (execute(lisp, code), false),
+        (Some("complete"), Some(code)) => {
+            let cursor = match field(&request, "cursor") {
+                Some(LispExp::Int(cursor)) if *cursor >= 0 => *cursor as usize,
+                _ => code.chars().count(),
+            };
+            (complete(lisp, code, cursor), false)
+        }
+        (Some("is_complete"), Some(code)) => (is_complete(lisp, code), false),
+        (Some("env"), _) => (env(lisp), false),
+        (Some("shutdown"), _) => (object(&[("status", escape("ok"))]), true),
+        (Some("execute" | "complete" | "is_complete"), None) => (failure("the request has no code"), false),
+        (Some(op), _) => (failure(&format!("there's no op {op}")), false),
+        (None, _) => (failure("the request has no op"), false),
+    }
+}
+
+// answers the requests from `input` on `output` until a shutdown or the end of the input
+pub fn serve(lisp: &Interpreter, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // an interrupt that came between requests isn't for the next one
+        if let Some(flag) = &lisp.interrupt {
+            flag.store(false, Ordering::Relaxed);
+        }
+        let (reply, shutdown) = reply(lisp, &line);
+        writeln!(output, "{reply}")?;
+        output.flush()?;
+        if shutdown {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(requests: &[&str]) -> Vec<String> {
+        let lisp = Interpreter::new();
+        let mut output = vec![];
+        serve(&lisp, requests.join("\n").as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap().lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn cells_share_the_interpreter() {
+        let replies = session(&[
+            r#"{"op":"execute","code":"(defn sq (x) (* x x)) (print \"hi\") (printf \"{}\" 1)"}"#,
+            r#"{"op":"execute","code":"(sq 12)"}"#,
+            r#"{"op":"execute","code":"(print 1)"}"#,
+        ]);
+        assert_eq!(replies[0], r#"{"stdout":"\"hi\"\n1","stderr":"","status":"ok","value":null}"#);
+        assert_eq!(replies[1], r#"{"stdout":"","stderr":"","status":"ok","value":"144"}"#);
+        assert_eq!(replies[2], r#"{"stdout":"1\n","stderr":"","status":"ok","value":null}"#);
+    }
+
+    #[test]
+    fn errors_have_their_id() {
+        let replies = session(&[r#"{"op":"execute","code":"(print 1) (car 1)"}"#]);
+        assert_eq!(
+            replies[0],
+            r#"{"stdout":"1\n","stderr":"","status":"error","ename":"type-mismatch","id":"E0101","evalue":"1 is not a List, it's a Int","traceback":["1 is not a List, it's a Int","  at 1:11","  in `car`","  called from top-level"]}"#
+        );
+    }
+
+    #[test]
+    fn completes_and_checks_cells() {
+        let replies = session(&[
+            r#"{"op":"execute","code":"(define printer-name \"x\")"}"#,
+            r#"{"op":"complete","code":"(λ (printe","cursor":10}"#,
+            r#"{"op":"is_complete","code":"(+ 1"}"#,
+            r#"{"op":"is_complete","code":"(+ 1)"}"#,
+            r#"{"op":"is_complete","code":"(+ 1))"}"#,
+        ]);
+        assert_eq!(replies[1], r#"{"status":"ok","matches":["printer-name"],"cursor_start":4,"cursor_end":10}"#);
+        assert_eq!(replies[2], r#"{"status":"incomplete"}"#);
+        assert_eq!(replies[3], r#"{"status":"complete"}"#);
+        assert_eq!(replies[4], r#"{"status":"invalid"}"#);
+    }
+
+    #[test]
+    fn lists_the_environment() {
+        let replies = session(&[r#"{"op":"execute","code":"(define n 2) (defn map (f l) l)"}"#, r#"{"op":"env"}"#]);
+        // `map` was redefined, so it's not the prelude's anymore
+        assert_eq!(replies[1], r#"{"status":"ok","env":{"map":"( lambda ( f l ) l )","n":"2"}}"#);
+    }
+
+    #[test]
+    fn bad_requests_and_shutdown() {
+        let replies = session(&[r#"nope"#, r#"{"op":"fly"}"#, r#"{"op":"execute"}"#, r#"{"op":"shutdown"}"#, r#"{"op":"execute","code":"1"}"#]);
+        assert!(replies[0].starts_with(r#"{"status":"error","ename":"request","evalue":"the request isn't JSON: "#), "{}", replies[0]);
+        assert_eq!(replies[1], r#"{"status":"error","ename":"request","evalue":"there's no op fly"}"#);
+        assert_eq!(replies[2], r#"{"status":"error","ename":"request","evalue":"the request has no code"}"#);
+        assert_eq!(replies[3], r#"{"status":"ok"}"#);
+        assert_eq!(replies.len(), 4);
+    }
+}
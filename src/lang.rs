@@ -0,0 +1,152 @@
+// the languages diagnostics are written in, picked with `--lang`. English is
+// what `LispError::message` gives; the other languages have their messages
+// here, one for each kind of error. Messages a program or builtin made up as
+// text, like `(error "...")`, stay as they were written, and `code()` is the
+// same in every language, for tools
+use crate::error::LispError;
+use crate::exp::LispExp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Pt,
+}
+
+impl Lang {
+    // `en` or `pt`, with or without a region like `pt-BR` or `pt_BR`
+    pub fn parse(name: &str) -> Option<Lang> {
+        let language = name.split(['-', '_']).next().unwrap_or_default();
+        match &language.to_ascii_lowercase()[..] {
+            "en" => Some(Lang::En),
+            "pt" => Some(Lang::Pt),
+            _ => None,
+        }
+    }
+    // the line after an error that points at `sxprs explain`
+    pub fn see_explain(self, id: &str) -> String {
+        match self {
+            Lang::En => format!("see `sxprs explain {id}`"),
+            Lang::Pt => format!("veja `sxprs explain {id}`"),
+        }
+    }
+    // the languages there are, as `--lang` takes them
+    pub fn names() -> &'static [&'static str] {
+        &["en", "pt"]
+    }
+}
+
+impl LispError {
+    // `message` in `lang`
+    pub fn message_in(&self, lang: Lang) -> String {
+        match lang {
+            Lang::En => self.message(),
+            Lang::Pt => portuguese(self),
+        }
+    }
+    // `summary` in `lang`
+    pub fn summary_in(&self, lang: Lang) -> String {
+        match (lang, self.root()) {
+            (Lang::En, _) => self.summary(),
+            (_, LispError::Incomplete { msg, .. } | LispError::Syntax { msg, .. }) => msg.clone(),
+            (_, LispError::UnexpectedToken { token, .. }) => format!("`{token}` inesperado"),
+            (_, LispError::UnclosedList(_)) => "não foi encontrado o `)` que fecha a lista".to_owned(),
+            (_, root) => root.message_in(lang),
+        }
+    }
+    // what `Display` writes, in `lang`
+    pub fn display_in(&self, lang: Lang) -> String {
+        match lang {
+            Lang::En => self.to_string(),
+            Lang::Pt => format!("Erro de processamento Lisp: {}", portuguese(self)),
+        }
+    }
+}
+
+fn portuguese(err: &LispError) -> String {
+    match err {
+        LispError::UnexpectedToken { pos, .. } | LispError::UnclosedList(pos) | LispError::Incomplete { pos, .. } | LispError::Syntax { pos, .. } => {
+            format!("{pos}: {}", err.summary_in(Lang::Pt))
+        }
+        LispError::TypeMismatch { expected, found, value } => format!("{value} não é um {expected}, é um {found}"),
+        LispError::UnknownSymbol(name) => format!("o símbolo {name} não está definido"),
+        LispError::ArityMismatch { name, min, max, found } => {
+            let expected = match max {
+                Some(max) if max == min => format!("{min}"),
+                Some(max) => format!("entre {min} e {max}"),
+                None => format!("pelo menos {min}"),
+            };
+            format!("`{name}` espera {expected} argumentos, recebeu {found}")
+        }
+        LispError::IndexOutOfRange { index, len } => format!("índice {index} fora do intervalo para o tamanho {len}"),
+        LispError::DivisionByZero => "divisão por zero".to_owned(),
+        LispError::TooDeep(limit) => format!("avaliação aninhada mais fundo que {limit} níveis"),
+        LispError::Raised(LispExp::Str(msg)) => msg.clone(),
+        LispError::Raised(value) => value.to_string(),
+        LispError::AssertionFailed { form, found } => format!("a asserção {form} falhou: {found}"),
+        LispError::Other(msg) => msg.clone(),
+        LispError::LimitExceeded { limit, max } => format!("excedeu o limite de {limit}, {max}"),
+        LispError::Interrupted => "interrompido".to_owned(),
+        LispError::Exit(code) => format!("saiu com o código {code}"),
+        LispError::Backtrace { error, calls, file, pos } => {
+            let mut msg = portuguese(error);
+            match (pos, file) {
+                (Some(pos), Some(file)) => msg.push_str(&format!("\n  em {file}:{pos}")),
+                (Some(pos), None) => msg.push_str(&format!("\n  em {pos}")),
+                (None, _) => {}
+            }
+            for (i, call) in calls.iter().enumerate() {
+                let from = if i == 0 { "em" } else { "chamado de" };
+                msg.push_str(&format!("\n  {from} `{call}`"));
+            }
+            msg.push_str("\n  chamado do nível superior");
+            if let Some(file) = file {
+                msg.push_str(&format!(" em {file}"));
+            }
+            msg
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    fn error(source: &str) -> LispError {
+        Interpreter::new().eval_str(source).unwrap_err()
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Lang::parse("pt"), Some(Lang::Pt));
+        assert_eq!(Lang::parse("pt_BR"), Some(Lang::Pt));
+        assert_eq!(Lang::parse("EN-us"), Some(Lang::En));
+        assert_eq!(Lang::parse("fr"), None);
+    }
+
+    #[test]
+    fn messages_in_portuguese() {
+        assert_eq!(error("(car 1)").summary_in(Lang::Pt), "1 não é um List, é um Int");
+        assert_eq!(error("(car)").summary_in(Lang::Pt), "`car` espera 1 argumentos, recebeu 0");
+        assert_eq!(error("(/ 1 0)").summary_in(Lang::Pt), "divisão por zero");
+        assert_eq!(error("(car 1").message_in(Lang::Pt), "1:1: não foi encontrado o `)` que fecha a lista");
+        assert_eq!(error(")").display_in(Lang::Pt), "Erro de processamento Lisp: 1:1: `)` inesperado");
+        // what the program says itself isn't translated
+        assert_eq!(error("(error \"no way\")").summary_in(Lang::Pt), "no way");
+    }
+
+    #[test]
+    fn backtraces_in_portuguese() {
+        let err = error("(defn f (x) (car x))\n(defn g () (+ 1 (f 1)))\n(g)");
+        assert_eq!(err.code(), "type-mismatch");
+        assert_eq!(err.message_in(Lang::Pt), "1 não é um List, é um Int\n  em 1:13\n  em `car`\n  chamado de `f`\n  chamado de `g`\n  chamado do nível superior");
+    }
+
+    #[test]
+    fn english_is_the_default() {
+        let err = error("(nope)");
+        assert_eq!(err.message_in(Lang::default()), err.message());
+        assert_eq!(err.display_in(Lang::En), err.to_string());
+    }
+}
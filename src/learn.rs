@@ -0,0 +1,171 @@
+// `sxprs learn`: a tutorial in the REPL. Each lesson explains a little of the
+// language and sets a task; what's typed runs like in the REPL, and the
+// lesson's check, a function of the last value run in the same interpreter,
+// decides when the task is done. Definitions stay from one lesson to the next
+use crate::error::LispError;
+use crate::eval::Interpreter;
+use crate::exp::LispExp;
+
+pub struct Lesson {
+    pub title: &'static str,
+    // what the lesson teaches, with examples
+    pub text: &'static str,
+    pub task: &'static str,
+    // the body of `(lambda (it) ...)`, given the value of the last form
+    // typed, which is true once the task is done
+    pub check: &'static str,
+    pub hint: &'static str,
+    // one way to do the task
+    pub answer: &'static str,
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        title: "Calling functions",
+        text: "A call is a list: the function first, then its arguments, all in parentheses.
+    (+ 1 2)         ; 3
+    (* 2 3 4)       ; 24",
+        task: "Add 40 and 2.",
+        check: "(= it 42)",
+        hint: "The function is `+`, and it goes first, inside the parentheses.",
+        answer: "(+ 40 2)",
+    },
+    Lesson {
+        title: "Nesting",
+        text: "An argument can be a call too; the innermost calls run first.
+    (* 2 (+ 1 2))   ; 6",
+        task: "Compute (2 + 3) × 7.",
+        check: "(= it 35)",
+        hint: "Multiply the result of (+ 2 3) by 7.",
+        answer: "(* (+ 2 3) 7)",
+    },
+    Lesson {
+        title: "Variables",
+        text: "`define` gives a value a name, for the rest of the program.
+    (define width 10)
+    (* width 2)     ; 20",
+        task: "Define `answer` as 42.",
+        check: "(= answer 42)",
+        hint: "(define name value)",
+        answer: "(define answer 42)",
+    },
+    Lesson {
+        title: "Functions",
+        text: "`defn` makes a function: its name, its parameters and the body, whose last value it returns.
+    (defn double (x) (* x 2))
+    (double 5)      ; 10",
+        task: "Define `square`, which multiplies a number by itself.",
+        check: "(and (= (square 3) 9) (= (square -4) 16))",
+        hint: "It takes one parameter, and multiplies it by itself with `*`.",
+        answer: "(defn square (x) (* x x))",
+    },
+    Lesson {
+        title: "Conditions",
+        text: "`if` runs one branch or the other; `cond` tries its clauses in order, and `else` always matches.
+    (if (> 3 2) \"bigger\" \"smaller\")
+    (cond ((< n 0) \"negative\") (else \"not negative\"))",
+        task: "Define `sign`, giving -1 for negative numbers, 0 for zero and 1 for positive ones.",
+        check: "(and (= (sign -5) -1) (= (sign 0) 0) (= (sign 7) 1))",
+        hint: "Three cases: (< n 0), (= n 0) and else.",
+        answer: "(defn sign (n) (cond ((< n 0) -1) ((= n 0) 0) (else 1)))",
+    },
+    Lesson {
+        title: "Lists",
+        text: "`list` makes a list of its arguments; a quote makes one from what's written, without running it.
+`car` is the first item, `cdr` the rest, and `cons` puts an item in front.
+    (list 1 (+ 1 1))    ; (1 2)
+    '(a b c)            ; (a b c)
+    (cons 0 '(1 2))     ; (0 1 2)",
+        task: "Make the list of 1, 2 and 3.",
+        check: "(equal? it '(1 2 3))",
+        hint: "(list 1 2 3), or quoted.",
+        answer: "(list 1 2 3)",
+    },
+    Lesson {
+        title: "Recursion",
+        text: "A function can call itself, on a smaller part of the problem, until there's nothing left.
+An empty list is false, like `nil` and `false`.
+    (defn count (xs) (if xs (+ 1 (count (cdr xs))) 0))",
+        task: "Define `sum`, adding up the numbers in a list, with an empty list adding up to 0.",
+        check: "(and (= (sum '(1 2 3)) 6) (= (sum '()) 0))",
+        hint: "The first number plus the sum of the rest, or 0 when the list is empty.",
+        answer: "(defn sum (xs) (if xs (+ (car xs) (sum (cdr xs))) 0))",
+    },
+    Lesson {
+        title: "Functions as values",
+        text: "`lambda` makes a function without a name. `map` calls a function on every item of a list,
+and `filter` keeps the items it's true for.
+    (map (lambda (x) (+ x 1)) '(1 2))           ; (2 3)
+    (filter (lambda (x) (> x 2)) '(1 2 3 4))    ; (3 4)",
+        task: "Double every number in '(1 2 3) with `map`.",
+        check: "(equal? it '(2 4 6))",
+        hint: "(map (lambda (x) ...) '(1 2 3))",
+        answer: "(map (lambda (x) (* 2 x)) '(1 2 3))",
+    },
+    Lesson {
+        title: "Strings",
+        text: "Strings are in double quotes. `str-concat` joins them, `str-length` counts their characters,
+and `format` puts values into a template.
+    (str-concat \"sx\" \"prs\")      ; \"sxprs\"
+    (format \"{} + {}\" 1 2)         ; \"1 + 2\"",
+        task: "Join \"foo\" and \"bar\".",
+        check: "(equal? it \"foobar\")",
+        hint: "`str-concat` takes the strings to join.",
+        answer: "(str-concat \"foo\" \"bar\")",
+    },
+    Lesson {
+        title: "Macros",
+        text: "A macro gets its arguments as written, not their values, and returns the code to run instead.
+A backquote writes that code, with `~` putting a value in and `~@` a list's items.
+    (defmacro twice (form) `(begin ~form ~form))
+`sxprs expand file.lsp` shows what a program's macros turn into.",
+        task: "Define a macro `my-unless`, running its body only when its test is false.",
+        check: "(and (equal? (my-unless false 1) 1) (equal? (my-unless true 1) nil))",
+        hint: "It's `if` with the branches swapped: `(if ~test nil (begin ~@body))`.",
+        answer: "(defmacro my-unless (test &rest body) `(if ~test nil (begin ~@body)))",
+    },
+];
+
+// runs `source` in `lisp`, then the lesson's check on the value of its last
+// form; gives that value and whether the task is done. A check that fails,
+// like on a function that isn't defined yet, means it isn't
+pub fn attempt(lisp: &Interpreter, lesson: &Lesson, source: &str) -> Result<(LispExp, bool), LispError> {
+    let value = lisp.eval_str(source)?;
+    let check = lisp.eval_str(&format!("(lambda (it) {})", lesson.check))?;
+    let done = lisp.apply(&check, vec![value.clone()]).is_ok_and(|done| done.is_truthy());
+    Ok((value, done))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_answer_passes() {
+        let lisp = Interpreter::new();
+        for lesson in LESSONS {
+            let (_, done) = attempt(&lisp, lesson, lesson.answer).unwrap();
+            assert!(done, "{}: {}", lesson.title, lesson.answer);
+        }
+    }
+
+    #[test]
+    fn wrong_answers_dont() {
+        let lisp = Interpreter::new();
+        let lesson = &LESSONS[3];
+        assert!(!attempt(&lisp, lesson, "(+ 1 1)").unwrap().1);
+        assert!(!attempt(&lisp, lesson, "(defn square (x) (* x 2))").unwrap().1);
+        assert!(attempt(&lisp, lesson, "(car 1)").is_err());
+        let (value, done) = attempt(&lisp, &LESSONS[0], "(+ 40 2)").unwrap();
+        assert_eq!((value.to_string(), done), ("42".to_owned(), true));
+    }
+
+    #[test]
+    fn checks_are_well_formed() {
+        let lisp = Interpreter::new();
+        for lesson in LESSONS {
+            assert!(lisp.eval_str(&format!("(lambda (it) {})", lesson.check)).is_ok(), "{}", lesson.title);
+            assert!(lesson.text.lines().all(|line| line.chars().count() <= 110), "{}", lesson.title);
+        }
+    }
+}
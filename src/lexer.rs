@@ -0,0 +1,509 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, BufRead};
+
+use crate::error::LispError;
+
+#[allow(clippy::enum_variant_names)]
+enum Parser {
+    OnSymbol,
+    // `escape` is where the `\` of an escape being read is
+    OnString { escape: Option<Pos> },
+    OnLineComment,
+    // block comments nest, `depth` counts the unclosed `#|`
+    OnBlockComment { depth: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Open,
+    Close,
+    // `[` and `]` around a vector literal
+    OpenBracket,
+    CloseBracket,
+    // `{` and `}` around a map literal
+    OpenBrace,
+    CloseBrace,
+    Atom(String),
+    Str(String),
+    // `#\a`, `#\space`
+    Char(char),
+    // reader prefixes, expand to (quote x), (quasiquote x), (unquote x) and (unquote-splicing x)
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+}
+
+// 1-based source position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Display for Pos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    // where the token starts
+    pub pos: Pos,
+}
+
+impl SpannedToken {
+    fn at(token: Token, pos: Pos) -> SpannedToken {
+        SpannedToken { token, pos }
+    }
+}
+
+// the characters that are written by name after `#\`
+const CHAR_NAMES: &[(&str, char)] = &[("newline", '\n'), ("space", ' '), ("tab", '\t'), ("return", '\r'), ("nul", '\0')];
+
+pub(crate) fn char_named(name: &str) -> Option<char> {
+    CHAR_NAMES.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+}
+
+pub(crate) fn char_name(chr: char) -> Option<&'static str> {
+    CHAR_NAMES.iter().find(|(_, c)| *c == chr).map(|(n, _)| *n)
+}
+
+// ends the atom being read, if any
+fn push_atom(ret: &mut VecDeque<Result<SpannedToken, LispError>>, buffer: &mut String, start: Pos) {
+    if !buffer.is_empty() {
+        ret.push_back(Ok(SpannedToken::at(Token::Atom(std::mem::take(buffer)), start)));
+    }
+}
+
+pub fn tokens(content: &str) -> Result<Vec<SpannedToken>, LispError> {
+    lex(content).map(|(tokens, _)| tokens)
+}
+
+// like `tokens`, also returning where each comment starts
+pub(crate) fn lex(content: &str) -> Result<(Vec<SpannedToken>, Vec<Pos>), LispError> {
+    let mut lexer = Lexer::new(content.chars().map(Ok));
+    let tokens = lexer.by_ref().collect::<Result<Vec<_>, _>>()?;
+    Ok((tokens, lexer.comments))
+}
+
+// tokens read from `reader` as they are needed, so the whole source never has
+// to be in memory at once; a malformed token is an error in its place, and the
+// stream only ends early if the reader fails or isn't UTF-8
+pub fn stream_tokens(reader: impl BufRead) -> impl Iterator<Item = Result<SpannedToken, LispError>> {
+    Lexer::new(Utf8Chars { reader })
+}
+
+// decodes the characters of a reader one at a time
+struct Utf8Chars<R> {
+    reader: R,
+}
+
+impl<R: BufRead> Utf8Chars<R> {
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        let Some(&byte) = self.reader.fill_buf()?.first() else {
+            return Ok(None);
+        };
+        self.reader.consume(1);
+        Ok(Some(byte))
+    }
+}
+
+impl<R: BufRead> Iterator for Utf8Chars<R> {
+    type Item = io::Result<char>;
+    fn next(&mut self) -> Option<io::Result<char>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8");
+        let first = match self.byte() {
+            Ok(Some(byte)) => byte,
+            Ok(None) => return None,
+            Err(err) => return Some(Err(err)),
+        };
+        let width = match first.leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => return Some(Err(invalid())),
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for slot in &mut bytes[1..width] {
+            *slot = match self.byte() {
+                Ok(Some(byte)) => byte,
+                Ok(None) => return Some(Err(invalid())),
+                Err(err) => return Some(Err(err)),
+            };
+        }
+        let decoded = std::str::from_utf8(&bytes[..width]).ok().and_then(|s| s.chars().next());
+        Some(decoded.ok_or_else(invalid))
+    }
+}
+
+// turns characters into tokens, reading only as far as the next token needs
+struct Lexer<I> {
+    chars: I,
+    // a character looked at but not taken yet
+    peeked: Option<char>,
+    // where the next character is
+    pos: Pos,
+    parser: Parser,
+    buffer: String,
+    // start of the atom, string or comment being read
+    start: Pos,
+    // where the comments read so far start, not counting a `#!` line
+    comments: Vec<Pos>,
+    // finished tokens and errors not handed out yet
+    ready: VecDeque<Result<SpannedToken, LispError>>,
+    // the input ended, or can't be read any further
+    done: bool,
+}
+
+impl<I: Iterator<Item = io::Result<char>>> Lexer<I> {
+    fn new(chars: I) -> Lexer<I> {
+        let start = Pos { line: 1, col: 1 };
+        Lexer {
+            chars,
+            peeked: None,
+            pos: start,
+            parser: Parser::OnSymbol,
+            buffer: String::new(),
+            start,
+            comments: vec![],
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+    fn peek(&mut self) -> Result<Option<char>, LispError> {
+        if self.peeked.is_none() {
+            self.peeked = self.chars.next().transpose().map_err(|err| {
+                self.done = true;
+                LispError::at(self.pos, err)
+            })?;
+        }
+        Ok(self.peeked)
+    }
+    fn next_char(&mut self) -> Result<Option<(Pos, char)>, LispError> {
+        let Some(chr) = self.peek()? else {
+            return Ok(None);
+        };
+        self.peeked = None;
+        let here = self.pos;
+        if chr == '\n' {
+            self.pos = Pos { line: here.line + 1, col: 1 };
+        } else {
+            self.pos.col += 1;
+        }
+        Ok(Some((here, chr)))
+    }
+    // takes the next character if it's `chr`
+    fn next_is(&mut self, chr: char) -> Result<bool, LispError> {
+        if self.peek()? == Some(chr) {
+            self.next_char()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+    fn emit(&mut self, token: Token, pos: Pos) {
+        self.ready.push_back(Ok(SpannedToken::at(token, pos)));
+    }
+    fn end_atom(&mut self) {
+        push_atom(&mut self.ready, &mut self.buffer, self.start);
+    }
+    fn step(&mut self, here: Pos, chr: char) -> Result<(), LispError> {
+        match self.parser {
+            Parser::OnSymbol => match chr {
+                // prefixes only count at the start of a token, so `don't` stays one symbol
+                '\'' if self.buffer.is_empty() => self.emit(Token::Quote, here),
+                '`' if self.buffer.is_empty() => self.emit(Token::Quasiquote, here),
+                '~' if self.buffer.is_empty() => {
+                    if self.next_is('@')? {
+                        self.emit(Token::UnquoteSplicing, here);
+                    } else {
+                        self.emit(Token::Unquote, here);
+                    }
+                }
+                '(' => {
+                    self.end_atom();
+                    self.emit(Token::Open, here);
+                }
+                ')' => {
+                    self.end_atom();
+                    self.emit(Token::Close, here);
+                }
+                '[' => {
+                    self.end_atom();
+                    self.emit(Token::OpenBracket, here);
+                }
+                ']' => {
+                    self.end_atom();
+                    self.emit(Token::CloseBracket, here);
+                }
+                '{' => {
+                    self.end_atom();
+                    self.emit(Token::OpenBrace, here);
+                }
+                '}' => {
+                    self.end_atom();
+                    self.emit(Token::CloseBrace, here);
+                }
+                // any whitespace separates tokens, so CRLF files split like LF ones
+                ws if ws.is_whitespace() => self.end_atom(),
+                '"' => {
+                    self.end_atom();
+                    self.start = here;
+                    self.parser = Parser::OnString { escape: None };
+                }
+                ';' => {
+                    self.end_atom();
+                    self.comments.push(here);
+                    self.parser = Parser::OnLineComment;
+                }
+                // a `#!` line starting the file is for the shell that runs it as a script
+                '#' if here == (Pos { line: 1, col: 1 }) && self.next_is('!')? => self.parser = Parser::OnLineComment,
+                '#' if self.buffer.is_empty() && self.next_is('\\')? => {
+                    let chr = self.char_literal(here)?;
+                    self.emit(Token::Char(chr), here);
+                }
+                '#' if self.buffer.is_empty() && self.next_is('|')? => {
+                    self.start = here;
+                    self.comments.push(here);
+                    self.parser = Parser::OnBlockComment { depth: 1 };
+                }
+                '#' if self.buffer.is_empty() => {
+                    let literal = self.hash_literal(here)?;
+                    self.emit(Token::Atom(literal.to_owned()), here);
+                }
+                other => {
+                    if self.buffer.is_empty() {
+                        self.start = here;
+                    }
+                    self.buffer.push(other);
+                }
+            },
+            Parser::OnString { escape: Some(escape) } => {
+                self.parser = Parser::OnString { escape: None };
+                let c = match chr {
+                    '"' => '"',
+                    '\\' => '\\',
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    'u' => self.unicode_escape(escape)?,
+                    other => {
+                        return Err(LispError::at(
+                            escape,
+                            format!("unknown escape `\\{other}`, expected one of \\\" \\\\ \\n \\t \\r \\0 \\u{{...}}"),
+                        ))
+                    }
+                };
+                self.buffer.push(c);
+            }
+            Parser::OnString { escape: None } => match chr {
+                '"' => {
+                    let string = std::mem::take(&mut self.buffer);
+                    self.emit(Token::Str(string), self.start);
+                    self.parser = Parser::OnSymbol;
+                }
+                '\\' => self.parser = Parser::OnString { escape: Some(here) },
+                other => self.buffer.push(other),
+            },
+            Parser::OnLineComment => {
+                if chr == '\n' {
+                    self.parser = Parser::OnSymbol;
+                }
+            }
+            Parser::OnBlockComment { depth } => {
+                if chr == '|' && self.next_is('#')? {
+                    self.parser = match depth {
+                        1 => Parser::OnSymbol,
+                        _ => Parser::OnBlockComment { depth: depth - 1 },
+                    };
+                } else if chr == '#' && self.next_is('|')? {
+                    self.parser = Parser::OnBlockComment { depth: depth + 1 };
+                }
+            }
+        }
+        Ok(())
+    }
+    // the rest of a `#t`, `#f` or `#nil` literal, as the atom it stands for
+    fn hash_literal(&mut self, start: Pos) -> Result<&'static str, LispError> {
+        let mut name = String::new();
+        while let Some(c) = self.peek()?.filter(|c| !c.is_whitespace() && !"()[]{}\";".contains(*c)) {
+            self.next_char()?;
+            name.push(c);
+        }
+        match &name[..] {
+            "t" | "true" => Ok("true"),
+            "f" | "false" => Ok("false"),
+            "nil" => Ok("nil"),
+            _ => Err(LispError::at(
+                start,
+                format!("unknown literal `#{name}`, expected #t, #f, #nil, a character like #\\a or a #| comment |#"),
+            )),
+        }
+    }
+    // the rest of a `#\` character literal, a single character or the name of one
+    fn char_literal(&mut self, start: Pos) -> Result<char, LispError> {
+        let Some((_, first)) = self.next_char()? else {
+            return Err(LispError::at(start, "expected a character after `#\\`"));
+        };
+        let mut name = String::from(first);
+        if first.is_alphanumeric() {
+            while let Some(c) = self.peek()?.filter(|c| c.is_alphanumeric()) {
+                self.next_char()?;
+                name.push(c);
+            }
+        }
+        if name.chars().count() == 1 {
+            return Ok(first);
+        }
+        char_named(&name).ok_or_else(|| LispError::at(start, format!("unknown character name `#\\{name}`")))
+    }
+    // the rest of a `\u{XXXX}` escape, 1 to 6 hex digits naming a unicode scalar value
+    fn unicode_escape(&mut self, escape: Pos) -> Result<char, LispError> {
+        let malformed = || LispError::at(escape, "malformed unicode escape, expected `\\u{XXXX}` with 1 to 6 hex digits");
+        if !self.next_is('{')? {
+            return Err(malformed());
+        }
+        let mut digits = String::new();
+        loop {
+            match self.next_char()? {
+                Some((_, '}')) if !digits.is_empty() => break,
+                Some((_, c)) if c.is_ascii_hexdigit() && digits.len() < 6 => digits.push(c),
+                _ => return Err(malformed()),
+            }
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| LispError::at(escape, format!("`\\u{{{digits}}}` is not a valid unicode character")))
+    }
+    // the end of the input, which must not be inside a string or block comment
+    fn finish(&mut self) -> Result<(), LispError> {
+        match self.parser {
+            Parser::OnString { .. } => {
+                return Err(LispError::Incomplete { pos: self.start, msg: "unterminated string".to_owned() })
+            }
+            Parser::OnBlockComment { .. } => {
+                return Err(LispError::Incomplete { pos: self.start, msg: "unterminated `#|` comment".to_owned() })
+            }
+            Parser::OnSymbol | Parser::OnLineComment => {}
+        }
+        self.end_atom();
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = io::Result<char>>> Iterator for Lexer<I> {
+    type Item = Result<SpannedToken, LispError>;
+    fn next(&mut self) -> Option<Result<SpannedToken, LispError>> {
+        loop {
+            if let Some(token) = self.ready.pop_front() {
+                return Some(token);
+            }
+            if self.done {
+                return None;
+            }
+            let res = match self.next_char() {
+                Ok(Some((here, chr))) => self.step(here, chr),
+                Ok(None) => {
+                    self.done = true;
+                    self.finish()
+                }
+                Err(err) => Err(err),
+            };
+            // the lexer picks up after the bad token, unless reading failed
+            if let Err(err) = res {
+                self.ready.push_back(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<Token> {
+        tokens(source).unwrap().into_iter().map(|token| token.token).collect()
+    }
+
+    fn atom(text: &str) -> Token {
+        Token::Atom(text.to_owned())
+    }
+
+    #[test]
+    fn delimiters_and_prefixes() {
+        assert_eq!(
+            kinds("('a `[b ~c ~@d] {:k 1})"),
+            [
+                Token::Open,
+                Token::Quote,
+                atom("a"),
+                Token::Quasiquote,
+                Token::OpenBracket,
+                atom("b"),
+                Token::Unquote,
+                atom("c"),
+                Token::UnquoteSplicing,
+                atom("d"),
+                Token::CloseBracket,
+                Token::OpenBrace,
+                atom(":k"),
+                atom("1"),
+                Token::CloseBrace,
+                Token::Close,
+            ]
+        );
+        // a prefix inside a symbol is part of it
+        assert_eq!(kinds("don't"), [atom("don't")]);
+    }
+
+    #[test]
+    fn strings_and_chars() {
+        assert_eq!(kinds(r#""a\"b\\c\n\t\u{e9}""#), [Token::Str("a\"b\\c\n\té".to_owned())]);
+        assert_eq!(kinds(r"#\a #\space #\( #\newline"), [Token::Char('a'), Token::Char(' '), Token::Char('('), Token::Char('\n')]);
+        assert_eq!(kinds("#t #false #nil"), [atom("true"), atom("false"), atom("nil")]);
+    }
+
+    #[test]
+    fn comments_and_positions() {
+        let source = "#!/usr/bin/env sxprs\n; a comment\n(a #| nested #| block |# |# b)\r\n  c";
+        let (tokens, comments) = lex(source).unwrap();
+        let positions: Vec<(Token, Pos)> = tokens.into_iter().map(|token| (token.token, token.pos)).collect();
+        assert_eq!(
+            positions,
+            [
+                (Token::Open, Pos { line: 3, col: 1 }),
+                (atom("a"), Pos { line: 3, col: 2 }),
+                (atom("b"), Pos { line: 3, col: 29 }),
+                (Token::Close, Pos { line: 3, col: 30 }),
+                (atom("c"), Pos { line: 4, col: 3 }),
+            ]
+        );
+        assert_eq!(comments, [Pos { line: 2, col: 1 }, Pos { line: 3, col: 4 }]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(tokens("\"open"), Err(LispError::Incomplete { pos: Pos { line: 1, col: 1 }, .. })));
+        assert!(matches!(tokens("#| open"), Err(LispError::Incomplete { .. })));
+        assert!(matches!(tokens(r#""\q""#), Err(LispError::Syntax { pos: Pos { line: 1, col: 2 }, .. })));
+        assert!(matches!(tokens(r#""\u{110000}""#), Err(LispError::Syntax { .. })));
+        assert!(matches!(tokens("#x"), Err(LispError::Syntax { .. })));
+        assert!(matches!(tokens(r"#\bogus"), Err(LispError::Syntax { .. })));
+    }
+
+    #[test]
+    fn streams_like_it_reads_a_string() {
+        let source = "(define s \"é\") ; comment\n[#\\λ 1.5]";
+        let streamed: Vec<SpannedToken> = stream_tokens(source.as_bytes()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(streamed, tokens(source).unwrap());
+        // a bad token is an error in its place, and reading goes on after it
+        let streamed: Vec<bool> = stream_tokens("a #x b".as_bytes()).map(|token| token.is_ok()).collect();
+        assert_eq!(streamed, [true, false, true]);
+        let invalid: &[u8] = b"(a \xff)";
+        assert!(stream_tokens(invalid).any(|token| token.is_err()));
+    }
+}
@@ -0,0 +1,73 @@
+//! S-expression parser and interpreter.
+//!
+//! ```
+//! let lisp = sxprs::Interpreter::new();
+//! let value = lisp.eval_str("(define x 2) (+ x 3)").unwrap();
+//! assert_eq!(value.get_int().unwrap(), 5);
+//! ```
+#[cfg(not(target_arch = "wasm32"))]
+mod attach;
+mod atom;
+mod builtins;
+mod error;
+mod eval;
+mod exp;
+mod format;
+#[cfg(feature = "graphics")]
+mod graphics;
+mod heap;
+mod help;
+mod image;
+mod math;
+mod num;
+mod opt;
+mod parallel;
+mod pipe;
+mod platform;
+#[cfg(feature = "serde")]
+mod serde;
+mod pretty;
+mod profile;
+mod schedule;
+mod span;
+mod structs;
+mod symbol;
+#[cfg(feature = "tracing")]
+mod telemetry;
+mod vm;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+pub mod audit;
+pub mod bundle;
+pub mod check;
+pub mod cst;
+pub mod embed;
+pub mod examples;
+pub mod expand;
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fmt;
+pub mod host;
+pub mod json;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod kernel;
+pub mod lang;
+pub mod learn;
+pub mod lexer;
+pub mod manifest;
+pub mod parser;
+pub mod project;
+#[cfg(feature = "python")]
+mod python;
+pub mod query;
+pub mod refactor;
+#[cfg(feature = "signing")]
+pub mod sign;
+
+pub use error::LispError;
+pub use eval::{Interpreter, Metrics, Tracer};
+pub use exp::{LispExp, LispLambda, MapKey};
+pub use host::ScriptHost;
+pub use profile::ProfileEntry;
+pub use symbol::SymbolId;
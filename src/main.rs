@@ -1,346 +1,1485 @@
-#[derive(Debug, Clone)]
-enum LispExp {
-    Symbol(String),
-    Number(f64),
-    List(Vec<LispExp>),
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use sxprs::audit::{audit, capabilities};
+use sxprs::bundle::bundle;
+use sxprs::check::check_types;
+use sxprs::embed::embed;
+use sxprs::examples::{categories, example, EXAMPLES};
+use sxprs::expand::expand_program;
+use sxprs::explain::{explain, id, EXPLANATIONS};
+use sxprs::fmt::{flat, format_program};
+use sxprs::json::{diagnostic, error_diagnostic, escape, to_json};
+use sxprs::kernel::serve;
+use sxprs::lang::Lang;
+use sxprs::learn::{attempt, LESSONS};
+use sxprs::lexer::{tokens, Pos};
+use sxprs::manifest::Manifest;
+use sxprs::parser::{parse_lenient, parse_program_spanned, parse_program_with_depth};
+use sxprs::project::Project;
+use sxprs::query::query;
+use sxprs::refactor::{extract, rename, span};
+use sxprs::{Interpreter, LispError, LispExp, MapKey, Tracer};
+
+// how source bytes that aren't valid UTF-8 are handled
+#[derive(Clone, Copy)]
+enum Encoding {
+    Utf8,
+    Lossy,
+    Latin1,
+}
+
+fn decode_source(bytes: Vec<u8>, encoding: Encoding) -> Result<String, String> {
+    match encoding {
+        Encoding::Utf8 => String::from_utf8(bytes).map_err(|e| {
+            format!(
+                "invalid UTF-8 at byte offset {} (use --lossy or --latin1 to read it anyway)",
+                e.utf8_error().valid_up_to()
+            )
+        }),
+        Encoding::Lossy => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+    }
+}
+
+// evaluates and prints every form in `program`, stopping at the first error
+fn eval_forms(lisp: &Interpreter, program: &[(LispExp, Pos)]) -> Result<(), LispError> {
+    for (exp, pos) in program {
+        println!("{}", lisp.show(&lisp.run_at(exp, *pos)?));
+    }
+    Ok(())
 }
 
-impl LispExp {
-    fn name(&self) -> &'static str {
+// the top-level forms of a program, with where each starts
+type Program = Vec<(LispExp, Pos)>;
+
+fn parse_source(source: &str) -> Result<Program, LispError> {
+    parse_program_spanned(&tokens(source)?, MAX_DEPTH)
+}
+
+// how errors are written to stderr: as text for people, in the language
+// `--lang` picked, or with `--error-format=json` as one JSON diagnostic per
+// line, for editors, which stays in English
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    Human(Lang),
+    Json,
+}
+
+impl ErrorFormat {
+    fn in_lang(self, lang: Lang) -> ErrorFormat {
         match self {
-            LispExp::Number(_)=>"Number",
-            LispExp::Symbol(_)=>"Symbol",
-            LispExp::List(_)=>"List",
+            ErrorFormat::Human(_) => ErrorFormat::Human(lang),
+            ErrorFormat::Json => ErrorFormat::Json,
         }
     }
-    fn get_symbol(&self) -> Result<&str, ListError> {
-        if let LispExp::Symbol(n) = self {
-            Ok(n)
-        } else {
-            Err(format!("`{self}`\n{self:?}\nis not a symbol, it's a {}", self.name()).into())
+}
+
+fn parse_error_format(arg: &str) -> Option<ErrorFormat> {
+    match arg.strip_prefix("--error-format=")? {
+        "human" => Some(ErrorFormat::Human(Lang::En)),
+        "json" => Some(ErrorFormat::Json),
+        other => fail(format!("unknown error format {other}, expected human or json")),
+    }
+}
+
+fn parse_lang(name: Option<String>) -> Lang {
+    let name = name.unwrap_or_else(|| fail(format!("--lang expects a language: {}", Lang::names().join(", "))));
+    Lang::parse(&name).unwrap_or_else(|| fail(format!("unknown language {name}, expected {}", Lang::names().join(" or "))))
+}
+
+fn report(err: &LispError, file: Option<&str>, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Human(lang) => {
+            eprintln!("{}", err.display_in(lang));
+            // what the program raised itself is explained by its message
+            if !matches!(err.root(), LispError::Raised(_)) {
+                eprintln!("  {}", lang.see_explain(err.id()));
+            }
         }
+        ErrorFormat::Json => eprintln!("{}", error_diagnostic(err, file)),
     }
-    fn get_number(&self) -> Result<f64, ListError> {
-        if let LispExp::Number(n) = self {
-            Ok(*n)
-        } else {
-            Err(ListError(format!("{self:?} is not a number")))
+}
+
+// reports `err` and exits with 1
+fn fail_with(err: &LispError, file: Option<&str>, format: ErrorFormat) -> ! {
+    report(err, file, format);
+    std::process::exit(1);
+}
+
+// completes the symbol before the cursor from the names bound in the
+// interpreter, which the REPL refreshes after every form
+#[derive(Default)]
+struct Completions {
+    names: Vec<String>,
+}
+
+// what can't be part of a symbol
+fn is_delimiter(chr: char) -> bool {
+    chr.is_whitespace() || "()[]{}'`,\"".contains(chr)
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rfind(|&(_, chr)| is_delimiter(chr))
+            .map_or(0, |(at, chr)| at + chr.len_utf8());
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((pos, vec![]));
         }
+        let matches = self.names.iter().filter(|name| name.starts_with(prefix)).cloned().collect();
+        Ok((start, matches))
     }
 }
 
-use std::fmt::Display;
-impl Display for LispExp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            LispExp::Symbol(symb) => write!(f, "\"{symb}\""),
-            LispExp::Number(num) => write!(f, "{}", num),
-            LispExp::List(cdr) => {
-                let cont: Vec<String> = cdr.iter().map(LispExp::to_string).collect();
-                write!(f, "( {} )", cont.join(" "))
+impl Hinter for Completions {
+    type Hint = String;
+}
+impl Highlighter for Completions {}
+impl Validator for Completions {}
+impl Helper for Completions {}
+
+type LispEditor = Editor<Completions, DefaultHistory>;
+
+// reads lines until they make up complete forms, continuing with a secondary
+// prompt while a list, string or the like is left open; None at the end of
+// input
+fn read_forms(editor: &mut LispEditor) -> Option<(String, Result<Program, LispError>)> {
+    let mut source = String::new();
+    loop {
+        let prompt = if source.is_empty() { "> " } else { ".. " };
+        match editor.readline(prompt) {
+            Ok(line) => source.push_str(&line),
+            // Ctrl-C drops what was typed so far
+            Err(ReadlineError::Interrupted) => {
+                source.clear();
+                continue;
+            }
+            // what's left open is reported before stopping
+            Err(ReadlineError::Eof) if !source.is_empty() => {
+                let program = parse_source(&source);
+                return Some((source, program));
+            }
+            Err(ReadlineError::Eof) => return None,
+            Err(err) => {
+                eprintln!("{err}");
+                return None;
+            }
+        }
+        match parse_source(&source) {
+            Err(err) if err.is_incomplete() => source.push('\n'),
+            res => return Some((source, res)),
+        }
+    }
+}
+
+// the user's own definitions, run before every program and the REPL unless
+// `--no-init`: `$XDG_CONFIG_HOME/sxprs/init.lsp`, or `~/.config/sxprs/init.lsp`
+fn init_path() -> Option<std::path::PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => std::env::home_dir()?.join(".config"),
+    };
+    Some(config.join("sxprs").join("init.lsp"))
+}
+
+// where the REPL keeps its history between sessions
+fn history_path() -> Option<std::path::PathBuf> {
+    std::env::home_dir().map(|home| home.join(".sxprs_history"))
+}
+
+// how far `sxprs learn` got: the lesson to pick up at, counting from 0
+fn learn_path() -> Option<std::path::PathBuf> {
+    std::env::home_dir().map(|home| home.join(".sxprs_learn"))
+}
+
+// `sxprs learn` is a tutorial in the REPL, picking up at the lesson after the
+// last one done; `sxprs learn 3` starts at the third and `--reset` at the
+// first. `:hint`, `:answer`, `:skip` and `:quit` are typed at the prompt
+fn learn_command(args: impl Iterator<Item = String>) {
+    let progress = learn_path();
+    let saved = progress.as_ref().and_then(|path| std::fs::read_to_string(path).ok()).and_then(|text| text.trim().parse::<usize>().ok());
+    let mut start = saved.unwrap_or(0);
+    for arg in args {
+        match &arg[..] {
+            "--reset" => start = 0,
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            number => match number.parse::<usize>() {
+                Ok(lesson) if (1..=LESSONS.len()).contains(&lesson) => start = lesson - 1,
+                _ => fail(format!("learn expects a lesson from 1 to {}", LESSONS.len())),
+            },
+        }
+    }
+    let save = |next: usize| {
+        if let Some(path) = &progress {
+            if let Err(err) = std::fs::write(path, format!("{next}\n")) {
+                eprintln!("couldn't save how far you got to {}: {err}", path.display());
+            }
+        }
+    };
+    if start >= LESSONS.len() {
+        println!("you've done every lesson; `sxprs learn --reset` starts over");
+        return;
+    }
+    save(start);
+    let mut lisp = Interpreter::new();
+    lisp.set_max_depth(MAX_DEPTH);
+    let mut editor = LispEditor::new().unwrap_or_else(|err| fail(err));
+    println!("type code at the prompt to try it; :hint, :answer, :skip or :quit");
+    for (at, lesson) in LESSONS.iter().enumerate().skip(start) {
+        println!("\n{}/{} {}\n\n{}\n\n{}", at + 1, LESSONS.len(), lesson.title, lesson.text, lesson.task);
+        loop {
+            // the lesson isn't done, so it's the one to pick up at
+            let Some((source, program)) = read_forms(&mut editor) else {
+                return;
+            };
+            match source.trim() {
+                "" => continue,
+                ":quit" => return,
+                ":skip" => break,
+                ":hint" => {
+                    println!("{}", lesson.hint);
+                    continue;
+                }
+                ":answer" => {
+                    println!("{}", lesson.answer);
+                    continue;
+                }
+                _ => {}
+            }
+            let _ = editor.add_history_entry(&source);
+            if let Err(err) = program {
+                report(&err, None, ErrorFormat::Human(Lang::En));
+                continue;
+            }
+            match attempt(&lisp, lesson, &source) {
+                Ok((value, done)) => {
+                    println!("{}", lisp.show(&value));
+                    if done {
+                        println!("that's it!");
+                        break;
+                    }
+                }
+                Err(LispError::Exit(_)) => return,
+                Err(err) => report(&err, None, ErrorFormat::Human(Lang::En)),
+            }
+        }
+        save(at + 1);
+    }
+    println!("\nthat was the last lesson; the README has the rest of the language");
+}
+
+// Ctrl-C while a form is being evaluated stops it, instead of the REPL
+fn repl(mut lisp: Interpreter, format: ErrorFormat) {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let handler = {
+        let interrupt = interrupt.clone();
+        move || interrupt.store(true, Ordering::Relaxed)
+    };
+    if let Err(err) = ctrlc::set_handler(handler) {
+        eprintln!("couldn't install the Ctrl-C handler: {err}");
+    }
+    lisp.set_interrupt(interrupt.clone());
+    let mut editor = LispEditor::new().unwrap_or_else(|err| fail(err));
+    let names = || lisp.names().iter().map(ToString::to_string).collect();
+    editor.set_helper(Some(Completions { names: names() }));
+    // piped input isn't something to recall later
+    let history = if std::io::stdin().is_terminal() { history_path() } else { None };
+    if let Some(path) = &history {
+        // there's no history yet on the first run
+        let _ = editor.load_history(path);
+    }
+    let save_history = |editor: &mut LispEditor| {
+        if let Some(path) = &history {
+            if let Err(err) = editor.save_history(path) {
+                eprintln!("couldn't save the history to {}: {err}", path.display());
+            }
+        }
+    };
+    while let Some((source, program)) = read_forms(&mut editor) {
+        if !source.trim().is_empty() {
+            let _ = editor.add_history_entry(&source);
+        }
+        // a Ctrl-C from before the form was entered doesn't stop it
+        interrupt.store(false, Ordering::Relaxed);
+        match program.and_then(|program| eval_forms(&lisp, &program)) {
+            Ok(()) => {}
+            Err(LispError::Exit(code)) => {
+                save_history(&mut editor);
+                std::process::exit(code);
             }
+            Err(err) => report(&err, None, format),
+        }
+        // what the form defined can be completed from now on
+        if let Some(helper) = editor.helper_mut() {
+            helper.names = names();
         }
     }
+    save_history(&mut editor);
 }
 
-#[derive(Debug)]
-struct ListError(String);
-impl Display for ListError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(f, "Lisp Processing Error: {}", self.0)
+// `--trace`: prints every expression as it's evaluated and the value it
+// evaluated to, indented by how nested it is
+struct PrintTracer;
+
+impl Tracer for PrintTracer {
+    fn enter(&self, exp: &LispExp, depth: usize) {
+        eprintln!("{}{exp}", "  ".repeat(depth));
+    }
+    fn value(&self, value: &LispExp, depth: usize) {
+        eprintln!("{}=> {value}", "  ".repeat(depth));
     }
 }
 
-use std::convert::From;
-impl From<String> for ListError {
-    fn from(value: String) -> ListError {
-        ListError(value)
+// `--profile`: how many times each function was called and how long it took,
+// the slowest first, written to stderr once the program is done
+fn print_profile(lisp: &Interpreter) {
+    eprintln!("{:>10} {:>12} {:>12}  function", "calls", "total ms", "self ms");
+    for entry in lisp.profile() {
+        let total = entry.total.as_secs_f64() * 1000.0;
+        let self_time = entry.self_time.as_secs_f64() * 1000.0;
+        eprintln!("{:>10} {total:>12.3} {self_time:>12.3}  {}", entry.calls, entry.name);
     }
 }
-impl From<&str> for ListError {
-    fn from(value: &str) -> ListError {
-        ListError(value.to_owned())
+
+// where the program comes from
+enum Input {
+    Repl,
+    File(String),
+    Stdin,
+    Expr(String),
+}
+
+fn fail(msg: impl Display) -> ! {
+    eprintln!("{msg}");
+    std::process::exit(1);
+}
+
+fn read_source(input: &Input, encoding: Encoding) -> Result<String, String> {
+    use std::io::Read;
+    let (name, bytes) = match input {
+        Input::File(path) => (&path[..], std::fs::read(path)),
+        Input::Stdin => {
+            let mut bytes = vec![];
+            let res = std::io::stdin().read_to_end(&mut bytes).map(|_| bytes);
+            ("<stdin>", res)
+        }
+        Input::Expr(expr) => return Ok(expr.clone()),
+        Input::Repl => unreachable!("the repl reads its own input"),
+    };
+    let bytes = bytes.map_err(|err| format!("{name}: {err}"))?;
+    decode_source(bytes, encoding).map_err(|err| format!("{name}: {err}"))
+}
+
+fn input_name(input: &Input) -> &str {
+    match input {
+        Input::File(path) => &path[..],
+        Input::Stdin => "<stdin>",
+        Input::Expr(_) => "<expr>",
+        Input::Repl => unreachable!("the repl isn't checked"),
     }
 }
 
-impl From<f64> for LispExp {
-    fn from(value: f64) -> LispExp {
-        LispExp::Number(value)
+// `--check`: parses the program without running it, printing a
+// `file:line:col: message` diagnostic for every problem; returns the program
+// as far as it could be read, with placeholders where it couldn't, and whether
+// there were no problems
+fn check_program(input: &Input, source: &str, format: ErrorFormat) -> (Vec<LispExp>, bool) {
+    let name = input_name(input);
+    let (program, mut errors) = parse_lenient(source, MAX_DEPTH);
+    errors.sort_by_key(|err| err.pos().map(|pos| (pos.line, pos.col)));
+    for err in &errors {
+        match (format, err.pos()) {
+            (ErrorFormat::Json, _) => eprintln!("{}", error_diagnostic(err, Some(name))),
+            (ErrorFormat::Human(lang), Some(_)) => eprintln!("{name}:{} [{}]", err.message_in(lang), err.id()),
+            (ErrorFormat::Human(lang), None) => eprintln!("{name}: {} [{}]", err.message_in(lang), err.id()),
+        }
     }
+    (program, errors.is_empty())
 }
-impl From<&str> for LispExp {
-    fn from(value: &str) -> LispExp {
-        LispExp::Symbol(value.to_owned())
+
+// `sxprs check [--types] file` is `--check`, and with --types also looks for
+// calls that can't work, without running the program
+fn check_command(args: impl Iterator<Item = String>) {
+    let mut types = false;
+    let mut format = ErrorFormat::Human(Lang::En);
+    let mut lang = Lang::En;
+    let mut path = None;
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if let Some(error_format) = parse_error_format(&arg) {
+            format = error_format;
+            continue;
+        }
+        match &arg[..] {
+            "--types" => types = true,
+            "--lang" => lang = parse_lang(args.next()),
+            flag if flag.starts_with('-') && flag != "-" => fail(format!("unknown argument {flag}")),
+            _ => path = Some(arg),
+        }
+    }
+    let input = match path.as_deref() {
+        None => fail("check expects a file"),
+        Some("-") => Input::Stdin,
+        Some(path) => Input::File(path.to_owned()),
+    };
+    let source = read_source(&input, Encoding::Utf8).unwrap_or_else(|err| fail(err));
+    // the rest of a file being edited is still worth checking
+    let format = format.in_lang(lang);
+    let (program, parsed) = check_program(&input, &source, format);
+    if !types {
+        if !parsed {
+            std::process::exit(1);
+        }
+        return;
+    }
+    let problems = check_types(&Interpreter::new(), &program);
+    let name = input_name(&input);
+    for problem in &problems {
+        match format {
+            ErrorFormat::Human(_) => eprintln!("{name}: {problem} [{}]", id("check").unwrap_or_default()),
+            ErrorFormat::Json => eprintln!("{}", diagnostic("error", "check", problem, Some(name), None)),
+        }
+    }
+    if !parsed || !problems.is_empty() {
+        std::process::exit(1);
     }
 }
-impl From<String> for LispExp {
-    fn from(value: String) -> LispExp {
-        LispExp::Symbol(value)
+
+// `sxprs test file` runs the file, then every test it defined with
+// `deftest`, exiting with 1 if any failed
+fn test_command(mut args: impl Iterator<Item = String>) {
+    let input = match args.next().as_deref() {
+        None => fail("test expects a file"),
+        Some("-") => Input::Stdin,
+        Some(path) => Input::File(path.to_owned()),
+    };
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let source = read_source(&input, Encoding::Utf8).unwrap_or_else(|err| fail(err));
+    let mut lisp = Interpreter::new();
+    lisp.set_max_depth(MAX_DEPTH);
+    if let Input::File(path) = &input {
+        lisp.set_file(path);
+    }
+    match lisp.eval_str(&source) {
+        Ok(_) => {}
+        Err(LispError::Exit(code)) => std::process::exit(code),
+        Err(err) => fail(err),
+    }
+    let results = lisp.run_tests();
+    let mut failed = 0;
+    for (name, res) in &results {
+        match res {
+            Ok(_) => println!("test {name} ... ok"),
+            Err(err) => {
+                failed += 1;
+                println!("test {name} ... FAILED");
+                for line in err.message().lines() {
+                    println!("    {line}");
+                }
+            }
+        }
+    }
+    let status = if failed == 0 { "ok" } else { "FAILED" };
+    println!("\ntest result: {status}. {} passed; {failed} failed", results.len() - failed);
+    if failed > 0 {
+        std::process::exit(1);
     }
 }
-impl From<Vec<LispExp>> for LispExp {
-    fn from(value: Vec<LispExp>) -> LispExp {
-        LispExp::List(value)
+
+// counts what the process allocates, so `bench` can say what a run really
+// allocated; a reallocation counts as a new block of its new size
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
     }
 }
 
-enum Parser {
-    OnSymbol,
-    OnString { on_special: bool },
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+// how long a benchmark's runs took, and how many bytes in how many
+// allocations the last one made
+struct Bench {
+    min: std::time::Duration,
+    avg: std::time::Duration,
+    bytes: usize,
+    allocations: usize,
 }
 
-fn tokens(content: String) -> Result<Vec<String>, ListError> {
-    let mut ret: Vec<String> = vec![];
-    let mut buffer = String::new();
-    let mut parser = Parser::OnSymbol;
+fn bench_runs(source: &str, runs: usize, new_interpreter: impl Fn() -> Interpreter) -> Bench {
+    let mut times = vec![];
+    let (mut bytes, mut allocations) = (0, 0);
+    for _ in 0..runs {
+        let lisp = new_interpreter();
+        let before = (ALLOCATED.load(Ordering::Relaxed), ALLOCATIONS.load(Ordering::Relaxed));
+        let start = std::time::Instant::now();
+        match lisp.eval_str(source) {
+            Ok(_) | Err(LispError::Exit(0)) => {}
+            Err(err) => fail(err),
+        }
+        times.push(start.elapsed());
+        bytes = ALLOCATED.load(Ordering::Relaxed) - before.0;
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - before.1;
+    }
+    let min = times.iter().min().copied().unwrap_or_default();
+    let avg = times.iter().sum::<std::time::Duration>() / runs as u32;
+    Bench { min, avg, bytes, allocations }
+}
 
-    for chr in content.chars() {
-        match parser {
-            Parser::OnSymbol => match chr {
-                '(' => {
-                    ret.push(buffer);
-                    ret.push("(".to_owned());
-                    buffer = String::new();
-                }
-                ')' => {
-                    ret.push(buffer);
-                    ret.push(")".to_owned());
-                    buffer = String::new();
-                }
-                ' '|'\n'|'\t' => {
-                    ret.push(buffer);
-                    buffer = String::new();
-                }
-                '"' => {
-                    parser = Parser::OnString { on_special: false };
-                }
-                other => {
-                    if !other.is_whitespace() {
-                        buffer.push_str(&other.to_string());
+// `sxprs bench` and `--bench n`: runs the program `n` times, each in a fresh
+// interpreter, and prints the fastest and average run to stderr; with
+// `--compare`, `n` times on each engine, and how the other engine's time
+// compares to the one picked
+fn bench_program(source: &str, runs: usize, compare: bool, vm: bool, new_interpreter: impl Fn() -> Interpreter) {
+    if !compare {
+        let Bench { min, avg, bytes, allocations } = bench_runs(source, runs, new_interpreter);
+        eprintln!("{runs} runs: min {min:?}, avg {avg:?}, {bytes} bytes in {allocations} allocations");
+        return;
+    }
+    let on = |vm| {
+        bench_runs(source, runs, || {
+            let mut lisp = new_interpreter();
+            lisp.set_vm(vm);
+            lisp
+        })
+    };
+    let (tree, on_vm) = (on(false), on(true));
+    for (engine, Bench { min, avg, bytes, allocations }) in [("tree", &tree), ("vm", &on_vm)] {
+        eprintln!("{engine:<4}  {runs} runs: min {min:?}, avg {avg:?}, {bytes} bytes in {allocations} allocations");
+    }
+    let ((picked, base), (other, compared)) = match vm {
+        false => (("tree", &tree), ("vm", &on_vm)),
+        true => (("vm", &on_vm), ("tree", &tree)),
+    };
+    let ratio = compared.avg.as_secs_f64() / base.avg.as_secs_f64().max(f64::MIN_POSITIVE);
+    eprintln!("{other:<4}  {ratio:.2}x the time of {picked}, on average");
+}
+
+// how often `--watch` checks whether the files changed
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+// when each file was last changed, None for the ones that can't be read
+fn modified(files: &[std::path::PathBuf]) -> Vec<Option<std::time::SystemTime>> {
+    files.iter().map(|file| std::fs::metadata(file).and_then(|meta| meta.modified()).ok()).collect()
+}
+
+// `--watch`: runs the program in a fresh interpreter, and again every time it
+// or a file it loaded changes, until it's stopped with Ctrl-C
+fn watch(
+    path: &str,
+    encoding: Encoding,
+    format: ErrorFormat,
+    profile: bool,
+    new_interpreter: impl Fn() -> Interpreter,
+) -> ! {
+    loop {
+        let lisp = new_interpreter();
+        let res = read_source(&Input::File(path.to_owned()), encoding)
+            .map_err(LispError::Other)
+            .and_then(|source| lisp.eval_str(&source));
+        if profile {
+            print_profile(&lisp);
+        }
+        match res {
+            Ok(_) | Err(LispError::Exit(0)) => eprintln!("--- {path} finished, waiting for changes ---"),
+            Err(LispError::Exit(code)) => eprintln!("--- {path} exited with {code}, waiting for changes ---"),
+            Err(err) => {
+                report(&err, Some(path), format);
+                eprintln!("--- {path} failed, waiting for changes ---");
+            }
+        }
+        let mut files = vec![std::path::PathBuf::from(path)];
+        files.extend(lisp.loaded_files());
+        let seen = modified(&files);
+        while modified(&files) == seen {
+            std::thread::sleep(WATCH_INTERVAL);
+        }
+        eprintln!();
+    }
+}
+
+// the interpreter runs on its own thread with a large stack: evaluation keeps
+// its frames on the heap, but the special forms that evaluate their arguments
+// themselves still nest natively, and so do printing, comparing, compiling and
+// dropping deeply nested values
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+const MAX_DEPTH: usize = 20_000;
+
+fn main() {
+    let interpreter = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .unwrap_or_else(|err| fail(err));
+    if interpreter.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+// `sxprs fmt [--check] file` prints the file reformatted, or with --check
+// exits with 1 if formatting would change it
+fn fmt_command(args: impl Iterator<Item = String>) {
+    let mut check = false;
+    let mut path = None;
+    for arg in args {
+        match &arg[..] {
+            "--check" => check = true,
+            flag if flag.starts_with('-') && flag != "-" => fail(format!("unknown argument {flag}")),
+            _ => path = Some(arg),
+        }
+    }
+    let input = match path.as_deref() {
+        None => fail("fmt expects a file"),
+        Some("-") => Input::Stdin,
+        Some(path) => Input::File(path.to_owned()),
+    };
+    let source = read_source(&input, Encoding::Utf8).unwrap_or_else(|err| fail(err));
+    let formatted = format_program(&source).unwrap_or_else(|err| fail(err));
+    if !check {
+        print!("{formatted}");
+    } else if formatted != source {
+        fail(format!("{} is not formatted", path.unwrap_or_default()));
+    }
+}
+
+// `sxprs query selector [file]` prints the subtrees of the file, or stdin,
+// that the selector picks, one per line, exiting with 1 if there were none
+fn query_command(mut args: impl Iterator<Item = String>) {
+    let selector = args.next().unwrap_or_else(|| fail("query expects a selector"));
+    let input = match args.next().as_deref() {
+        None | Some("-") => Input::Stdin,
+        Some(path) => Input::File(path.to_owned()),
+    };
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let source = read_source(&input, Encoding::Utf8).unwrap_or_else(|err| fail(err));
+    let program = tokens(&source)
+        .and_then(|tokens| parse_program_with_depth(&tokens, MAX_DEPTH))
+        .unwrap_or_else(|err| fail(format!("{}:{}", input_name(&input), err.message())));
+    let found = query(&selector, &program).unwrap_or_else(|err| fail(err.message()));
+    for exp in &found {
+        println!("{}", flat(exp));
+    }
+    if found.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// `sxprs bundle file` prints the program with the files it loads bundled
+// into it, so it can be deployed as one file
+fn bundle_command(mut args: impl Iterator<Item = String>) {
+    let path = args.next().unwrap_or_else(|| fail("bundle expects a file"));
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let include = find_project(&Input::File(path.clone())).map(|project| project.include).unwrap_or_default();
+    let (bundled, warnings) = bundle(std::path::Path::new(&path), &include).unwrap_or_else(|err| fail(err.message()));
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    print!("{bundled}");
+}
+
+// `sxprs embed file [-o out.rs]` prints, or writes to `out.rs`, the `main`
+// of a standalone binary that runs the file and the files it loads
+fn embed_command(mut args: impl Iterator<Item = String>) {
+    let mut path = None;
+    let mut out = None;
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-o" => out = Some(args.next().unwrap_or_else(|| fail("-o expects a file to write"))),
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            _ if path.is_some() => fail(format!("unexpected argument {arg}")),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail("embed expects a file"));
+    let include = find_project(&Input::File(path.clone())).map(|project| project.include).unwrap_or_default();
+    let (rust, warnings) = embed(std::path::Path::new(&path), &include).unwrap_or_else(|err| match err.pos() {
+        Some(_) => fail(format!("{path}:{}", err.message())),
+        None => fail(err.message()),
+    });
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    match out {
+        Some(out) => std::fs::write(&out, rust).unwrap_or_else(|err| fail(format!("{out}: {err}"))),
+        None => print!("{rust}"),
+    }
+}
+
+// `sxprs kernel` answers notebook requests, one JSON line each, on stdin
+// and stdout; it's what `kernel/sxprs_kernel.py` runs for Jupyter. An
+// interrupt stops the cell that's running, like Ctrl-C in the REPL
+fn kernel_command(mut args: impl Iterator<Item = String>) {
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let mut lisp = Interpreter::new();
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let handler = {
+        let interrupt = interrupt.clone();
+        move || interrupt.store(true, Ordering::Relaxed)
+    };
+    if let Err(err) = ctrlc::set_handler(handler) {
+        eprintln!("couldn't install the Ctrl-C handler: {err}");
+    }
+    lisp.set_interrupt(interrupt);
+    if let Err(err) = serve(&lisp, std::io::stdin().lock(), std::io::stdout().lock()) {
+        fail(err);
+    }
+}
+
+// the field `name` of a JSON object read with `json_to_lisp`, nil when it has none
+fn field(object: &LispExp, name: &str) -> LispExp {
+    object.get_map().ok().and_then(|object| object.get(&MapKey::Str(name.to_owned())).cloned()).unwrap_or(LispExp::Nil)
+}
+
+// `sxprs attach addr` is a REPL for an interpreter running elsewhere, one
+// a host, or `sxprs --listen addr`, let clients attach to: forms are
+// evaluated there, while it runs, and what they print and their values shown
+// here. `:env` lists the variables the code running there can see
+fn attach_command(mut args: impl Iterator<Item = String>) {
+    let addr = args.next().unwrap_or_else(|| fail("attach expects an address"));
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let (reader, mut writer): (Box<dyn std::io::Read>, Box<dyn std::io::Write>) = if addr.contains('/') {
+        #[cfg(unix)]
+        {
+            let stream = std::os::unix::net::UnixStream::connect(&addr).unwrap_or_else(|err| fail(format!("{addr}: {err}")));
+            (Box::new(stream.try_clone().unwrap_or_else(|err| fail(err))), Box::new(stream))
+        }
+        #[cfg(not(unix))]
+        fail("there are no unix sockets here")
+    } else {
+        let stream = std::net::TcpStream::connect(&addr).unwrap_or_else(|err| fail(format!("{addr}: {err}")));
+        (Box::new(stream.try_clone().unwrap_or_else(|err| fail(err))), Box::new(stream))
+    };
+    let mut reader = std::io::BufReader::new(reader);
+    let mut request = |request: String| -> LispExp {
+        let mut reply = String::new();
+        let sent = writeln!(writer, "{request}").and_then(|_| writer.flush());
+        match sent.and_then(|_| std::io::BufRead::read_line(&mut reader, &mut reply)) {
+            Ok(0) => fail("the session ended"),
+            Ok(_) => sxprs::json::json_to_lisp(&reply, MAX_DEPTH).unwrap_or_else(|err| fail(format!("bad reply: {}", err.message()))),
+            Err(err) => fail(err),
+        }
+    };
+    let mut editor = LispEditor::new().unwrap_or_else(|err| fail(err));
+    editor.set_helper(Some(Completions::default()));
+    while let Some((source, program)) = read_forms(&mut editor) {
+        let _ = editor.add_history_entry(&source);
+        if source.trim() == ":env" {
+            let reply = request("{\"op\":\"env\"}".to_owned());
+            if let Ok(env) = field(&reply, "env").get_map() {
+                for (name, value) in env {
+                    if let MapKey::Str(name) = name {
+                        println!("{name} = {}", value.get_string().unwrap_or_default());
                     }
                 }
-            },
-            Parser::OnString { on_special } => {
-                if on_special {
-                    let c = match chr {
-                        '"' => Ok("\""),
-                        '\\' => Ok("\\"),
-                        'n' => Ok("\n"),
-                        other => Err(format!("no special formatting for '\\{}'", other)),
-                    }?;
-                    buffer.push_str(c);
-                    parser = Parser::OnString { on_special: false }
-                } else {
-                    match chr {
-                        '\"' => {
-                            ret.push(buffer);
-                            buffer = String::new();
-                            parser = Parser::OnSymbol;
-                        }
-                        '\\' => parser = Parser::OnString { on_special: true },
-                        other => {
-                            buffer.push_str(&other.to_string());
-                        }
-                    }
+            }
+            continue;
+        }
+        if let Err(err) = program {
+            report(&err, None, ErrorFormat::Human(Lang::En));
+            continue;
+        }
+        let reply = request(format!("{{\"op\":\"execute\",\"code\":{}}}", escape(&source)));
+        print!("{}", field(&reply, "stdout").get_string().unwrap_or_default());
+        eprint!("{}", field(&reply, "stderr").get_string().unwrap_or_default());
+        match field(&reply, "status").get_string() {
+            Ok("ok") => {
+                if let Ok(value) = field(&reply, "value").get_string() {
+                    println!("{value}");
+                }
+            }
+            _ => {
+                for line in field(&reply, "traceback").get_list().into_iter().flat_map(|lines| lines.iter()) {
+                    eprintln!("{}", line.get_string().unwrap_or_default());
                 }
             }
         }
     }
-    Ok(ret.into_iter().filter(|x| !x.is_empty()).collect())
 }
 
-fn parse(tokens: &[String]) -> Result<(LispExp, &[String]), ListError> {
-    let (token, rest) = tokens
-        .split_first()
-        .ok_or(ListError::from("could not get token"))?;
-    match &token[..] {
-        "(" => read_seq(rest),
-        ")" => Err(ListError::from("unexpected `)`")),
-        _ => Ok((parse_atom(token), rest)),
+// `sxprs audit file` prints where the program, and the files it loads and
+// imports, can run commands, touch files or the environment, or evaluate code
+// made while running, and then what that adds up to
+fn audit_command(mut args: impl Iterator<Item = String>) {
+    let path = args.next().unwrap_or_else(|| fail("audit expects a file"));
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let include = find_project(&Input::File(path.clone())).map(|project| project.include).unwrap_or_default();
+    let (findings, warnings) = audit(std::path::Path::new(&path), &include).unwrap_or_else(|err| fail(err.message()));
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    for found in &findings {
+        println!("{}:{}:{}: {} {}", found.file, found.line, found.col, found.capability, found.form);
+    }
+    match &capabilities(&findings)[..] {
+        [] => println!("needs nothing outside the interpreter"),
+        needs => println!("needs {}", needs.join(", ")),
     }
 }
 
-fn read_seq(tokens: &[String]) -> Result<(LispExp, &[String]), ListError> {
-    let mut res: Vec<LispExp> = vec![];
-    let mut xs = tokens;
-    loop {
-        let (next_token, rest) = xs
-            .split_first()
-            .ok_or(ListError::from("could not find closing `)`"))?;
-        if next_token == ")" {
-            return Ok((LispExp::List(res), rest));
-        }
-        let (exp, new_xs) = parse(xs)?;
-        res.push(exp);
-        xs = new_xs;
-    }
-}
-
-fn parse_atom(token: &str) -> LispExp {
-    token
-        .parse::<f64>()
-        .map(LispExp::from)
-        .unwrap_or(LispExp::from(token))
-}
-
-use std::boxed::Box;
-use std::collections::HashMap;
-type LispFN = Box<dyn Fn(&LispInfo, &[LispExp]) -> Result<LispExp, ListError>>;
-struct LispInfo {
-    functions: HashMap<String, LispFN>,
-    root: LispExp,
-}
-
-impl LispInfo {
-
-    fn value(&self, vl: &LispExp) -> Result<LispExp, ListError> {
-        if let LispExp::List(stuff) = vl {
-            let (car, cdr) = stuff.split_first()
-                .ok_or(ListError::from("could not get token"))?;
-            let car_str = car.get_symbol()?;
-            if self.functions.contains_key(car_str) {
-                Ok(self.exec(car_str, cdr)?.clone())
-            } else if !cdr.is_empty() {
-                Err(ListError(format!("symbol {} not defined as funtion so it takes arguments", car)))
-            } else {
-                Ok(car.clone())
+// `sxprs expand file` prints the program with its macros expanded, each
+// top-level form after where it was in the file; `--opt` folds constants too
+fn expand_command(args: impl Iterator<Item = String>) {
+    let mut fold = false;
+    let mut path = None;
+    for arg in args {
+        match &arg[..] {
+            "--opt" => fold = true,
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            _ if path.is_some() => fail(format!("unexpected argument {arg}")),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail("expand expects a file"));
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    let mut lisp = Interpreter::new();
+    lisp.set_max_depth(MAX_DEPTH);
+    lisp.set_file(&path);
+    for dir in find_project(&Input::File(path.clone())).map(|project| project.include).unwrap_or_default() {
+        lisp.add_include_path(dir);
+    }
+    let expanded = expand_program(&lisp, &source, &path, fold).unwrap_or_else(|err| match err.pos() {
+        Some(_) => fail(format!("{path}:{}", err.message())),
+        None => fail(err.message()),
+    });
+    print!("{expanded}");
+}
+
+// `sxprs examples` lists the examples built in, by category; `sxprs examples
+// name args...` runs one, with the arguments as `argv`, and `--show name`
+// prints it
+fn examples_command(mut args: impl Iterator<Item = String>) {
+    let find = |name: &str| example(name).unwrap_or_else(|| fail(format!("there's no example {name}; `sxprs examples` lists them")));
+    match args.next().as_deref() {
+        None => {
+            for category in categories() {
+                println!("{category}");
+                for example in EXAMPLES.iter().filter(|example| example.category == category) {
+                    println!("  {:<8} {}", example.name, example.about);
+                }
+            }
+            println!("\nrun one with `sxprs examples name`, or read it with `sxprs examples --show name`");
+        }
+        Some("--show") => {
+            let name = args.next().unwrap_or_else(|| fail("--show expects an example"));
+            print!("{}", find(&name).source);
+        }
+        Some(flag) if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+        Some(name) => {
+            let example = find(name);
+            let mut lisp = Interpreter::new();
+            lisp.set_max_depth(MAX_DEPTH);
+            lisp.set_args(args.collect());
+            let file = format!("{name}.lsp");
+            match lisp.eval_str(example.source) {
+                Ok(_) => {}
+                Err(LispError::Exit(code)) => std::process::exit(code),
+                Err(err) => fail_with(&err, Some(&file), ErrorFormat::Human(Lang::En)),
             }
-        } else {
-            Ok(vl.clone())
         }
     }
-    fn exec(&self, car: &str, cdr: &[LispExp]) -> Result<LispExp, ListError> {
-        let func = self.functions
-            .get(car)
-            .ok_or(ListError(format!("can't find function {car}")))?;
-        //func(&cdr.iter().map(|a|self.value(a)).collect::<Result<Vec<LispExp>, ListError>>()?)
-        func(self, cdr)
-    }
-    fn run(&self) -> Result<LispExp, ListError> {
-        self.value(&self.root)
-    }
-}
-
-macro_rules! record {
-  ($env: expr, $symb: expr, $check_fn:expr) => {{
-      $env.insert(String::from($symb), Box::new($check_fn))
-  }}
 }
 
-// helper functions
-fn get_floats(cont: &[LispExp]) -> Result<Vec<f64>, ListError> {
-    cont
-        .iter()
-        .map(LispExp::get_number)
-        .collect()
+// `sxprs explain E0101` says more about a kind of error, found by its id or
+// its code; without one it lists them all
+fn explain_command(mut args: impl Iterator<Item = String>) {
+    let Some(name) = args.next() else {
+        for explanation in EXPLANATIONS {
+            println!("{} {:<18} {}", explanation.id, explanation.code, explanation.title);
+        }
+        return;
+    };
+    if let Some(arg) = args.next() {
+        fail(format!("unexpected argument {arg}"));
+    }
+    let explanation = explain(&name).unwrap_or_else(|| fail(format!("there's no error {name}; `sxprs explain` lists them")));
+    println!("{} {}: {}\n\n{}", explanation.id, explanation.code, explanation.title, explanation.text);
 }
 
-fn unpack(cont: &[LispExp]) -> Result<(&LispExp, &[LispExp]), ListError> {
-    cont
-        .split_first()
-        .ok_or(ListError::from("could not get token"))
+// `sxprs rename old new file` renames what the program defines as `old`,
+// in the file and the files it loads and imports, rewriting them in place;
+// `--dry-run` only prints where it would
+fn rename_command(args: impl Iterator<Item = String>) {
+    let mut dry_run = false;
+    let mut rest = vec![];
+    for arg in args {
+        match &arg[..] {
+            "--dry-run" => dry_run = true,
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            _ => rest.push(arg),
+        }
+    }
+    let [old, new, path] = &rest[..] else {
+        fail("rename expects the old name, the new name and a file");
+    };
+    let include = find_project(&Input::File(path.clone())).map(|project| project.include).unwrap_or_default();
+    let changed = rename(std::path::Path::new(path), &include, old, new).unwrap_or_else(|err| fail(err.message()));
+    for file in &changed {
+        if dry_run {
+            for place in &file.places {
+                println!("{}:{place}: {old} -> {new}", file.path.display());
+            }
+            continue;
+        }
+        std::fs::write(&file.path, &file.source).unwrap_or_else(|err| fail(format!("{}: {err}", file.path.display())));
+        let places = file.places.len();
+        println!("{}: renamed {places} {}", file.path.display(), if places == 1 { "place" } else { "places" });
+    }
 }
-//fn car(cont: &[LispExp]) -> Result<&LispExp, ListError> {
-//    cont
-//        .first()
-//        .ok_or(ListError::from("could not get token"))
-//}
-//fn cdr(cont: &[LispExp]) -> Result<&[LispExp], ListError> {
-//    let (_, cdr) = cont
-//        .split_first()
-//        .ok_or(ListError::from("could not get token"))?;
-//    Ok(cdr)
-//}
 
-// after implementing user func definitions i could
-// implement eval_some and only eval lists with car Symb('~') or smth like that
-// macro creation would be as simple as:
-/*
-( def is-three
-    ' (y) (
-        (= ~(y) 3)
-    )
-)
-*/
-fn eval_all(env: &LispInfo, r: &[LispExp]) -> Result<Vec<LispExp>, ListError> {
-    r.iter().map(|a|env.value(a)).collect()
+// `sxprs extract file --span a..b --name f` makes the expression at the span
+// into the function `f`, rewriting the file in place; `--dry-run` prints the
+// file as it would be instead
+fn extract_command(args: impl Iterator<Item = String>) {
+    let mut dry_run = false;
+    let (mut selection, mut name, mut path) = (None, None, None);
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--dry-run" => dry_run = true,
+            "--span" => selection = Some(args.next().unwrap_or_else(|| fail("--span expects a span like 10..24 or 2:5..2:19"))),
+            "--name" => name = Some(args.next().unwrap_or_else(|| fail("--name expects a name for the function"))),
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            _ if path.is_some() => fail(format!("unexpected argument {arg}")),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail("extract expects a file"));
+    let (Some(selection), Some(name)) = (selection, name) else {
+        fail("extract expects --span and --name");
+    };
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    let span = span(&source, &selection).unwrap_or_else(|err| fail(err.message()));
+    let (extracted, call) = extract(&source, span, &name).unwrap_or_else(|err| match err.pos() {
+        Some(_) => fail(format!("{path}:{}", err.message())),
+        None => fail(err.message()),
+    });
+    if dry_run {
+        print!("{extracted}");
+        return;
+    }
+    std::fs::write(&path, extracted).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    println!("{path}: extracted {name}, called as {call}");
 }
 
-fn lisp_add(env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    let cont = eval_all(env, cont)?;
-    let (car, cdr) = unpack(&cont)?;
-    let car = car.get_number()?;
-    Ok(get_floats(cdr)?.iter().fold(car, |acc, f|acc+f).into())
+// `sxprs keygen name` writes a new secret key to `name` and its public key
+// to `name.pub`; `sxprs sign --key name file` writes `file.sig`, and
+// `sxprs verify --key name.pub file` checks it
+#[cfg(feature = "signing")]
+fn signing_command(command: &str, args: impl Iterator<Item = String>) {
+    use sxprs::sign::{keygen, sign, verify};
+    let read = |path: &str| std::fs::read_to_string(path).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    let mut key = None;
+    let mut path = None;
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--key" if command != "keygen" => key = Some(args.next().unwrap_or_else(|| fail("--key expects a file"))),
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            _ if path.is_some() => fail(format!("unexpected argument {arg}")),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail(format!("{command} expects a file")));
+    if command == "keygen" {
+        let public = format!("{path}.pub");
+        if let Some(exists) = [&path, &public].into_iter().find(|file| std::path::Path::new(file).exists()) {
+            fail(format!("{exists} already exists"));
+        }
+        let (secret, public_key) = keygen().unwrap_or_else(|err| fail(err.message()));
+        write_secret(&path, &format!("{secret}\n")).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+        std::fs::write(&public, format!("{public_key}\n")).unwrap_or_else(|err| fail(format!("{public}: {err}")));
+        eprintln!("wrote {path} and {public}");
+        return;
+    }
+    let key = read(&key.unwrap_or_else(|| fail(format!("{command} expects --key"))));
+    let bytes = std::fs::read(&path).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    let signature = format!("{path}.sig");
+    if command == "sign" {
+        let signed = sign(&key, &bytes).unwrap_or_else(|err| fail(err.message()));
+        std::fs::write(&signature, format!("{signed}\n")).unwrap_or_else(|err| fail(format!("{signature}: {err}")));
+        return;
+    }
+    match verify(&key, &bytes, &read(&signature)) {
+        Ok(()) => println!("{path}: good signature"),
+        Err(err) => fail(format!("{path}: {}", err.message())),
+    }
 }
-fn lisp_sub(env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    let cont = eval_all(env, cont)?;
-    let (car, cdr) = unpack(&cont)?;
-    let car = car.get_number()?;
-    Ok(get_floats(cdr)?.iter().fold(car, |acc, f|acc-f).into())
+
+// the secret key is only for its owner to read
+#[cfg(feature = "signing")]
+fn write_secret(path: &str, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
 }
-fn lisp_mul(env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    let cont = eval_all(env, cont)?;
-    let (car, cdr) = unpack(&cont)?;
-    let car = car.get_number()?;
-    Ok(get_floats(cdr)?.iter().fold(car, |acc, f|acc+f).into())
+
+#[cfg(not(feature = "signing"))]
+fn signing_command(command: &str, _args: impl Iterator<Item = String>) {
+    fail(format!("{command} needs sxprs built with the `signing` feature"));
 }
-fn lisp_div(env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    let cont = eval_all(env, cont)?;
-    let (car, cdr) = unpack(&cont)?;
-    let car = car.get_number()?;
-    Ok(get_floats(cdr)?.iter().fold(car, |acc, f|acc+f).into())
+
+// fails unless `path.sig` is a good signature of `bytes`, what was read from
+// `path`, by the public key `key`
+#[cfg(feature = "signing")]
+fn check_signature(path: &std::path::Path, bytes: &[u8], key: &str) -> Result<(), LispError> {
+    let signature = format!("{}.sig", path.display());
+    let signature = std::fs::read_to_string(&signature).map_err(|err| LispError::Other(format!("{signature}: {err}")))?;
+    sxprs::sign::verify(key, bytes, &signature)
 }
 
-fn lisp_debug(_env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    for item in cont {
-        println!("{item}");
+// the program at `path`, read once and checked against `path.sig`, so what
+// runs is what was checked
+#[cfg(feature = "signing")]
+fn require_signature(path: &str, key: &str) -> Vec<u8> {
+    let bytes = std::fs::read(path).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    if let Err(err) = check_signature(std::path::Path::new(path), &bytes, key) {
+        fail(format!("{path}: {}", err.message()));
     }
-    Ok((0.0).into())
+    bytes
 }
 
-fn lisp_print(env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    let cont = eval_all(env, cont)?;
-    for item in cont {
-        println!("{item}");
-    }
-    Ok((0.0).into())
+// has every file the program loads or imports checked against its own
+// signature by `key` before it runs
+#[cfg(feature = "signing")]
+fn require_signatures(lisp: &mut Interpreter, key: &str) {
+    let key = key.to_owned();
+    lisp.set_load_check(move |path, source| check_signature(path, source.as_bytes(), &key));
 }
 
-fn lisp_also(env: &LispInfo, cont: &[LispExp]) -> Result<LispExp, ListError> {
-    let ev = eval_all(env, cont)?;
-    let ev = ev.last().ok_or(ListError::from(""))?;
-    Ok(ev.clone())
-}
-
-fn builtin_funcs() -> HashMap<String, LispFN> {
-    let mut funcs: HashMap<String, LispFN> = HashMap::new();
-    record!(funcs, "+", lisp_add);
-    record!(funcs, "-", lisp_sub);
-    record!(funcs, "*", lisp_mul);
-    record!(funcs, "/", lisp_div);
-    record!(funcs, "print", lisp_print);
-    record!(funcs, "'", lisp_debug);
-    record!(funcs, ",", lisp_also);
-    funcs
+#[cfg(not(feature = "signing"))]
+fn require_signature(_path: &str, _key: &str) -> Vec<u8> {
+    fail("--require-signature needs sxprs built with the `signing` feature");
 }
 
-fn main() {
-    let content = std::fs::read_to_string("example.lsp").unwrap();
-    let content = tokens(content).unwrap();
-    let (parsed, missing) = parse(&content).map_err(|a| a.to_string()).unwrap();
-    if !missing.is_empty() {
-        println!("{missing:?}");
-        panic!("not all tokens parsed")
-    }
-    let lisp = LispInfo {
-        root: parsed,
-        functions: builtin_funcs(),
+#[cfg(not(feature = "signing"))]
+fn require_signatures(_lisp: &mut Interpreter, _key: &str) {
+    unreachable!("the program's own signature can't be checked without the feature");
+}
+
+// the sxprs.toml that applies to the program: the nearest one up from its
+// file's directory, or from the current directory for the REPL, -e and stdin
+fn find_project(input: &Input) -> Option<Project> {
+    let dir = match input {
+        Input::File(path) => std::path::Path::new(path).parent().map(|dir| dir.to_owned()).unwrap_or_default(),
+        _ => std::path::PathBuf::new(),
     };
-    let code = lisp.run();
-    let code = code.map(|a|a.get_number());
-    let code = code.expect("failed to run code");
-    let code = code.expect("code didn't exit with number");
-    let code = unsafe { code.to_int_unchecked::<i32>() };
-    std::process::exit(code);
+    let dir = if dir.as_os_str().is_empty() { std::path::PathBuf::from(".") } else { dir };
+    Project::find(&dir).unwrap_or_else(|err| fail(err.message()))
 }
 
+fn run() {
+    let mut encoding = Encoding::Utf8;
+    let mut input = Input::Repl;
+    let mut dump_ast = false;
+    let mut check = false;
+    let mut vm = false;
+    let mut opt = false;
+    let mut color = std::env::var_os("NO_COLOR").is_none();
+    let mut prelude = true;
+    let mut init = true;
+    let mut trace = false;
+    let mut profile = false;
+    let mut image = None;
+    let mut resume = false;
+    let mut seed = None;
+    let mut manifest = None;
+    let mut trusted = None;
+    let mut leaks = false;
+    let mut heap_at_exit = None;
+    let mut watching = false;
+    let mut bench = None;
+    let mut compare = false;
+    let mut listen = None;
+    let mut error_format = ErrorFormat::Human(Lang::En);
+    let mut lang = Lang::En;
+    let command_line: Vec<String> = std::env::args().skip(1).collect();
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if_eq("fmt").is_some() {
+        fmt_command(args);
+        return;
+    }
+    if args.next_if_eq("check").is_some() {
+        check_command(args);
+        return;
+    }
+    if args.next_if_eq("test").is_some() {
+        test_command(args);
+        return;
+    }
+    if args.next_if_eq("query").is_some() {
+        query_command(args);
+        return;
+    }
+    if args.next_if_eq("bundle").is_some() {
+        bundle_command(args);
+        return;
+    }
+    if args.next_if_eq("embed").is_some() {
+        embed_command(args);
+        return;
+    }
+    if args.next_if_eq("attach").is_some() {
+        attach_command(args);
+        return;
+    }
+    if args.next_if_eq("kernel").is_some() {
+        kernel_command(args);
+        return;
+    }
+    if args.next_if_eq("audit").is_some() {
+        audit_command(args);
+        return;
+    }
+    if args.next_if_eq("examples").is_some() {
+        examples_command(args);
+        return;
+    }
+    if args.next_if_eq("learn").is_some() {
+        learn_command(args);
+        return;
+    }
+    if args.next_if_eq("expand").is_some() {
+        expand_command(args);
+        return;
+    }
+    if args.next_if_eq("explain").is_some() {
+        explain_command(args);
+        return;
+    }
+    if args.next_if_eq("extract").is_some() {
+        extract_command(args);
+        return;
+    }
+    if args.next_if_eq("rename").is_some() {
+        rename_command(args);
+        return;
+    }
+    if let Some(command) = args.next_if(|arg| matches!(&arg[..], "keygen" | "sign" | "verify")) {
+        signing_command(&command, args);
+        return;
+    }
+    // `sxprs bench file` is `--bench` with ten runs, or as many as `--runs` says
+    let benching = args.next_if_eq("bench").is_some();
+    if benching {
+        bench = Some(10);
+    }
+    // `sxprs daemon file` runs the file, then the handlers it scheduled
+    // whenever they're due, until one exits
+    let daemon = args.next_if_eq("daemon").is_some();
+    while let Some(arg) = args.next() {
+        if let Some(format) = parse_error_format(&arg) {
+            error_format = format;
+            continue;
+        }
+        match &arg[..] {
+            "--lossy" => encoding = Encoding::Lossy,
+            "--latin1" => encoding = Encoding::Latin1,
+            "--repl" => input = Input::Repl,
+            "--dump-ast" => dump_ast = true,
+            "--check" => check = true,
+            "--lang" => lang = parse_lang(args.next()),
+            "--vm" => vm = true,
+            "--engine" => match args.next().as_deref() {
+                Some("tree") => vm = false,
+                Some("vm") => vm = true,
+                _ => fail("--engine expects tree or vm"),
+            },
+            "--compare" => compare = true,
+            "--opt" => opt = true,
+            "--no-color" => color = false,
+            "--no-prelude" => prelude = false,
+            "--no-init" => init = false,
+            "--trace" => trace = true,
+            "--profile" => profile = true,
+            "--watch" => watching = true,
+            "--resume" => resume = true,
+            "--leaks" => leaks = true,
+            "--heap-at-exit" => heap_at_exit = Some(args.next().unwrap_or_else(|| fail("--heap-at-exit expects a file to write"))),
+            "--seed" => seed = Some(args.next().and_then(|n| n.parse::<u64>().ok()).unwrap_or_else(|| fail("--seed expects a number"))),
+            "--manifest" => manifest = Some(args.next().unwrap_or_else(|| fail("--manifest expects a file to write"))),
+            "--require-signature" => trusted = Some(args.next().unwrap_or_else(|| fail("--require-signature expects a public key file"))),
+            "--listen" => listen = Some(args.next().unwrap_or_else(|| fail("--listen expects an address"))),
+            "--load-image" => image = Some(args.next().unwrap_or_else(|| fail("--load-image expects a file"))),
+            "--bench" => {
+                let runs = args.next().and_then(|n| n.parse::<usize>().ok()).filter(|&n| n > 0);
+                bench = Some(runs.unwrap_or_else(|| fail("--bench expects a number of runs")));
+            }
+            "--runs" if benching => {
+                let runs = args.next().and_then(|n| n.parse::<usize>().ok()).filter(|&n| n > 0);
+                bench = Some(runs.unwrap_or_else(|| fail("--runs expects a number of runs")));
+            }
+            // `bench` takes its flags after the program too, which gets no arguments
+            "-e" | "-" if benching && !matches!(input, Input::Repl) => fail(format!("unexpected argument {arg}")),
+            "-e" => {
+                let expr = args.next().unwrap_or_else(|| fail("-e expects an expression"));
+                input = Input::Expr(expr);
+                if !benching {
+                    break;
+                }
+            }
+            "-" => {
+                input = Input::Stdin;
+                if !benching {
+                    break;
+                }
+            }
+            flag if flag.starts_with('-') => fail(format!("unknown argument {flag}")),
+            path if benching && !matches!(input, Input::Repl) => fail(format!("unexpected argument {path}")),
+            path => {
+                input = Input::File(path.to_owned());
+                if !benching {
+                    break;
+                }
+            }
+        }
+    }
+    let error_format = error_format.in_lang(lang);
+    if daemon && !matches!(input, Input::File(_)) {
+        fail("daemon expects a file");
+    }
+    // a signed program is checked before anything of it runs, and then runs
+    // without the init file, the sxprs.toml or an image, which aren't signed
+    let verified = trusted.as_ref().map(|key| {
+        let key = std::fs::read_to_string(key).unwrap_or_else(|err| fail(format!("{key}: {err}")));
+        match &input {
+            Input::File(path) => (require_signature(path, &key), key),
+            _ => fail("--require-signature expects a file"),
+        }
+    });
+    if verified.is_some() {
+        if image.is_some() {
+            fail("--require-signature can't be combined with --load-image");
+        }
+        if resume {
+            fail("--require-signature can't be combined with --resume");
+        }
+        init = false;
+    }
+    // the VM only hands the tree-walker what it can't compile, so most of it wouldn't show
+    if trace && (vm || compare) {
+        fail("--trace can't be combined with --vm or --compare");
+    }
+    // everything after the program is handed to it as `argv`
+    let argv: Vec<String> = args.collect();
+    let project = if verified.is_some() { None } else { find_project(&input) };
+    if let Some(project) = &project {
+        vm |= project.vm;
+        opt |= project.opt;
+        prelude &= project.prelude;
+    }
+    let new_interpreter = || {
+        let mut lisp = if prelude { Interpreter::new() } else { Interpreter::bare() };
+        lisp.set_max_depth(MAX_DEPTH);
+        lisp.set_vm(vm);
+        if opt {
+            lisp.optimize();
+        }
+        if let Some(project) = &project {
+            if !project.math {
+                lisp.disable_math();
+            }
+            for dir in &project.include {
+                lisp.add_include_path(dir);
+            }
+        }
+        // wide values are broken up and colored for people, not for pipes
+        let tty = std::io::stdout().is_terminal();
+        lisp.set_pretty(tty);
+        lisp.set_color(tty && color);
+        if trace {
+            lisp.set_tracer(Box::new(PrintTracer));
+        }
+        lisp.set_profiling(profile);
+        lisp.set_resume(resume);
+        if let Some(seed) = seed {
+            lisp.set_seed(seed);
+        }
+        if let Input::File(path) = &input {
+            lisp.set_file(path);
+        }
+        lisp.set_args(argv.clone());
+        if let Some((_, key)) = &verified {
+            require_signatures(&mut lisp, key);
+        }
+        if let Some(path) = init_path().filter(|path| init && path.exists()) {
+            if let Err(err) = lisp.load_file(&path) {
+                fail_with(&err, Some(&path.display().to_string()), error_format);
+            }
+        }
+        if let Some(image) = &image {
+            if let Err(err) = lisp.load_image(image) {
+                fail_with(&err, Some(image), error_format);
+            }
+        }
+        if let Some(addr) = &listen {
+            match lisp.listen(addr) {
+                Ok(bound) => eprintln!("listening for `sxprs attach` on {bound}"),
+                Err(err) => fail(format!("--listen {addr}: {err}")),
+            }
+        }
+        lisp
+    };
+    if let Input::Repl = input {
+        if dump_ast {
+            fail("--dump-ast expects a file, - or -e");
+        }
+        if check {
+            fail("--check expects a file, - or -e");
+        }
+        if benching {
+            fail("bench expects a file, - or -e");
+        }
+        if bench.is_some() {
+            fail("--bench expects a file, - or -e");
+        }
+        if profile {
+            fail("--profile expects a file, - or -e");
+        }
+        if watching {
+            fail("--watch expects a file");
+        }
+        if manifest.is_some() {
+            fail("--manifest expects a file, - or -e");
+        }
+        if leaks {
+            fail("--leaks expects a file, - or -e");
+        }
+        if heap_at_exit.is_some() {
+            fail("--heap-at-exit expects a file, - or -e");
+        }
+        repl(new_interpreter(), error_format);
+        return;
+    }
+    let source = match &verified {
+        Some((bytes, _)) => decode_source(bytes.clone(), encoding).map_err(|err| format!("{}: {err}", input_name(&input))),
+        None => read_source(&input, encoding),
+    };
+    let source = source.unwrap_or_else(|err| fail(err));
+    let name = input_name(&input);
+    if check {
+        if !check_program(&input, &source, error_format).1 {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if dump_ast {
+        let program = tokens(&source)
+            .and_then(|tokens| parse_program_with_depth(&tokens, MAX_DEPTH))
+            .unwrap_or_else(|err| fail_with(&err, Some(name), error_format));
+        let forms: Vec<String> = program.iter().map(to_json).collect();
+        println!("[{}]", forms.join(","));
+        return;
+    }
+    // the program is read again when it changes, and the new one isn't signed
+    if trusted.is_some() && watching {
+        fail("--require-signature can't be combined with --watch");
+    }
+    if leaks && (bench.is_some() || watching) {
+        fail("--leaks can't be combined with --bench or --watch");
+    }
+    if heap_at_exit.is_some() && (bench.is_some() || watching) {
+        fail("--heap-at-exit can't be combined with --bench or --watch");
+    }
+    if manifest.is_some() && (bench.is_some() || watching) {
+        fail("--manifest can't be combined with --bench or --watch");
+    }
+    // every run would listen again, at the address the first one still has
+    if listen.is_some() && (bench.is_some() || watching) {
+        fail("--listen can't be combined with --bench or --watch");
+    }
+    if compare && bench.is_none() {
+        fail("--compare expects bench or --bench");
+    }
+    if let Some(runs) = bench {
+        bench_program(&source, runs, compare, vm, new_interpreter);
+        return;
+    }
+    if watching {
+        let Input::File(path) = &input else {
+            fail("--watch expects a file");
+        };
+        watch(path, encoding, error_format, profile, new_interpreter);
+    }
+    let lisp = new_interpreter();
+    let started_from = lisp.seed();
+    let mut res = lisp.eval_str(&source);
+    if daemon && res.is_ok() {
+        res = match lisp.scheduled() {
+            0 => Err(LispError::from("nothing was scheduled")),
+            _ => lisp.run_schedule().map(|_| LispExp::Nil),
+        };
+    }
+    if profile {
+        print_profile(&lisp);
+    }
+    if leaks {
+        for task in lisp.unawaited() {
+            eprintln!("leak: {task} was never awaited");
+        }
+    }
+    if let Some(path) = &heap_at_exit {
+        std::fs::write(path, lisp.heap_dump() + "\n").unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    }
+    if let Some(path) = &manifest {
+        let exit = match &res {
+            Ok(_) => 0,
+            Err(LispError::Exit(code)) => *code,
+            Err(_) => 1,
+        };
+        let manifest = Manifest { name: name.to_owned(), source: source.clone(), args: command_line, seed: started_from, exit };
+        std::fs::write(path, manifest.to_json(&lisp) + "\n").unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    }
+    match res {
+        Ok(value) => {
+            if let Input::Expr(_) = input {
+                println!("{}", lisp.show(&value));
+            }
+        }
+        Err(LispError::Exit(code)) => std::process::exit(code),
+        Err(err) => fail_with(&err, Some(name), error_format),
+    }
+}
@@ -0,0 +1,126 @@
+// run manifests: what a run of a program was made of, as JSON, for telling
+// later whether another run was the same one: the interpreter's version, the
+// program's and every loaded file's SHA-256, the command line, the random
+// seed and which optional builtins were there
+use crate::eval::Interpreter;
+use crate::json::{escape, object};
+use crate::platform;
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3,
+    0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13,
+    0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+    0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+// the SHA-256 of `bytes` in hex; written out here, as it's all the crate
+// needs a hash for
+pub fn sha256(bytes: &[u8]) -> String {
+    let mut state: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+    let mut padded = bytes.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(bytes.len() as u64 * 8).to_be_bytes());
+    for block in padded.chunks(64) {
+        let mut words = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            words[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = words[i - 15].rotate_right(7) ^ words[i - 15].rotate_right(18) ^ (words[i - 15] >> 3);
+            let s1 = words[i - 2].rotate_right(17) ^ words[i - 2].rotate_right(19) ^ (words[i - 2] >> 10);
+            words[i] = words[i - 16].wrapping_add(s0).wrapping_add(words[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(ROUND_CONSTANTS[i]).wrapping_add(words[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+    state.iter().map(|word| format!("{word:08x}")).collect()
+}
+
+pub struct Manifest {
+    // the program's file, `<expr>` or `<stdin>`, and its source
+    pub name: String,
+    pub source: String,
+    // the command line, without the binary
+    pub args: Vec<String>,
+    // what the random generator started from
+    pub seed: u64,
+    // what the program exited with
+    pub exit: i32,
+}
+
+impl Manifest {
+    // the manifest as JSON, with the files `lisp` loaded while running the
+    // program; a file that can't be read anymore has a null hash
+    pub fn to_json(&self, lisp: &Interpreter) -> String {
+        let file = |path: String, hash: Option<String>| object(&[("path", escape(&path)), ("sha256", hash.map_or("null".to_owned(), |hash| escape(&hash)))]);
+        let loaded: Vec<String> = lisp
+            .loaded_files()
+            .into_iter()
+            .map(|path| file(path.display().to_string(), platform::read_file(&path).ok().map(|text| sha256(text.as_bytes()))))
+            .collect();
+        let strings = |items: &[String]| format!("[{}]", items.iter().map(|item| escape(item)).collect::<Vec<_>>().join(","));
+        object(&[
+            ("version", escape(env!("CARGO_PKG_VERSION"))),
+            ("program", file(self.name.clone(), Some(sha256(self.source.as_bytes())))),
+            ("loaded", format!("[{}]", loaded.join(","))),
+            ("args", strings(&self.args)),
+            ("seed", self.seed.to_string()),
+            ("capabilities", strings(&lisp.capabilities())),
+            ("exit", self.exit.to_string()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_of_known_inputs() {
+        assert_eq!(sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // two blocks once padded
+        let long = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        assert_eq!(sha256(long), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(sha256(&[b'a'; 1000]), "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3");
+    }
+
+    #[test]
+    fn manifest() {
+        let lisp = Interpreter::new();
+        let manifest = Manifest { name: "<expr>".to_owned(), source: "abc".to_owned(), args: vec!["-e".to_owned(), "abc".to_owned()], seed: 7, exit: 0 };
+        let json = manifest.to_json(&lisp);
+        assert!(json.starts_with(&format!("{{\"version\":\"{}\",\"program\":{{\"path\":\"<expr>\",\"sha256\":\"ba7816bf", env!("CARGO_PKG_VERSION"))), "{json}");
+        let mut capabilities = "[\"math\"".to_owned();
+        for (name, on) in [("serde", cfg!(feature = "serde")), ("graphics", cfg!(feature = "graphics"))] {
+            if on {
+                capabilities.push_str(&format!(",\"{name}\""));
+            }
+        }
+        capabilities.push(']');
+        assert!(json.ends_with(&format!(",\"loaded\":[],\"args\":[\"-e\",\"abc\"],\"seed\":7,\"capabilities\":{capabilities},\"exit\":0}}")), "{json}");
+    }
+}
@@ -0,0 +1,246 @@
+use std::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, Zero};
+
+use crate::builtins::{record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::LispExp;
+use crate::num;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// evaluates a single numeric argument and applies `op` to it as a float
+fn float_fn(cont: &[LispExp], op: fn(f64) -> f64) -> Result<LispExp, LispError> {
+    let (car, _) = unpack(cont)?;
+    Ok(op(car.get_number()?).into())
+}
+
+// like `float_fn`, but ints are passed through unchanged and ratios are
+// rounded exactly with `exact_op`
+fn rounding_fn(
+    cont: &[LispExp],
+    exact_op: fn(&BigRational) -> BigRational,
+    op: fn(f64) -> f64,
+) -> Result<LispExp, LispError> {
+    let (car, _) = unpack(cont)?;
+    match car {
+        LispExp::Int(_) | LispExp::BigInt(_) => Ok(car.clone()),
+        LispExp::Ratio(ratio) => Ok(num::from_ratio(exact_op(ratio))),
+        other => Ok(op(other.get_number()?).into()),
+    }
+}
+
+fn lisp_sqrt(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::sqrt)
+}
+fn lisp_exp(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::exp)
+}
+fn lisp_log(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::ln)
+}
+fn lisp_sin(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::sin)
+}
+fn lisp_cos(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::cos)
+}
+fn lisp_tan(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::tan)
+}
+fn lisp_asin(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::asin)
+}
+fn lisp_acos(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::acos)
+}
+fn lisp_atan(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    float_fn(cont, f64::atan)
+}
+fn lisp_floor(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    rounding_fn(cont, BigRational::floor, f64::floor)
+}
+fn lisp_ceil(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    rounding_fn(cont, BigRational::ceil, f64::ceil)
+}
+fn lisp_round(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    rounding_fn(cont, BigRational::round, f64::round)
+}
+
+fn lisp_abs(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (car, _) = unpack(cont)?;
+    match car {
+        LispExp::Int(int) => Ok(int.checked_abs().map_or_else(|| num::from_big(BigInt::from(*int).abs()), LispExp::Int)),
+        LispExp::BigInt(int) => Ok(num::from_big(int.abs())),
+        LispExp::Ratio(ratio) => Ok(num::from_ratio(ratio.abs())),
+        other => Ok(other.get_number()?.abs().into()),
+    }
+}
+
+// (pow base exponent), exact for an exact base and an int exponent, so
+// `(pow 2 -1)` is 1/2
+fn lisp_pow(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (base, rest) = unpack(cont)?;
+    let (exponent, _) = unpack(rest)?;
+    if let (LispExp::Int(base), LispExp::Int(exponent)) = (base, exponent) {
+        if let Some(int) = u32::try_from(*exponent).ok().and_then(|exponent| base.checked_pow(exponent)) {
+            return Ok(LispExp::Int(int));
+        }
+    }
+    if let (Some(base), LispExp::Int(exponent)) = (num::exact(base), exponent) {
+        if let Ok(exponent) = i32::try_from(*exponent) {
+            if base.is_zero() && exponent < 0 {
+                return Err(LispError::DivisionByZero);
+            }
+            // the result has about `exponent` times the digits of the base
+            let bits = (base.numer().bits().saturating_sub(1) + base.denom().bits().saturating_sub(1)).saturating_mul(exponent.unsigned_abs().into());
+            env.can_allocate((bits / 8) as usize)?;
+            return Ok(num::from_ratio(base.pow(exponent)));
+        }
+    }
+    Ok(base.get_number()?.powf(exponent.get_number()?).into())
+}
+
+// (mod a b), with the sign of `b` like python's %
+fn lisp_mod(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (a, rest) = unpack(cont)?;
+    let (b, _) = unpack(rest)?;
+    match (a, b) {
+        (LispExp::Int(a), LispExp::Int(b)) if a.checked_rem(*b).is_some() => {
+            let r = a % b;
+            Ok(LispExp::Int(if r != 0 && (r < 0) != (*b < 0) { r + b } else { r }))
+        }
+        (a, b) => {
+            if let (Some(a), Some(b)) = (num::exact(a), num::exact(b)) {
+                return Ok(num::from_ratio(num::modulo(a, b)?));
+            }
+            let (a, b) = (a.get_number()?, b.get_number()?);
+            let r = a % b;
+            Ok((if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r }).into())
+        }
+    }
+}
+
+// the smallest or largest argument, returned as it was given; `replace` is
+// whether a new argument orders before the best so far as it should
+fn pick(cont: &[LispExp], replace: Ordering) -> Result<LispExp, LispError> {
+    let (car, cdr) = unpack(cont)?;
+    car.get_number()?;
+    let mut best = car;
+    for item in cdr {
+        if num::compare(item, best)? == Some(replace) {
+            best = item;
+        }
+    }
+    Ok(best.clone())
+}
+fn lisp_min(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    pick(cont, Ordering::Less)
+}
+fn lisp_max(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    pick(cont, Ordering::Greater)
+}
+
+// std's hashers are randomly keyed per process, which is enough entropy for scripts
+pub(crate) fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new().build_hasher().finish()
+}
+
+// splitmix64, small and good enough for simulations, not for cryptography
+fn next_random(env: &Interpreter) -> u64 {
+    let state = env.rng.get().wrapping_add(0x9e3779b97f4a7c15);
+    env.rng.set(state);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// a float in [0, 1)
+fn lisp_random(env: &Interpreter, _cont: &[LispExp]) -> Result<LispExp, LispError> {
+    // the top 53 bits fill an f64 mantissa exactly
+    Ok(((next_random(env) >> 11) as f64 / (1u64 << 53) as f64).into())
+}
+
+// (random-int lo hi), an int in [lo, hi)
+fn lisp_random_int(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (lo, rest) = unpack(cont)?;
+    let (hi, _) = unpack(rest)?;
+    let (lo, hi) = (lo.get_int()?, hi.get_int()?);
+    if hi <= lo {
+        return Err(LispError::Other(format!("random-int expects lo < hi, got {lo} and {hi}")));
+    }
+    let span = hi.abs_diff(lo);
+    Ok(lo.wrapping_add((next_random(env) % span) as i64).into())
+}
+
+// (seed n) makes the following random numbers reproducible
+fn lisp_seed(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (seed, _) = unpack(cont)?;
+    env.rng.set(seed.get_int()? as u64);
+    Ok(seed.clone())
+}
+
+// registered by `builtin_funcs`, and removed again by `Interpreter::disable_math`
+pub(crate) fn math_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "sqrt", lisp_sqrt, 1, "(sqrt x)", "the square root of x");
+    record!(funcs, "pow", lisp_pow, 2, "(pow base exponent)", "base to the power of exponent");
+    record!(funcs, "exp", lisp_exp, 1, "(exp x)", "e to the power of x");
+    record!(funcs, "log", lisp_log, 1, "(log x)", "the natural logarithm of x");
+    record!(funcs, "mod", lisp_mod, 2, "(mod a b)", "the remainder of a divided by b, with the sign of b");
+    record!(funcs, "abs", lisp_abs, 1, "(abs x)", "the absolute value of x");
+    record!(funcs, "floor", lisp_floor, 1, "(floor x)", "the largest integer not above x");
+    record!(funcs, "ceil", lisp_ceil, 1, "(ceil x)", "the smallest integer not below x");
+    record!(funcs, "round", lisp_round, 1, "(round x)", "the nearest integer to x, halves away from zero");
+    record!(funcs, "min", lisp_min, 1.., "(min x y...)", "the smallest number");
+    record!(funcs, "max", lisp_max, 1.., "(max x y...)", "the largest number");
+    record!(funcs, "sin", lisp_sin, 1, "(sin x)", "the sine of x radians");
+    record!(funcs, "cos", lisp_cos, 1, "(cos x)", "the cosine of x radians");
+    record!(funcs, "tan", lisp_tan, 1, "(tan x)", "the tangent of x radians");
+    record!(funcs, "asin", lisp_asin, 1, "(asin x)", "the arcsine of x, in radians");
+    record!(funcs, "acos", lisp_acos, 1, "(acos x)", "the arccosine of x, in radians");
+    record!(funcs, "atan", lisp_atan, 1, "(atan x)", "the arctangent of x, in radians");
+    record!(funcs, "random", lisp_random, 0, "(random)", "a random float from 0 up to 1");
+    record!(funcs, "random-int", lisp_random_int, 2, "(random-int lo hi)", "a random int from lo up to hi");
+    record!(funcs, "seed", lisp_seed, 1, "(seed n)", "makes the following random numbers reproducible");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(run("(list (sqrt 16) (floor 2.5) (floor 7/2) (ceil 7/2) (round 2.5) (abs -3) (mod -7 3))"), "( 4.0 2.0 3 4 3.0 3 2 )");
+        assert_eq!(run("(list (min 3 1/2 2.0) (max 1 2.5))"), "( 1/2 2.5 )");
+        assert_eq!(run("(sqrt \"x\")"), "error: \"x\" is not a Number, it's a String");
+    }
+
+    #[test]
+    fn seeded_random_repeats() {
+        let source = "(seed 42) (define a (list (random) (random-int 0 100))) \
+                      (seed 42) (equal? a (list (random) (random-int 0 100)))";
+        assert_eq!(run(source), "true");
+        assert_eq!(run("(random-int 5 5)"), "error: random-int expects lo < hi, got 5 and 5");
+    }
+
+    #[test]
+    fn can_be_disabled() {
+        let mut lisp = Interpreter::new();
+        lisp.disable_math();
+        assert!(lisp.eval_str("(sqrt 4)").is_err());
+        assert_eq!(lisp.eval_str("(+ 1 2)").unwrap().to_string(), "3");
+    }
+}
@@ -0,0 +1,135 @@
+// the exact numbers: ints, which become big ints instead of overflowing, and
+// the ratios `/` makes of ints that don't divide evenly; every value is kept
+// in its smallest form, so a ratio is never whole and a big int never fits
+// in an i64
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::error::LispError;
+use crate::exp::LispExp;
+
+// the value as a ratio, None for floats and non-numbers
+pub(crate) fn exact(value: &LispExp) -> Option<BigRational> {
+    match value {
+        LispExp::Int(num) => Some(BigRational::from_integer((*num).into())),
+        LispExp::BigInt(num) => Some(BigRational::from_integer((**num).clone())),
+        LispExp::Ratio(num) => Some((**num).clone()),
+        _ => None,
+    }
+}
+
+pub(crate) fn from_big(num: BigInt) -> LispExp {
+    match num.to_i64() {
+        Some(num) => LispExp::Int(num),
+        None => LispExp::BigInt(Rc::new(num)),
+    }
+}
+
+pub(crate) fn from_ratio(num: BigRational) -> LispExp {
+    if num.is_integer() {
+        return from_big(num.to_integer());
+    }
+    LispExp::Ratio(Rc::new(num))
+}
+
+// roughly how many bytes the digits of a big int or ratio take, 0 for
+// anything else
+pub(crate) fn size(value: &LispExp) -> usize {
+    let bits = match value {
+        LispExp::BigInt(num) => num.bits(),
+        LispExp::Ratio(num) => num.numer().bits() + num.denom().bits(),
+        _ => 0,
+    };
+    bits.div_ceil(8) as usize
+}
+
+pub(crate) fn to_float(num: &BigRational) -> f64 {
+    num.to_f64().unwrap_or(f64::NAN)
+}
+
+// `a op b` for two numbers: exact while both are, as a float otherwise;
+// `int_op` is the fast path for two ints, None when it would overflow
+pub(crate) fn arith(
+    a: &LispExp,
+    b: &LispExp,
+    int_op: fn(i64, i64) -> Option<i64>,
+    exact_op: fn(BigRational, BigRational) -> Result<BigRational, LispError>,
+    float_op: fn(f64, f64) -> f64,
+) -> Result<LispExp, LispError> {
+    if let (LispExp::Int(x), LispExp::Int(y)) = (a, b) {
+        if let Some(num) = int_op(*x, *y) {
+            return Ok(LispExp::Int(num));
+        }
+    }
+    match (exact(a), exact(b)) {
+        (Some(x), Some(y)) => Ok(from_ratio(exact_op(x, y)?)),
+        _ => Ok(float_op(a.get_number()?, b.get_number()?).into()),
+    }
+}
+
+pub(crate) fn divide(a: BigRational, b: BigRational) -> Result<BigRational, LispError> {
+    if b.is_zero() {
+        return Err(LispError::DivisionByZero);
+    }
+    Ok(a / b)
+}
+
+// how two numbers order, exactly when both are exact; None when either is NaN
+pub(crate) fn compare(a: &LispExp, b: &LispExp) -> Result<Option<Ordering>, LispError> {
+    if let (LispExp::Int(x), LispExp::Int(y)) = (a, b) {
+        return Ok(Some(x.cmp(y)));
+    }
+    match (exact(a), exact(b)) {
+        (Some(x), Some(y)) => Ok(Some(x.cmp(&y))),
+        _ => Ok(a.get_number()?.partial_cmp(&b.get_number()?)),
+    }
+}
+
+// the remainder with the sign of `b`, like python's %
+pub(crate) fn modulo(a: BigRational, b: BigRational) -> Result<BigRational, LispError> {
+    if b.is_zero() {
+        return Err(LispError::DivisionByZero);
+    }
+    let quotient = (&a / &b).floor();
+    Ok(a - b * quotient)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn ints_promote_instead_of_overflowing() {
+        assert_eq!(run("(+ 9223372036854775807 1)"), "9223372036854775808");
+        assert_eq!(run("(* 4294967296 4294967296)"), "18446744073709551616");
+        assert_eq!(run("(- (+ 9223372036854775807 1) 1)"), "9223372036854775807");
+        assert_eq!(run("(type-of (- (+ 9223372036854775807 1) 1))"), "Int");
+        assert_eq!(run("(pow 2 100)"), "1267650600228229401496703205376");
+        assert_eq!(run("(abs -9223372036854775808)"), "9223372036854775808");
+        assert_eq!(run("(mod -9223372036854775808 -1)"), "0");
+    }
+
+    #[test]
+    fn ratios() {
+        assert_eq!(run("(/ 1 3)"), "1/3");
+        assert_eq!(run("(+ 1/3 2/3)"), "1");
+        assert_eq!(run("(type-of (+ 1/3 2/3))"), "Int");
+        assert_eq!(run("(* 1/2 0.5)"), "0.25");
+        assert_eq!(run("(pow 2 -1)"), "1/2");
+        assert_eq!(run("(= 1/2 0.5)"), "true");
+        assert_eq!(run("(< 1/3 0.34)"), "true");
+        assert_eq!(run("(round 5/2)"), "3");
+        assert_eq!(run("(/ 1 0)"), "error: division by zero");
+    }
+}
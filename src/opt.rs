@@ -0,0 +1,215 @@
+// constant folding: calls to pure builtins whose arguments are all literals
+// are replaced by their value before the form is evaluated, so
+// `(* 2 (+ 3 4))` runs as `14`
+use std::collections::HashSet;
+
+use crate::eval::Interpreter;
+use crate::exp::LispExp;
+use crate::span;
+use crate::symbol::SymbolId;
+
+// the builtins that only compute their value from their arguments; folding
+// assumes the host didn't replace them with register_fn
+const PURE: &[&str] = &[
+    "+", "-", "*", "/", "=", "<", ">", "<=", ">=", "equal?", "eq?", "not",
+    "sqrt", "pow", "exp", "log", "mod", "abs", "floor", "ceil", "round", "min", "max",
+    "sin", "cos", "tan", "asin", "acos", "atan",
+    "str-concat", "str-length", "substring", "str-contains?", "upcase", "downcase", "char->int", "int->char",
+    "string->number", "number->string", "format",
+];
+
+fn is_literal(exp: &LispExp) -> bool {
+    matches!(exp, LispExp::Int(_) | LispExp::BigInt(_) | LispExp::Ratio(_) | LispExp::Number(_) | LispExp::Str(_) | LispExp::Char(_) | LispExp::Bool(_) | LispExp::Nil)
+}
+
+// the names `form` defines or assigns anywhere, which can't be trusted to
+// still be the builtin by the time they're called
+fn assigned(form: &LispExp, names: &mut HashSet<SymbolId>) {
+    let LispExp::List(items) = form else {
+        return;
+    };
+    if let [LispExp::Symbol(head), LispExp::Symbol(name), ..] = &items[..] {
+        if matches!(&*head.name(), "define" | "set!" | "defn" | "defmacro") {
+            names.insert(*name);
+        }
+    }
+    items.iter().for_each(|item| assigned(item, names));
+}
+
+// every symbol in `exp`
+fn symbols(exp: &LispExp, names: &mut HashSet<SymbolId>) {
+    match exp {
+        LispExp::Symbol(name) => {
+            names.insert(*name);
+        }
+        LispExp::List(items) => items.iter().for_each(|item| symbols(item, names)),
+        _ => {}
+    }
+}
+
+// `form` with its constant calls folded
+pub(crate) fn fold(lisp: &Interpreter, form: &LispExp) -> LispExp {
+    let mut shadowed = HashSet::new();
+    assigned(form, &mut shadowed);
+    Folder { lisp, shadowed }.fold(form)
+}
+
+struct Folder<'a> {
+    lisp: &'a Interpreter,
+    // names bound by the form itself, as parameters, let bindings or definitions
+    shadowed: HashSet<SymbolId>,
+}
+
+impl Folder<'_> {
+    fn fold_all(&mut self, items: &[LispExp]) -> Vec<LispExp> {
+        items.iter().map(|item| self.fold(item)).collect()
+    }
+    // parameter lists are names, only their defaults are evaluated
+    fn fold_params(&mut self, params: &LispExp) -> LispExp {
+        let LispExp::List(params) = params else {
+            return params.clone();
+        };
+        let params = params
+            .iter()
+            .map(|param| match param {
+                LispExp::Symbol(name) => {
+                    self.shadowed.insert(*name);
+                    param.clone()
+                }
+                LispExp::List(pair) => match &pair[..] {
+                    [LispExp::Symbol(name), default] => {
+                        self.shadowed.insert(*name);
+                        LispExp::from(vec![LispExp::Symbol(*name), self.fold(default)])
+                    }
+                    _ => param.clone(),
+                },
+                other => self.fold(other),
+            })
+            .collect::<Vec<_>>();
+        LispExp::from(params)
+    }
+    // rebuilt lists keep the span of the one they were folded from
+    fn fold(&mut self, form: &LispExp) -> LispExp {
+        let folded = self.fold_form(form);
+        span::copy(form, &folded);
+        folded
+    }
+    fn fold_form(&mut self, form: &LispExp) -> LispExp {
+        if let Some(items) = form.literal_items() {
+            return form.with_literal_items(self.fold_all(&items)).unwrap_or_else(|_| form.clone());
+        }
+        let LispExp::List(items) = form else {
+            return form.clone();
+        };
+        let Some((LispExp::Symbol(head), args)) = items.split_first() else {
+            return LispExp::from(self.fold_all(items));
+        };
+        let head = *head;
+        let rebuilt = |args: Vec<LispExp>| LispExp::from([vec![LispExp::Symbol(head)], args].concat());
+        match (&*head.name(), args) {
+            // like `quote`, or an assertion's message showing its forms as they were written
+            _ if self.lisp.is_unevaluated(head) => return form.clone(),
+            ("lambda", [params, body @ ..]) => {
+                let params = self.fold_params(params);
+                return rebuilt([vec![params], self.fold_all(body)].concat());
+            }
+            ("defn" | "defmacro", [name, params, body @ ..]) => {
+                let params = self.fold_params(params);
+                return rebuilt([vec![name.clone(), params], self.fold_all(body)].concat());
+            }
+            // patterns aren't evaluated, and can capture any name
+            ("match", [exp, clauses @ ..]) => {
+                let exp = self.fold(exp);
+                let clauses = clauses
+                    .iter()
+                    .map(|clause| match clause {
+                        LispExp::List(clause) if !clause.is_empty() => {
+                            symbols(&clause[0], &mut self.shadowed);
+                            LispExp::from([vec![clause[0].clone()], self.fold_all(&clause[1..])].concat())
+                        }
+                        _ => clause.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                return rebuilt([vec![exp], clauses].concat());
+            }
+            ("for", [LispExp::Symbol(name), keyword, lst, body @ ..]) => {
+                let lst = self.fold(lst);
+                self.shadowed.insert(*name);
+                return rebuilt([vec![LispExp::Symbol(*name), keyword.clone(), lst], self.fold_all(body)].concat());
+            }
+            ("dotimes", [LispExp::List(header), body @ ..]) => {
+                let header = match &header[..] {
+                    [LispExp::Symbol(name), count] => {
+                        self.shadowed.insert(*name);
+                        LispExp::from(vec![LispExp::Symbol(*name), self.fold(count)])
+                    }
+                    _ => LispExp::List(header.clone()),
+                };
+                return rebuilt([vec![header], self.fold_all(body)].concat());
+            }
+            ("let", [LispExp::List(bindings), body @ ..]) => {
+                let bindings = bindings
+                    .iter()
+                    .map(|binding| match binding {
+                        LispExp::List(pair) => match &pair[..] {
+                            [LispExp::Symbol(name), vl] => {
+                                self.shadowed.insert(*name);
+                                LispExp::from(vec![LispExp::Symbol(*name), self.fold(vl)])
+                            }
+                            _ => binding.clone(),
+                        },
+                        _ => binding.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                return rebuilt([vec![LispExp::from(bindings)], self.fold_all(body)].concat());
+            }
+            _ => {}
+        }
+        // a macro gets its arguments as written
+        if let Ok(LispExp::Macro(_)) = self.lisp.resolve(head) {
+            return form.clone();
+        }
+        let args = self.fold_all(args);
+        let foldable = PURE.contains(&&*head.name())
+            && !self.shadowed.contains(&head)
+            && matches!(self.lisp.resolve(head), Ok(LispExp::Builtin(_)))
+            && args.iter().all(is_literal);
+        if foldable {
+            // errors are left for when the program runs, with a backtrace
+            if let Some(vl) = self.lisp.call_pure(head, &args).filter(is_literal) {
+                return vl;
+            }
+        }
+        rebuilt(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folded(source: &str) -> String {
+        let lisp = Interpreter::new();
+        let form = lisp.parse(source).unwrap().remove(0);
+        fold(&lisp, &form).to_string()
+    }
+
+    #[test]
+    fn folds_pure_calls() {
+        assert_eq!(folded("(* 2 (+ 3 4))"), "14");
+        assert_eq!(folded("(print (str-concat \"a\" \"b\"))"), "( print \"ab\" )");
+    }
+
+    #[test]
+    fn leaves_unevaluated_arguments() {
+        assert_eq!(folded("(debug (+ 1 2))"), "( debug ( + 1 2 ) )");
+        assert_eq!(folded("(quote (+ 1 2))"), "( quote ( + 1 2 ) )");
+        assert_eq!(folded("(assert-eq (+ 1 2) 3)"), "( assert-eq ( + 1 2 ) 3 )");
+        assert_eq!(folded("(help +)"), "( help + )");
+    }
+
+    #[test]
+    fn leaves_redefined_builtins() {
+        assert_eq!(folded("(begin (define + -) (+ 1 2))"), "( begin ( define + - ) ( + 1 2 ) )");
+    }
+}
@@ -0,0 +1,559 @@
+// `pmap`, `spawn`, `await` and `with-tasks`: evaluation on other threads. Interpreters and
+// their values can't be shared between threads, so every worker gets an
+// interpreter of its own and the values it needs are copied into it: data as
+// it is, functions as their source along with copies of the variables they
+// use. Changes a worker makes, like to a vector, aren't seen by the caller.
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::builtins::{eval_body, make_lambda, record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter, Scope, Settings};
+use crate::exp::{LispExp, LispLambda, MapKey, Struct};
+use crate::fmt::flat;
+use crate::span;
+use crate::symbol::{SymbolId, SymbolMap, OPTIONAL, QUOTE, REST};
+
+// workers nest as deep as the interpreter they're started from, so they get
+// as much stack as the binary gives its own; it's only reserved, not used
+const WORKER_STACK: usize = 256 * 1024 * 1024;
+
+// how often `with-tasks` looks for tasks that finished
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// a value copied out of an interpreter, which can be sent to another thread
+#[derive(Clone)]
+enum Sendable {
+    Symbol(String),
+    Str(String),
+    Char(char),
+    Int(i64),
+    BigInt(BigInt),
+    Ratio(BigRational),
+    Number(f64),
+    Bool(bool),
+    Nil,
+    List(Vec<Sendable>),
+    Pair(Box<(Sendable, Sendable)>),
+    Map(Vec<(MapKey, Sendable)>),
+    Vector(Vec<Sendable>),
+    Builtin(String),
+    Struct {
+        name: String,
+        fields: Vec<Sendable>,
+        values: Vec<Sendable>,
+    },
+    // a lambda or macro as its parameter list and body, with the local
+    // variables it uses; the global ones are in the Job
+    Lambda {
+        params: Box<Sendable>,
+        body: Vec<Sendable>,
+        locals: Vec<(String, Sendable)>,
+        name: Option<String>,
+        is_macro: bool,
+    },
+}
+
+// a value and the global variables the functions in it use
+#[derive(Clone)]
+pub(crate) struct Job {
+    globals: Vec<(String, Sendable)>,
+    value: Sendable,
+}
+
+// a worker's error, sent back as its message, except for the ones that stop
+// the whole program, which stay uncatchable
+enum Failure {
+    Message(String),
+    LimitExceeded { limit: &'static str, max: u64 },
+    Interrupted,
+    Exit(i32),
+}
+
+impl From<LispError> for Failure {
+    fn from(err: LispError) -> Failure {
+        match err.root() {
+            LispError::LimitExceeded { limit, max } => Failure::LimitExceeded { limit, max: *max },
+            LispError::Interrupted => Failure::Interrupted,
+            LispError::Exit(code) => Failure::Exit(*code),
+            other => Failure::Message(other.message()),
+        }
+    }
+}
+
+impl From<Failure> for LispError {
+    fn from(failure: Failure) -> LispError {
+        match failure {
+            Failure::Message(msg) => LispError::Other(msg),
+            Failure::LimitExceeded { limit, max } => LispError::LimitExceeded { limit, max },
+            Failure::Interrupted => LispError::Interrupted,
+            Failure::Exit(code) => LispError::Exit(code),
+        }
+    }
+}
+
+// the tasks `spawn` started and `await` hasn't collected, by id
+#[derive(Default)]
+pub(crate) struct Tasks {
+    next: i64,
+    running: HashMap<i64, Task>,
+}
+
+struct Task {
+    handle: JoinHandle<Result<Job, Failure>>,
+    // where `spawn` was called, for `--leaks`
+    site: String,
+}
+
+// spawned expressions longer than this are cut short in `unawaited`
+const SHOWN: usize = 60;
+
+#[derive(Default)]
+struct Packer {
+    globals: Vec<(String, Sendable)>,
+    global_names: HashSet<String>,
+    // the lambdas being packed, to catch local functions that refer to themselves
+    packing: Vec<*const LispLambda>,
+}
+
+// every symbol in `exp`, which includes the free variables of code
+fn symbols(exp: &LispExp, names: &mut Vec<SymbolId>) {
+    match exp {
+        LispExp::Symbol(name) if !name.is_keyword() && !names.contains(name) => names.push(*name),
+        LispExp::List(items) => items.iter().for_each(|item| symbols(item, names)),
+        LispExp::Vector(_) | LispExp::Map(_) => exp.literal_items().unwrap_or_default().iter().for_each(|item| symbols(item, names)),
+        _ => {}
+    }
+}
+
+// the parameter list `func` was made from
+fn params(func: &LispLambda) -> LispExp {
+    let mut params: Vec<LispExp> = func.params.iter().map(|param| LispExp::Symbol(*param)).collect();
+    if !func.optional.is_empty() {
+        params.push(LispExp::Symbol(OPTIONAL));
+        for (param, default) in &func.optional {
+            params.push(LispExp::from(vec![LispExp::Symbol(*param), default.clone()]));
+        }
+    }
+    for (_, keyword, default) in &func.keys {
+        params.push(LispExp::Symbol(*keyword));
+        params.push(default.clone());
+    }
+    if let Some(rest) = func.rest {
+        params.push(LispExp::Symbol(REST));
+        params.push(LispExp::Symbol(rest));
+    }
+    LispExp::from(params)
+}
+
+impl Packer {
+    fn pack(mut self, value: &LispExp) -> Result<Job, LispError> {
+        let value = self.value(value)?;
+        Ok(Job { globals: self.globals, value })
+    }
+    fn value(&mut self, value: &LispExp) -> Result<Sendable, LispError> {
+        Ok(match value {
+            LispExp::Symbol(name) => Sendable::Symbol(name.to_string()),
+            LispExp::Str(string) => Sendable::Str(string.clone()),
+            LispExp::Char(chr) => Sendable::Char(*chr),
+            LispExp::Int(num) => Sendable::Int(*num),
+            LispExp::BigInt(num) => Sendable::BigInt((**num).clone()),
+            LispExp::Ratio(num) => Sendable::Ratio((**num).clone()),
+            LispExp::Number(num) => Sendable::Number(*num),
+            LispExp::Bool(b) => Sendable::Bool(*b),
+            LispExp::Nil => Sendable::Nil,
+            LispExp::List(items) => Sendable::List(self.values(items)?),
+            LispExp::Pair(pair) => Sendable::Pair(Box::new((self.value(&pair.0)?, self.value(&pair.1)?))),
+            LispExp::Vector(items) => Sendable::Vector(self.values(&items.borrow())?),
+            LispExp::Map(map) => Sendable::Map(
+                map.iter()
+                    .map(|(key, vl)| Ok((key.clone(), self.value(vl)?)))
+                    .collect::<Result<_, LispError>>()?,
+            ),
+            LispExp::Builtin(name) => Sendable::Builtin(name.to_string()),
+            LispExp::Struct(record) => Sendable::Struct {
+                name: record.name.to_string(),
+                fields: self.values(&record.fields)?,
+                values: self.values(&record.values)?,
+            },
+            LispExp::Lambda(func) => self.lambda(func, false)?,
+            LispExp::Macro(func) => self.lambda(func, true)?,
+        })
+    }
+    fn values(&mut self, items: &[LispExp]) -> Result<Vec<Sendable>, LispError> {
+        items.iter().map(|item| self.value(item)).collect()
+    }
+    fn lambda(&mut self, func: &LispLambda, is_macro: bool) -> Result<Sendable, LispError> {
+        if self.packing.contains(&(func as *const _)) {
+            return Err(LispError::from("a local function that refers to itself can't be sent to another thread"));
+        }
+        self.packing.push(func);
+        let params = params(func);
+        let mut names = vec![];
+        symbols(&params, &mut names);
+        func.body.iter().for_each(|exp| symbols(exp, &mut names));
+        let mut locals = vec![];
+        for name in names {
+            match func.env.lookup(name) {
+                Some((vl, true)) => self.global(name, &vl)?,
+                Some((vl, false)) => locals.push((name.to_string(), self.value(&vl)?)),
+                None => {}
+            }
+        }
+        self.packing.pop();
+        Ok(Sendable::Lambda {
+            params: Box::new(self.value(&params)?),
+            body: self.values(&func.body)?,
+            locals,
+            name: func.name.get().map(SymbolId::to_string),
+            is_macro,
+        })
+    }
+    fn global(&mut self, name: SymbolId, value: &LispExp) -> Result<(), LispError> {
+        // added before it's packed, so a function can refer to itself
+        if self.global_names.insert(name.to_string()) {
+            let value = self.value(value)?;
+            self.globals.push((name.to_string(), value));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn pack(value: &LispExp) -> Result<Job, LispError> {
+    Packer::default().pack(value)
+}
+
+impl Job {
+    // the value rebuilt in `lisp`, with the globals its functions use in a
+    // scope of their own, so they don't replace the interpreter's
+    pub(crate) fn unpack(self, lisp: &Interpreter) -> Result<LispExp, LispError> {
+        let scope = Scope::child(&lisp.current_scope());
+        for (name, value) in self.globals {
+            let value = unpack_value(lisp, &scope, value)?;
+            scope.insert(SymbolId::new(&name), value);
+        }
+        unpack_value(lisp, &scope, self.value)
+    }
+}
+
+fn unpack_value(lisp: &Interpreter, scope: &Rc<Scope>, value: Sendable) -> Result<LispExp, LispError> {
+    let values = |items: Vec<Sendable>| {
+        items
+            .into_iter()
+            .map(|item| unpack_value(lisp, scope, item))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    Ok(match value {
+        Sendable::Symbol(name) => LispExp::Symbol(SymbolId::new(&name)),
+        Sendable::Str(string) => LispExp::Str(string),
+        Sendable::Char(chr) => LispExp::Char(chr),
+        Sendable::Int(num) => LispExp::Int(num),
+        Sendable::BigInt(num) => LispExp::BigInt(Rc::new(num)),
+        Sendable::Ratio(num) => LispExp::Ratio(Rc::new(num)),
+        Sendable::Number(num) => LispExp::Number(num),
+        Sendable::Bool(b) => LispExp::Bool(b),
+        Sendable::Nil => LispExp::Nil,
+        Sendable::List(items) => LispExp::from(values(items)?),
+        Sendable::Pair(pair) => {
+            let (car, cdr) = *pair;
+            LispExp::Pair(Rc::new((unpack_value(lisp, scope, car)?, unpack_value(lisp, scope, cdr)?)))
+        }
+        Sendable::Vector(items) => LispExp::Vector(Rc::new(values(items)?.into())),
+        Sendable::Map(entries) => LispExp::Map(
+            entries
+                .into_iter()
+                .map(|(key, vl)| Ok((key, unpack_value(lisp, scope, vl)?)))
+                .collect::<Result<_, LispError>>()?,
+        ),
+        Sendable::Builtin(name) => LispExp::Builtin(SymbolId::new(&name)),
+        Sendable::Struct { name, fields, values: items } => LispExp::Struct(Rc::new(Struct {
+            name: SymbolId::new(&name),
+            fields: values(fields)?.into(),
+            values: values(items)?,
+        })),
+        Sendable::Lambda { params, body, locals, name, is_macro } => {
+            let env = Scope::child(scope);
+            for (local, vl) in locals {
+                env.insert(SymbolId::new(&local), unpack_value(lisp, scope, vl)?);
+            }
+            let params = unpack_value(lisp, scope, *params)?;
+            let body = values(body)?;
+            let func = lisp.with_scope(env, || make_lambda(lisp, &params, &body))?;
+            if let Some(name) = name {
+                let _ = func.name.set(SymbolId::new(&name));
+            }
+            if is_macro {
+                LispExp::Macro(func)
+            } else {
+                LispExp::Lambda(func)
+            }
+        }
+    })
+}
+
+// runs `work` on a new thread, in an interpreter made with `settings`, and
+// packs up what it returns
+fn run_worker(settings: Settings, work: impl FnOnce(&Interpreter) -> Result<LispExp, LispError>) -> Result<Job, Failure> {
+    let lisp = Interpreter::with_settings(settings);
+    work(&lisp).and_then(|vl| pack(&vl)).map_err(Failure::from)
+}
+
+fn spawn_error(err: std::io::Error) -> LispError {
+    LispError::Other(format!("couldn't start a thread: {err}"))
+}
+
+// (pmap f lst) is `(map f lst)`, with the list split between as many threads
+// as there are cores
+fn lisp_pmap(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (func, rest) = unpack(cont)?;
+    let (lst, _) = unpack(rest)?;
+    let items = lst.get_list()?;
+    if items.is_empty() {
+        return Ok(LispExp::from(vec![]));
+    }
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get()).min(items.len());
+    let chunk_len = items.len().div_ceil(threads);
+    let jobs = items
+        .chunks(chunk_len)
+        .map(|chunk| pack(&LispExp::from(vec![func.clone(), LispExp::from(chunk.to_vec())])))
+        .collect::<Result<Vec<_>, _>>()?;
+    let results = std::thread::scope(|scope| {
+        let handles = jobs
+            .into_iter()
+            .map(|job| {
+                let settings = env.settings();
+                std::thread::Builder::new().stack_size(WORKER_STACK).spawn_scoped(scope, move || {
+                    run_worker(settings, |lisp| {
+                        let job = job.unpack(lisp)?;
+                        let [func, chunk] = job.get_list()? else {
+                            unreachable!("jobs are a function and a chunk")
+                        };
+                        let mapped = chunk
+                            .get_list()?
+                            .iter()
+                            .map(|item| lisp.apply(func, vec![item.clone()]))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(LispExp::from(mapped))
+                    })
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(spawn_error)?;
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(Failure::Message("a pmap thread panicked".to_owned()))))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LispError::from)
+    })?;
+    let mut ret = vec![];
+    for job in results {
+        ret.extend(job.unpack(env)?.get_list()?.iter().cloned());
+    }
+    Ok(LispExp::from(ret))
+}
+
+// (spawn exp) starts evaluating `exp` on another thread and returns a task
+// id for `await`
+pub(crate) fn lisp_spawn(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let [exp] = cont else {
+        return Err(LispError::ArityMismatch { name: "spawn".to_owned(), min: 1, max: Some(1), found: cont.len() });
+    };
+    // a lambda of the expression takes the variables it uses along
+    let thunk = LispExp::Lambda(make_lambda(env, &LispExp::from(vec![]), std::slice::from_ref(exp))?);
+    let job = pack(&thunk)?;
+    let settings = env.settings();
+    let handle = std::thread::Builder::new()
+        .stack_size(WORKER_STACK)
+        .spawn(move || run_worker(settings, |lisp| lisp.apply(&job.unpack(lisp)?, vec![])))
+        .map_err(spawn_error)?;
+    let site = site(env, exp);
+    let mut tasks = env.tasks.borrow_mut();
+    let id = tasks.next;
+    tasks.next += 1;
+    tasks.running.insert(id, Task { handle, site });
+    Ok(LispExp::Int(id))
+}
+
+// where `(spawn exp)` is: the file being run and where `exp` is in it, if
+// the parser read it, and the form
+fn site(env: &Interpreter, exp: &LispExp) -> String {
+    let pos = match exp {
+        LispExp::List(items) => span::span(items),
+        _ => None,
+    };
+    let file = env.current_file().map(|file| file.display().to_string());
+    let at = match (file, pos) {
+        (Some(file), Some(pos)) => format!("{file}:{pos}"),
+        (None, Some(pos)) => pos.to_string(),
+        (Some(file), None) => file,
+        (None, None) => "?".to_owned(),
+    };
+    let mut form = format!("(spawn {})", flat(exp));
+    if form.chars().count() > SHOWN {
+        form = form.chars().take(SHOWN - 3).collect::<String>() + "...";
+    }
+    format!("{at}: {form}")
+}
+
+impl Interpreter {
+    // the tasks `spawn` started that nothing awaited, oldest first, as
+    // `task id, spawned at file:line:col: (spawn exp)`; they're left running
+    // when the program ends, and whatever they fail with is lost
+    pub fn unawaited(&self) -> Vec<String> {
+        let tasks = self.tasks.borrow();
+        let mut ids: Vec<&i64> = tasks.running.keys().collect();
+        ids.sort();
+        ids.into_iter().map(|id| format!("task {id}, spawned at {}", tasks.running[id].site)).collect()
+    }
+}
+
+// (await task) waits for a task `spawn` started and returns its value, or
+// fails with its error
+fn lisp_await(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (id, _) = unpack(cont)?;
+    let id = id.get_int()?;
+    let task = env
+        .tasks
+        .borrow_mut()
+        .running
+        .remove(&id)
+        .ok_or_else(|| LispError::Other(format!("there's no task {id} to await")))?;
+    let job = task
+        .handle
+        .join()
+        .unwrap_or_else(|_| Err(Failure::Message("the task panicked".to_owned())))?;
+    job.unpack(env)
+}
+
+// (with-tasks (t) body...) runs the body with `(t exp)` spawning `exp`, then
+// waits for every task the body started and didn't await. Once one fails,
+// the others are cancelled, and the first error is the form's; otherwise
+// it's the body's value
+pub(crate) fn lisp_with_tasks(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (names, body) = unpack(cont)?;
+    let [LispExp::Symbol(name)] = names.get_list()? else {
+        return Err(LispError::Other(format!("with-tasks expects a (name) to spawn with, found {names}")));
+    };
+    // `(t exp)` is `(spawn exp)`
+    let exp = LispExp::Symbol(SymbolId::new("exp"));
+    let spawn = LispExp::from(vec![LispExp::Symbol(SymbolId::new("list")), LispExp::from(vec![LispExp::Symbol(QUOTE), LispExp::Symbol(SymbolId::new("spawn"))]), exp.clone()]);
+    let spawner = make_lambda(env, &LispExp::from(vec![exp]), &[spawn])?;
+    let _ = spawner.name.set(*name);
+    let scope = Scope::child(&env.current_scope());
+    scope.insert(*name, LispExp::Macro(spawner));
+    let first = env.tasks.borrow().next;
+    let cancel = Arc::new(AtomicBool::new(false));
+    env.cancel.borrow_mut().push(cancel.clone());
+    let res = env.with_scope(scope, || eval_body(env, body));
+    env.cancel.borrow_mut().pop();
+    let mut pending: Vec<i64> = env.tasks.borrow().running.keys().copied().filter(|&id| id >= first).collect();
+    pending.sort();
+    let mut failed = res.as_ref().err().cloned();
+    if failed.is_some() {
+        cancel.store(true, Ordering::Relaxed);
+    }
+    loop {
+        let finished: Vec<i64> = {
+            let tasks = env.tasks.borrow();
+            pending.iter().copied().filter(|id| tasks.running[id].handle.is_finished()).collect()
+        };
+        for id in finished {
+            pending.retain(|&other| other != id);
+            let task = env.tasks.borrow_mut().running.remove(&id).expect("pending tasks are running");
+            let res = task.handle.join().unwrap_or_else(|_| Err(Failure::Message("the task panicked".to_owned())));
+            if let (Err(failure), None) = (res, &failed) {
+                failed = Some(failure.into());
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+        if failed.is_none() {
+            // Ctrl-C stops the tasks too
+            if let Err(err) = env.step() {
+                failed = Some(err);
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    match failed {
+        Some(err) => Err(err),
+        None => res,
+    }
+}
+
+pub(crate) fn parallel_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "pmap", lisp_pmap, 2, "(pmap f lst)", "like `map`, with the list split between threads");
+    record!(funcs, "await", lisp_await, 1, "(await task)", "waits for a task `spawn` started and returns its value");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn pmap_is_map() {
+        assert_eq!(run("(pmap (lambda (x) (* x x)) '(1 2 3 4 5 6 7 8 9))"), "( 1 4 9 16 25 36 49 64 81 )");
+        assert_eq!(run("(begin (define k 3) (defn add-k (x) (+ x k)) (pmap add-k '(1 2)))"), "( 4 5 )");
+        assert_eq!(run("(let ((k 2)) (pmap (lambda (x) (* x k)) '(1 2)))"), "( 2 4 )");
+        assert_eq!(run("(pmap car '(1))"), "error: 1 is not a List, it's a Int");
+    }
+
+    #[test]
+    fn spawn_and_await() {
+        assert_eq!(run("(begin (define n 10) (await (spawn (+ n 1))))"), "11");
+        assert_eq!(run("(try (await (spawn (error \"bad\"))) (catch e e))"), "\"bad\"");
+        // the worker changes its own copy
+        assert_eq!(run("(begin (define v [1 2]) (await (spawn (vec-push! v 3))) v)"), "[ 1 2 ]");
+    }
+
+    #[test]
+    fn async_is_spawn() {
+        assert_eq!(run("(begin (define a (async (* 6 7))) (define b (async (list 1))) (list (await b) (await a)))"), "( ( 1 ) 42 )");
+        assert_eq!(run("(begin (define a (async 1)) (await a) (await a))"), "error: there's no task 0 to await");
+    }
+
+    #[test]
+    fn unawaited_tasks() {
+        let lisp = Interpreter::new();
+        lisp.eval_str("(define a (spawn 1)) (define b (spawn (+ 1\n (* 2 3)))) (with-tasks (t) (t 3)) (await a)").unwrap();
+        assert_eq!(lisp.unawaited(), ["task 1, spawned at 1:39: (spawn (+ 1 (* 2 3)))"]);
+        lisp.eval_str("(await b)").unwrap();
+        assert!(lisp.unawaited().is_empty());
+    }
+
+    #[test]
+    fn with_tasks_waits_and_cancels() {
+        assert_eq!(run("(with-tasks (t) (define a (t (* 2 3))) (define b (t (+ 1 1))) (t (list 1)) (+ (await a) (await b)))"), "8");
+        // the first failure cancels the task that would run forever
+        let spin = "(t (begin (defn spin () (spin)) (spin)))";
+        assert_eq!(run(&format!("(try (with-tasks (t) {spin} (t (begin (sleep 20) (error \"boom\")))) (catch e e))")), "\"boom\"");
+        assert_eq!(run(&format!("(try (with-tasks (t) {spin} (car 1)) (catch e e))")), "\"1 is not a List, it's a Int\"");
+        assert_eq!(run("(with-tasks (t) (t 1 2))"), "error: `t` expects 1 arguments, got 2");
+    }
+
+    #[test]
+    fn limits_cross_threads() {
+        let lisp = Interpreter::with_limits(10_000, 200, 1 << 20);
+        let err = lisp.eval_str("(await (spawn (begin (defn f () (f)) (f))))").unwrap_err();
+        assert!(err.summary().contains("limit"), "{}", err.summary());
+    }
+}
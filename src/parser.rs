@@ -0,0 +1,594 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::Zero;
+
+use crate::error::LispError;
+use crate::exp::{LispExp, MapKey};
+use crate::lexer::{stream_tokens, Pos, SpannedToken, Token};
+use crate::num;
+use crate::span;
+use crate::symbol::{SymbolId, QUASIQUOTE, QUOTE, UNQUOTE, UNQUOTE_SPLICING};
+
+// how deep lists may nest by default, both when parsing and evaluating
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// a form that is still being read
+#[derive(Clone)]
+enum Frame {
+    // `(` at the position, with the items read so far
+    List(Pos, Vec<LispExp>),
+    // `[` at the position, read as a vector of the items
+    Vector(Pos, Vec<LispExp>),
+    // `{` at the position, read as a map of the keys and values
+    Map(Pos, Vec<LispExp>),
+    // a reader prefix waiting for its expression
+    Prefix(SymbolId, Pos),
+}
+
+// a list read at `pos`, recorded so errors evaluating it can point there
+fn spanned(items: Rc<[LispExp]>, pos: Pos) -> LispExp {
+    span::record(&items, pos);
+    LispExp::List(items)
+}
+
+// the list `items` read between `(` at `open` and `)` at `close`, or the
+// dotted pair they make when the one before last is a `.`, as in `(a b . c)`
+fn dotted(mut items: Vec<LispExp>, open: Pos, close: Pos) -> Result<LispExp, LispError> {
+    let dot = SymbolId::new(".");
+    let Some(at) = items.iter().position(|item| matches!(item, LispExp::Symbol(name) if *name == dot)) else {
+        return Ok(spanned(items.into(), open));
+    };
+    if at + 2 != items.len() {
+        return Err(LispError::at(close, "expected one expression after `.`"));
+    }
+    let cdr = items.pop().unwrap_or_else(|| unreachable!("the dot is followed by an item"));
+    items.pop();
+    Ok(items.into_iter().rev().fold(cdr, |cdr, car| LispExp::cons(car, cdr)))
+}
+
+// the form `frame` was reading, finished by the closing delimiter `token`,
+// which has to match the one it was opened with
+fn close(frame: Option<Frame>, token: &SpannedToken) -> Result<LispExp, LispError> {
+    let found = match token.token {
+        Token::Close => ")",
+        Token::CloseBracket => "]",
+        _ => "}",
+    };
+    match frame {
+        Some(Frame::List(open, items)) if found == ")" => dotted(items, open, token.pos),
+        Some(Frame::Vector(_, items)) if found == "]" => Ok(LispExp::Vector(Rc::new(RefCell::new(items)))),
+        Some(Frame::Map(_, items)) if found == "}" && items.len() % 2 == 1 => {
+            Err(LispError::at(token.pos, "a map literal needs a value for every key"))
+        }
+        Some(Frame::Map(open, items)) if found == "}" => map_literal(items, open),
+        frame => Err(mismatched(frame.as_ref(), token, found)),
+    }
+}
+
+// the map `{k v ...}` read at `open`, whose keys are read as they're written
+fn map_literal(items: Vec<LispExp>, open: Pos) -> Result<LispExp, LispError> {
+    let mut map = BTreeMap::new();
+    let mut items = items.into_iter();
+    while let (Some(key), Some(vl)) = (items.next(), items.next()) {
+        let Ok(map_key) = MapKey::try_from(&key) else {
+            return Err(LispError::at(open, format!("a map literal's keys are booleans, ints, strings or symbols, found {key}")));
+        };
+        if map.insert(map_key, vl).is_some() {
+            return Err(LispError::at(open, format!("a map literal has the key {key} twice")));
+        }
+    }
+    Ok(LispExp::Map(map))
+}
+
+// the error for the closing delimiter `token`, written `found`, not closing `frame`
+fn mismatched(frame: Option<&Frame>, token: &SpannedToken, found: &str) -> LispError {
+    let (open, expected) = match frame {
+        Some(Frame::List(open, _)) => (open, ")"),
+        Some(Frame::Vector(open, _)) => (open, "]"),
+        Some(Frame::Map(open, _)) => (open, "}"),
+        Some(Frame::Prefix(..)) | None => return LispError::UnexpectedToken { pos: token.pos, token: found.to_owned() },
+    };
+    LispError::at(token.pos, format!("expected `{expected}` to close the one opened at {open}, found `{found}`"))
+}
+
+// the error for the input ending before `frame` was finished
+fn unfinished(frame: Option<&Frame>) -> LispError {
+    match frame {
+        Some(Frame::List(open, _)) => LispError::UnclosedList(*open),
+        Some(Frame::Vector(open, _)) => LispError::Incomplete {
+            pos: *open,
+            msg: "could not find closing `]`".to_owned(),
+        },
+        Some(Frame::Map(open, _)) => LispError::Incomplete {
+            pos: *open,
+            msg: "could not find closing `}`".to_owned(),
+        },
+        Some(Frame::Prefix(form, pos)) => LispError::Incomplete {
+            pos: *pos,
+            msg: format!("expected an expression to {form}"),
+        },
+        None => LispError::from("could not get token"),
+    }
+}
+
+// parses one form, keeping unfinished lists on a heap stack instead of recursing,
+// so nesting only costs memory and is limited by `max_depth`
+pub fn parse(tokens: &[SpannedToken], max_depth: usize) -> Result<(LispExp, &[SpannedToken]), LispError> {
+    let mut stack: Vec<Frame> = vec![];
+    let mut rest = tokens;
+    loop {
+        let Some((token, tail)) = rest.split_first() else {
+            return Err(unfinished(stack.last()));
+        };
+        rest = tail;
+        let prefix = match &token.token {
+            Token::Quote => Some(QUOTE),
+            Token::Quasiquote => Some(QUASIQUOTE),
+            Token::Unquote => Some(UNQUOTE),
+            Token::UnquoteSplicing => Some(UNQUOTE_SPLICING),
+            _ => None,
+        };
+        if prefix.is_some() || matches!(token.token, Token::Open | Token::OpenBracket | Token::OpenBrace) {
+            if stack.len() >= max_depth {
+                return Err(LispError::at(token.pos, format!("nesting deeper than {max_depth} levels")));
+            }
+            stack.push(match (prefix, &token.token) {
+                (Some(form), _) => Frame::Prefix(form, token.pos),
+                (None, Token::Open) => Frame::List(token.pos, vec![]),
+                (None, Token::OpenBracket) => Frame::Vector(token.pos, vec![]),
+                (None, _) => Frame::Map(token.pos, vec![]),
+            });
+            continue;
+        }
+        let mut exp = match &token.token {
+            Token::Close | Token::CloseBracket | Token::CloseBrace => close(stack.pop(), token)?,
+            // the dot of a pair, read as a `.` symbol until the list is closed
+            Token::Atom(atom) if atom == "." && !matches!(stack.last(), Some(Frame::List(_, items)) if !items.is_empty()) => {
+                return Err(LispError::UnexpectedToken { pos: token.pos, token: ".".to_owned() })
+            }
+            Token::Atom(atom) => parse_atom(atom).map_err(|msg| LispError::at(token.pos, msg))?,
+            Token::Str(string) => LispExp::Str(string.clone()),
+            Token::Char(chr) => LispExp::Char(*chr),
+            _ => unreachable!("prefixes and opening delimiters are pushed above"),
+        };
+        // hand the finished expression to whatever is waiting for it
+        loop {
+            match stack.last_mut() {
+                None => return Ok((exp, rest)),
+                Some(Frame::List(_, items) | Frame::Vector(_, items) | Frame::Map(_, items)) => {
+                    items.push(exp);
+                    break;
+                }
+                Some(Frame::Prefix(form, pos)) => {
+                    exp = spanned(Rc::new([LispExp::Symbol(*form), exp]), *pos);
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
+
+// parses every top-level form
+pub fn parse_program(tokens: &[SpannedToken]) -> Result<Vec<LispExp>, LispError> {
+    parse_program_with_depth(tokens, DEFAULT_MAX_DEPTH)
+}
+
+pub fn parse_program_with_depth(tokens: &[SpannedToken], max_depth: usize) -> Result<Vec<LispExp>, LispError> {
+    Ok(parse_program_spanned(tokens, max_depth)?.into_iter().map(|(exp, _)| exp).collect())
+}
+
+// every top-level form with where it starts, which atoms don't keep like
+// lists do, so an error in a bare symbol can point at it too
+pub fn parse_program_spanned(tokens: &[SpannedToken], max_depth: usize) -> Result<Vec<(LispExp, Pos)>, LispError> {
+    let mut ret = vec![];
+    let mut rest = tokens;
+    while let Some(first) = rest.first() {
+        let (exp, new_rest) = parse(rest, max_depth)?;
+        ret.push((exp, first.pos));
+        rest = new_rest;
+    }
+    Ok(ret)
+}
+
+// like `parse_program_with_depth`, but keeps going after an error, returning
+// the forms that parsed along with every error; after an error it starts over
+// at the next token in the first column, taken as the next top-level form
+pub fn parse_program_recovering(tokens: &[SpannedToken], max_depth: usize) -> (Vec<LispExp>, Vec<LispError>) {
+    let mut forms = vec![];
+    let mut errors = vec![];
+    let mut rest = tokens;
+    while !rest.is_empty() {
+        match parse(rest, max_depth) {
+            Ok((exp, new_rest)) => {
+                forms.push(exp);
+                rest = new_rest;
+            }
+            Err(err) => {
+                errors.push(err);
+                let Some(next) = rest.iter().skip(1).position(|token| token.pos.col == 1) else {
+                    break;
+                };
+                rest = &rest[next + 1..];
+            }
+        }
+    }
+    (forms, errors)
+}
+
+// the head of the placeholder `parse_lenient` leaves where something couldn't
+// be read, `(#<error> "message")`; no source reads as it, since `#<` isn't a token
+const PLACEHOLDER: &str = "#<error>";
+
+fn placeholder(err: &LispError) -> LispExp {
+    let items: Rc<[LispExp]> = Rc::new([LispExp::Symbol(SymbolId::new(PLACEHOLDER)), LispExp::Str(err.summary())]);
+    match err.pos() {
+        Some(pos) => spanned(items, pos),
+        None => LispExp::List(items),
+    }
+}
+
+// the message of `exp` if it's a placeholder `parse_lenient` left
+pub fn parse_error(exp: &LispExp) -> Option<&str> {
+    match exp {
+        LispExp::List(items) => match &items[..] {
+            [LispExp::Symbol(head), LispExp::Str(msg)] if *head == SymbolId::new(PLACEHOLDER) => Some(msg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// reads a whole file however broken it is, for tools that work on files
+// while they're being edited: every token that can't be read, delimiter that
+// closes nothing and form nested too deep becomes a placeholder in its place,
+// a delimiter closing an outer form also closes the ones inside it, and the
+// forms still open when the file ends are closed there. Returns what was read,
+// placeholders and all, with an error for each place something was made up
+pub fn parse_lenient(source: &str, max_depth: usize) -> (Vec<LispExp>, Vec<LispError>) {
+    let mut lenient = Lenient { max_depth, ..Lenient::default() };
+    for token in stream_tokens(source.as_bytes()) {
+        match token {
+            Ok(token) => lenient.token(&token),
+            Err(err) => lenient.fail(err),
+        }
+    }
+    while let Some(frame) = lenient.stack.pop() {
+        let err = unfinished(Some(&frame));
+        lenient.cut_short(frame, err);
+    }
+    (lenient.forms, lenient.errors)
+}
+
+#[derive(Default)]
+struct Lenient {
+    max_depth: usize,
+    stack: Vec<Frame>,
+    forms: Vec<LispExp>,
+    errors: Vec<LispError>,
+    // how many delimiters are open in a form nested too deep, which is skipped
+    skipping: usize,
+}
+
+impl Lenient {
+    // hands the finished expression to whatever is waiting for it
+    fn finished(&mut self, mut exp: LispExp) {
+        loop {
+            match self.stack.last_mut() {
+                None => return self.forms.push(exp),
+                Some(Frame::List(_, items) | Frame::Vector(_, items) | Frame::Map(_, items)) => return items.push(exp),
+                Some(Frame::Prefix(form, pos)) => {
+                    exp = spanned(Rc::new([LispExp::Symbol(*form), exp]), *pos);
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+    fn fail(&mut self, err: LispError) {
+        self.finished(placeholder(&err));
+        self.errors.push(err);
+    }
+    // `frame` as far as it was read, ended early because of `err`
+    fn cut_short(&mut self, frame: Frame, err: LispError) {
+        let exp = match frame {
+            Frame::List(open, items) => spanned(items.into(), open),
+            Frame::Vector(_, items) => LispExp::Vector(Rc::new(RefCell::new(items))),
+            // the pairs that could be read
+            Frame::Map(_, items) => {
+                LispExp::Map(items.chunks_exact(2).filter_map(|pair| Some((MapKey::try_from(&pair[0]).ok()?, pair[1].clone()))).collect())
+            }
+            Frame::Prefix(form, pos) => spanned(Rc::new([LispExp::Symbol(form), placeholder(&err)]), pos),
+        };
+        self.errors.push(err);
+        self.finished(exp);
+    }
+    fn token(&mut self, token: &SpannedToken) {
+        let opens = matches!(token.token, Token::Open | Token::OpenBracket | Token::OpenBrace);
+        let closes = matches!(token.token, Token::Close | Token::CloseBracket | Token::CloseBrace);
+        if self.skipping > 0 {
+            self.skipping = self.skipping + usize::from(opens) - usize::from(closes);
+            return;
+        }
+        let prefix = match &token.token {
+            Token::Quote => Some(QUOTE),
+            Token::Quasiquote => Some(QUASIQUOTE),
+            Token::Unquote => Some(UNQUOTE),
+            Token::UnquoteSplicing => Some(UNQUOTE_SPLICING),
+            _ => None,
+        };
+        if prefix.is_some() || opens {
+            if self.stack.len() >= self.max_depth {
+                self.skipping = usize::from(opens);
+                return self.fail(LispError::at(token.pos, format!("nesting deeper than {} levels", self.max_depth)));
+            }
+            self.stack.push(match (prefix, &token.token) {
+                (Some(form), _) => Frame::Prefix(form, token.pos),
+                (None, Token::Open) => Frame::List(token.pos, vec![]),
+                (None, Token::OpenBracket) => Frame::Vector(token.pos, vec![]),
+                (None, _) => Frame::Map(token.pos, vec![]),
+            });
+            return;
+        }
+        match &token.token {
+            Token::Close | Token::CloseBracket | Token::CloseBrace => self.close(token),
+            Token::Atom(atom) if atom == "." && !matches!(self.stack.last(), Some(Frame::List(_, items)) if !items.is_empty()) => {
+                self.fail(LispError::UnexpectedToken { pos: token.pos, token: ".".to_owned() })
+            }
+            Token::Atom(atom) => match parse_atom(atom) {
+                Ok(exp) => self.finished(exp),
+                Err(msg) => self.fail(LispError::at(token.pos, msg)),
+            },
+            Token::Str(string) => self.finished(LispExp::Str(string.clone())),
+            Token::Char(chr) => self.finished(LispExp::Char(*chr)),
+            _ => unreachable!("prefixes and opening delimiters are pushed above"),
+        }
+    }
+    // a closing delimiter ends the innermost form it can close, and the ones
+    // inside that weren't closed; one that closes nothing is left out
+    fn close(&mut self, token: &SpannedToken) {
+        let found = match token.token {
+            Token::Close => ")",
+            Token::CloseBracket => "]",
+            _ => "}",
+        };
+        let closed = self.stack.iter().rposition(|frame| {
+            matches!((frame, found), (Frame::List(..), ")") | (Frame::Vector(..), "]") | (Frame::Map(..), "}"))
+        });
+        let Some(at) = closed else {
+            return self.fail(mismatched(self.stack.last(), token, found));
+        };
+        while self.stack.len() > at + 1 {
+            let frame = self.stack.pop().unwrap_or_else(|| unreachable!("the stack is longer than `at`"));
+            let err = match frame {
+                Frame::Prefix(..) => unfinished(Some(&frame)),
+                _ => mismatched(Some(&frame), token, found),
+            };
+            self.cut_short(frame, err);
+        }
+        let frame = self.stack.pop().unwrap_or_else(|| unreachable!("`at` is on the stack"));
+        // a list with a misplaced `.` or a map missing a value is kept as it was read
+        match close(Some(frame.clone()), token) {
+            Ok(exp) => self.finished(exp),
+            Err(err) => self.cut_short(frame, err),
+        }
+    }
+}
+
+// a token is a number only if all of it reads as one, so `-` and `-x` are
+// symbols; a sign belongs to the number when a digit or `.` follows it. One
+// that starts with a digit, or a sign and a digit, can't be a symbol, so
+// `1.2.3` and `12abc` are errors rather than names nothing defines
+pub(crate) fn parse_atom(token: &str) -> Result<LispExp, String> {
+    match token {
+        "true" => return Ok(LispExp::Bool(true)),
+        "false" => return Ok(LispExp::Bool(false)),
+        "nil" => return Ok(LispExp::Nil),
+        _ => {}
+    }
+    if let Some(num) = parse_number(token) {
+        return num;
+    }
+    if token.strip_prefix(['-', '+']).unwrap_or(token).starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("{token} is not a valid number"));
+    }
+    Ok(LispExp::Symbol(SymbolId::new(token)))
+}
+
+// digits in `radix`, with `_` allowed between them as a separator;
+// None unless it starts and ends with a digit
+fn digit_group(group: &str, radix: u32) -> Option<String> {
+    let starts = group.chars().next().is_some_and(|c| c.is_digit(radix));
+    let ends = group.chars().last().is_some_and(|c| c.is_digit(radix));
+    let valid = group.chars().all(|c| c == '_' || c.is_digit(radix));
+    (starts && ends && valid).then(|| group.replace('_', ""))
+}
+
+// an int in `radix`, big if it doesn't fit in 64 bits
+fn parse_int(digits: &str, radix: u32) -> LispExp {
+    match i64::from_str_radix(digits, radix) {
+        Ok(int) => LispExp::Int(int),
+        Err(_) => num::from_big(BigInt::parse_bytes(digits.as_bytes(), radix).unwrap_or_default()),
+    }
+}
+
+// decimal ints and floats (`1_000`, `-2.5`, `.5`, `1.5e3`), hex and binary
+// ints (`0xFF`, `-0b1010`) and ratios (`1/3`); None when `token` isn't a
+// number at all, an error when it's one that can't be
+pub(crate) fn parse_number(token: &str) -> Option<Result<LispExp, String>> {
+    let (sign, body) = match token.strip_prefix(['-', '+']) {
+        Some(body) => (&token[..1], body),
+        None => ("", token),
+    };
+    let prefixed = |prefix: [&str; 2]| body.strip_prefix(prefix[0]).or_else(|| body.strip_prefix(prefix[1]));
+    for (prefix, radix) in [(["0x", "0X"], 16), (["0b", "0B"], 2)] {
+        if let Some(digits) = prefixed(prefix) {
+            let digits = digit_group(digits, radix)?;
+            return Some(Ok(parse_int(&format!("{sign}{digits}"), radix)));
+        }
+    }
+    if let Some((numer, denom)) = body.split_once('/') {
+        let numer = BigInt::parse_bytes(format!("{sign}{}", digit_group(numer, 10)?).as_bytes(), 10)?;
+        let denom = BigInt::parse_bytes(digit_group(denom, 10)?.as_bytes(), 10)?;
+        if denom.is_zero() {
+            return Some(Err(format!("ratio literal {token} has a zero denominator")));
+        }
+        return Some(Ok(num::from_ratio(BigRational::new(numer, denom))));
+    }
+    let (mantissa, exponent) = match body.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (body, None),
+    };
+    let (int, frac) = match mantissa.split_once('.') {
+        Some((int, frac)) => (int, Some(frac)),
+        None => (mantissa, None),
+    };
+    // either side of the point may be empty, but not both
+    let int = if int.is_empty() && frac.is_some() { String::new() } else { digit_group(int, 10)? };
+    let frac = match frac {
+        Some("") if !int.is_empty() => Some(String::new()),
+        Some(frac) => Some(digit_group(frac, 10)?),
+        None => None,
+    };
+    let exponent = match exponent {
+        Some(exponent) => {
+            let (exp_sign, digits) = match exponent.strip_prefix(['-', '+']) {
+                Some(digits) => (&exponent[..1], digits),
+                None => ("", exponent),
+            };
+            Some(format!("{exp_sign}{}", digit_group(digits, 10)?))
+        }
+        None => None,
+    };
+    if frac.is_none() && exponent.is_none() {
+        return Some(Ok(parse_int(&format!("{sign}{int}"), 10)));
+    }
+    let float = format!(
+        "{sign}{int}.{}e{}",
+        frac.unwrap_or_default(),
+        exponent.unwrap_or_else(|| "0".to_owned())
+    );
+    Some(float.parse::<f64>().map(LispExp::Number).map_err(|err| format!("{token}: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokens;
+
+    fn read(source: &str) -> Result<String, LispError> {
+        let forms = parse_program(&tokens(source)?)?;
+        Ok(forms.iter().map(LispExp::to_string).collect::<Vec<_>>().join(" | "))
+    }
+
+    fn number(token: &str) -> String {
+        match parse_number(token) {
+            Some(Ok(num)) => format!("{num} {}", num.name()),
+            Some(Err(msg)) => format!("error: {msg}"),
+            None => "not a number".to_owned(),
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(number("1_000"), "1000 Int");
+        assert_eq!(number("-2.5"), "-2.5 Number");
+        assert_eq!(number(".5"), "0.5 Number");
+        assert_eq!(number("1.5e3"), "1500.0 Number");
+        assert_eq!(number("0xFF"), "255 Int");
+        assert_eq!(number("-0b1010"), "-10 Int");
+        assert_eq!(number("2/4"), "1/2 Ratio");
+        assert_eq!(number("4/2"), "2 Int");
+        assert_eq!(number("99999999999999999999"), "99999999999999999999 BigInt");
+        assert_eq!(number("1/0"), "error: ratio literal 1/0 has a zero denominator");
+        for symbol in ["-", "+", "-x", "1+", "_1", "1_", "0x", ".", "1.2.3", "e5"] {
+            assert_eq!(number(symbol), "not a number", "{symbol}");
+        }
+    }
+
+    #[test]
+    fn forms() {
+        assert_eq!(read("(a 'b `(c ~d ~@e)) [1 2] {:k \"v\"} true nil #\\x").unwrap(), "( a ( quote b ) ( quasiquote ( c ( unquote d ) ( unquote-splicing e ) ) ) ) | [ 1 2 ] | { :k \"v\" } | true | nil | #\\x");
+        assert_eq!(read("(1 . 2) (1 2 . 3)").unwrap(), "( 1 . 2 ) | ( 1 2 . 3 )");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| read(source).unwrap_err().message();
+        assert_eq!(error("(a (b)"), "1:1: could not find closing `)`");
+        assert_eq!(error("a)"), "1:2: unexpected `)`");
+        assert_eq!(error("(a]"), "1:3: expected `)` to close the one opened at 1:1, found `]`");
+        assert_eq!(error("{:k}"), "1:4: a map literal needs a value for every key");
+        assert_eq!(error("{(f) 1}"), "1:1: a map literal's keys are booleans, ints, strings or symbols, found ( f )");
+        assert_eq!(error("{:k 1 :k 2}"), "1:1: a map literal has the key :k twice");
+        assert_eq!(error("(. a)"), "1:2: unexpected `.`");
+        assert_eq!(error("(a . b c)"), "1:9: expected one expression after `.`");
+        assert_eq!(error("(f 1.2.3)"), "1:4: 1.2.3 is not a valid number");
+        assert_eq!(error("(a\n  12abc)"), "2:3: 12abc is not a valid number");
+        assert_eq!(error("-1x"), "1:1: -1x is not a valid number");
+        assert_eq!(read("-x +y _1 .x").unwrap(), "-x | +y | _1 | .x");
+        assert!(read("'").unwrap_err().is_incomplete());
+        assert!(read("[1").unwrap_err().is_incomplete());
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let deep = format!("{}{}", "(".repeat(1000), ")".repeat(1000));
+        assert_eq!(parse_program_with_depth(&tokens(&deep).unwrap(), 10).unwrap_err().message(), "1:11: nesting deeper than 10 levels");
+        assert!(parse_program(&tokens(&deep).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn recovers_at_the_next_top_level_form() {
+        let (forms, errors) = parse_program_recovering(&tokens("(a\n  (b]\n(c)\n)\n(d)").unwrap(), DEFAULT_MAX_DEPTH);
+        assert_eq!(forms.iter().map(LispExp::to_string).collect::<Vec<_>>(), ["( c )", "( d )"]);
+        assert_eq!(errors.len(), 2);
+    }
+
+    // the forms `parse_lenient` reads from `source`, and the errors it found
+    fn lenient(source: &str) -> (Vec<String>, Vec<String>) {
+        let (forms, errors) = parse_lenient(source, DEFAULT_MAX_DEPTH);
+        (forms.iter().map(LispExp::to_string).collect(), errors.iter().map(LispError::message).collect())
+    }
+
+    #[test]
+    fn lenient_parsing_reads_everything() {
+        let (forms, errors) = lenient("(define x 1)\n(f #x 2 .)\n(g ] 3)\n");
+        assert!(forms[1].starts_with("( f ( #<error> \"unknown literal `#x`"), "{}", forms[1]);
+        assert!(forms[1].ends_with(" ) 2 . )"), "{}", forms[1]);
+        assert_eq!(forms[2..], ["( g ( #<error> \"expected `)` to close the one opened at 3:1, found `]`\" ) 3 )"]);
+        assert_eq!(errors.len(), 3);
+        let (forms, errors) = lenient("(defn f (x)\n  [1 (+ x 2)\n(print ')");
+        assert_eq!(forms, ["( defn f ( x ) [ 1 ( + x 2 ) ( print ( quote ( #<error> \"expected an expression to quote\" ) ) ) ] )"]);
+        assert_eq!(errors, ["3:8: expected an expression to quote", "2:3: could not find closing `]`", "1:1: could not find closing `)`"]);
+        let (forms, _) = lenient("(a \"open");
+        assert_eq!(forms, ["( a ( #<error> \"unterminated string\" ) )"]);
+    }
+
+    #[test]
+    fn placeholders_are_only_made_by_lenient_parsing() {
+        let (forms, errors) = parse_lenient("(a))", DEFAULT_MAX_DEPTH);
+        assert_eq!(parse_error(&forms[1]), Some("unexpected `)`"));
+        assert_eq!(errors[0].pos(), Some(Pos { line: 1, col: 4 }));
+        assert!(tokens("(#<error> \"x\")").is_err());
+        assert_eq!(parse_error(&parse_program(&tokens("(error \"x\")").unwrap()).unwrap()[0]), None);
+        let deep = format!("(a {}{} b)", "(".repeat(2000), ")".repeat(2000));
+        let (forms, errors) = parse_lenient(&deep, DEFAULT_MAX_DEPTH);
+        assert_eq!(errors.iter().map(LispError::message).collect::<Vec<_>>(), ["1:1003: nesting deeper than 1000 levels"]);
+        // the lists as deep as they may be, with a placeholder for the rest
+        let LispExp::List(items) = &forms[0] else { panic!("{}", errors[0]) };
+        assert_eq!(items[2].to_string(), "b");
+        let mut exp = &items[1];
+        let mut depth = 1;
+        while let LispExp::List(items) = exp {
+            if parse_error(exp).is_some() {
+                break;
+            }
+            depth += 1;
+            exp = items.last().unwrap();
+        }
+        assert_eq!(depth, 1000);
+    }
+}
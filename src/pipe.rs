@@ -0,0 +1,265 @@
+// `pipe`: a source, stages and a sink, run as a stream that's pulled one item
+// at a time from the sink's end. Only the item being worked on is in memory,
+// and a `batch` of them, so a file can be processed a line at a time however
+// large it is, and a source that never ends is fine as long as a stage like
+// `take` stops pulling from it.
+//
+//   sources: (from lst) (lines path) (stdin) (repeatedly f) (iterate f x)
+//   stages:  (map f) (filter f) (take n) (drop n) (take-while f) (batch n)
+//   sinks:   (collect) (count) (for-each f) (reduce f init) (write-lines path)
+//
+// a pipe without a sink collects the items into a list
+use std::path::Path;
+
+use crate::builtins::{expand_path, io_error, record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::LispExp;
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+type Stream<'a> = Box<dyn Iterator<Item = Result<LispExp, LispError>> + 'a>;
+
+// the name of a stage and its arguments, evaluated
+fn stage(env: &Interpreter, form: &LispExp) -> Result<(String, Vec<LispExp>), LispError> {
+    let Some((LispExp::Symbol(name), args)) = form.get_list().ok().and_then(|items| items.split_first()) else {
+        return Err(LispError::Other(format!("expected a pipe stage like (map f), found {form}")));
+    };
+    let args = args.iter().map(|arg| env.value(arg)).collect::<Result<_, _>>()?;
+    Ok((name.to_string(), args))
+}
+
+fn expect(name: &str, args: &[LispExp], count: usize) -> Result<(), LispError> {
+    if args.len() != count {
+        return Err(LispError::ArityMismatch { name: name.to_owned(), min: count, max: Some(count), found: args.len() });
+    }
+    Ok(())
+}
+
+fn count(vl: &LispExp) -> Result<usize, LispError> {
+    usize::try_from(vl.get_int()?).map_err(|_| LispError::Other(format!("expected a count, found {vl}")))
+}
+
+fn source<'a>(env: &'a Interpreter, name: &str, args: Vec<LispExp>) -> Result<Stream<'a>, LispError> {
+    Ok(match name {
+        "from" => {
+            expect(name, &args, 1)?;
+            let items = match &args[0] {
+                LispExp::Vector(items) => items.borrow().clone(),
+                lst => lst.get_list()?.to_vec(),
+            };
+            Box::new(items.into_iter().map(Ok))
+        }
+        "lines" => {
+            expect(name, &args, 1)?;
+            let path = expand_path(args[0].get_string()?)?;
+            let lines = platform::read_lines(Path::new(&path)).map_err(|err| io_error(&path, err))?;
+            Box::new(lines.map(move |line| line.map(LispExp::from).map_err(|err| io_error(&path, err))))
+        }
+        "stdin" => {
+            expect(name, &args, 0)?;
+            Box::new(std::iter::from_fn(|| match platform::read_line() {
+                Ok(Some(line)) => Some(Ok(LispExp::from(line.trim_end_matches(['\n', '\r'])))),
+                Ok(None) => None,
+                Err(err) => Some(Err(io_error("<stdin>", err))),
+            }))
+        }
+        // (repeatedly f) calls f until it returns nil
+        "repeatedly" => {
+            expect(name, &args, 1)?;
+            let func = args[0].clone();
+            Box::new(std::iter::from_fn(move || match env.apply(&func, vec![]) {
+                Ok(LispExp::Nil) => None,
+                res => Some(res),
+            }))
+        }
+        // (iterate f x) is x, (f x), (f (f x)) and so on
+        "iterate" => {
+            expect(name, &args, 2)?;
+            let [func, first] = <[LispExp; 2]>::try_from(args).expect("checked above");
+            let mut next = Some(Ok(first));
+            Box::new(std::iter::from_fn(move || {
+                let current = next.take()?;
+                if let Ok(vl) = &current {
+                    next = Some(env.apply(&func, vec![vl.clone()]));
+                }
+                Some(current)
+            }))
+        }
+        _ => return Err(LispError::Other(format!("`{name}` isn't a pipe source, like (from lst) or (lines path)"))),
+    })
+}
+
+fn through<'a>(env: &'a Interpreter, stream: Stream<'a>, name: &str, args: Vec<LispExp>) -> Result<Stream<'a>, LispError> {
+    let func = args.first().cloned().unwrap_or(LispExp::Nil);
+    // whether `func` is true of an item, keeping the item
+    let test = move |item: Result<LispExp, LispError>| -> Result<(bool, LispExp), LispError> {
+        let item = item?;
+        Ok((env.apply(&func, vec![item.clone()])?.is_truthy(), item))
+    };
+    Ok(match name {
+        "map" => {
+            expect(name, &args, 1)?;
+            let func = args[0].clone();
+            Box::new(stream.map(move |item| env.apply(&func, vec![item?])))
+        }
+        "filter" => {
+            expect(name, &args, 1)?;
+            Box::new(stream.filter_map(move |item| match test(item) {
+                Ok((true, item)) => Some(Ok(item)),
+                Ok((false, _)) => None,
+                Err(err) => Some(Err(err)),
+            }))
+        }
+        "take-while" => {
+            expect(name, &args, 1)?;
+            Box::new(stream.map(test).map_while(|res| match res {
+                Ok((true, item)) => Some(Ok(item)),
+                Ok((false, _)) => None,
+                Err(err) => Some(Err(err)),
+            }))
+        }
+        "take" => {
+            expect(name, &args, 1)?;
+            Box::new(stream.take(count(&args[0])?))
+        }
+        "drop" => {
+            expect(name, &args, 1)?;
+            Box::new(stream.skip(count(&args[0])?))
+        }
+        // (batch n) groups the items into lists of n, the last one shorter
+        "batch" => {
+            expect(name, &args, 1)?;
+            let size = count(&args[0])?.max(1);
+            let mut stream = stream;
+            Box::new(std::iter::from_fn(move || {
+                let items = stream.by_ref().take(size).collect::<Result<Vec<_>, _>>();
+                match items {
+                    Ok(items) if items.is_empty() => None,
+                    items => Some(items.map(LispExp::from)),
+                }
+            }))
+        }
+        _ => return Err(LispError::Other(format!("`{name}` isn't a pipe stage, like (map f) or (filter f)"))),
+    })
+}
+
+fn sink(env: &Interpreter, mut stream: Stream, name: &str, args: Vec<LispExp>) -> Result<LispExp, LispError> {
+    match name {
+        "collect" => {
+            expect(name, &args, 0)?;
+            Ok(LispExp::from(stream.collect::<Result<Vec<_>, _>>()?))
+        }
+        "count" => {
+            expect(name, &args, 0)?;
+            stream.try_fold(0, |count, item| item.map(|_| count + 1)).map(LispExp::Int)
+        }
+        "for-each" => {
+            expect(name, &args, 1)?;
+            stream.try_for_each(|item| env.apply(&args[0], vec![item?]).map(drop))?;
+            Ok(LispExp::Nil)
+        }
+        "reduce" => {
+            expect(name, &args, 2)?;
+            stream.try_fold(args[1].clone(), |acc, item| env.apply(&args[0], vec![acc, item?]))
+        }
+        // strings are written as they are, and other values as `print` shows them
+        "write-lines" => {
+            expect(name, &args, 1)?;
+            let path = expand_path(args[0].get_string()?)?;
+            let mut file = platform::create_file(Path::new(&path)).map_err(|err| io_error(&path, err))?;
+            let mut lines = 0;
+            for item in stream {
+                let line = match item? {
+                    LispExp::Str(text) => text,
+                    item => item.to_string(),
+                };
+                writeln!(file, "{line}").map_err(|err| io_error(&path, err))?;
+                lines += 1;
+            }
+            file.flush().map_err(|err| io_error(&path, err))?;
+            Ok(LispExp::Int(lines))
+        }
+        _ => Err(LispError::Other(format!("`{name}` isn't a pipe sink, like (collect) or (for-each f)"))),
+    }
+}
+
+// (pipe source stages... [sink]) pulls the source's items through the stages
+// into the sink
+fn lisp_pipe(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (first, rest) = unpack(cont)?;
+    let (name, args) = stage(env, first)?;
+    let mut stream = source(env, &name, args)?;
+    for (i, form) in rest.iter().enumerate() {
+        let (name, args) = stage(env, form)?;
+        let last = i + 1 == rest.len();
+        if last && matches!(&*name, "collect" | "count" | "for-each" | "reduce" | "write-lines") {
+            return sink(env, stream, &name, args);
+        }
+        stream = through(env, stream, &name, args)?;
+    }
+    sink(env, stream, "collect", vec![])
+}
+
+pub(crate) fn pipe_forms() -> SymbolMap<Builtin> {
+    let mut specials: SymbolMap<Builtin> = SymbolMap::default();
+    record!(specials, "pipe", lisp_pipe, 1.., "(pipe source stages... [sink])", "pulls the items of a source, like (lines path), through stages like (map f) into a sink, like (for-each f), one at a time", unevaluated);
+    specials
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn stages() {
+        assert_eq!(run("(pipe (from '(1 2 3 4)) (map (lambda (x) (* x x))) (filter (lambda (x) (> x 1))))"), "( 4 9 16 )");
+        assert_eq!(run("(pipe (iterate (lambda (x) (+ x 1)) 0) (drop 2) (take 5) (batch 2))"), "( ( 2 3 ) ( 4 5 ) ( 6 ) )");
+        assert_eq!(run("(pipe (from [1 2 3 1]) (take-while (lambda (x) (< x 3))) (reduce + 10))"), "13");
+        assert_eq!(run("(define n 0) (pipe (repeatedly (lambda () (set! n (+ n 1)) (if (< n 4) n))) (count))"), "3");
+        assert_eq!(run("(pipe (from '()) (collect))"), "(  )");
+    }
+
+    #[test]
+    fn only_what_is_needed_is_pulled() {
+        // the source never ends, and the map fails after the third item
+        let source = "(define seen 0) \
+                      (pipe (iterate (lambda (x) (+ x 1)) 1) (map (lambda (x) (set! seen x) (if (> x 3) (car x) x))) (take 3) (collect)) \
+                      seen";
+        assert_eq!(run(source), "3");
+        assert_eq!(run("(pipe (from '(1 2)) (map car) (count))"), "error: 1 is not a List, it's a Int");
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("sxprs-pipe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.txt"), dir.join("out.txt"));
+        std::fs::write(&input, "a\nbb\n\nccc\n").unwrap();
+        let source = format!(
+            "(pipe (lines {:?}) (filter (lambda (line) (> (str-length line) 0))) (map str-length) (write-lines {:?}))",
+            input.display().to_string(),
+            output.display().to_string()
+        );
+        let res = run(&source);
+        let written = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res, "3");
+        assert_eq!(written, "1\n2\n3\n");
+    }
+
+    #[test]
+    fn mistakes() {
+        assert_eq!(run("(pipe (nowhere))"), "error: `nowhere` isn't a pipe source, like (from lst) or (lines path)");
+        assert_eq!(run("(pipe (from '(1)) (squash))"), "error: `squash` isn't a pipe stage, like (map f) or (filter f)");
+        assert_eq!(run("(pipe (from '(1)) (take))"), "error: `take` expects 1 arguments, got 0");
+        assert_eq!(run("(pipe 1)"), "error: expected a pipe stage like (map f), found 1");
+    }
+}
@@ -0,0 +1,338 @@
+// everything the interpreter asks of the host; the browser build has no
+// filesystem, stdin, process environment or monotonic clock, so there output
+// is buffered for `take_output`, the environment lives in memory and file
+// access fails as unsupported
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[cfg(target_arch = "wasm32")]
+use std::cell::RefCell;
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashMap;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+    static ENV: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+// where `print` and `eprint` write instead of stdout and stderr while
+// `sxprs kernel` runs a cell, for the cell's reply; the worker threads the
+// cell starts write there too
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type Capture = std::sync::Arc<std::sync::Mutex<(String, String)>>;
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    static CAPTURE: std::cell::RefCell<Option<Capture>> = const { std::cell::RefCell::new(None) };
+}
+
+// gives `f` what was captured so far, false when nothing is being captured
+#[cfg(not(target_arch = "wasm32"))]
+fn captured(f: impl FnOnce(&mut (String, String))) -> bool {
+    CAPTURE.with(|capture| {
+        let capture = capture.borrow();
+        capture.as_ref().map(|capture| f(&mut capture.lock().unwrap_or_else(std::sync::PoisonError::into_inner))).is_some()
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn capture() -> Option<Capture> {
+    CAPTURE.with(|capture| capture.borrow().clone())
+}
+
+// captures what this thread prints from now on, or stops with None
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn set_capture(capture: Option<Capture>) {
+    CAPTURE.with(|current| *current.borrow_mut() = capture);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "not available in the browser")
+}
+
+// a line of the program's output, like `print`'s
+pub(crate) fn print(line: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if !captured(|(out, _)| {
+        out.push_str(line);
+        out.push('\n');
+    }) {
+        println!("{line}");
+    }
+    #[cfg(target_arch = "wasm32")]
+    OUTPUT.with(|out| {
+        let mut out = out.borrow_mut();
+        out.push_str(line);
+        out.push('\n');
+    });
+}
+
+// output without a newline after it, like `printf`'s
+pub(crate) fn write(text: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if !captured(|(out, _)| out.push_str(text)) {
+        use std::io::Write;
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(text.as_bytes()).and_then(|()| stdout.flush());
+    }
+    #[cfg(target_arch = "wasm32")]
+    OUTPUT.with(|out| out.borrow_mut().push_str(text));
+}
+
+// a line of diagnostics, like `time`'s
+pub(crate) fn eprint(line: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if !captured(|(_, err)| {
+        err.push_str(line);
+        err.push('\n');
+    }) {
+        eprintln!("{line}");
+    }
+    #[cfg(target_arch = "wasm32")]
+    print(line);
+}
+
+// what was printed since the last call
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn take_output() -> String {
+    OUTPUT.with(|out| out.take())
+}
+
+pub(crate) fn read_file(path: &Path) -> io::Result<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::fs::read_to_string(path);
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        Err(unsupported())
+    }
+}
+
+// writes or appends `contents` to the file at `path`, creating it if needed
+pub(crate) fn write_file(path: &Path, contents: &str, append: bool) -> io::Result<()> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::io::Write;
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (path, contents, append);
+        Err(unsupported())
+    }
+}
+
+// the lines of the file at `path` without their endings, read as they're
+// taken
+pub(crate) fn read_lines(path: &Path) -> io::Result<Box<dyn Iterator<Item = io::Result<String>>>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::io::BufRead;
+        let file = std::fs::File::open(path)?;
+        Ok(Box::new(io::BufReader::new(file).lines()))
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        Err(unsupported())
+    }
+}
+
+// a new file at `path`, replacing what was there, buffered for writing a
+// piece at a time
+pub(crate) fn create_file(path: &Path) -> io::Result<Box<dyn io::Write>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Ok(Box::new(io::BufWriter::new(std::fs::File::create(path)?)));
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        Err(unsupported())
+    }
+}
+
+// replaces the file at `path` with `contents` all at once, through a file
+// beside it, so a crash while writing leaves the old one; the directory is
+// made if needed
+pub(crate) fn replace_file(path: &Path, contents: &str) -> io::Result<()> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        std::fs::write(&partial, contents)?;
+        std::fs::rename(&partial, path)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (path, contents);
+        Err(unsupported())
+    }
+}
+
+// a line from stdin with its line ending, None at the end of input; a cell
+// the kernel runs has no input, stdin being the kernel's requests
+pub(crate) fn read_line() -> io::Result<Option<String>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if captured(|_| {}) {
+            return Ok(None);
+        }
+        let mut line = String::new();
+        let read = io::stdin().read_line(&mut line)?;
+        Ok((read > 0).then_some(line))
+    }
+    #[cfg(target_arch = "wasm32")]
+    Ok(None)
+}
+
+pub(crate) fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return path.canonicalize();
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        Err(unsupported())
+    }
+}
+
+pub(crate) fn getenv(name: &str) -> Option<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::env::var(name).ok();
+    #[cfg(target_arch = "wasm32")]
+    ENV.with(|env| env.borrow().get(name).cloned())
+}
+
+pub(crate) fn setenv(name: &str, value: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    std::env::set_var(name, value);
+    #[cfg(target_arch = "wasm32")]
+    ENV.with(|env| env.borrow_mut().insert(name.to_owned(), value.to_owned()));
+}
+
+// milliseconds since the unix epoch
+pub(crate) fn epoch_millis() -> i64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64);
+    #[cfg(target_arch = "wasm32")]
+    return now() as i64;
+}
+
+// blocks the thread for `duration`; the browser's can't be blocked
+pub(crate) fn sleep(duration: Duration) -> io::Result<()> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::thread::sleep(duration);
+        Ok(())
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = duration;
+        Err(unsupported())
+    }
+}
+
+// every file under the directory at `path`, or `path` itself when it's a
+// file, with when it was last changed and its size; nothing when it doesn't
+// exist
+pub(crate) fn scan(path: &Path) -> io::Result<Vec<(PathBuf, SystemTime, u64)>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        fn walk(path: &Path, files: &mut Vec<(PathBuf, SystemTime, u64)>) -> io::Result<()> {
+            let meta = match std::fs::metadata(path) {
+                Ok(meta) => meta,
+                // removed while it was being scanned
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            if !meta.is_dir() {
+                files.push((path.to_owned(), meta.modified()?, meta.len()));
+                return Ok(());
+            }
+            for entry in std::fs::read_dir(path)? {
+                walk(&entry?.path(), files)?;
+            }
+            Ok(())
+        }
+        let mut files = vec![];
+        walk(path, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = path;
+        Err(unsupported())
+    }
+}
+
+// what a finished process left: its exit code, None if a signal killed it,
+// and what it wrote to stdout and stderr
+pub(crate) struct Output {
+    pub(crate) code: Option<i32>,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+// runs `program` with `args` and waits for it to finish; the browser can't
+// start processes
+pub(crate) fn exec(program: &str, args: &[String]) -> io::Result<Output> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let output = std::process::Command::new(program).args(args).output()?;
+        Ok(Output {
+            code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (program, args);
+        Err(unsupported())
+    }
+}
+
+// measures wall-clock time, with the JS clock in the browser where std's panics
+pub(crate) struct Timer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date)]
+    fn now() -> f64;
+}
+
+impl Timer {
+    pub(crate) fn start() -> Timer {
+        #[cfg(not(target_arch = "wasm32"))]
+        return Timer { start: std::time::Instant::now() };
+        #[cfg(target_arch = "wasm32")]
+        Timer { start: now() }
+    }
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        Duration::from_secs_f64((now() - self.start).max(0.0) / 1000.0)
+    }
+}
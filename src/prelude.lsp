@@ -0,0 +1,78 @@
+; library functions written in lisp itself, evaluated into every new
+; interpreter before the program runs (Interpreter::bare skips them)
+
+(defn second (lst)
+  "the second item of a list"
+  (car (cdr lst)))
+
+(defn last (lst)
+  "the last item of a non-empty list"
+  (nth lst (- (length lst) 1)))
+
+; lists are copied on every cons, so the halves are built separately and
+; appended
+(defn range (from to)
+  "the integers from `from` up to, but not including, `to`"
+  (if (<= (- to from) 1)
+    (if (< from to) (list from) '())
+    (let ((half 1))
+      (while (< (* half 2) (- to from))
+        (set! half (* half 2)))
+      (append (range from (+ from half)) (range (+ from half) to)))))
+
+(defn assoc (key alist)
+  "the first (key value...) pair of `alist` whose key is `key`, or false"
+  (if alist
+    (if (equal? (car (car alist)) key)
+      (car alist)
+      (assoc key (cdr alist)))
+    false))
+
+(defn retry (opts thunk)
+  "calls (thunk) until it doesn't fail, at most :times times (3), waiting :delay ms (100) after the first failure and, with :backoff, twice as long after each one (:exp, the default), :delay longer (:linear) or the same (:fixed); the last error is raised again"
+  (let ((times (dict-get opts :times))
+        (delay (dict-get opts :delay))
+        (backoff (dict-get opts :backoff)))
+    (if (equal? times nil) (set! times 3))
+    (if (equal? delay nil) (set! delay 100))
+    (if (equal? backoff nil) (set! backoff :exp))
+    (if (not (or (= backoff :exp) (= backoff :linear) (= backoff :fixed)))
+      (error (format "retry: :backoff should be :exp, :linear or :fixed, not {:?}" backoff)))
+    (let ((tries 1) (wait delay) (done false) (result nil))
+      (while (not done)
+        (try
+          (set! result (thunk))
+          (set! done true)
+          (catch e
+            (if (>= tries times) (error e))
+            (sleep wait)
+            (set! tries (+ tries 1))
+            (cond
+              ((= backoff :exp) (set! wait (* wait 2)))
+              ((= backoff :linear) (set! wait (+ wait delay)))))))
+      result)))
+
+; the times of the calls are kept in the order they were made, so the
+; oldest one in the window is the first
+(defn rate-limit (n per-ms f)
+  "a function calling f with its arguments, which waits first when f was already called n times in the last per-ms milliseconds"
+  (let ((calls '()))
+    (lambda (&rest args)
+      (let ((now (clock)))
+        (set! calls (filter (lambda (at) (> at (- now per-ms))) calls))
+        (if (>= (length calls) n)
+          (begin
+            (sleep (- (+ (car calls) per-ms) now))
+            (set! now (clock))
+            (set! calls (cdr calls))))
+        (set! calls (append calls (list now)))
+        (apply f args)))))
+
+(defn debounce (ms f)
+  "a function calling f with its arguments, unless it was last called less than ms milliseconds before, when it does nothing and is nil"
+  (let ((previous nil))
+    (lambda (&rest args)
+      (let ((now (clock)))
+        (let ((quiet (or (equal? previous nil) (>= (- now previous) ms))))
+          (set! previous now)
+          (if quiet (apply f args)))))))
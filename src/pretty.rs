@@ -0,0 +1,173 @@
+// renders values for people to read: lists, vectors and maps that don't fit
+// on a line get an item per line, indented by how deep they are, and atoms
+// can be colored by type with ANSI escapes
+use crate::exp::LispExp;
+use crate::symbol::SymbolId;
+
+// values longer than this are broken up
+const WIDTH: usize = 80;
+
+// the ANSI color of an atom, None for ones left plain
+fn color_of(value: &LispExp) -> Option<u8> {
+    match value {
+        LispExp::Int(_) | LispExp::BigInt(_) | LispExp::Ratio(_) | LispExp::Number(_) => Some(33),
+        LispExp::Str(_) | LispExp::Char(_) => Some(32),
+        LispExp::Symbol(_) => Some(36),
+        LispExp::Bool(_) | LispExp::Nil => Some(35),
+        LispExp::Lambda(_) | LispExp::Builtin(_) | LispExp::Macro(_) => Some(34),
+        LispExp::List(_) | LispExp::Pair(_) | LispExp::Map(_) | LispExp::Vector(_) | LispExp::Struct(_) => None,
+    }
+}
+
+fn atom(value: &LispExp, color: bool, out: &mut String) {
+    match color_of(value).filter(|_| color) {
+        Some(code) => out.push_str(&format!("\x1b[{code}m{value}\x1b[0m")),
+        None => out.push_str(&value.to_string()),
+    }
+}
+
+// the delimiters and items of a container, with a map's keys and values
+// paired up; None for atoms
+fn parts(value: &LispExp) -> Option<(&'static str, &'static str, Vec<Vec<LispExp>>)> {
+    match value {
+        LispExp::List(items) => Some(("(", ")", items.iter().map(|item| vec![item.clone()]).collect())),
+        // the dot and what the pairs end in go on one line
+        LispExp::Pair(pair) => {
+            let (items, tail) = LispExp::pair_items(pair);
+            let mut items: Vec<Vec<LispExp>> = items.into_iter().map(|item| vec![item]).collect();
+            items.push(vec![LispExp::Symbol(SymbolId::new(".")), tail]);
+            Some(("(", ")", items))
+        }
+        LispExp::Vector(items) => Some(("[", "]", items.borrow().iter().map(|item| vec![item.clone()]).collect())),
+        LispExp::Map(map) => Some((
+            "{",
+            "}",
+            map.iter().map(|(k, v)| vec![LispExp::from(k.clone()), v.clone()]).collect(),
+        )),
+        // the name, then each field's keyword with its value
+        LispExp::Struct(record) => {
+            let mut items = vec![vec![LispExp::Symbol(record.name)]];
+            for (field, vl) in record.fields.iter().zip(&record.values) {
+                items.push(vec![LispExp::Symbol(SymbolId::new(&format!(":{field}"))), vl.clone()]);
+            }
+            Some(("(", ")", items))
+        }
+        _ => None,
+    }
+}
+
+// the value on a single line, like Display but colored
+fn flat(value: &LispExp, color: bool, out: &mut String) {
+    let Some((open, close, items)) = parts(value) else {
+        return atom(value, color, out);
+    };
+    out.push_str(open);
+    out.push(' ');
+    for (i, item) in items.iter().flatten().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        flat(item, color, out);
+    }
+    out.push(' ');
+    out.push_str(close);
+}
+
+// writes `value` starting at column `indent`, breaking containers that don't
+// fit; list and vector items fill each line, map entries get one each
+fn write(value: &LispExp, indent: usize, color: bool, out: &mut String) {
+    let width = value.to_string().chars().count();
+    let parts = parts(value).filter(|(_, _, items)| items.len() > 1 && indent + width > WIDTH);
+    let Some((open, close, items)) = parts else {
+        return flat(value, color, out);
+    };
+    let start = indent + open.len() + 1;
+    out.push_str(open);
+    out.push(' ');
+    let mut column = start;
+    for (i, item) in items.iter().enumerate() {
+        let width = item.iter().map(|part| part.to_string().chars().count() + 1).sum::<usize>() - 1;
+        if i > 0 {
+            if open != "{" && column + 1 + width <= WIDTH {
+                out.push(' ');
+                column += 1;
+            } else {
+                out.push('\n');
+                out.push_str(&" ".repeat(start));
+                column = start;
+            }
+        }
+        // a map entry's value goes after its key
+        for (j, part) in item.iter().enumerate() {
+            if j > 0 {
+                out.push(' ');
+                column += 1;
+            }
+            let before = out.len();
+            write(part, column, color, out);
+            // columns are counted without the color escapes
+            let written = strip_colors(&out[before..]);
+            column = match written.rfind('\n') {
+                Some(newline) => written[newline + 1..].chars().count(),
+                None => column + written.chars().count(),
+            };
+        }
+    }
+    out.push(' ');
+    out.push_str(close);
+}
+
+fn strip_colors(text: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = text.chars();
+    while let Some(chr) = chars.next() {
+        if chr == '\x1b' {
+            chars.by_ref().find(|&chr| chr == 'm');
+        } else {
+            ret.push(chr);
+        }
+    }
+    ret
+}
+
+// `value` as `print` shows it: broken over lines if `wrap` and it's too wide,
+// with colors if `color`
+pub fn render(value: &LispExp, wrap: bool, color: bool) -> String {
+    let mut out = String::new();
+    if wrap {
+        write(value, 0, color, &mut out);
+    } else {
+        flat(value, color, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Interpreter;
+
+    fn value(source: &str) -> LispExp {
+        Interpreter::new().eval_str(source).unwrap()
+    }
+
+    #[test]
+    fn short_values_stay_on_a_line() {
+        let vl = value("(list 1 \"a\" [2] {:k 'v})");
+        assert_eq!(render(&vl, true, false), vl.to_string());
+        assert_eq!(render(&vl, false, true), "( \x1b[33m1\x1b[0m \x1b[32m\"a\"\x1b[0m [ \x1b[33m2\x1b[0m ] { \x1b[36m:k\x1b[0m \x1b[36mv\x1b[0m } )");
+    }
+
+    #[test]
+    fn wide_values_are_broken_up() {
+        let vl = value("(list (range 0 30) {:name \"a rather long name to push the map past the width, and further\" :id 1})");
+        let rendered = render(&vl, true, false);
+        // list items fill each line, and map entries get one each
+        assert_eq!(
+            rendered,
+            "( ( 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28\n    29 )\n  { :id 1\n    :name \"a rather long name to push the map past the width, and further\" } )"
+        );
+        // colors don't count towards the width
+        assert_eq!(strip_colors(&render(&vl, true, true)), rendered);
+    }
+}
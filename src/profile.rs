@@ -0,0 +1,115 @@
+// `--profile`: how often each function was called and how long the calls
+// took, counted as calls are entered and left
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::platform::Timer;
+use crate::symbol::SymbolId;
+
+// what was measured for one function; `total` includes the functions it
+// called and `self_time` doesn't, and a recursive function's total only
+// counts its outermost call
+#[derive(Debug, Clone)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub calls: u64,
+    pub total: Duration,
+    pub self_time: Duration,
+}
+
+// a call that hasn't returned yet
+struct Open {
+    name: Option<SymbolId>,
+    started: Timer,
+    // how long the calls it made took
+    children: Duration,
+}
+
+#[derive(Default)]
+struct Counts {
+    calls: u64,
+    total: Duration,
+    self_time: Duration,
+    // how many of its calls are open, so recursion isn't counted twice
+    open: usize,
+}
+
+// None is an anonymous lambda
+#[derive(Default)]
+pub(crate) struct Profiler {
+    open: Vec<Open>,
+    counts: HashMap<Option<SymbolId>, Counts>,
+}
+
+impl Profiler {
+    pub(crate) fn enter(&mut self, name: Option<SymbolId>) {
+        let counts = self.counts.entry(name).or_default();
+        counts.calls += 1;
+        counts.open += 1;
+        self.open.push(Open { name, started: Timer::start(), children: Duration::ZERO });
+    }
+    pub(crate) fn leave(&mut self) {
+        let Some(call) = self.open.pop() else { return };
+        let elapsed = call.started.elapsed();
+        if let Some(caller) = self.open.last_mut() {
+            caller.children += elapsed;
+        }
+        let counts = self.counts.entry(call.name).or_default();
+        counts.open -= 1;
+        counts.self_time += elapsed.saturating_sub(call.children);
+        if counts.open == 0 {
+            counts.total += elapsed;
+        }
+    }
+    // leaves the calls an error unwound past, down to `depth` open ones
+    pub(crate) fn unwind(&mut self, depth: usize) {
+        while self.open.len() > depth {
+            self.leave();
+        }
+    }
+    // the slowest functions first
+    pub(crate) fn entries(&self) -> Vec<ProfileEntry> {
+        let mut entries: Vec<ProfileEntry> = self
+            .counts
+            .iter()
+            .map(|(name, counts)| ProfileEntry {
+                name: name.map_or("lambda".to_owned(), |name| name.to_string()),
+                calls: counts.calls,
+                total: counts.total,
+                self_time: counts.self_time,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.total.cmp(&a.total).then(b.self_time.cmp(&a.self_time)).then(a.name.cmp(&b.name)));
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    #[test]
+    fn counts_calls() {
+        let mut lisp = Interpreter::new();
+        lisp.set_profiling(true);
+        lisp.eval_str("(defn fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))) (fib 10) ((lambda () 1))").unwrap();
+        let entries = lisp.profile();
+        let calls = |name: &str| entries.iter().find(|entry| entry.name == name).map(|entry| entry.calls);
+        assert_eq!(calls("fib"), Some(177));
+        assert_eq!(calls("+"), Some(88));
+        assert_eq!(calls("lambda"), Some(1));
+        let fib = entries.iter().find(|entry| entry.name == "fib").unwrap();
+        assert!(fib.self_time <= fib.total);
+    }
+
+    #[test]
+    fn errors_leave_their_calls() {
+        let mut lisp = Interpreter::new();
+        lisp.set_profiling(true);
+        lisp.eval_str("(defn bad () (car 1)) (try (bad) (catch e e))").unwrap();
+        lisp.eval_str("(bad)").unwrap_err();
+        let entries = lisp.profile();
+        assert_eq!(entries.iter().find(|entry| entry.name == "bad").map(|entry| entry.calls), Some(2));
+        assert_eq!(entries.iter().find(|entry| entry.name == "car").map(|entry| entry.calls), Some(2));
+    }
+}
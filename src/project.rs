@@ -0,0 +1,156 @@
+// project settings: an `sxprs.toml` in the program's directory or the
+// nearest one above it sets defaults for every program under it, which flags
+// given on the command line add to
+//
+//   # sxprs.toml
+//   vm = true                  # like --vm
+//   opt = true                 # like --opt
+//   prelude = false            # like --no-prelude
+//   math = false               # leave out sqrt, sin, random and the rest
+//   include = ["lib", "vendor"] # where `import` looks after the importing file's directory
+//
+// only these keys are read, and of TOML only the `key = value` lines with
+// booleans, strings and lists of strings they need
+use std::path::{Path, PathBuf};
+
+use crate::error::LispError;
+use crate::platform;
+
+pub const FILE_NAME: &str = "sxprs.toml";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    // the sxprs.toml these were read from
+    pub path: PathBuf,
+    pub vm: bool,
+    pub opt: bool,
+    pub prelude: bool,
+    pub math: bool,
+    // relative to the directory of the sxprs.toml
+    pub include: Vec<PathBuf>,
+}
+
+enum Value {
+    Bool(bool),
+    Str(String),
+    List(Vec<String>),
+}
+
+fn string(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    // escapes aren't needed for paths, so they aren't read
+    (!inner.contains(['"', '\\'])).then(|| inner.to_owned())
+}
+
+fn value(text: &str) -> Option<Value> {
+    match text {
+        "true" => return Some(Value::Bool(true)),
+        "false" => return Some(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(items) = text.strip_prefix('[').and_then(|text| text.strip_suffix(']')) {
+        let items = items.trim().trim_end_matches(',');
+        if items.trim().is_empty() {
+            return Some(Value::List(vec![]));
+        }
+        return items.split(',').map(|item| string(item.trim())).collect::<Option<_>>().map(Value::List);
+    }
+    string(text).map(Value::Str)
+}
+
+// a `#` comment, unless it's inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, chr) in line.char_indices() {
+        match chr {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+impl Project {
+    // the settings in `text`, read from the sxprs.toml at `path`
+    pub fn parse(path: &Path, text: &str) -> Result<Project, LispError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut project = Project { path: path.to_owned(), vm: false, opt: false, prelude: true, math: true, include: vec![] };
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fail = |msg: &str| LispError::Other(format!("{}:{}: {msg}", path.display(), i + 1));
+            let Some((key, text)) = line.split_once('=') else {
+                return Err(fail("expected `key = value`"));
+            };
+            let (key, text) = (key.trim(), text.trim());
+            let value = value(text).ok_or_else(|| fail(&format!("`{text}` isn't a boolean, string or list of strings")))?;
+            match (key, value) {
+                ("vm", Value::Bool(b)) => project.vm = b,
+                ("opt", Value::Bool(b)) => project.opt = b,
+                ("prelude", Value::Bool(b)) => project.prelude = b,
+                ("math", Value::Bool(b)) => project.math = b,
+                ("include", Value::List(dirs)) => project.include = dirs.iter().map(|include| dir.join(include)).collect(),
+                ("include", Value::Str(include)) => project.include = vec![dir.join(include)],
+                ("vm" | "opt" | "prelude" | "math", _) => return Err(fail(&format!("`{key}` should be true or false"))),
+                ("include", _) => return Err(fail("`include` should be a list of directories")),
+                _ => return Err(fail(&format!("unknown setting `{key}`"))),
+            }
+        }
+        Ok(project)
+    }
+    // the settings in the sxprs.toml in `dir` or the nearest directory above
+    // it, if there is one
+    pub fn find(dir: &Path) -> Result<Option<Project>, LispError> {
+        let dir = platform::canonicalize(dir).unwrap_or_else(|_| dir.to_owned());
+        for dir in dir.ancestors() {
+            let path = dir.join(FILE_NAME);
+            if path.is_file() {
+                let text = platform::read_file(&path).map_err(|err| LispError::Other(format!("{}: {err}", path.display())))?;
+                return Project::parse(&path, &text).map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Project, String> {
+        Project::parse(Path::new("/p/sxprs.toml"), text).map_err(|err| err.message())
+    }
+
+    #[test]
+    fn reads_settings() {
+        let project = parse("# settings\nvm = true\nprelude=false # no prelude\n\ninclude = [\"lib\", \"vendor/#x\",]\n").unwrap();
+        assert!(project.vm && !project.opt && !project.prelude && project.math);
+        assert_eq!(project.include, [PathBuf::from("/p/lib"), PathBuf::from("/p/vendor/#x")]);
+        assert_eq!(parse("include = \"lib\"").unwrap().include, [PathBuf::from("/p/lib")]);
+        assert_eq!(parse("").unwrap(), Project { path: PathBuf::from("/p/sxprs.toml"), vm: false, opt: false, prelude: true, math: true, include: vec![] });
+    }
+
+    #[test]
+    fn rejects_what_it_doesnt_know() {
+        assert_eq!(parse("vm = 1").unwrap_err(), "/p/sxprs.toml:1: `1` isn't a boolean, string or list of strings");
+        assert_eq!(parse("\nvm = \"yes\"").unwrap_err(), "/p/sxprs.toml:2: `vm` should be true or false");
+        assert_eq!(parse("dialect = \"scheme\"").unwrap_err(), "/p/sxprs.toml:1: unknown setting `dialect`");
+        assert_eq!(parse("[section]").unwrap_err(), "/p/sxprs.toml:1: expected `key = value`");
+    }
+
+    #[test]
+    fn found_upward() {
+        let root = std::env::temp_dir().join(format!("sxprs-project-{}", std::process::id()));
+        let inner = root.join("a").join("b");
+        std::fs::create_dir_all(&inner).unwrap();
+        assert_eq!(Project::find(&inner).unwrap().filter(|project| project.path.starts_with(&root)), None);
+        std::fs::write(root.join(FILE_NAME), "opt = true\n").unwrap();
+        let project = Project::find(&inner).unwrap().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert!(project.opt);
+        assert!(project.path.ends_with("sxprs.toml"));
+    }
+}
@@ -0,0 +1,221 @@
+// Python bindings, with the `python` feature: a `sxprs` module, built with
+// maturin, whose `Interp` evaluates lisp and calls Python functions from it.
+// Values are converted both ways: nil and None, booleans, ints of any size,
+// floats, fractions and ratios, strings, lists and tuples, and maps and dicts,
+// whose string keys stay strings, like with `json->lisp`. Characters and
+// symbols become strings, and what has no Python equivalent, like a
+// function, the string it prints as. Errors are raised as `sxprs.LispError`,
+// with the error's stable `id` and `code`
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+
+use crate::error::LispError;
+use crate::eval::Interpreter;
+use crate::exp::{LispExp, MapKey};
+use crate::num::{from_big, from_ratio};
+
+create_exception!(sxprs, PyLispError, PyException);
+
+fn raise(py: Python<'_>, err: &LispError) -> PyErr {
+    let raised = PyLispError::new_err(err.message());
+    let value = raised.value(py);
+    let _ = value.setattr("id", err.id());
+    let _ = value.setattr("code", err.code());
+    raised
+}
+
+fn to_python<'py>(py: Python<'py>, value: &LispExp) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        LispExp::Nil => py.None().into_bound(py),
+        LispExp::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        LispExp::Int(n) => n.into_pyobject(py)?.into_any(),
+        LispExp::BigInt(n) => py.import("builtins")?.getattr("int")?.call1((n.to_string(),))?,
+        LispExp::Ratio(n) => py.import("fractions")?.getattr("Fraction")?.call1((n.to_string(),))?,
+        LispExp::Number(n) => PyFloat::new(py, *n).into_any(),
+        LispExp::Str(text) => PyString::new(py, text).into_any(),
+        LispExp::Char(c) => PyString::new(py, &c.to_string()).into_any(),
+        LispExp::Symbol(name) => PyString::new(py, &name.name()).into_any(),
+        LispExp::List(items) => PyList::new(py, items.iter().map(|item| to_python(py, item)).collect::<PyResult<Vec<_>>>()?)?.into_any(),
+        LispExp::Vector(items) => {
+            PyList::new(py, items.borrow().iter().map(|item| to_python(py, item)).collect::<PyResult<Vec<_>>>()?)?.into_any()
+        }
+        LispExp::Pair(pair) => PyTuple::new(py, [to_python(py, &pair.0)?, to_python(py, &pair.1)?])?.into_any(),
+        LispExp::Map(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map {
+                let key = match key {
+                    MapKey::Str(text) | MapKey::Symbol(text) => PyString::new(py, text).into_any(),
+                    MapKey::Int(n) => n.into_pyobject(py)?.into_any(),
+                    MapKey::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+                };
+                dict.set_item(key, to_python(py, value)?)?;
+            }
+            dict.into_any()
+        }
+        LispExp::Lambda(_) | LispExp::Builtin(_) | LispExp::Macro(_) | LispExp::Struct(_) => PyString::new(py, &value.to_string()).into_any(),
+    })
+}
+
+fn from_python(value: &Bound<'_, PyAny>) -> PyResult<LispExp> {
+    let py = value.py();
+    if value.is_none() {
+        return Ok(LispExp::Nil);
+    }
+    // a bool is an int too in Python
+    if let Ok(b) = value.downcast::<PyBool>() {
+        return Ok(LispExp::Bool(b.is_true()));
+    }
+    if value.is_instance_of::<PyInt>() {
+        return Ok(match value.extract::<i64>() {
+            Ok(n) => LispExp::Int(n),
+            Err(_) => from_big(value.str()?.to_str()?.parse::<BigInt>().expect("Python prints ints in decimal")),
+        });
+    }
+    if let Ok(n) = value.downcast::<PyFloat>() {
+        return Ok(LispExp::Number(n.value()));
+    }
+    if let Ok(text) = value.downcast::<PyString>() {
+        return Ok(LispExp::Str(text.to_str()?.to_owned()));
+    }
+    if value.is_instance(&py.import("fractions")?.getattr("Fraction")?)? {
+        let part = |name| -> PyResult<BigInt> { Ok(value.getattr(name)?.str()?.to_str()?.parse().expect("Python prints ints in decimal")) };
+        return Ok(from_ratio(BigRational::new(part("numerator")?, part("denominator")?)));
+    }
+    if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        return Ok(LispExp::List(value.try_iter()?.map(|item| from_python(&item?)).collect::<PyResult<Rc<[_]>>>()?));
+    }
+    if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = std::collections::BTreeMap::new();
+        for (key, value) in dict {
+            let key = MapKey::try_from(&from_python(&key)?).map_err(|err| PyTypeError::new_err(format!("a map can't have the key {key}: {}", err.message())))?;
+            map.insert(key, from_python(&value)?);
+        }
+        return Ok(LispExp::Map(map));
+    }
+    Err(PyTypeError::new_err(format!("a {} can't be a lisp value", value.get_type().name()?)))
+}
+
+// an interpreter, with the prelude; it's used from the thread that made it
+#[pyclass(unsendable, name = "Interp")]
+struct Interp {
+    lisp: Interpreter,
+}
+
+#[pymethods]
+impl Interp {
+    #[new]
+    fn new() -> Interp {
+        Interp { lisp: Interpreter::new() }
+    }
+    // runs the program `source` and gives the value of its last form
+    fn eval<'py>(&self, py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+        let value = self.lisp.eval_str(source).map_err(|err| raise(py, &err))?;
+        to_python(py, &value)
+    }
+    // calls the lisp function `name` with Python values
+    #[pyo3(signature = (name, *args))]
+    fn call<'py>(&self, py: Python<'py>, name: &str, args: &Bound<'py, PyTuple>) -> PyResult<Bound<'py, PyAny>> {
+        let args = args.iter().map(|arg| from_python(&arg)).collect::<PyResult<Vec<_>>>()?;
+        let value = self
+            .lisp
+            .resolve(crate::symbol::SymbolId::new(name))
+            .and_then(|func| self.lisp.apply(&func, args))
+            .map_err(|err| raise(py, &err))?;
+        to_python(py, &value)
+    }
+    // makes the Python callable `f` a lisp function named `name`; an
+    // exception it raises fails the call with its message
+    fn register(&mut self, name: &str, f: PyObject) {
+        self.lisp.register_fn(name, move |_, args| {
+            Python::with_gil(|py| {
+                let args = args.iter().map(|arg| to_python(py, arg)).collect::<PyResult<Vec<_>>>()?;
+                from_python(&f.call1(py, PyTuple::new(py, args)?)?.into_bound(py))
+            })
+            .map_err(|err: PyErr| LispError::Other(err.to_string()))
+        });
+    }
+}
+
+#[pymodule]
+fn sxprs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Interp>()?;
+    m.add("LispError", m.py().get_type::<PyLispError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::ffi::c_str;
+
+    // runs `code` with `interp` bound to a new Interp
+    fn python(code: &std::ffi::CStr) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("interp", Bound::new(py, Interp::new()).unwrap()).unwrap();
+            globals.set_item("LispError", py.get_type::<PyLispError>()).unwrap();
+            py.run(code, Some(&globals), None).map_err(|err| err.display(py)).unwrap();
+        });
+    }
+
+    #[test]
+    fn converts_values_both_ways() {
+        python(c_str!(
+            r#"
+from fractions import Fraction
+assert interp.eval("(+ 1 2)") == 3
+assert interp.eval("(list nil true 1.5 \"s\" #\\c 'sym (/ 1 3) (pow 2 70))") == [None, True, 1.5, "s", "c", "sym", Fraction(1, 3), 2 ** 70]
+assert interp.eval("{:a [1 2] \"b\" (cons 1 2)}") == {":a": [1, 2], "b": (1, 2)}
+interp.eval("(defn describe (x) (list (type-of x) x))")
+assert interp.call("describe", {"k": (1, 2)}) == ["Map", {"k": [1, 2]}]
+assert interp.call("describe", 2 ** 80) == ["BigInt", 2 ** 80]
+assert interp.call("describe", Fraction(2, 4)) == ["Ratio", Fraction(1, 2)]
+assert interp.call("describe", False) == ["Bool", False]
+assert interp.eval("(lambda (x) x)") == "( lambda ( x ) x )"
+try:
+    interp.call("describe", object())
+    raise AssertionError("objects aren't values")
+except TypeError as err:
+    assert str(err) == "a object can't be a lisp value", err
+"#
+        ));
+    }
+
+    #[test]
+    fn raises_lisp_errors() {
+        python(c_str!(
+            r#"
+try:
+    interp.eval("(car 1)")
+    raise AssertionError("car of 1")
+except LispError as err:
+    assert (err.id, err.code) == ("E0101", "type-mismatch"), (err.id, err.code)
+    assert str(err).startswith("1 is not a List, it's a Int"), str(err)
+"#
+        ));
+    }
+
+    #[test]
+    fn calls_python_functions() {
+        python(c_str!(
+            r#"
+interp.register("py-upper", lambda s: s.upper())
+interp.register("py-fail", lambda: 1 / 0)
+assert interp.eval("(py-upper \"hi\")") == "HI"
+assert interp.eval("(map py-upper '(\"a\" \"b\"))") == ["A", "B"]
+try:
+    interp.eval("(py-fail)")
+    raise AssertionError("1 / 0")
+except LispError as err:
+    assert str(err).startswith("ZeroDivisionError: division by zero"), str(err)
+"#
+        ));
+    }
+}
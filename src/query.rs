@@ -0,0 +1,111 @@
+// structural search over s-expression data: a selector is a path of steps,
+// each taking the subtrees selected so far to new ones, starting from the
+// list of the file's top-level forms
+//
+//   N        the Nth item of each list, counting from 0; negative counts from the end
+//   *        every item of each list
+//   **       each subtree and everything inside it, at any depth
+//   depth:N  everything exactly N levels inside each subtree
+//   name     the items of each list that are lists headed by the symbol `name`
+use crate::error::LispError;
+use crate::exp::LispExp;
+
+enum Step {
+    Index(i64),
+    Items,
+    Descendants,
+    Depth(usize),
+    Head(String),
+}
+
+fn parse_step(step: &str) -> Result<Step, LispError> {
+    if let Ok(index) = step.parse::<i64>() {
+        return Ok(Step::Index(index));
+    }
+    match step {
+        "*" => Ok(Step::Items),
+        "**" => Ok(Step::Descendants),
+        _ => match step.strip_prefix("depth:") {
+            Some(depth) => depth
+                .parse()
+                .map(Step::Depth)
+                .map_err(|_| LispError::Other(format!("expected a depth after `depth:`, found `{depth}`"))),
+            None => Ok(Step::Head(step.to_owned())),
+        },
+    }
+}
+
+fn children(exp: &LispExp) -> &[LispExp] {
+    match exp {
+        LispExp::List(items) => items,
+        _ => &[],
+    }
+}
+
+fn descendants(exp: &LispExp, out: &mut Vec<LispExp>) {
+    out.push(exp.clone());
+    for item in children(exp) {
+        descendants(item, out);
+    }
+}
+
+fn at_depth(exp: &LispExp, depth: usize, out: &mut Vec<LispExp>) {
+    match depth {
+        0 => out.push(exp.clone()),
+        _ => children(exp).iter().for_each(|item| at_depth(item, depth - 1, out)),
+    }
+}
+
+fn apply(step: &Step, selected: Vec<LispExp>) -> Vec<LispExp> {
+    let mut out = vec![];
+    for exp in &selected {
+        let items = children(exp);
+        match step {
+            Step::Index(index) => {
+                let index = if *index < 0 { items.len() as i64 + index } else { *index };
+                out.extend(usize::try_from(index).ok().and_then(|index| items.get(index)).cloned());
+            }
+            Step::Items => out.extend(items.iter().cloned()),
+            Step::Descendants => descendants(exp, &mut out),
+            Step::Depth(depth) => at_depth(exp, *depth, &mut out),
+            Step::Head(name) => out.extend(
+                items
+                    .iter()
+                    .filter(|item| matches!(children(item).first(), Some(LispExp::Symbol(head)) if *head.name() == **name))
+                    .cloned(),
+            ),
+        }
+    }
+    out
+}
+
+// the subtrees of `forms` that `selector`, steps separated by whitespace, picks
+pub fn query(selector: &str, forms: &[LispExp]) -> Result<Vec<LispExp>, LispError> {
+    let steps = selector.split_whitespace().map(parse_step).collect::<Result<Vec<_>, _>>()?;
+    let root = LispExp::from(forms.to_vec());
+    Ok(steps.iter().fold(vec![root], |selected, step| apply(step, selected)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokens;
+    use crate::parser::parse_program;
+
+    fn select(selector: &str) -> Result<String, LispError> {
+        let forms = parse_program(&tokens("(define a 1) (defn f (x) (define b 2) x) (define c (list 3))")?)?;
+        Ok(query(selector, &forms)?.iter().map(LispExp::to_string).collect::<Vec<_>>().join(" | "))
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(select("0 1").unwrap(), "a");
+        assert_eq!(select("-1 -1").unwrap(), "( list 3 )");
+        assert_eq!(select("define 2").unwrap(), "1 | ( list 3 )");
+        assert_eq!(select("* define").unwrap(), "( define b 2 )");
+        assert_eq!(select("** list").unwrap(), "( list 3 )");
+        assert_eq!(select("depth:2 0").unwrap(), "x | define | list");
+        assert_eq!(select("5").unwrap(), "");
+        assert!(select("depth:x").is_err());
+    }
+}
@@ -0,0 +1,596 @@
+// refactorings built on the concrete syntax tree, which change only the code
+// they're about and leave the rest of a file as it was written.
+//
+// `rename` renames a variable or function the program defines with `define`,
+// `defn` or `defmacro`, everywhere it's referred to: in the file, and in the
+// files it loads and imports. A parameter or local with the same name is
+// another variable and is left alone, and so is quoted data. A name defined
+// inside `(module m ...)` is renamed there, in its export list, and as `m/name`
+// wherever the module is imported.
+//
+// `extract` lifts an expression out into a function of its own, with the
+// local variables it uses as parameters, and calls the function in its place
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::builtins::expand_path;
+use crate::cst::{apply_edits, parse_cst, Cst, CstKind, CstNode, Edit};
+use crate::error::LispError;
+use crate::eval::Interpreter;
+use crate::exp::LispExp;
+use crate::fmt::{format_program, offsets};
+use crate::lexer::Pos;
+use crate::parser::parse_atom;
+use crate::platform;
+use crate::symbol::SymbolId;
+
+// a file read for a refactoring
+struct Source {
+    path: PathBuf,
+    cst: Cst,
+}
+
+// a file a refactoring changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changed {
+    pub path: PathBuf,
+    // what the file is now
+    pub source: String,
+    // where each change was made, in the file as it was
+    pub places: Vec<Pos>,
+}
+
+// the line and column of byte `offset` in `source`
+fn position(source: &str, offset: usize) -> Pos {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    Pos { line: before.matches('\n').count() + 1, col: before[line_start..].chars().count() + 1 }
+}
+
+fn head(node: &CstNode) -> Option<&str> {
+    match &node.kind {
+        CstKind::List { open: "(", items, .. } => items.first().and_then(CstNode::symbol),
+        _ => None,
+    }
+}
+
+// the name `form` defines, if it's a `define`, `defn` or `defmacro`
+fn definition(form: &CstNode) -> Option<&str> {
+    match (head(form), form.children()) {
+        (Some("define" | "defn" | "defmacro"), [_, name, ..]) => name.symbol(),
+        _ => None,
+    }
+}
+
+// the names a parameter list binds, and the default values in it
+fn params(list: &CstNode) -> (Vec<String>, Vec<&CstNode>) {
+    let mut names = vec![];
+    let mut defaults = vec![];
+    let mut items = list.children().iter();
+    while let Some(item) = items.next() {
+        match (item.symbol(), item.children()) {
+            (Some(name), _) if name.len() > 1 && name.starts_with(':') => {
+                names.push(name[1..].to_owned());
+                defaults.extend(items.next());
+            }
+            (Some(name), _) => names.push(name.to_owned()),
+            (None, [name, default]) if name.symbol().is_some() => {
+                names.push(name.symbol().unwrap_or_default().to_owned());
+                defaults.push(default);
+            }
+            _ => {}
+        }
+    }
+    (names, defaults)
+}
+
+// the names a `match` pattern captures
+fn captures(pattern: &CstNode, names: &mut Vec<String>) {
+    match &pattern.kind {
+        CstKind::Atom(_) => {
+            if let Some(name) = pattern.symbol().filter(|name| !name.starts_with(':')) {
+                names.push(name.to_owned());
+            }
+        }
+        CstKind::Prefix(prefix, _) if prefix == "'" => {}
+        _ if head(pattern) == Some("quote") => {}
+        _ => pattern.children().iter().for_each(|item| captures(item, names)),
+    }
+}
+
+// the files `path` loads and imports, found the way `load` and `import` would
+// find them, and `path` first
+fn project(path: &Path, include: &[PathBuf]) -> Result<Vec<Source>, LispError> {
+    let mut sources: Vec<Source> = vec![];
+    let mut seen = HashSet::new();
+    let mut modules = HashSet::new();
+    let mut queue = vec![path.to_owned()];
+    while let Some(path) = queue.pop() {
+        let shown = path.display().to_string();
+        let canonical = platform::canonicalize(&path).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        if !seen.insert(canonical.clone()) {
+            continue;
+        }
+        let text = platform::read_file(&canonical).map_err(|err| LispError::Other(format!("{shown}: {err}")))?;
+        let cst = parse_cst(&text).map_err(|err| LispError::Other(format!("{shown}:{}", err.message())))?;
+        let dir = path.parent().map(Path::to_owned).unwrap_or_default();
+        let mut found = vec![];
+        let mut stack: Vec<&CstNode> = cst.forms.iter().collect();
+        while let Some(node) = stack.pop() {
+            match (head(node), node.children()) {
+                (Some("quote"), _) => continue,
+                (Some("load"), [_, file]) => {
+                    if let Ok(LispExp::Str(file)) = file.exp() {
+                        found.push(dir.join(expand_path(&file)?));
+                    }
+                }
+                (Some("module"), [_, name, ..]) => modules.extend(name.symbol().map(str::to_owned)),
+                (Some("import"), [_, name, ..]) => {
+                    if let Some(name) = name.symbol().filter(|name| !modules.contains(*name)) {
+                        let file = format!("{name}.lsp");
+                        let beside = dir.join(&file);
+                        found.extend(Some(beside).into_iter().chain(include.iter().map(|dir| dir.join(&file))).find(|path| path.exists()));
+                    }
+                }
+                _ => {}
+            }
+            stack.extend(node.children());
+        }
+        queue.extend(found.into_iter().rev());
+        sources.push(Source { path, cst });
+    }
+    Ok(sources)
+}
+
+// what a walk over code, in the order its scopes nest, is told about
+trait Visitor {
+    // a symbol in code that's evaluated, with the parameters and local
+    // variables in scope there, innermost last
+    fn reference(&mut self, node: &CstNode, locals: &[String]) -> Result<(), LispError>;
+    // an expression that's evaluated, before what's in it
+    fn expression(&mut self, _node: &CstNode, _locals: &[String]) -> Result<(), LispError> {
+        Ok(())
+    }
+    // the body of `(module name ...)` is about to be walked; returns whether
+    // the visitor's own names were visible outside it, for `leave_module`
+    fn enter_module(&mut self, _name: Option<&str>, _body: &[CstNode]) -> bool {
+        false
+    }
+    fn leave_module(&mut self, _outer: bool) {}
+}
+
+// walks code, keeping track of the local variables in scope, the way the
+// forms that bind them do
+struct Walker<'v, V> {
+    visitor: &'v mut V,
+    locals: Vec<String>,
+}
+
+impl<V: Visitor> Walker<'_, V> {
+    fn with(&mut self, names: Vec<String>, body: &[CstNode]) -> Result<(), LispError> {
+        let outer = self.locals.len();
+        self.locals.extend(names);
+        body.iter().try_for_each(|form| self.walk(form))?;
+        self.locals.truncate(outer);
+        Ok(())
+    }
+    fn walk(&mut self, node: &CstNode) -> Result<(), LispError> {
+        self.visitor.expression(node, &self.locals)?;
+        let items = match &node.kind {
+            CstKind::Atom(_) if node.symbol().is_some() => return self.visitor.reference(node, &self.locals),
+            CstKind::Atom(_) => return Ok(()),
+            CstKind::Prefix(prefix, form) => {
+                return match &prefix[..] {
+                    "'" => Ok(()),
+                    "`" => self.template(form),
+                    _ => self.walk(form),
+                };
+            }
+            CstKind::List { items, .. } => items,
+        };
+        match (head(node), &items[..]) {
+            (Some("quote"), _) => Ok(()),
+            (Some("quasiquote"), [_, template]) => self.template(template),
+            (Some("lambda"), [_, list, body @ ..]) | (Some("defn" | "defmacro"), [_, _, list, body @ ..]) => {
+                if head(node) != Some("lambda") {
+                    self.visitor.reference(&items[1], &self.locals)?;
+                }
+                let (names, defaults) = params(list);
+                defaults.into_iter().try_for_each(|default| self.walk(default))?;
+                self.with(names, body)
+            }
+            // the variable is named, not evaluated
+            (Some("define" | "set!"), [_, name, rest @ ..]) => {
+                if name.symbol().is_some() {
+                    self.visitor.reference(name, &self.locals)?;
+                }
+                rest.iter().try_for_each(|item| self.walk(item))
+            }
+            (Some("let"), [_, bindings, body @ ..]) => {
+                let mut names = vec![];
+                for binding in bindings.children() {
+                    if let [name, value] = binding.children() {
+                        self.walk(value)?;
+                        names.extend(name.symbol().map(str::to_owned));
+                    }
+                }
+                self.with(names, body)
+            }
+            (Some("for"), [_, name, _, list, body @ ..]) => {
+                self.walk(list)?;
+                self.with(name.symbol().into_iter().map(str::to_owned).collect(), body)
+            }
+            (Some("dotimes"), [_, header, body @ ..]) => match header.children() {
+                [name, count] => {
+                    self.walk(count)?;
+                    self.with(name.symbol().into_iter().map(str::to_owned).collect(), body)
+                }
+                _ => Ok(()),
+            },
+            (Some("match"), [_, exp, clauses @ ..]) => {
+                self.walk(exp)?;
+                for clause in clauses {
+                    if let [pattern, body @ ..] = clause.children() {
+                        let mut names = vec![];
+                        captures(pattern, &mut names);
+                        self.with(names, body)?;
+                    }
+                }
+                Ok(())
+            }
+            (Some("try"), [_, body @ .., catch]) if head(catch) == Some("catch") => {
+                body.iter().try_for_each(|form| self.walk(form))?;
+                match catch.children() {
+                    [_, err, handler @ ..] => self.with(err.symbol().into_iter().map(str::to_owned).collect(), handler),
+                    _ => Ok(()),
+                }
+            }
+            (Some("with-tasks"), [_, names, body @ ..]) => {
+                let names = names.children().iter().filter_map(CstNode::symbol).map(str::to_owned).collect();
+                self.with(names, body)
+            }
+            // the stages are named by their heads, which aren't calls
+            (Some("pipe"), [_, stages @ ..]) => {
+                stages.iter().try_for_each(|stage| stage.children().iter().skip(1).try_for_each(|arg| self.walk(arg)))
+            }
+            (Some("module"), [_, name, body @ ..]) => {
+                let outer = self.visitor.enter_module(name.symbol(), body);
+                body.iter().try_for_each(|form| self.walk(form))?;
+                self.visitor.leave_module(outer);
+                Ok(())
+            }
+            _ => items.iter().try_for_each(|item| self.walk(item)),
+        }
+    }
+    // only what's unquoted in a quasiquote template is evaluated
+    fn template(&mut self, template: &CstNode) -> Result<(), LispError> {
+        match (&template.kind, head(template), template.children()) {
+            (CstKind::Prefix(prefix, form), ..) if prefix.starts_with('~') => self.walk(form),
+            (_, Some("unquote" | "unquote-splicing"), [_, exp]) => self.walk(exp),
+            (_, _, items) => items.iter().try_for_each(|item| self.template(item)),
+        }
+    }
+}
+
+// walks the top-level `forms` with `visitor`
+fn visit(forms: &[CstNode], visitor: &mut impl Visitor) -> Result<(), LispError> {
+    let mut walker = Walker { visitor, locals: vec![] };
+    forms.iter().try_for_each(|form| walker.walk(form))
+}
+
+// what's renamed: `name` where it's in scope, and `module/name` everywhere
+// when it's defined in a module
+struct Renamer<'a> {
+    old: &'a str,
+    new: &'a str,
+    module: Option<&'a str>,
+    // whether an unqualified `old` is the one being renamed here
+    in_scope: bool,
+    source: String,
+    edits: Vec<Edit>,
+    places: Vec<Pos>,
+}
+
+impl Renamer<'_> {
+    fn rename(&mut self, node: &CstNode, to: String) {
+        self.edits.push(Edit { span: node.span.clone(), text: to });
+        self.places.push(position(&self.source, node.span.start));
+    }
+}
+
+impl Visitor for Renamer<'_> {
+    fn reference(&mut self, node: &CstNode, locals: &[String]) -> Result<(), LispError> {
+        let name = node.symbol().unwrap_or_default();
+        if name == self.old && self.in_scope && !locals.iter().any(|local| local == name) {
+            if locals.iter().any(|local| local == self.new) {
+                let pos = position(&self.source, node.span.start);
+                return Err(LispError::at(pos, format!("`{}` would be the local `{}` there", self.old, self.new)));
+            }
+            self.rename(node, self.new.to_owned());
+        } else if let Some(module) = self.module.filter(|module| name == format!("{module}/{}", self.old)) {
+            self.rename(node, format!("{module}/{}", self.new));
+        }
+        Ok(())
+    }
+    // inside a module its own definitions hide the global ones
+    fn enter_module(&mut self, name: Option<&str>, body: &[CstNode]) -> bool {
+        let outer = self.in_scope;
+        self.in_scope = match self.module {
+            Some(module) => name == Some(module),
+            None => outer && !body.iter().any(|form| definition(form) == Some(self.old)),
+        };
+        outer
+    }
+    fn leave_module(&mut self, outer: bool) {
+        self.in_scope = outer;
+    }
+}
+
+// the files changed by renaming `old` to `new` in the program at `path` and
+// the files it loads and imports; `include` is where `import` looks after
+// the importing file's directory
+pub fn rename(path: &Path, include: &[PathBuf], old: &str, new: &str) -> Result<Vec<Changed>, LispError> {
+    if !matches!(parse_atom(new), Ok(LispExp::Symbol(_))) || new.contains('/') || new.starts_with(':') {
+        return Err(LispError::Other(format!("`{new}` can't be a variable's name")));
+    }
+    let sources = project(path, include)?;
+    // where `old` is defined: at the top of a file, or in which modules
+    let mut global = false;
+    let mut modules: Vec<&str> = vec![];
+    let mut taken = false;
+    for source in &sources {
+        for form in &source.cst.forms {
+            match (head(form), form.children()) {
+                (Some("module"), [_, name, body @ ..]) => {
+                    let defines = |wanted: &str| body.iter().any(|form| definition(form) == Some(wanted));
+                    if defines(old) {
+                        modules.extend(name.symbol());
+                    }
+                    taken |= defines(new);
+                }
+                _ => {
+                    global |= definition(form) == Some(old);
+                    taken |= definition(form) == Some(new);
+                }
+            }
+        }
+    }
+    let module = match (global, &modules[..]) {
+        (true, _) => None,
+        (false, [module]) => Some(*module),
+        (false, []) => return Err(LispError::Other(format!("`{old}` isn't defined with define, defn or defmacro in {} or the files it loads", path.display()))),
+        (false, _) => return Err(LispError::Other(format!("`{old}` is defined in more than one module: {}", modules.join(", ")))),
+    };
+    if taken || (module.is_none() && Interpreter::new().resolve(SymbolId::new(new)).is_ok()) {
+        return Err(LispError::Other(format!("`{new}` is already defined")));
+    }
+    let mut changed = vec![];
+    for source in &sources {
+        let mut renamer = Renamer { old, new, module, in_scope: module.is_none(), source: source.cst.to_string(), edits: vec![], places: vec![] };
+        visit(&source.cst.forms, &mut renamer).map_err(|err| LispError::Other(format!("{}:{}", source.path.display(), err.message())))?;
+        if !renamer.edits.is_empty() {
+            let text = apply_edits(&source.cst, &renamer.edits)?;
+            changed.push(Changed { path: source.path.clone(), source: text, places: renamer.places });
+        }
+    }
+    Ok(changed)
+}
+
+// finds the selected expression, and the locals it uses from around it
+struct Extractor<'a> {
+    span: &'a Range<usize>,
+    // the locals in scope where the selection is, once it's been reached
+    outer: Option<Vec<String>>,
+    params: Vec<String>,
+    source: &'a str,
+}
+
+impl Extractor<'_> {
+    fn selected(&self, node: &CstNode) -> bool {
+        self.span.start <= node.span.start && node.span.end <= self.span.end
+    }
+    // the local from around the selection `name` refers to where `locals` are in scope
+    fn outer_local(&self, name: &str, locals: &[String]) -> bool {
+        let outer = self.outer.as_ref().map_or(0, Vec::len);
+        locals.iter().rposition(|local| local == name).is_some_and(|at| at < outer)
+    }
+}
+
+impl Visitor for Extractor<'_> {
+    fn expression(&mut self, node: &CstNode, locals: &[String]) -> Result<(), LispError> {
+        if node.span == *self.span {
+            self.outer = Some(locals.to_vec());
+        }
+        // a function can't change its caller's variables
+        if let (Some("set!"), [_, name, ..]) = (head(node), node.children()) {
+            let name = name.symbol().unwrap_or_default();
+            if self.outer.is_some() && self.selected(node) && self.outer_local(name, locals) {
+                let pos = position(self.source, node.span.start);
+                return Err(LispError::at(pos, format!("the expression sets the local `{name}`, which a function can't do for it")));
+            }
+        }
+        Ok(())
+    }
+    fn reference(&mut self, node: &CstNode, locals: &[String]) -> Result<(), LispError> {
+        let name = node.symbol().unwrap_or_default();
+        if self.outer.is_some() && self.selected(node) && self.outer_local(name, locals) && !self.params.iter().any(|param| param == name) {
+            self.params.push(name.to_owned());
+        }
+        Ok(())
+    }
+}
+
+// the bytes `text` names in `source`: `start..end` as byte offsets, or as
+// `line:col..line:col`, the end not included either way
+pub fn span(source: &str, text: &str) -> Result<Range<usize>, LispError> {
+    let bad = || LispError::Other(format!("expected a span like 10..24 or 2:5..2:19, found `{text}`"));
+    let (start, end) = text.split_once("..").ok_or_else(bad)?;
+    let offset = |at: &str| -> Result<usize, LispError> {
+        let Some((line, col)) = at.split_once(':') else {
+            return at.parse().map_err(|_| bad());
+        };
+        let pos = Pos { line: line.parse().map_err(|_| bad())?, col: col.parse().map_err(|_| bad())? };
+        if pos.line == 0 || pos.col == 0 || pos.line > source.split('\n').count() {
+            return Err(LispError::Other(format!("{pos} isn't in the file")));
+        }
+        Ok(offsets(source, &[pos])[0])
+    };
+    let span = offset(start)?..offset(end)?;
+    if span.start > span.end || span.end > source.len() || !source.is_char_boundary(span.start) || !source.is_char_boundary(span.end) {
+        return Err(LispError::Other(format!("bytes {}..{} aren't in the file", span.start, span.end)));
+    }
+    Ok(span)
+}
+
+// `source` with the expression at `span` made into the function `name`,
+// defined before the top-level form it's in, or the form in a module's body,
+// and called in its place; and the call
+pub fn extract(source: &str, span: Range<usize>, name: &str) -> Result<(String, String), LispError> {
+    if !matches!(parse_atom(name), Ok(LispExp::Symbol(_))) || name.contains('/') || name.starts_with(':') {
+        return Err(LispError::Other(format!("`{name}` can't be a function's name")));
+    }
+    let cst = parse_cst(source)?;
+    // the selection, less the spaces around it
+    let selected = &source[span.clone()];
+    let span = span.start + (selected.len() - selected.trim_start().len())..span.end - (selected.len() - selected.trim_end().len());
+    let node = cst.node(&span).ok_or_else(|| LispError::at(position(source, span.start), "the selection isn't one whole expression"))?;
+    if let Some(defined) = definition(node) {
+        return Err(LispError::Other(format!("the expression defines `{defined}`, which would only be defined inside `{name}`")));
+    }
+    let mut taken = Interpreter::new().resolve(SymbolId::new(name)).is_ok();
+    let mut stack: Vec<&CstNode> = cst.forms.iter().collect();
+    while let Some(form) = stack.pop() {
+        taken |= definition(form) == Some(name);
+        if head(form) == Some("module") {
+            stack.extend(form.children());
+        }
+    }
+    if taken {
+        return Err(LispError::Other(format!("`{name}` is already defined")));
+    }
+    let mut extractor = Extractor { span: &span, outer: None, params: vec![], source };
+    visit(&cst.forms, &mut extractor)?;
+    let Some(outer) = extractor.outer else {
+        return Err(LispError::at(position(source, span.start), "the selection is data or a name being bound, not code that runs"));
+    };
+    if outer.iter().any(|local| local == name) {
+        return Err(LispError::Other(format!("`{name}` is a local variable where the expression is")));
+    }
+    // the form the function goes before, which is in a module's body if the
+    // expression is, so the function sees what the module defines
+    let contains = |form: &&CstNode| form.span.start <= span.start && span.end <= form.span.end;
+    let mut before = cst.forms.iter().find(contains).unwrap_or(node);
+    while let (Some("module"), [_, _, body @ ..]) = (head(before), before.children()) {
+        match body.iter().find(contains) {
+            Some(form) => before = form,
+            None => break,
+        }
+    }
+    let params = extractor.params.join(" ");
+    let function = format_program(&format!("(defn {name} ({params}) {})", node.text()))?;
+    let call = if params.is_empty() { format!("({name})") } else { format!("({name} {params})") };
+    let edits = [Edit::insert_before(&cst, before.span.clone(), function.trim_end())?, Edit::replace(&cst, span, &call)?];
+    Ok((apply_edits(&cst, &edits)?, call))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes `files` to a directory of their own and renames in the first
+    // one, giving what each changed file is now
+    fn renamed(dir: &str, files: &[(&str, &str)], old: &str, new: &str) -> Result<Vec<(String, String)>, String> {
+        let dir = std::env::temp_dir().join(format!("sxprs-rename-{}-{dir}", std::process::id()));
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        let changed = rename(&dir.join(files[0].0), &[dir.join("lib")], old, new);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let prefix = format!("{}/", dir.display());
+        let changed = changed.map_err(|err| err.message().replace(&prefix, ""))?;
+        Ok(changed.into_iter().map(|file| (file.path.display().to_string().replace(&prefix, ""), file.source)).collect())
+    }
+
+    #[test]
+    fn renames_what_refers_to_the_definition() {
+        let source = "; the total\n(defn total (xs)   ; sums\n  (reduce + 0 xs))\n\n(define total-count 0)\n(print (total '(1 2)) 'total)\n(defn f (total) (+ total 1))\n(let ((x (total [1])) (total 2)) total)\n(map total `(total ~total))\n";
+        let changed = renamed("scopes", &[("main.lsp", source)], "total", "sum").unwrap();
+        assert_eq!(
+            changed,
+            [(
+                "main.lsp".to_owned(),
+                "; the total\n(defn sum (xs)   ; sums\n  (reduce + 0 xs))\n\n(define total-count 0)\n(print (sum '(1 2)) 'total)\n(defn f (total) (+ total 1))\n(let ((x (sum [1])) (total 2)) total)\n(map sum `(total ~sum))\n".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn renames_across_loads_and_imports() {
+        let files = [
+            ("main.lsp", "(load \"helpers.lsp\")\n(import geometry)\n(print (geometry/area 2 3) (double 2))\n"),
+            ("helpers.lsp", "(defn double (x) (* 2 x))\n"),
+            ("lib/geometry.lsp", "(module geometry (export area)\n  (defn area (w h) (* w h))\n  (defn square (x) (area x x)))\n"),
+        ];
+        let changed = renamed("modules", &files, "area", "surface").unwrap();
+        assert_eq!(
+            changed,
+            [
+                ("main.lsp".to_owned(), "(load \"helpers.lsp\")\n(import geometry)\n(print (geometry/surface 2 3) (double 2))\n".to_owned()),
+                ("lib/geometry.lsp".to_owned(), "(module geometry (export surface)\n  (defn surface (w h) (* w h))\n  (defn square (x) (surface x x)))\n".to_owned()),
+            ]
+        );
+        let changed = renamed("loads", &files, "double", "twice").unwrap();
+        assert_eq!(changed.iter().map(|(path, _)| &path[..]).collect::<Vec<_>>(), ["main.lsp", "helpers.lsp"]);
+    }
+
+    #[test]
+    fn refuses_what_would_change_meaning() {
+        let source = "(define n 1)\n(define m 2)\n(defn f (m) (+ m n))\n";
+        let files = [("main.lsp", source)];
+        assert_eq!(renamed("taken", &files, "n", "m").unwrap_err(), "`m` is already defined");
+        assert_eq!(renamed("builtin", &files, "n", "print").unwrap_err(), "`print` is already defined");
+        let files = [("main.lsp", "(define n 1)\n(defn f (k) (+ k n))\n")];
+        assert_eq!(renamed("captured", &files, "n", "k").unwrap_err(), "main.lsp:2:18: `n` would be the local `k` there");
+        assert_eq!(renamed("missing", &files, "g", "h").unwrap_err(), "`g` isn't defined with define, defn or defmacro in main.lsp or the files it loads");
+        assert_eq!(renamed("bad", &files, "n", "1").unwrap_err(), "`1` can't be a variable's name");
+    }
+
+    // extracts what `selected` is the first place of in `source`
+    fn extracted(source: &str, selected: &str, name: &str) -> Result<String, String> {
+        let start = source.find(selected).unwrap();
+        extract(source, start..start + selected.len(), name).map(|(source, _)| source).map_err(|err| err.message())
+    }
+
+    #[test]
+    fn extracts_with_the_locals_used_as_parameters() {
+        let source = "(define rate 3)\n\n; prices\n(defn price (base qty)\n  (let ((total (* base qty)))\n    (+ total (* total rate))))   ; with tax\n";
+        assert_eq!(
+            extracted(source, " (+ total (* total rate))", "with-tax").unwrap(),
+            "(define rate 3)\n\n(defn with-tax (total) (+ total (* total rate)))\n\n; prices\n(defn price (base qty)\n  (let ((total (* base qty)))\n    (with-tax total)))   ; with tax\n"
+        );
+        let (_, call) = extract(source, span(source, "5:16..5:28").unwrap(), "cost").unwrap();
+        assert_eq!(call, "(cost base qty)");
+        assert_eq!(extracted(source, "3", "the-rate").unwrap().lines().take(3).collect::<Vec<_>>(), ["(defn the-rate () 3)", "(define rate (the-rate))", ""]);
+        // what's in a module stays in it
+        let source = "(module shop (export total)\n  (define tax 2)\n  (defn total (x) (+ x tax)))\n";
+        assert_eq!(extracted(source, "(+ x tax)", "taxed").unwrap(), "(module shop (export total)\n  (define tax 2)\n  (defn taxed (x) (+ x tax))\n  (defn total (x) (taxed x)))\n");
+    }
+
+    #[test]
+    fn refuses_what_cant_be_extracted() {
+        let source = "(defn f (x)\n  (set! x (+ x 1))\n  '(a b))\n";
+        assert_eq!(extracted(source, "(set! x (+ x 1))", "g").unwrap_err(), "2:3: the expression sets the local `x`, which a function can't do for it");
+        assert_eq!(extracted(source, "(a b)", "g").unwrap_err(), "3:4: the selection is data or a name being bound, not code that runs");
+        assert_eq!(extracted(source, "x", "g").unwrap_err(), "1:10: the selection is data or a name being bound, not code that runs");
+        assert_eq!(extracted("(define rate 3)", "rate", "g").unwrap_err(), "1:9: the selection is data or a name being bound, not code that runs");
+        assert_eq!(extracted(source, "(+ x", "g").unwrap_err(), "2:11: the selection isn't one whole expression");
+        assert_eq!(extracted(source, "(+ x 1)", "f").unwrap_err(), "`f` is already defined");
+        assert_eq!(extracted(source, "(+ x 1)", "x").unwrap_err(), "`x` is a local variable where the expression is");
+        assert_eq!(extracted(source, "(+ x 1)", "map").unwrap_err(), "`map` is already defined");
+        assert_eq!(span(source, "1..x").unwrap_err().message(), "expected a span like 10..24 or 2:5..2:19, found `1..x`");
+        assert_eq!(span(source, "9:1..9:2").unwrap_err().message(), "9:1 isn't in the file");
+    }
+}
@@ -0,0 +1,231 @@
+// `schedule` and `sxprs daemon`: handlers to run at the minutes a cron
+// line picks, `minute hour day-of-month month day-of-week`, in UTC. Each
+// field is `*`, a number, a range `a-b`, any of those with a step like `*/5`,
+// or a comma-separated list of them; days of the week count from 0 for
+// Sunday, and 7 is Sunday too. When both day fields are restricted, a day
+// that matches either runs, like cron
+use std::time::Duration;
+
+use crate::builtins::{record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::LispExp;
+use crate::platform;
+use crate::symbol::{SymbolId, SymbolMap};
+
+// the values each field can have, by position
+const FIELDS: [(&str, u32, u32); 5] = [("minute", 0, 59), ("hour", 0, 23), ("day of month", 1, 31), ("month", 1, 12), ("day of week", 0, 7)];
+
+// a parsed cron line: which values of each field match, as bits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Cron {
+    fields: [u64; 5],
+    // whether the day fields were `*`, which changes how they combine
+    any_day: bool,
+    any_weekday: bool,
+}
+
+// a handler `schedule` registered, for the daemon to run
+#[derive(Clone)]
+pub(crate) struct Job {
+    spec: String,
+    cron: Cron,
+    handler: LispExp,
+}
+
+fn field(text: &str, (name, min, max): (&str, u32, u32)) -> Result<u64, String> {
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&step| step > 0).ok_or(format!("bad step `{step}` in the {name}"))?),
+            None => (part, 1),
+        };
+        let number = |text: &str| text.parse::<u32>().ok().filter(|n| (min..=max).contains(n)).ok_or(format!("the {name} should be {min} to {max}, not `{text}`"));
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((from, to)) => (number(from)?, number(to)?),
+                // `5/15` is every 15 from 5
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if from > to {
+            return Err(format!("the {name} range `{range}` is backwards"));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl Cron {
+    pub(crate) fn parse(spec: &str) -> Result<Cron, LispError> {
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        let fail = |msg: String| LispError::Other(format!("bad schedule `{spec}`: {msg}"));
+        if parts.len() != 5 {
+            return Err(fail(format!("expected 5 fields, found {}", parts.len())));
+        }
+        let mut fields = [0; 5];
+        for (i, part) in parts.iter().enumerate() {
+            fields[i] = field(part, FIELDS[i]).map_err(fail)?;
+        }
+        // Sunday is both 0 and 7
+        if fields[4] & 1 << 7 != 0 {
+            fields[4] |= 1;
+        }
+        Ok(Cron { fields, any_day: parts[2] == "*", any_weekday: parts[4] == "*" })
+    }
+    // whether the minute `epoch_minute` minutes after the unix epoch is one
+    // the line picks
+    pub(crate) fn matches(&self, epoch_minute: i64) -> bool {
+        let days = epoch_minute.div_euclid(24 * 60);
+        let minute = epoch_minute.rem_euclid(60);
+        let hour = epoch_minute.div_euclid(60).rem_euclid(24);
+        let (_, month, day) = civil_from_days(days);
+        // the epoch was a Thursday
+        let weekday = (days + 4).rem_euclid(7);
+        let has = |field: usize, value: i64| self.fields[field] & 1 << value != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => has(2, day) || has(4, weekday),
+            _ => has(2, day) && has(4, weekday),
+        };
+        has(0, minute) && has(1, hour) && has(3, month) && day_matches
+    }
+}
+
+// the year, month and day `days` days after the unix epoch, after Howard
+// Hinnant's `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// (schedule spec handler) has `sxprs daemon` call `(handler)` at every
+// minute the cron line `spec` picks
+fn lisp_schedule(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (spec, rest) = unpack(cont)?;
+    let (handler, _) = unpack(rest)?;
+    let spec = spec.get_string()?;
+    if !matches!(handler, LispExp::Lambda(_) | LispExp::Builtin(_)) {
+        return Err(handler.mismatch("function"));
+    }
+    let cron = Cron::parse(spec)?;
+    env.jobs.borrow_mut().push(Job { spec: spec.to_owned(), cron, handler: handler.clone() });
+    Ok(LispExp::Nil)
+}
+
+impl Interpreter {
+    // how many handlers `schedule` registered
+    pub fn scheduled(&self) -> usize {
+        self.jobs.borrow().len()
+    }
+    // calls the handlers scheduled for the minute `epoch_minute` minutes after
+    // the unix epoch, writing the errors they fail with to stderr; only the
+    // errors that stop the whole program, like `exit`, are returned
+    pub fn run_due(&self, epoch_minute: i64) -> Result<(), LispError> {
+        let jobs = self.jobs.borrow().clone();
+        for job in jobs.iter().filter(|job| job.cron.matches(epoch_minute)) {
+            match self.apply(&job.handler, vec![]) {
+                Ok(_) => {}
+                Err(err) if matches!(err.root(), LispError::Exit(_) | LispError::Interrupted | LispError::LimitExceeded { .. }) => return Err(err),
+                Err(err) => platform::eprint(&format!("error: scheduled `{}`: {}", job.spec, err.message())),
+            }
+        }
+        Ok(())
+    }
+    // runs the scheduled handlers at their minutes, until one exits
+    pub fn run_schedule(&self) -> Result<(), LispError> {
+        let mut last = platform::epoch_millis().div_euclid(60_000);
+        loop {
+            let now = platform::epoch_millis();
+            let minute = now.div_euclid(60_000);
+            if minute > last {
+                last = minute;
+                self.run_due(minute)?;
+            }
+            // until the next minute starts, but no more than a second, so Ctrl-C
+            // and changes to the clock are seen soon
+            let wait = (60_000 - now.rem_euclid(60_000)).clamp(1, 1000) as u64;
+            platform::sleep(Duration::from_millis(wait)).map_err(|err| LispError::Other(format!("schedule: {err}")))?;
+            self.step()?;
+        }
+    }
+}
+
+pub(crate) fn schedule_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "schedule", lisp_schedule, 2, "(schedule spec handler)", "has `sxprs daemon` call (handler) at every minute the cron line spec picks, in UTC");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // minutes since the epoch of a UTC date and time
+    fn at(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> i64 {
+        let days = (0..)
+            .map(|days| (days, civil_from_days(days)))
+            .find(|(_, date)| *date == (year, month, day))
+            .unwrap()
+            .0;
+        (days * 24 + hour) * 60 + minute
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn matching() {
+        let cron = |spec: &str| Cron::parse(spec).unwrap();
+        // 2024-03-04 was a Monday
+        let monday = at(2024, 3, 4, 9, 30);
+        assert!(cron("* * * * *").matches(monday));
+        assert!(cron("*/15 9 * * 1-5").matches(monday));
+        assert!(!cron("*/15 9 * * 0,6").matches(monday));
+        assert!(cron("30 9 4 3 *").matches(monday));
+        assert!(!cron("31 9 4 3 *").matches(monday));
+        // either day field matches when both are given
+        assert!(cron("30 9 1 * 1").matches(monday));
+        assert!(cron("30 9 4 * 0").matches(monday));
+        assert!(!cron("30 9 1 * 0").matches(monday));
+        assert!(cron("0 0 * * 7").matches(at(2024, 3, 3, 0, 0)));
+        assert!(cron("5/20 * * * *").matches(at(2024, 3, 3, 0, 45)));
+    }
+
+    #[test]
+    fn bad_specs() {
+        let err = |spec: &str| Cron::parse(spec).unwrap_err().message();
+        assert_eq!(err("* * *"), "bad schedule `* * *`: expected 5 fields, found 3");
+        assert_eq!(err("60 * * * *"), "bad schedule `60 * * * *`: the minute should be 0 to 59, not `60`");
+        assert_eq!(err("*/0 * * * *"), "bad schedule `*/0 * * * *`: bad step `0` in the minute");
+        assert_eq!(err("* 5-2 * * *"), "bad schedule `* 5-2 * * *`: the hour range `5-2` is backwards");
+    }
+
+    #[test]
+    fn running_due_handlers() {
+        let lisp = Interpreter::new();
+        lisp.eval_str("(define runs 0) (schedule \"0 * * * *\" (lambda () (set! runs (+ runs 1)))) (schedule \"0 * * * *\" (lambda () (car 1)))").unwrap();
+        assert_eq!(lisp.scheduled(), 2);
+        lisp.run_due(at(2024, 3, 4, 9, 0)).unwrap();
+        lisp.run_due(at(2024, 3, 4, 9, 1)).unwrap();
+        assert_eq!(lisp.eval_str("runs").unwrap().to_string(), "1");
+        lisp.eval_str("(schedule \"* * * * *\" (lambda () (exit 2)))").unwrap();
+        assert!(matches!(lisp.run_due(at(2024, 3, 4, 9, 1)), Err(LispError::Exit(2))));
+        assert!(lisp.eval_str("(schedule \"* * * * *\" 1)").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+// serde support, behind the `serde` feature: values serialize like an
+// externally tagged enum, `{"Int":1}` or `{"List":[...]}` in JSON, so every
+// type survives the round trip; maps are lists of [key, value] pairs, and
+// functions can't be serialized
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, VariantAccess, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::exp::{LispExp, MapKey};
+use crate::num;
+use crate::symbol::SymbolId;
+
+const VARIANTS: &[&str] = &["Symbol", "Str", "Char", "Int", "Number", "Bool", "Nil", "List", "Map", "Vector", "Pair", "BigInt", "Ratio"];
+
+impl Serialize for LispExp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            LispExp::Symbol(name) => serializer.serialize_newtype_variant("LispExp", 0, "Symbol", &*name.name()),
+            LispExp::Str(string) => serializer.serialize_newtype_variant("LispExp", 1, "Str", string),
+            LispExp::Char(chr) => serializer.serialize_newtype_variant("LispExp", 2, "Char", chr),
+            LispExp::Int(num) => serializer.serialize_newtype_variant("LispExp", 3, "Int", num),
+            LispExp::Number(num) => serializer.serialize_newtype_variant("LispExp", 4, "Number", num),
+            LispExp::Bool(b) => serializer.serialize_newtype_variant("LispExp", 5, "Bool", b),
+            LispExp::Nil => serializer.serialize_unit_variant("LispExp", 6, "Nil"),
+            LispExp::List(items) => serializer.serialize_newtype_variant("LispExp", 7, "List", &items[..]),
+            LispExp::Map(map) => {
+                let entries: Vec<(LispExp, &LispExp)> = map.iter().map(|(k, v)| (k.clone().into(), v)).collect();
+                serializer.serialize_newtype_variant("LispExp", 8, "Map", &entries)
+            }
+            LispExp::Vector(items) => serializer.serialize_newtype_variant("LispExp", 9, "Vector", &*items.borrow()),
+            LispExp::Pair(pair) => serializer.serialize_newtype_variant("LispExp", 10, "Pair", &**pair),
+            // as text, since few formats have numbers that big
+            LispExp::BigInt(num) => serializer.serialize_newtype_variant("LispExp", 11, "BigInt", &num.to_string()),
+            LispExp::Ratio(num) => serializer.serialize_newtype_variant("LispExp", 12, "Ratio", &num.to_string()),
+            other => Err(ser::Error::custom(format!("can't serialize a {}", other.name()))),
+        }
+    }
+}
+
+// a variant, by name in self-describing formats or by index in the others
+struct Tag(usize);
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tag, D::Error> {
+        struct TagVisitor;
+        impl Visitor<'_> for TagVisitor {
+            type Value = Tag;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a LispExp variant")
+            }
+            fn visit_u64<E: de::Error>(self, index: u64) -> Result<Tag, E> {
+                match usize::try_from(index) {
+                    Ok(index) if index < VARIANTS.len() => Ok(Tag(index)),
+                    _ => Err(E::invalid_value(de::Unexpected::Unsigned(index), &self)),
+                }
+            }
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Tag, E> {
+                VARIANTS
+                    .iter()
+                    .position(|variant| *variant == name)
+                    .map(Tag)
+                    .ok_or_else(|| E::unknown_variant(name, VARIANTS))
+            }
+        }
+        deserializer.deserialize_identifier(TagVisitor)
+    }
+}
+
+struct ExpVisitor;
+
+impl<'de> Visitor<'de> for ExpVisitor {
+    type Value = LispExp;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a LispExp")
+    }
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<LispExp, A::Error> {
+        let (Tag(index), variant) = data.variant::<Tag>()?;
+        Ok(match VARIANTS[index] {
+            "Symbol" => LispExp::Symbol(SymbolId::new(&variant.newtype_variant::<String>()?)),
+            "Str" => LispExp::Str(variant.newtype_variant()?),
+            "Char" => LispExp::Char(variant.newtype_variant()?),
+            "Int" => LispExp::Int(variant.newtype_variant()?),
+            "Number" => LispExp::Number(variant.newtype_variant()?),
+            "Bool" => LispExp::Bool(variant.newtype_variant()?),
+            "Nil" => {
+                variant.unit_variant()?;
+                LispExp::Nil
+            }
+            "List" => LispExp::from(variant.newtype_variant::<Vec<LispExp>>()?),
+            "Map" => {
+                let entries = variant.newtype_variant::<Vec<(LispExp, LispExp)>>()?;
+                let map = entries
+                    .into_iter()
+                    .map(|(k, v)| MapKey::try_from(&k).map(|k| (k, v)))
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| de::Error::custom(err.message()))?;
+                LispExp::Map(map)
+            }
+            "Pair" => LispExp::Pair(Rc::new(variant.newtype_variant()?)),
+            "BigInt" => {
+                let text = variant.newtype_variant::<String>()?;
+                num::from_big(text.parse::<BigInt>().map_err(de::Error::custom)?)
+            }
+            "Ratio" => {
+                let text = variant.newtype_variant::<String>()?;
+                num::from_ratio(text.parse::<BigRational>().map_err(de::Error::custom)?)
+            }
+            _ => LispExp::Vector(Rc::new(RefCell::new(variant.newtype_variant()?))),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for LispExp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LispExp, D::Error> {
+        deserializer.deserialize_enum("LispExp", VARIANTS, ExpVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+    use crate::exp::LispExp;
+
+    #[test]
+    fn round_trips() {
+        let lisp = Interpreter::new();
+        let vl = lisp.eval_str("(list 'a \"b\" #\\c 1 1.5 true nil [1] {:k 1 \"s\" 2} (cons 1 2) 12345678901234567890123 1/3)").unwrap();
+        let json = serde_json::to_string(&vl).unwrap();
+        assert!(json.starts_with("{\"List\":[{\"Symbol\":\"a\"},{\"Str\":\"b\"}"), "{json}");
+        let back: LispExp = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, vl);
+    }
+
+    #[test]
+    fn functions_dont_serialize() {
+        let lisp = Interpreter::new();
+        assert!(serde_json::to_string(&lisp.eval_str("(lambda (x) x)").unwrap()).is_err());
+        assert!(serde_json::from_str::<LispExp>("{\"Lambda\":1}").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+// ed25519 signatures for scripts: `sxprs keygen` makes a key pair,
+// `sxprs sign` writes `file.sig` beside a file and `sxprs verify`, or running
+// with `--require-signature`, checks it against a trusted public key. Keys and
+// signatures are hex on one line; the secret key file holds the 32 byte seed.
+// A signature covers the file's bytes only; under `--require-signature` every
+// file the program loads or imports needs a signature of its own
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::error::LispError;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+// the `N` bytes written as hex in `text`, which is what `what` is for errors
+fn unhex<const N: usize>(text: &str, what: &str) -> Result<[u8; N], LispError> {
+    let text = text.trim();
+    let bad = || LispError::Other(format!("expected {what} as {} hex digits", 2 * N));
+    if text.len() != 2 * N || !text.is_ascii() {
+        return Err(bad());
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).map_err(|_| bad())?;
+    }
+    Ok(bytes)
+}
+
+// a new secret key and its public key, as hex
+pub fn keygen() -> Result<(String, String), LispError> {
+    let mut seed = [0; 32];
+    getrandom::getrandom(&mut seed).map_err(|err| LispError::Other(format!("keygen: {err}")))?;
+    Ok(keys(&seed))
+}
+
+fn keys(seed: &[u8; 32]) -> (String, String) {
+    let key = SigningKey::from_bytes(seed);
+    (hex(&key.to_bytes()), hex(key.verifying_key().as_bytes()))
+}
+
+// the signature of `bytes` with the secret key `secret`, as hex
+pub fn sign(secret: &str, bytes: &[u8]) -> Result<String, LispError> {
+    let key = SigningKey::from_bytes(&unhex(secret, "a secret key")?);
+    Ok(hex(&key.sign(bytes).to_bytes()))
+}
+
+// whether `signature` is the signature of `bytes` by the key `public` belongs to
+pub fn verify(public: &str, bytes: &[u8], signature: &str) -> Result<(), LispError> {
+    let key = VerifyingKey::from_bytes(&unhex(public, "a public key")?).map_err(|_| LispError::Other("that isn't an ed25519 public key".to_owned()))?;
+    let signature = Signature::from_bytes(&unhex(signature, "a signature")?);
+    key.verify(bytes, &signature).map_err(|_| LispError::Other("the signature doesn't match the file and key".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        // the first test vector of RFC 8032
+        let (secret, public) = keys(&unhex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60", "").unwrap());
+        assert_eq!(public, "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let signature = sign(&secret, b"").unwrap();
+        assert_eq!(
+            signature,
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        );
+        assert!(verify(&public, b"", &signature).is_ok());
+        assert_eq!(verify(&public, b"changed", &signature).unwrap_err().message(), "the signature doesn't match the file and key");
+        let (_, other) = keygen().unwrap();
+        assert!(verify(&other, b"", &signature).is_err());
+        assert_eq!(verify("abc", b"", &signature).unwrap_err().message(), "expected a public key as 64 hex digits");
+    }
+}
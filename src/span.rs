@@ -0,0 +1,69 @@
+// where in the source the lists the parser read came from, so an error while
+// evaluating one can point at the code; kept beside the lists instead of in
+// them, looked up by the address the list is shared at
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::exp::LispExp;
+use crate::lexer::Pos;
+
+// how many lists are recorded before the first sweep for dropped ones
+const FIRST_SWEEP: usize = 1024;
+
+#[derive(Default)]
+struct Spans {
+    // weak, so the table doesn't keep the items alive, but the address isn't
+    // reused for another list while it's recorded
+    lists: HashMap<*const LispExp, (Weak<[LispExp]>, Pos)>,
+    // how many were left after the last sweep
+    live: usize,
+}
+
+// like symbols, spans are per thread, as the lists they're for are
+thread_local! {
+    static SPANS: RefCell<Spans> = RefCell::new(Spans::default());
+}
+
+pub(crate) fn record(list: &Rc<[LispExp]>, pos: Pos) {
+    SPANS.with(|spans| {
+        let mut spans = spans.borrow_mut();
+        // lists nothing uses anymore are dropped once the table doubles
+        if spans.lists.len() >= (2 * spans.live).max(FIRST_SWEEP) {
+            spans.lists.retain(|_, (list, _)| list.strong_count() > 0);
+            spans.live = spans.lists.len();
+        }
+        spans.lists.insert(list.as_ptr().cast(), (Rc::downgrade(list), pos));
+    });
+}
+
+// where `list` starts in the source, if the parser read it
+pub(crate) fn span(list: &Rc<[LispExp]>) -> Option<Pos> {
+    SPANS.with(|spans| spans.borrow().lists.get(&list.as_ptr().cast()).map(|(_, pos)| *pos))
+}
+
+// gives `to`, a list rebuilt from `from`, the same span
+pub(crate) fn copy(from: &LispExp, to: &LispExp) {
+    if let (LispExp::List(from), LispExp::List(to)) = (from, to) {
+        if let Some(pos) = span(from) {
+            record(to, pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_follow_lists() {
+        let list: Rc<[LispExp]> = Rc::from(vec![LispExp::Int(1)]);
+        assert_eq!(span(&list), None);
+        record(&list, Pos { line: 2, col: 3 });
+        assert_eq!(span(&list), Some(Pos { line: 2, col: 3 }));
+        let rebuilt = LispExp::from(vec![LispExp::Int(2)]);
+        copy(&LispExp::List(list.clone()), &rebuilt);
+        let LispExp::List(rebuilt) = rebuilt else { unreachable!() };
+        assert_eq!(span(&rebuilt), Some(Pos { line: 2, col: 3 }));
+    }
+}
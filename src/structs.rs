@@ -0,0 +1,130 @@
+// structs: `defstruct` names a record type and defines its constructor,
+// accessors and predicate as ordinary functions calling `make-struct` and
+// `struct-get`, so they show in `help`, run on the VM and save in images like
+// functions written by hand
+use std::rc::Rc;
+
+use crate::builtins::{make_lambda, record, unpack};
+use crate::error::LispError;
+use crate::eval::{Builtin, Interpreter};
+use crate::exp::{LispExp, Struct};
+use crate::symbol::{SymbolId, SymbolMap, OPTIONAL, QUOTE, REST};
+
+fn symbol(name: &str) -> LispExp {
+    LispExp::Symbol(SymbolId::new(name))
+}
+
+fn quote(vl: LispExp) -> LispExp {
+    LispExp::from(vec![LispExp::Symbol(QUOTE), vl])
+}
+
+// defines `name` as a function of `params` that returns `body`
+fn define(env: &Interpreter, name: &str, params: Vec<LispExp>, doc: &str, body: Vec<LispExp>) -> Result<(), LispError> {
+    let func = make_lambda(env, &LispExp::from(params), &[LispExp::from(doc), LispExp::from(body)])?;
+    env.define(SymbolId::new(name), LispExp::Lambda(func));
+    Ok(())
+}
+
+// (defstruct name fields...) defines `(name fields...)` to make one,
+// `(name-field s)` for each field and `(name? v)`
+fn lisp_defstruct(env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, fields) = unpack(cont)?;
+    let name = name.get_symbol()?;
+    for (i, field) in fields.iter().enumerate() {
+        let field = field.get_symbol()?;
+        // they're the constructor's parameters too
+        if field.is_keyword() || field == OPTIONAL || field == REST || fields[..i].contains(&LispExp::Symbol(field)) {
+            return Err(LispError::Other(format!("`{field}` can't be a field of `{name}`")));
+        }
+    }
+    let kind = quote(LispExp::Symbol(name));
+    let make = [vec![symbol("make-struct"), kind.clone(), quote(LispExp::from(fields.to_vec()))], fields.to_vec()].concat();
+    define(env, &name.name(), fields.to_vec(), &format!("makes a {name}"), make)?;
+    for field in fields {
+        let get = vec![symbol("struct-get"), LispExp::Symbol(name), quote(field.clone()), kind.clone()];
+        define(env, &format!("{name}-{field}"), vec![LispExp::Symbol(name)], &format!("the {field} of a {name}"), get)?;
+    }
+    let is = vec![symbol("eq?"), LispExp::from(vec![symbol("type-of"), symbol("value")]), kind];
+    define(env, &format!("{name}?"), vec![symbol("value")], &format!("whether value is a {name}"), is)?;
+    Ok(LispExp::Symbol(name))
+}
+
+// (make-struct name fields values...) a struct of the type `name` with a value
+// for each field in the list `fields`
+fn lisp_make_struct(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (name, rest) = unpack(cont)?;
+    let (fields, values) = unpack(rest)?;
+    let name = name.get_symbol()?;
+    let LispExp::List(fields) = fields else {
+        return Err(fields.mismatch("List"));
+    };
+    for field in fields.iter() {
+        field.get_symbol()?;
+    }
+    if fields.len() != values.len() {
+        return Err(LispError::Other(format!("a {name} has {} fields, but {} values were given", fields.len(), values.len())));
+    }
+    Ok(LispExp::Struct(Rc::new(Struct { name, fields: fields.clone(), values: values.to_vec() })))
+}
+
+// (struct-get s field [type]) the value of a field of `s`, which has to be a
+// struct of `type` when one is given
+fn lisp_struct_get(_env: &Interpreter, cont: &[LispExp]) -> Result<LispExp, LispError> {
+    let (vl, rest) = unpack(cont)?;
+    let (field, kind) = unpack(rest)?;
+    let field = field.get_symbol()?;
+    if let Some(kind) = kind.first() {
+        let kind = kind.get_symbol()?;
+        if !matches!(vl, LispExp::Struct(record) if record.name == kind) {
+            let found = match vl {
+                LispExp::Struct(record) => record.name.to_string(),
+                vl => vl.name().to_owned(),
+            };
+            return Err(LispError::Other(format!("{vl} is not a {kind}, it's a {found}")));
+        }
+    }
+    let LispExp::Struct(record) = vl else {
+        return Err(vl.mismatch("Struct"));
+    };
+    record.get(field).cloned().ok_or_else(|| LispError::Other(format!("a {} has no field `{field}`", record.name)))
+}
+
+pub(crate) fn struct_forms() -> SymbolMap<Builtin> {
+    let mut specials: SymbolMap<Builtin> = SymbolMap::default();
+    record!(specials, "defstruct", lisp_defstruct, 1.., "(defstruct name fields...)", "defines (name fields...) to make a struct, (name-field s) to read each field and (name? v)", unevaluated);
+    specials
+}
+
+pub(crate) fn struct_funcs() -> SymbolMap<Builtin> {
+    let mut funcs: SymbolMap<Builtin> = SymbolMap::default();
+    record!(funcs, "make-struct", lisp_make_struct, 2.., "(make-struct name (fields...) values...)", "a struct of type name with a value for each field");
+    record!(funcs, "struct-get", lisp_struct_get, 2..=3, "(struct-get s field [type])", "the value of a field of s, which has to be a type if one is given");
+    funcs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Interpreter;
+
+    fn run(source: &str) -> String {
+        match Interpreter::new().eval_str(source) {
+            Ok(vl) => vl.to_string(),
+            Err(err) => format!("error: {}", err.summary()),
+        }
+    }
+
+    #[test]
+    fn defstruct_defines_functions() {
+        let source = "(defstruct point x y) (define p (point 1 2))";
+        assert_eq!(run(&format!("{source} (list (point-x p) (point-y p) (point? p) (point? 1) (type-of p))")), "( 1 2 true false point )");
+        assert_eq!(run(&format!("{source} (point-x 5)")), "error: 5 is not a point, it's a Int");
+    }
+
+    #[test]
+    fn bad_structs() {
+        assert_eq!(run("(defstruct bad x x)"), "error: `x` can't be a field of `bad`");
+        assert_eq!(run("(defstruct bad &rest)"), "error: `&rest` can't be a field of `bad`");
+        assert_eq!(run("(make-struct 'p '(a) 1 2)"), "error: a p has 1 fields, but 2 values were given");
+        assert_eq!(run("(struct-get (make-struct 'p '(a) 1) 'b)"), "error: a p has no field `b`");
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+// an interned symbol, comparing and hashing two of them only looks at the id;
+// ids are per thread, so they can't be sent to another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(u32, PhantomData<*const ()>);
+
+// symbols the interpreter itself looks for, interned up front so checking for
+// them doesn't need the interner
+const KNOWN: &[&str] = &["quote", "quasiquote", "unquote", "unquote-splicing", "if", "vector", "catch", "export", "&rest", "&optional", "and", "or", "cond", "else", "dict", "let", "begin"];
+pub(crate) const QUOTE: SymbolId = SymbolId(0, PhantomData);
+pub(crate) const QUASIQUOTE: SymbolId = SymbolId(1, PhantomData);
+pub(crate) const UNQUOTE: SymbolId = SymbolId(2, PhantomData);
+pub(crate) const UNQUOTE_SPLICING: SymbolId = SymbolId(3, PhantomData);
+pub(crate) const IF: SymbolId = SymbolId(4, PhantomData);
+pub(crate) const VECTOR: SymbolId = SymbolId(5, PhantomData);
+pub(crate) const CATCH: SymbolId = SymbolId(6, PhantomData);
+pub(crate) const EXPORT: SymbolId = SymbolId(7, PhantomData);
+pub(crate) const REST: SymbolId = SymbolId(8, PhantomData);
+pub(crate) const OPTIONAL: SymbolId = SymbolId(9, PhantomData);
+pub(crate) const AND: SymbolId = SymbolId(10, PhantomData);
+pub(crate) const OR: SymbolId = SymbolId(11, PhantomData);
+pub(crate) const COND: SymbolId = SymbolId(12, PhantomData);
+pub(crate) const ELSE: SymbolId = SymbolId(13, PhantomData);
+pub(crate) const DICT: SymbolId = SymbolId(14, PhantomData);
+pub(crate) const LET: SymbolId = SymbolId(15, PhantomData);
+pub(crate) const BEGIN: SymbolId = SymbolId(16, PhantomData);
+
+struct Interner {
+    ids: HashMap<Rc<str>, SymbolId>,
+    names: Vec<Rc<str>>,
+}
+
+impl Interner {
+    fn new() -> Interner {
+        let mut interner = Interner { ids: HashMap::new(), names: vec![] };
+        for name in KNOWN {
+            interner.intern(name);
+        }
+        interner
+    }
+    fn intern(&mut self, name: &str) -> SymbolId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = SymbolId(self.names.len() as u32, PhantomData);
+        let name: Rc<str> = Rc::from(name);
+        self.names.push(name.clone());
+        self.ids.insert(name, id);
+        id
+    }
+}
+
+// symbols live as long as the thread, like the interpreters that use them
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl SymbolId {
+    pub fn new(name: &str) -> SymbolId {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+    // whether a symbol called `name` was ever made, by the reader or otherwise
+    pub(crate) fn exists(name: &str) -> bool {
+        INTERNER.with(|interner| interner.borrow().ids.contains_key(name))
+    }
+    pub fn name(self) -> Rc<str> {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize].clone())
+    }
+    // keywords like `:greeting` name keyword arguments and evaluate to themselves
+    pub fn is_keyword(self) -> bool {
+        let name = self.name();
+        name.len() > 1 && name.starts_with(':')
+    }
+}
+
+impl Display for SymbolId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name())
+    }
+}
+
+// ids are already unique small ints, so they hash as themselves
+#[derive(Default)]
+pub(crate) struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8) | u64::from(*byte);
+        }
+    }
+    fn write_u32(&mut self, id: u32) {
+        self.0 = u64::from(id);
+    }
+}
+
+pub(crate) type SymbolMap<V> = HashMap<SymbolId, V, BuildHasherDefault<IdHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        assert_eq!(SymbolId::new("let"), LET);
+        assert_eq!(SymbolId::new("begin"), BEGIN);
+        assert_eq!(SymbolId::new("some-name"), SymbolId::new("some-name"));
+        assert_ne!(SymbolId::new("a"), SymbolId::new("b"));
+        assert_eq!(&*SymbolId::new("some-name").name(), "some-name");
+        assert!(SymbolId::new(":key").is_keyword());
+        assert!(!SymbolId::new("key").is_keyword());
+    }
+}
@@ -0,0 +1,123 @@
+// with the `tracing` feature, every call to a lisp function, and to the
+// builtins that reach outside the interpreter, is a `tracing` span named
+// `sxprs.call`, so a service embedding the interpreter sees its scripts in its
+// traces. Spans have the function's name, the program being run and the
+// file being loaded when the call was made, and the line the function's body
+// starts at when the parser read it
+use std::path::PathBuf;
+
+use tracing::span::EnteredSpan;
+
+use crate::exp::{LispExp, LispLambda};
+use crate::span;
+use crate::symbol::SymbolId;
+
+// builtins that wait on or change the world outside, which are worth a span
+const TRACED_BUILTINS: &[&str] = &[
+    "exec", "shell", "read-file", "write-file", "append-file", "read-line", "load", "sleep", "await", "pmap", "watch-path", "save-image", "checkpoint",
+];
+
+// the spans of the calls being made, innermost last; calls that don't get a
+// span have a None, so they're left in step with the interpreter's calls
+#[derive(Default)]
+pub(crate) struct Spans {
+    open: Vec<Option<EnteredSpan>>,
+}
+
+impl Spans {
+    pub(crate) fn enter(&mut self, name: Option<SymbolId>, func: Option<&LispLambda>, files: &[PathBuf]) {
+        let traced = match func {
+            Some(_) => true,
+            None => name.is_some_and(|name| TRACED_BUILTINS.contains(&&*name.name())),
+        };
+        if !traced {
+            self.open.push(None);
+            return;
+        }
+        let function = name.map_or("lambda".to_owned(), |name| name.to_string());
+        let line = func.and_then(|func| match func.body.first() {
+            Some(LispExp::List(items)) => span::span(items).map(|pos| pos.line as u64),
+            _ => None,
+        });
+        let script = files.first().map(|file| file.display().to_string());
+        let file = files.last().map(|file| file.display().to_string());
+        let span = tracing::info_span!("sxprs.call", function = function.as_str(), script, file, line);
+        self.open.push(Some(span.entered()));
+    }
+    pub(crate) fn leave(&mut self) {
+        self.open.pop();
+    }
+    // the innermost are left first, as they were entered last
+    pub(crate) fn unwind(&mut self, depth: usize) {
+        while self.open.len() > depth {
+            self.open.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::eval::Interpreter;
+
+    // the spans opened, as their fields, and how deep each was entered
+    #[derive(Default)]
+    struct Recorder {
+        spans: Mutex<Vec<String>>,
+        depth: Mutex<(usize, Vec<usize>)>,
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={value:?}", field.name()));
+        }
+    }
+
+    struct Shared(Arc<Recorder>);
+
+    impl Subscriber for Shared {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            let mut fields = Fields(attrs.metadata().name().to_owned());
+            attrs.record(&mut fields);
+            let mut spans = self.0.spans.lock().unwrap();
+            spans.push(fields.0);
+            Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {
+            let mut depth = self.0.depth.lock().unwrap();
+            depth.0 += 1;
+            let now = depth.0;
+            depth.1.push(now);
+        }
+        fn exit(&self, _: &Id) {
+            self.0.depth.lock().unwrap().0 -= 1;
+        }
+    }
+
+    #[test]
+    fn calls_are_spans() {
+        let recorder = Arc::new(Recorder::default());
+        let source = "(defn sq (x)\n  (* x x))\n(defn f (x) (sleep 0) (+ (sq x) 1))\n(f 2)\n(try (f \"a\") (catch e e))";
+        let value = tracing::subscriber::with_default(Shared(recorder.clone()), || Interpreter::new().eval_str(source).unwrap());
+        // the error was caught
+        assert!(value.get_string().is_ok());
+        let spans = recorder.spans.lock().unwrap();
+        assert_eq!(spans[..3], ["sxprs.call function=\"f\" line=3", "sxprs.call function=\"sleep\"", "sxprs.call function=\"sq\" line=2"]);
+        // every span was left, even the ones an error unwound
+        assert_eq!(recorder.depth.lock().unwrap().0, 0);
+        assert_eq!(recorder.depth.lock().unwrap().1[..3], [1, 2, 2]);
+    }
+}
@@ -0,0 +1,485 @@
+use std::rc::Rc;
+
+use crate::error::LispError;
+use crate::eval::{Interpreter, Scope};
+use crate::exp::{LispExp, LispLambda};
+use crate::symbol::{SymbolId, ELSE};
+
+#[derive(Debug)]
+pub(crate) enum Op {
+    Const(LispExp),
+    Load(SymbolId),
+    // bind the value on top of the stack, leaving it there
+    Define(SymbolId),
+    Set(SymbolId),
+    Pop,
+    Jump(usize),
+    // pops the condition
+    JumpIfFalse(usize),
+    // jumps, leaving the value on top of the stack, if its truthiness is the
+    // bool, and pops it otherwise; for `and` and `or`
+    JumpKeepingIf(bool, usize),
+    // if the callee on top of the stack is a macro, expand it with these
+    // unevaluated arguments, evaluate the expansion and jump past the call
+    CheckMacro(Vec<LispExp>, usize),
+    // the callee and that many arguments are on the stack
+    Call(usize),
+    TailCall(usize),
+    // if the program bound the builtin's name, as a parameter or a definition,
+    // evaluate the call with the tree-walker, which calls what it's bound to,
+    // and jump past the builtin's call
+    CheckBound(SymbolId, LispExp, usize),
+    CallBuiltin(SymbolId, usize),
+    // anything the compiler doesn't lower is left to the tree-walker
+    Eval(LispExp),
+    // pops one value per name and binds them in a new scope
+    EnterScope(Vec<SymbolId>),
+    ExitScope,
+    Return,
+}
+
+#[derive(Debug)]
+pub(crate) struct Chunk {
+    ops: Vec<Op>,
+}
+
+// points the jump at `at`, emitted before its target was known, at the next op
+fn patch(ops: &mut [Op], at: usize) {
+    let target = ops.len();
+    match &mut ops[at] {
+        Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpKeepingIf(_, to) | Op::CheckMacro(_, to) | Op::CheckBound(_, _, to) => *to = target,
+        _ => unreachable!("only jumps are patched"),
+    }
+}
+
+// compiles `exps` in order, leaving only the last value on the stack
+fn compile_body(env: &Interpreter, exps: &[LispExp], tail: bool, ops: &mut Vec<Op>) {
+    let Some((last, init)) = exps.split_last() else {
+        ops.push(Op::Const(LispExp::Nil));
+        return;
+    };
+    for exp in init {
+        compile(env, exp, false, ops);
+        ops.push(Op::Pop);
+    }
+    compile(env, last, tail, ops);
+}
+
+// the names and values of `((name value) ...)`, None if it's malformed
+fn let_bindings(bindings: &LispExp) -> Option<(Vec<SymbolId>, Vec<&LispExp>)> {
+    let mut names = vec![];
+    let mut values = vec![];
+    for binding in bindings.get_list().ok()? {
+        let [LispExp::Symbol(name), vl] = binding.get_list().ok()? else {
+            return None;
+        };
+        names.push(*name);
+        values.push(vl);
+    }
+    Some((names, values))
+}
+
+fn compile(env: &Interpreter, exp: &LispExp, tail: bool, ops: &mut Vec<Op>) {
+    let items = match exp {
+        LispExp::Symbol(name) => return ops.push(Op::Load(*name)),
+        LispExp::List(items) if !items.is_empty() => items,
+        // a vector or map literal is built from its evaluated items each time
+        LispExp::Vector(_) | LispExp::Map(_) => {
+            let call = exp.literal_call().unwrap_or_else(|| unreachable!("it's a literal"));
+            let (LispExp::Builtin(builtin), items) = (&call[0], &call[1..]) else {
+                unreachable!("literals call a builtin");
+            };
+            for item in items {
+                compile(env, item, false, ops);
+            }
+            return ops.push(Op::CallBuiltin(*builtin, items.len()));
+        }
+        other => return ops.push(Op::Const(other.clone())),
+    };
+    let (head, args) = items.split_first().unwrap_or_else(|| unreachable!("empty lists are constants"));
+    if let LispExp::Symbol(name) = head {
+        if env.is_special(*name) || env.is_builtin(*name) {
+            return compile_builtin(env, exp, *name, args, tail, ops);
+        }
+    }
+    compile(env, head, false, ops);
+    let check = ops.len();
+    ops.push(Op::CheckMacro(args.to_vec(), 0));
+    for arg in args {
+        compile(env, arg, false, ops);
+    }
+    ops.push(if tail { Op::TailCall(args.len()) } else { Op::Call(args.len()) });
+    patch(ops, check);
+}
+
+fn compile_builtin(env: &Interpreter, exp: &LispExp, builtin: SymbolId, args: &[LispExp], tail: bool, ops: &mut Vec<Op>) {
+    // a special form given the wrong number of arguments is left for the tree-walker to report
+    if env.is_special(builtin) && env.arity(builtin).is_some_and(|arity| arity.check(builtin, args.len()).is_err()) {
+        return ops.push(Op::Eval(exp.clone()));
+    }
+    match (&*builtin.name(), args) {
+        ("quote", [arg]) => ops.push(Op::Const(arg.clone())),
+        ("if", [cond, then, rest @ ..]) => {
+            compile(env, cond, false, ops);
+            let to_else = ops.len();
+            ops.push(Op::JumpIfFalse(0));
+            compile(env, then, tail, ops);
+            let to_end = ops.len();
+            ops.push(Op::Jump(0));
+            patch(ops, to_else);
+            match rest.first() {
+                Some(otherwise) => compile(env, otherwise, tail, ops),
+                None => ops.push(Op::Const(LispExp::Nil)),
+            }
+            patch(ops, to_end);
+        }
+        // clauses that aren't `(test body...)` lists are left for the tree-walker to report
+        ("cond", clauses) if clauses.iter().all(|clause| matches!(clause, LispExp::List(c) if !c.is_empty())) => {
+            let mut to_end = vec![];
+            let mut has_else = false;
+            for clause in clauses {
+                let (test, body) = clause.get_list().ok().and_then(<[LispExp]>::split_first).unwrap_or_else(|| unreachable!("clauses were checked"));
+                if let LispExp::Symbol(ELSE) = test {
+                    compile_body(env, body, tail, ops);
+                    has_else = true;
+                    break;
+                }
+                compile(env, test, false, ops);
+                if body.is_empty() {
+                    to_end.push(ops.len());
+                    ops.push(Op::JumpKeepingIf(true, 0));
+                    continue;
+                }
+                let to_next = ops.len();
+                ops.push(Op::JumpIfFalse(0));
+                compile_body(env, body, tail, ops);
+                to_end.push(ops.len());
+                ops.push(Op::Jump(0));
+                patch(ops, to_next);
+            }
+            if !has_else {
+                ops.push(Op::Const(LispExp::Nil));
+            }
+            for jump in to_end {
+                patch(ops, jump);
+            }
+        }
+        ("and" | "or", args) => {
+            let stop_at = &*builtin.name() == "or";
+            let Some((last, init)) = args.split_last() else {
+                return ops.push(Op::Const(LispExp::Bool(!stop_at)));
+            };
+            let mut jumps = vec![];
+            for arg in init {
+                compile(env, arg, false, ops);
+                jumps.push(ops.len());
+                ops.push(Op::JumpKeepingIf(stop_at, 0));
+            }
+            compile(env, last, tail, ops);
+            for jump in jumps {
+                patch(ops, jump);
+            }
+        }
+        // one with a docstring is left to the tree-walker, which keeps it
+        ("define", [LispExp::Symbol(name), vl]) if !env.is_special(*name) => {
+            compile(env, vl, false, ops);
+            ops.push(Op::Define(*name));
+        }
+        ("set!", [LispExp::Symbol(name), vl, ..]) => {
+            compile(env, vl, false, ops);
+            ops.push(Op::Set(*name));
+        }
+        // the last body value stays on the stack, nil until the body runs
+        ("while", [cond, body @ ..]) => {
+            ops.push(Op::Const(LispExp::Nil));
+            let start = ops.len();
+            compile(env, cond, false, ops);
+            let to_end = ops.len();
+            ops.push(Op::JumpIfFalse(0));
+            ops.push(Op::Pop);
+            compile_body(env, body, false, ops);
+            ops.push(Op::Jump(start));
+            patch(ops, to_end);
+        }
+        ("let", [bindings, body @ ..]) if !body.is_empty() => {
+            let Some((names, values)) = let_bindings(bindings) else {
+                return ops.push(Op::Eval(exp.clone()));
+            };
+            for vl in values {
+                compile(env, vl, false, ops);
+            }
+            ops.push(Op::EnterScope(names));
+            compile_body(env, body, false, ops);
+            ops.push(Op::ExitScope);
+        }
+        ("begin" | "do", body) if !body.is_empty() => {
+            ops.push(Op::EnterScope(vec![]));
+            compile_body(env, body, false, ops);
+            ops.push(Op::ExitScope);
+        }
+        // special forms the compiler doesn't lower are left to the tree-walker
+        _ if env.is_special(builtin) => ops.push(Op::Eval(exp.clone())),
+        (_, args) => {
+            let check = ops.len();
+            ops.push(Op::CheckBound(builtin, exp.clone(), 0));
+            for arg in args {
+                compile(env, arg, false, ops);
+            }
+            ops.push(Op::CallBuiltin(builtin, args.len()));
+            patch(ops, check);
+        }
+    }
+}
+
+// the lambda's body, compiled the first time the VM calls it
+fn code(env: &Interpreter, func: &LispLambda) -> Rc<Chunk> {
+    func.code
+        .get_or_init(|| {
+            let mut ops = vec![];
+            compile_body(env, &func.body, true, &mut ops);
+            ops.push(Op::Return);
+            Rc::new(Chunk { ops })
+        })
+        .clone()
+}
+
+struct Frame {
+    chunk: Rc<Chunk>,
+    pc: usize,
+    // stack height when the frame was entered
+    base: usize,
+    scope: Rc<Scope>,
+    // scopes to go back to when the `let`s in this frame end
+    saved: Vec<Rc<Scope>>,
+}
+
+struct Vm<'a> {
+    env: &'a Interpreter,
+    stack: Vec<LispExp>,
+    frames: Vec<Frame>,
+    // how many calls the interpreter was in when the VM started
+    base_calls: usize,
+}
+
+impl Vm<'_> {
+    fn pop(&mut self) -> LispExp {
+        self.stack.pop().unwrap_or_else(|| unreachable!("the compiler balances the stack"))
+    }
+    fn pop_n(&mut self, n: usize) -> Vec<LispExp> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap_or_else(|| unreachable!("ops only run inside a frame"))
+    }
+    fn enter_scope(&mut self, scope: Rc<Scope>) {
+        self.env.swap_scope(scope.clone());
+        self.frame().scope = scope;
+    }
+    // binds the arguments and runs the lambda's body, in place of the current
+    // frame for tail calls so they don't grow the frame stack
+    fn call(&mut self, func: &LispLambda, args: Vec<LispExp>, tail: bool) -> Result<(), LispError> {
+        let scope = func.bind(self.env, args)?;
+        let chunk = code(self.env, func);
+        let name = func.name.get().copied();
+        if tail {
+            // the top-level frame isn't a call, so there is nothing to replace
+            if self.env.call_depth() > self.base_calls {
+                self.env.replace_call(name, Some(func));
+            } else {
+                self.env.enter_call(name, Some(func));
+            }
+            let base = self.frame().base;
+            self.stack.truncate(base);
+            *self.frame() = Frame { chunk, pc: 0, base, scope: scope.clone(), saved: vec![] };
+        } else {
+            if self.frames.len() >= self.env.max_depth() {
+                return Err(LispError::TooDeep(self.env.max_depth()));
+            }
+            let base = self.stack.len();
+            self.env.enter_call(name, Some(func));
+            self.frames.push(Frame { chunk, pc: 0, base, scope: scope.clone(), saved: vec![] });
+        }
+        self.env.swap_scope(scope);
+        Ok(())
+    }
+    fn run(&mut self) -> Result<LispExp, LispError> {
+        loop {
+            self.env.step()?;
+            let frame = self.frame();
+            let chunk = frame.chunk.clone();
+            let op = &chunk.ops[frame.pc];
+            frame.pc += 1;
+            match op {
+                Op::Const(vl) => self.stack.push(vl.clone()),
+                Op::Load(name) => self.stack.push(self.env.resolve(*name)?),
+                Op::Define(name) => {
+                    let vl = self.pop();
+                    self.env.define(*name, vl.clone());
+                    self.stack.push(vl);
+                }
+                Op::Set(name) => {
+                    let vl = self.pop();
+                    if !self.env.current_scope().set(*name, vl.clone()) {
+                        return Err(LispError::UnknownSymbol(name.to_string()));
+                    }
+                    self.stack.push(vl);
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Jump(to) => self.frame().pc = *to,
+                Op::JumpIfFalse(to) => {
+                    if !self.pop().is_truthy() {
+                        self.frame().pc = *to;
+                    }
+                }
+                Op::JumpKeepingIf(stop_at, to) => {
+                    if self.stack.last().is_some_and(|vl| vl.is_truthy() == *stop_at) {
+                        self.frame().pc = *to;
+                    } else {
+                        self.pop();
+                    }
+                }
+                Op::CheckMacro(args, to) => {
+                    if let Some(LispExp::Macro(mac)) = self.stack.last() {
+                        let mac = mac.clone();
+                        self.pop();
+                        let expansion = self.env.call(&mac, args.clone())?;
+                        let vl = self.env.value(&expansion)?;
+                        self.stack.push(vl);
+                        self.frame().pc = *to;
+                    }
+                }
+                Op::Call(argc) | Op::TailCall(argc) => {
+                    let args = self.pop_n(*argc);
+                    match self.pop() {
+                        LispExp::Lambda(func) => self.call(&func, args, matches!(op, Op::TailCall(_)))?,
+                        builtin @ LispExp::Builtin(_) => self.stack.push(self.env.apply(&builtin, args)?),
+                        head if args.is_empty() => self.stack.push(head),
+                        other => return Err(other.mismatch("function")),
+                    }
+                }
+                Op::CheckBound(name, exp, to) => {
+                    if self.env.current_scope().binds(*name) {
+                        let vl = self.env.value(exp)?;
+                        self.stack.push(vl);
+                        self.frame().pc = *to;
+                    }
+                }
+                Op::CallBuiltin(name, argc) => {
+                    let args = self.pop_n(*argc);
+                    self.stack.push(self.env.apply_builtin(*name, args)?);
+                }
+                Op::Eval(exp) => self.stack.push(self.env.value(exp)?),
+                Op::EnterScope(names) => {
+                    let values = self.pop_n(names.len());
+                    let scope = Scope::child(&self.frame().scope);
+                    for (name, vl) in names.iter().zip(values) {
+                        scope.insert(*name, vl);
+                    }
+                    let outer = self.frame().scope.clone();
+                    self.frame().saved.push(outer);
+                    self.enter_scope(scope);
+                }
+                Op::ExitScope => {
+                    let outer = self.frame().saved.pop().unwrap_or_else(|| unreachable!("scopes are balanced"));
+                    self.enter_scope(outer);
+                }
+                Op::Return => {
+                    let vl = self.pop();
+                    let Some(frame) = self.frames.pop() else { unreachable!() };
+                    self.stack.truncate(frame.base);
+                    let Some(caller) = self.frames.last() else {
+                        return Ok(vl);
+                    };
+                    self.env.exit_call();
+                    self.env.swap_scope(caller.scope.clone());
+                    self.stack.push(vl);
+                }
+            }
+        }
+    }
+}
+
+// compiles `exp` to bytecode and runs it, leaving the current scope as it found it
+pub(crate) fn run(env: &Interpreter, exp: &LispExp) -> Result<LispExp, LispError> {
+    let mut ops = vec![];
+    compile(env, exp, true, &mut ops);
+    ops.push(Op::Return);
+    let scope = env.current_scope();
+    let depth = env.call_depth();
+    let mut vm = Vm {
+        env,
+        base_calls: depth,
+        stack: vec![],
+        frames: vec![Frame { chunk: Rc::new(Chunk { ops }), pc: 0, base: 0, scope: scope.clone(), saved: vec![] }],
+    };
+    let res = vm.run().map_err(|err| env.backtrace(err));
+    env.unwind_calls(depth);
+    env.swap_scope(scope);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what `source` evaluates to on the VM, checked against the tree-walker
+    fn run_both(source: &str) -> String {
+        let show = |vm: bool| {
+            let mut lisp = Interpreter::new();
+            lisp.set_vm(vm);
+            match lisp.eval_str(source) {
+                Ok(vl) => vl.to_string(),
+                Err(err) => format!("error: {}", err.summary()),
+            }
+        };
+        let on_vm = show(true);
+        assert_eq!(on_vm, show(false), "{source}");
+        on_vm
+    }
+
+    #[test]
+    fn matches_the_tree_walker() {
+        assert_eq!(run_both("(defn fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))) (fib 15)"), "610");
+        assert_eq!(run_both("(cond ((= 1 2) 'a) ((+ 1 1)) (else 'c))"), "2");
+        assert_eq!(run_both("(list (and 1 nil 2) (or nil 3) (and) (or))"), "( nil 3 true false )");
+        assert_eq!(run_both("(let ((x 1) (y 2)) (begin (define z 3) (+ x y z)))"), "6");
+        assert_eq!(run_both("(define i 0) (define total 0) (while (< i 10) (set! total (+ total i)) (set! i (+ i 1))) total"), "45");
+        assert_eq!(run_both("(defmacro twice (x) `(begin ~x ~x)) (define n 0) (twice (set! n (+ n 1))) n"), "2");
+        assert_eq!(run_both("((lambda (a &optional (b 2) &rest r) (list a b r)) 1)"), "( 1 2 (  ) )");
+        assert_eq!(run_both("(map (lambda (x) (* x x)) '(1 2 3))"), "( 1 4 9 )");
+    }
+
+    #[test]
+    fn errors_match() {
+        assert_eq!(run_both("(car 5)"), "error: 5 is not a List, it's a Int");
+        assert_eq!(run_both("(undefined 1)"), "error: symbol undefined is not defined");
+        assert_eq!(run_both("(5 1)"), "error: 5 is not a function, it's a Int");
+        assert_eq!(run_both("(if)"), "error: `if` expects between 2 and 3 arguments, got 0");
+        assert_eq!(run_both("(set! nope 1)"), "error: symbol nope is not defined");
+    }
+
+    #[test]
+    fn tail_calls_and_depth() {
+        assert_eq!(run_both("(defn count (n) (if (= n 0) 'done (count (- n 1)))) (count 100000)"), "done");
+        let mut lisp = Interpreter::with_limits(10_000_000, 100, 1 << 20);
+        lisp.set_vm(true);
+        let res = lisp.eval_str("(defn sum (n) (if (= n 0) 0 (+ n (sum (- n 1))))) (sum 1000)");
+        assert!(matches!(res.map_err(|err| err.root().clone()), Err(LispError::TooDeep(100))));
+    }
+
+    #[test]
+    fn backtraces_name_the_calls() {
+        let mut lisp = Interpreter::new();
+        lisp.set_vm(true);
+        let res = lisp.eval_str("(defn inner (x) (car x)) (defn outer (x) (+ 1 (inner x))) (outer 5)");
+        let LispError::Backtrace { calls, .. } = res.unwrap_err() else {
+            panic!("expected a backtrace");
+        };
+        assert_eq!(calls, ["car", "inner", "outer"]);
+        // the scope and calls are as they were before the failed form
+        assert_eq!(lisp.eval_str("(list (outer '(1)) x)").map_err(|err| err.summary()).unwrap_err(), "symbol x is not defined");
+        assert_eq!(lisp.call_depth(), 0);
+    }
+}
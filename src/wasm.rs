@@ -0,0 +1,19 @@
+// the browser-facing API, for playgrounds built with wasm-bindgen
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::eval::Interpreter;
+use crate::platform::take_output;
+
+// runs `src` in a fresh interpreter, returning what it printed followed by
+// its value, or by the error that stopped it
+#[wasm_bindgen]
+pub fn eval_string(src: &str) -> String {
+    let lisp = Interpreter::new();
+    let res = lisp.eval_str(src);
+    let mut out = take_output();
+    match res {
+        Ok(vl) => out.push_str(&vl.to_string()),
+        Err(err) => out.push_str(&err.to_string()),
+    }
+    out
+}
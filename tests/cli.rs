@@ -0,0 +1,584 @@
+// end-to-end runs of the `sxprs` binary: its subcommands, flags and exit codes
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+struct Run {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+// the binary, without the user's own init.lsp
+fn command(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sxprs"));
+    command.args(args).env("NO_COLOR", "1").env("XDG_CONFIG_HOME", scratch("no-config"));
+    command
+}
+
+fn run(mut command: Command, input: &str) -> Run {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    Run {
+        code: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+fn sxprs_with_input(args: &[&str], input: &str) -> Run {
+    run(command(args), input)
+}
+
+fn sxprs(args: &[&str]) -> Run {
+    sxprs_with_input(args, "")
+}
+
+// a path in cargo's scratch directory; every test names its own, so they
+// can run at the same time
+fn scratch(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+fn file(name: &str, contents: &str) -> PathBuf {
+    let path = scratch(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn runs_expressions_files_and_stdin() {
+    let run = sxprs(&["-e", "(+ 1 2)"]);
+    assert_eq!((run.code, &run.stdout[..]), (0, "3\n"));
+    let run = sxprs_with_input(&["-"], "(print (+ 1 2))");
+    assert_eq!((run.code, &run.stdout[..]), (0, "3\n"));
+    let script = file("args.lsp", "#!/usr/bin/env sxprs\n(print (car argv))\n(exit 3)\n");
+    let run = sxprs(&[script.to_str().unwrap(), "hi"]);
+    assert_eq!((run.code, &run.stdout[..]), (3, "\"hi\"\n"));
+}
+
+#[test]
+fn reports_errors() {
+    let run = sxprs(&["-e", "(car 1)"]);
+    assert_eq!(run.code, 1);
+    assert_eq!(run.stderr, "Lisp Processing Error: 1 is not a List, it's a Int\n  at 1:1\n  in `car`\n  called from top-level\n  see `sxprs explain E0101`\n");
+    let run = sxprs(&["--error-format=json", "-e", "(car 1)"]);
+    assert_eq!(
+        run.stderr,
+        "{\"severity\":\"error\",\"code\":\"type-mismatch\",\"id\":\"E0101\",\"message\":\"1 is not a List, it's a Int\",\"file\":\"<expr>\",\"span\":{\"line\":1,\"col\":1},\"backtrace\":[\"car\"]}\n"
+    );
+    let run = sxprs(&["--lang", "pt", "-e", "(car 1)"]);
+    assert_eq!(run.stderr, "Erro de processamento Lisp: 1 não é um List, é um Int\n  em 1:1\n  em `car`\n  chamado do nível superior\n  veja `sxprs explain E0101`\n");
+    // what the program raises is explained by its own message
+    assert!(!sxprs(&["-e", "(error \"stop\")"]).stderr.contains("explain"));
+    // the JSON stays in English, for tools
+    let run = sxprs(&["--lang", "pt", "--error-format=json", "-e", "(car 1)"]);
+    assert!(run.stderr.contains("\"message\":\"1 is not a List, it's a Int\""), "{}", run.stderr);
+    let path = file("unclosed.lsp", "(car 1");
+    let run = sxprs(&["check", "--lang", "pt", path.to_str().unwrap()]);
+    assert_eq!(run.stderr, format!("{}:1:1: não foi encontrado o `)` que fecha a lista [E0002]\n", path.display()));
+    assert_eq!(sxprs(&["--lang", "fr", "-e", "1"]).stderr, "unknown language fr, expected en or pt\n");
+    let run = sxprs(&["--frobnicate"]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.contains("unknown argument --frobnicate"));
+}
+
+#[test]
+fn vm_and_optimizer_agree() {
+    let source = "(defn fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))) (list (fib 15) (* 2 (+ 3 4)))";
+    for flags in [&[][..], &["--vm"], &["--opt"], &["--vm", "--opt"]] {
+        let run = sxprs(&[flags, &["-e", source]].concat());
+        assert_eq!(run.stdout, "( 610 14 )\n", "{flags:?}");
+    }
+    assert_eq!(sxprs(&["--trace", "--vm", "-e", "1"]).code, 1);
+}
+
+#[test]
+fn trace_and_profile() {
+    let run = sxprs(&["--trace", "-e", "((lambda (x) (+ x 1)) 1)"]);
+    assert_eq!(run.stdout, "2\n");
+    assert!(run.stderr.starts_with("( ( lambda ( x ) ( + x 1 ) ) 1 )\n"), "{}", run.stderr);
+    assert!(run.stderr.ends_with("=> 2\n"), "{}", run.stderr);
+    let run = sxprs(&["--profile", "-e", "(defn sq (x) (* x x)) (sq (sq 2))"]);
+    assert_eq!(run.stdout, "16\n");
+    assert!(run.stderr.contains("function"), "{}", run.stderr);
+    assert!(run.stderr.lines().any(|line| line.trim_start().starts_with('2') && line.ends_with(" sq")), "{}", run.stderr);
+}
+
+#[test]
+fn prelude_can_be_left_out() {
+    assert_eq!(sxprs(&["-e", "(second (list 1 2))"]).stdout, "2\n");
+    let run = sxprs(&["--no-prelude", "-e", "(second (list 1 2))"]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.contains("symbol second is not defined"));
+}
+
+#[test]
+fn check_and_dump_ast() {
+    let run = sxprs(&["--check", "-e", "(a"]);
+    assert_eq!((run.code, &run.stderr[..]), (1, "<expr>:1:1: could not find closing `)` [E0002]\n"));
+    assert_eq!(sxprs(&["--check", "-e", "(undefined-thing)"]).code, 0);
+    let run = sxprs(&["--dump-ast", "-e", "(a 1 \"s\")"]);
+    assert_eq!(
+        run.stdout,
+        "[{\"type\":\"List\",\"items\":[{\"type\":\"Symbol\",\"value\":\"a\"},{\"type\":\"Int\",\"value\":1},{\"type\":\"String\",\"value\":\"s\"}]}]\n"
+    );
+    let program = file("types.lsp", "(defn f (x) (+ x \"a\"))\n(g 1)\n");
+    let run = sxprs(&["check", "--types", program.to_str().unwrap()]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.contains("in `f`: `+` takes numbers, but is given the String \"a\""), "{}", run.stderr);
+    assert!(run.stderr.contains("`g` is called, but it isn't defined"), "{}", run.stderr);
+    assert_eq!(sxprs(&["check", program.to_str().unwrap()]).code, 0);
+    // a file being edited is still checked past what can't be read
+    let editing = file("editing.lsp", "(defn f (x) (+ x #q))\n(g 1))\n(defn h ()\n  (k 2)\n");
+    let run = sxprs(&["check", "--types", editing.to_str().unwrap()]);
+    assert_eq!(run.code, 1);
+    let lines: Vec<&str> = run.stderr.lines().map(|line| line.trim_start_matches(editing.to_str().unwrap())).collect();
+    assert_eq!(lines[0], ":1:18: unknown literal `#q`, expected #t, #f, #nil, a character like #\\a or a #| comment |# [E0004]");
+    assert_eq!(lines[1..], [":2:6: unexpected `)` [E0001]", ":3:1: could not find closing `)` [E0002]", ": `g` is called, but it isn't defined [E0401]", ": in `h`: `k` is called, but it isn't defined [E0401]"]);
+}
+
+#[test]
+fn fmt() {
+    let messy = file("messy.lsp", "#!/usr/bin/env sxprs\n(define  x   1) ; keep\n(defn f (a) (+ a\n1))\n");
+    let run = sxprs(&["fmt", messy.to_str().unwrap()]);
+    assert_eq!(run.stdout, "#!/usr/bin/env sxprs\n(define x 1) ; keep\n(defn f (a) (+ a 1))\n");
+    assert_eq!(sxprs(&["fmt", "--check", messy.to_str().unwrap()]).code, 1);
+    let tidy = file("tidy.lsp", &run.stdout);
+    assert_eq!(sxprs(&["fmt", "--check", tidy.to_str().unwrap()]).code, 0);
+    let run = sxprs_with_input(&["fmt", "-"], "(a (b");
+    assert_eq!(run.code, 1);
+}
+
+#[test]
+fn test_command() {
+    let tests = file("tests.lsp", "(deftest ok (assert-eq 1 1))\n(deftest bad (assert-eq 1 2))\n");
+    let run = sxprs(&["test", tests.to_str().unwrap()]);
+    assert_eq!(run.code, 1);
+    assert!(run.stdout.starts_with("test ok ... ok\ntest bad ... FAILED\n    assertion ( assert-eq 1 2 ) failed: 1 is 1, but 2 is 2\n"), "{}", run.stdout);
+    assert!(run.stdout.ends_with("\ntest result: FAILED. 1 passed; 1 failed\n"), "{}", run.stdout);
+    let passing = file("passing.lsp", "(deftest ok (assert (= 1 1)))\n");
+    let run = sxprs(&["test", passing.to_str().unwrap()]);
+    assert_eq!(run.code, 0);
+    assert!(run.stdout.ends_with("test result: ok. 1 passed; 0 failed\n"), "{}", run.stdout);
+}
+
+#[test]
+fn query() {
+    let program = "(define x 1)\n(defn f (a) (+ a 1))\n";
+    let run = sxprs_with_input(&["query", "defn"], program);
+    assert_eq!((run.code, &run.stdout[..]), (0, "(defn f (a) (+ a 1))\n"));
+    assert_eq!(sxprs_with_input(&["query", "nothing"], program).code, 1);
+}
+
+#[test]
+fn images() {
+    let image = scratch("image.lsp");
+    let image = image.to_str().unwrap();
+    let save = format!("(define n 5) (defn sq (x) (* x x)) (save-image \"{image}\")");
+    assert_eq!(sxprs(&["-e", &save]).code, 0);
+    let run = sxprs(&["--load-image", image, "-e", "(sq n)"]);
+    assert_eq!((run.code, &run.stdout[..]), (0, "25\n"));
+    let run = sxprs(&["--load-image", image, "--vm", "-e", "(sq n)"]);
+    assert_eq!(run.stdout, "25\n");
+}
+
+#[test]
+fn bundle() {
+    file("bundled-lib.lsp", "(define more 7)\n");
+    let main = file("bundled-main.lsp", "(load \"bundled-lib.lsp\")\n(print (* 2 more))\n");
+    let run = sxprs(&["bundle", main.to_str().unwrap()]);
+    assert_eq!((run.code, &run.stdout[..]), (0, "; bundled-lib.lsp\n(define more 7)\n(print (* 2 more))\n"));
+    let run = sxprs_with_input(&["-"], &run.stdout);
+    assert_eq!(run.stdout, "14\n");
+}
+
+#[test]
+fn attach() {
+    let mut host = command(&["--listen", "127.0.0.1:0", "-e", "(define stop false) (define n 5) (while (not stop) (sleep 5)) (print n)"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut listening = String::new();
+    BufReader::new(host.stderr.take().unwrap()).read_line(&mut listening).unwrap();
+    let addr = listening.trim_end().strip_prefix("listening for `sxprs attach` on ").unwrap();
+    let run = sxprs_with_input(&["attach", addr], "(* n 2)\n(print \"hi\")\n:env\n(car 1)\n(set! n 6)\n(set! stop true)\n");
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert_eq!(run.stdout, "10\n\"hi\"\nn = 5\nstop = false\n6\ntrue\n");
+    assert!(run.stderr.starts_with("1 is not a List, it's a Int\n"), "{}", run.stderr);
+    let output = host.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\nnil\n");
+    assert_eq!(sxprs(&["attach"]).stderr, "attach expects an address\n");
+}
+
+#[test]
+fn kernel() {
+    let requests = [
+        r#"{"op":"execute","code":"(define n 2) (print n) (* n 3)"}"#,
+        r#"{"op":"execute","code":"(/ n 0)"}"#,
+        r#"{"op":"is_complete","code":"(print"}"#,
+        r#"{"op":"shutdown"}"#,
+    ];
+    let run = sxprs_with_input(&["kernel"], &requests.join("\n"));
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let replies: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(replies[0], r#"{"stdout":"2\n","stderr":"","status":"ok","value":"6"}"#);
+    assert!(replies[1].starts_with(r#"{"stdout":"","stderr":"","status":"error","ename":"#), "{}", replies[1]);
+    assert_eq!(replies[2..], [r#"{"status":"incomplete"}"#, r#"{"status":"ok"}"#]);
+    assert_eq!(sxprs(&["kernel", "x"]).stderr, "unexpected argument x\n");
+}
+
+#[test]
+fn embed() {
+    file("embedded-lib.lsp", "(define more 7)\n");
+    let main = file("embedded-main.lsp", "(load \"embedded-lib.lsp\")\n(print (* 2 more))\n");
+    let run = sxprs(&["embed", main.to_str().unwrap()]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert!(run.stdout.contains("const SOURCE: &str = r#\"; embedded-lib.lsp\n(define more 7)\n(print (* 2 more))\n\"#;\n"), "{}", run.stdout);
+    let out = scratch("embedded.rs");
+    assert_eq!(sxprs(&["embed", main.to_str().unwrap(), "-o", out.to_str().unwrap()]).stdout, "");
+    assert_eq!(std::fs::read_to_string(out).unwrap(), run.stdout);
+    assert_eq!(sxprs(&["embed"]).stderr, "embed expects a file\n");
+}
+
+#[test]
+fn audit() {
+    file("audited-lib.lsp", "(defn run (cmd)\n  (exec cmd))\n");
+    let main = file("audited-main.lsp", "(load \"audited-lib.lsp\")\n(print (getenv \"HOME\"))\n");
+    let run = sxprs(&["audit", main.to_str().unwrap()]);
+    let lib = scratch("audited-lib.lsp");
+    let expected = format!(
+        "{main}:1:1: fs-read (load \"audited-lib.lsp\")\n{lib}:2:3: exec (exec cmd)\n{main}:2:8: env (getenv \"HOME\")\nneeds env, exec, fs-read\n",
+        main = main.display(),
+        lib = lib.display()
+    );
+    assert_eq!((run.code, &run.stdout[..]), (0, &expected[..]));
+    let run = sxprs(&["audit", file("audited-none.lsp", "(+ 1 2)").to_str().unwrap()]);
+    assert_eq!(run.stdout, "needs nothing outside the interpreter\n");
+}
+
+#[test]
+fn rename() {
+    let lib = file("renamed/lib.lsp", "; counts\n(defn tally (xs) (length xs))\n");
+    let main = file("renamed/main.lsp", "(load \"lib.lsp\")\n(print (tally '(1 2)))   ; two\n");
+    let run = sxprs(&["rename", "--dry-run", "tally", "count-of", main.to_str().unwrap()]);
+    assert_eq!(run.stdout, format!("{}:2:9: tally -> count-of\n{}:2:7: tally -> count-of\n", main.display(), lib.display()));
+    let run = sxprs(&["rename", "tally", "count-of", main.to_str().unwrap()]);
+    assert_eq!(run.stdout, format!("{}: renamed 1 place\n{}: renamed 1 place\n", main.display(), lib.display()));
+    assert_eq!(std::fs::read_to_string(&main).unwrap(), "(load \"lib.lsp\")\n(print (count-of '(1 2)))   ; two\n");
+    assert_eq!(std::fs::read_to_string(&lib).unwrap(), "; counts\n(defn count-of (xs) (length xs))\n");
+    assert_eq!(sxprs(&[main.to_str().unwrap()]).stdout, "2\n");
+    let run = sxprs(&["rename", "tally", "x", main.to_str().unwrap()]);
+    assert_eq!((run.code, run.stderr), (1, format!("`tally` isn't defined with define, defn or defmacro in {} or the files it loads\n", main.display())));
+}
+
+#[test]
+fn extract() {
+    let source = "(defn area (w h)\n  (* w h 1/2))\n(print (area 3 4))\n";
+    let path = file("extracted.lsp", source);
+    let at = path.to_str().unwrap();
+    let run = sxprs(&["extract", at, "--span", "2:3..2:14", "--name", "half", "--dry-run"]);
+    assert_eq!(run.stdout, "(defn half (w h) (* w h 1/2))\n(defn area (w h)\n  (half w h))\n(print (area 3 4))\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), source);
+    let run = sxprs(&["extract", at, "--span", "19..30", "--name", "half"]);
+    assert_eq!(run.stdout, format!("{at}: extracted half, called as (half w h)\n"));
+    assert_eq!(sxprs(&[at]).stdout, "6\n");
+    let run = sxprs(&["extract", at, "--span", "1:7..1:11", "--name", "g"]);
+    assert_eq!((run.code, run.stderr), (1, format!("{at}:1:7: the selection is data or a name being bound, not code that runs\n")));
+}
+
+#[test]
+fn bench_compare() {
+    let fib = file("bench-fib.lsp", "(defn fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))\n(fib 10)\n");
+    let fib = fib.to_str().unwrap();
+    let run = sxprs(&["bench", fib, "--engine", "tree", "--compare", "--runs", "2"]);
+    let lines: Vec<&str> = run.stderr.lines().collect();
+    assert_eq!((run.code, lines.len()), (0, 3), "{}", run.stderr);
+    assert!(lines[0].starts_with("tree  2 runs: min ") && lines[1].starts_with("vm    2 runs: min "), "{}", run.stderr);
+    // what the runs really allocated, which a recursive function without lists still does
+    for line in &lines[..2] {
+        let (bytes, allocations) = line.rsplit_once(", ").unwrap().1.trim_end_matches(" allocations").split_once(" bytes in ").unwrap();
+        assert!(bytes.parse::<usize>().unwrap() > 0 && allocations.parse::<usize>().unwrap() > 0, "{line}");
+    }
+    assert!(lines[2].starts_with("vm    ") && lines[2].ends_with("x the time of tree, on average"), "{}", run.stderr);
+    // the ratio is against the engine picked
+    let run = sxprs(&["bench", fib, "--engine", "vm", "--compare", "--runs", "1"]);
+    assert!(run.stderr.ends_with("x the time of vm, on average\n"), "{}", run.stderr);
+    let run = sxprs(&["bench", "--runs", "2", "-e", "(+ 1 2)"]);
+    assert!(run.stderr.starts_with("2 runs: min ") && run.stderr.ends_with(" allocations\n"), "{}", run.stderr);
+    assert_eq!(sxprs(&["bench", fib, "more"]).stderr, "unexpected argument more\n");
+    assert_eq!(sxprs(&["bench"]).stderr, "bench expects a file, - or -e\n");
+    let run = sxprs(&["--bench", "2", "--compare", "-e", "(map (lambda (x) (* x x)) (range 0 10))"]);
+    assert_eq!((run.code, run.stderr.lines().count()), (0, 3), "{}", run.stderr);
+    assert_eq!(sxprs(&["--compare", "-e", "1"]).stderr, "--compare expects bench or --bench\n");
+    assert_eq!(sxprs(&["--engine", "vm", "-e", "(+ 1 2)"]).stdout, "3\n");
+    assert_eq!(sxprs(&["--engine", "jit", "-e", "1"]).stderr, "--engine expects tree or vm\n");
+}
+
+#[test]
+fn examples() {
+    let list = sxprs(&["examples"]).stdout;
+    assert!(list.starts_with("math\n  fib      fibonacci numbers, past 64 bits\n"), "{list}");
+    assert!(list.contains("\nhttp\n  fetch    fetching a page with curl\n"), "{list}");
+    assert_eq!(sxprs(&["examples", "caesar"]).stdout, "Khoor, Zruog!\nHello, World!\n");
+    assert!(sxprs(&["examples", "fib"]).stdout.ends_with("fib 150 = 9969216677189303386214405760200\n"));
+    assert_eq!(sxprs(&["examples", "primes"]).stdout.lines().last(), Some("25 primes below 100, the largest 97"));
+    assert_eq!(sxprs(&["examples", "stats"]).stdout, "mean 13.85\nstddev 0.93\n");
+    assert_eq!(sxprs(&["examples", "words"]).stdout, "11 words, 9 different\n\"the\" is there 3 times\n");
+    let dir = scratch("examples-tmp");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut notes = command(&["examples", "notes"]);
+    notes.env("TMPDIR", &dir);
+    let run = run(notes, "");
+    assert_eq!(run.stdout, format!("2 notes in {}/sxprs-notes.txt:\n- buy milk\n- water the plants\n", dir.display()));
+    assert!(sxprs(&["examples", "--show", "fetch"]).stdout.contains("(exec \"curl\""));
+    let run = sxprs(&["examples", "nope"]);
+    assert_eq!((run.code, run.stderr), (1, "there's no example nope; `sxprs examples` lists them\n".to_owned()));
+}
+
+#[test]
+fn learn() {
+    let home = scratch("learn-home");
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(&home).unwrap();
+    let learn = |args: &[&str], input: &str| {
+        let mut command = command(&[&["learn"], args].concat());
+        command.env("HOME", &home);
+        run(command, input)
+    };
+    let run = learn(&[], "(+ 1 1)\n:hint\n(+ 40\n  2)\n:skip\n(car 1)\n");
+    assert!(run.stdout.contains("1/10 Calling functions\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("Add 40 and 2.\n2\nThe function is `+`"), "{}", run.stdout);
+    assert!(run.stdout.contains("42\nthat's it!\n\n2/10 Nesting"), "{}", run.stdout);
+    assert!(run.stdout.contains("3/10 Variables"), "{}", run.stdout);
+    assert!(run.stderr.contains("1 is not a List"), "{}", run.stderr);
+    // it picks up at the lesson that wasn't done
+    assert_eq!(std::fs::read_to_string(home.join(".sxprs_learn")).unwrap(), "2\n");
+    let run = learn(&[], ":answer\n(define answer 42)\n:quit\n");
+    assert!(run.stdout.contains("3/10 Variables"), "{}", run.stdout);
+    assert!(run.stdout.contains("(define answer 42)\n42\nthat's it!\n\n4/10 Functions"), "{}", run.stdout);
+    let run = learn(&["10"], "(defmacro my-unless (test &rest body) `(if ~test nil (begin ~@body)))\n");
+    assert!(run.stdout.ends_with("that was the last lesson; the README has the rest of the language\n"), "{}", run.stdout);
+    assert_eq!(learn(&[], "").stdout, "you've done every lesson; `sxprs learn --reset` starts over\n");
+    assert!(learn(&["--reset"], "").stdout.contains("1/10 Calling functions"));
+    assert_eq!(learn(&["11"], "").stderr, "learn expects a lesson from 1 to 10\n");
+}
+
+#[test]
+fn explain() {
+    let run = sxprs(&["explain", "E0105"]);
+    assert!(run.stdout.starts_with("E0105 division-by-zero: division by zero\n\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("(/ 1 0)"));
+    assert_eq!(sxprs(&["explain", "unknown-symbol"]).stdout.lines().next(), Some("E0103 unknown-symbol: a name that isn't defined"));
+    let list = sxprs(&["explain"]).stdout;
+    assert!(list.starts_with("E0001 unexpected-token   a token where it can't be\n"), "{list}");
+    assert!(list.lines().any(|line| line.starts_with("E0401 check")));
+    let run = sxprs(&["explain", "E9999"]);
+    assert_eq!((run.code, run.stderr), (1, "there's no error E9999; `sxprs explain` lists them\n".to_owned()));
+}
+
+#[test]
+fn expand() {
+    let path = file("expanded.lsp", "(defmacro twice (x) `(+ ~x ~x))\n(print \"starting\")\n(print (twice (* 2 3)))\n");
+    let at = path.to_str().unwrap();
+    let run = sxprs(&["expand", at]);
+    assert_eq!(run.stdout, format!("; {at}:1:1\n(defmacro twice (x) `(+ ~x ~x))\n\n; {at}:2:1\n(print \"starting\")\n\n; {at}:3:1, expands twice at 3:8\n(print (+ (* 2 3) (* 2 3)))\n"));
+    let run = sxprs(&["expand", "--opt", at]);
+    assert!(run.stdout.ends_with("twice at 3:8\n(print 12)\n"), "{}", run.stdout);
+    let path = file("expanded-error.lsp", "(defmacro bad () (car 1))\n(bad)\n");
+    let at = path.to_str().unwrap();
+    let run = sxprs(&["expand", at]);
+    assert_eq!((run.code, run.stdout.is_empty()), (1, true));
+    assert!(run.stderr.starts_with(&format!("{at}:2:1: expanding bad: ")), "{}", run.stderr);
+}
+
+#[cfg(feature = "graphics")]
+#[test]
+fn graphics() {
+    let dir = scratch("graphics");
+    std::fs::create_dir_all(&dir).unwrap();
+    let square = file("graphics/square.lsp", "(dotimes (i 4) (forward 10) (turn 90))\n(render \"square.svg\")\n");
+    let mut run_square = command(&[square.to_str().unwrap()]);
+    run_square.current_dir(&dir);
+    assert_eq!(run(run_square, "").code, 0);
+    let svg = std::fs::read_to_string(dir.join("square.svg")).unwrap();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-10 -20 30 30\""), "{svg}");
+    assert_eq!(svg.matches("<line").count(), 4);
+    let mut life = command(&["examples", "life"]);
+    life.env("TMPDIR", &dir);
+    let run = run(life, "");
+    assert!(run.stdout.ends_with(&format!("generation 3: 5 cells\ndrawn to {}/sxprs-life.svg\n", dir.display())), "{}", run.stdout);
+    let svg = std::fs::read_to_string(dir.join("sxprs-life.svg")).unwrap();
+    assert_eq!(svg.matches("fill=\"black\"").count(), 20);
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn signatures() {
+    let dir = scratch("signing");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let at = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+    let main = file("signing/main.lsp", "(print \"trusted\")\n");
+    assert_eq!(sxprs(&["keygen", &at("key")]).code, 0);
+    assert_eq!(sxprs(&["keygen", &at("key")]).stderr, format!("{} already exists\n", at("key")));
+    assert_eq!(sxprs(&["keygen", &at("other")]).code, 0);
+    assert_eq!(sxprs(&["sign", "--key", &at("key"), &at("main.lsp")]).code, 0);
+    let run = sxprs(&["verify", "--key", &at("key.pub"), &at("main.lsp")]);
+    assert_eq!((run.code, run.stdout), (0, format!("{}: good signature\n", at("main.lsp"))));
+    let run = sxprs(&["--require-signature", &at("key.pub"), &at("main.lsp")]);
+    assert_eq!((run.code, &run.stdout[..]), (0, "\"trusted\"\n"));
+    let run = sxprs(&["--require-signature", &at("other.pub"), &at("main.lsp")]);
+    assert_eq!((run.code, run.stderr), (1, format!("{}: the signature doesn't match the file and key\n", at("main.lsp"))));
+    std::fs::write(&main, "(print \"changed\")\n").unwrap();
+    let run = sxprs(&["--require-signature", &at("key.pub"), &at("main.lsp")]);
+    assert_eq!((run.code, &run.stdout[..]), (1, ""));
+    // what it loads needs a signature of its own, and the init file doesn't run
+    file("signing/lib.lsp", "(define greeting \"signed\")\n");
+    file("signing/loader.lsp", "(load \"lib.lsp\")\n(print greeting)\n");
+    assert_eq!(sxprs(&["sign", "--key", &at("key"), &at("loader.lsp")]).code, 0);
+    let run = sxprs(&["--require-signature", &at("key.pub"), &at("loader.lsp")]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.starts_with(&format!("Lisp Processing Error: {}: {}.sig: ", at("lib.lsp"), at("lib.lsp"))), "{}", run.stderr);
+    assert_eq!(sxprs(&["sign", "--key", &at("key"), &at("lib.lsp")]).code, 0);
+    let run = sxprs(&["--require-signature", &at("key.pub"), &at("loader.lsp")]);
+    assert_eq!((run.code, &run.stdout[..]), (0, "\"signed\"\n"), "{}", run.stderr);
+    file("signing/lib.lsp", "(define greeting \"swapped\")\n");
+    let run = sxprs(&["--require-signature", &at("key.pub"), &at("loader.lsp")]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.contains("the signature doesn't match the file and key"), "{}", run.stderr);
+    file("signing-config/sxprs/init.lsp", "(define greeting \"from init\")\n");
+    file("signing/uses-init.lsp", "(print greeting)\n");
+    assert_eq!(sxprs(&["sign", "--key", &at("key"), &at("uses-init.lsp")]).code, 0);
+    let mut with_init = command(&["--require-signature", &at("key.pub"), &at("uses-init.lsp")]);
+    with_init.env("XDG_CONFIG_HOME", scratch("signing-config"));
+    let without_init = self::run(with_init, "");
+    assert_eq!(without_init.code, 1);
+    assert!(without_init.stderr.contains("symbol greeting is not defined"), "{}", without_init.stderr);
+}
+
+#[cfg(not(feature = "signing"))]
+#[test]
+fn signatures_need_the_feature() {
+    let run = sxprs(&["sign", "--key", "key", "main.lsp"]);
+    assert_eq!((run.code, &run.stderr[..]), (1, "sign needs sxprs built with the `signing` feature\n"));
+}
+
+#[test]
+fn init_file() {
+    file("config/sxprs/init.lsp", "(load \"helpers.lsp\")\n");
+    file("config/sxprs/helpers.lsp", "(defn greet (name) (str-concat \"hi \" name))\n");
+    let mut with_init = command(&["-e", "(greet \"ana\")"]);
+    with_init.env("XDG_CONFIG_HOME", scratch("config"));
+    assert_eq!(run(with_init, "").stdout, "\"hi ana\"\n");
+    let mut without = command(&["--no-init", "-e", "(greet \"ana\")"]);
+    without.env("XDG_CONFIG_HOME", scratch("config"));
+    assert_eq!(run(without, "").code, 1);
+    file("bad-config/sxprs/init.lsp", "(car 1)\n");
+    let mut bad = command(&["-e", "1"]);
+    bad.env("XDG_CONFIG_HOME", scratch("bad-config"));
+    let bad = run(bad, "");
+    assert_eq!(bad.code, 1);
+    assert!(bad.stderr.contains("init.lsp"), "{}", bad.stderr);
+}
+
+#[test]
+fn project_settings() {
+    file("project/sxprs.toml", "prelude = false\nmath = false\ninclude = [\"lib\"]\n");
+    file("project/lib/shapes.lsp", "(module shapes (export area) (defn area (w h) (* w h)))\n");
+    let main = file("project/src/main.lsp", "(import shapes area)\n(print (area 2 3) (shapes/area 1 2))\n(print (try (second '(1 2)) (catch e e)))\n(sqrt 4)\n");
+    let run = sxprs(&[main.to_str().unwrap()]);
+    assert_eq!(run.code, 1);
+    assert_eq!(run.stdout, "6\n2\n\"symbol second is not defined\"\n");
+    assert!(run.stderr.contains("symbol sqrt is not defined"), "{}", run.stderr);
+    file("bad-project/sxprs.toml", "dialect = \"scheme\"\n");
+    let main = file("bad-project/main.lsp", "1\n");
+    let run = sxprs(&[main.to_str().unwrap()]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.ends_with("sxprs.toml:1: unknown setting `dialect`\n"), "{}", run.stderr);
+}
+
+#[test]
+fn daemon() {
+    let idle = file("idle.lsp", "(print 1)\n");
+    let run = sxprs(&["daemon", idle.to_str().unwrap()]);
+    assert_eq!((run.code, &run.stdout[..]), (1, "1\n"));
+    assert!(run.stderr.contains("nothing was scheduled"), "{}", run.stderr);
+    let bad = file("bad-schedule.lsp", "(schedule \"61 * * * *\" (lambda () 1))\n");
+    let run = sxprs(&["daemon", bad.to_str().unwrap()]);
+    assert_eq!(run.code, 1);
+    assert!(run.stderr.contains("the minute should be 0 to 59, not `61`"), "{}", run.stderr);
+    assert_eq!(sxprs(&["daemon", "-e", "1"]).code, 1);
+}
+
+#[test]
+fn checkpoints() {
+    let dir = scratch("checkpoints");
+    let _ = std::fs::remove_dir_all(&dir);
+    // stops partway through the first time, as if it was killed
+    let job = file(
+        "checkpoints/job.lsp",
+        "(define state (resume \"job\" {:done 0 :sum 0}))\n\
+         (print (dict-get state :done))\n\
+         (while (< (dict-get state :done) 6)\n\
+           (define i (dict-get state :done))\n\
+           (if (and (= i 3) (not (getenv \"FINISH\"))) (exit 9))\n\
+           (set! state (checkpoint \"job\" {:done (+ i 1) :sum (+ (dict-get state :sum) i)})))\n\
+         (print (dict-get state :sum))\n",
+    );
+    let job = job.to_str().unwrap();
+    let mut first = command(&[job]);
+    first.current_dir(&dir);
+    let first = run(first, "");
+    assert_eq!((first.code, &first.stdout[..]), (9, "0\n"));
+    let mut resumed = command(&["--resume", job]);
+    resumed.current_dir(&dir).env("FINISH", "1");
+    let resumed = run(resumed, "");
+    assert_eq!((resumed.code, &resumed.stdout[..]), (0, "3\n15\n"));
+    assert!(dir.join(".sxprs-checkpoints/job.lsp").exists());
+    // without --resume it starts over
+    let mut again = command(&[job]);
+    again.current_dir(&dir).env("FINISH", "1");
+    assert_eq!(run(again, "").stdout, "0\n15\n");
+}
+
+#[test]
+fn leaks() {
+    let job = file("leaky.lsp", "(define a (spawn 1))\n(define b (spawn (* 2 3)))\n(await a)\n");
+    let run = sxprs(&["--leaks", job.to_str().unwrap()]);
+    let job = job.canonicalize().unwrap();
+    assert_eq!((run.code, run.stderr), (0, format!("leak: task 1, spawned at {}:2:18: (spawn (* 2 3)) was never awaited\n", job.display())));
+    assert_eq!(sxprs(&["--leaks", "-e", "(await (spawn 1))"]).stderr, "");
+}
+
+#[test]
+fn heap_dumps() {
+    let dump = scratch("heap.json");
+    let _ = std::fs::remove_file(&dump);
+    let run = sxprs(&["--heap-at-exit", dump.to_str().unwrap(), "-e", "(define cache (range 0 100)) (exit 3)"]);
+    assert_eq!(run.code, 3);
+    let json = std::fs::read_to_string(&dump).unwrap();
+    assert!(json.starts_with("{\"globals\":1,\"values\":"), "{json}");
+    assert!(json.contains("\"largest\":[{\"name\":\"cache\",\"values\":101,"), "{json}");
+}
+
+#[test]
+fn manifest() {
+    file("manifest/lib.lsp", "(define offset 1)\n");
+    let program = file("manifest/main.lsp", "(load \"lib.lsp\")\n(print (+ offset (random-int 0 1000)))\n");
+    let out = scratch("manifest/run.json");
+    let (program, out) = (program.to_str().unwrap(), out.to_str().unwrap());
+    let first = sxprs(&["--manifest", out, program]);
+    assert_eq!(first.code, 0);
+    let manifest = std::fs::read_to_string(out).unwrap();
+    assert!(manifest.contains("main.lsp\",\"sha256\":\"230b21e1"), "{manifest}");
+    assert!(manifest.contains("lib.lsp\",\"sha256\":\""), "{manifest}");
+    assert!(manifest.ends_with(",\"exit\":0}\n"), "{manifest}");
+    // the seed it recorded repeats the run
+    let seed = manifest.split("\"seed\":").nth(1).unwrap().split(',').next().unwrap();
+    assert_eq!(sxprs(&["--seed", seed, program]).stdout, first.stdout);
+    assert_eq!(sxprs(&["--manifest", out]).code, 1);
+}
@@ -0,0 +1,155 @@
+// runs generated programs with the tree-walker and with the VM, and checks
+// they agree on every value and error, so the two engines don't drift apart.
+// The programs come from a fixed seed, so a failure names the program and it
+// can be run again as it is. SXPRS_PROGRAMS=100000 tries more of them
+use sxprs::Interpreter;
+
+const PROGRAMS: u64 = 1000;
+const DEPTH: usize = 4;
+
+// xorshift, enough to pick between forms
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+struct Generator {
+    random: Random,
+    // the variables in scope where the expression being made is
+    vars: Vec<String>,
+    fresh: usize,
+}
+
+impl Generator {
+    fn var(&mut self) -> String {
+        self.fresh += 1;
+        format!("v{}", self.fresh)
+    }
+    fn leaf(&mut self) -> String {
+        match self.random.below(10) {
+            0..=2 if !self.vars.is_empty() => self.vars[self.random.below(self.vars.len())].clone(),
+            0..=5 => (self.random.below(25) as i64 - 5).to_string(),
+            6 => self.random.pick(&["true", "false", "nil"]).to_owned(),
+            7 => self.random.pick(&["\"a\"", "\"bc\"", "\"\""]).to_owned(),
+            8 => self.random.pick(&["'(1 2 3)", "'()", "'(a (b))", "[1 2]"]).to_owned(),
+            _ => self.random.pick(&["2.5", "-0.5", "1/2"]).to_owned(),
+        }
+    }
+    fn expr(&mut self, depth: usize) -> String {
+        if depth == 0 || self.random.below(5) == 0 {
+            return self.leaf();
+        }
+        let mut sub = |this: &mut Generator| this.expr(depth - 1);
+        match self.random.below(16) {
+            0 => {
+                let op = self.random.pick(&["+", "-", "*", "/", "mod", "<", "<=", "=", "max"]);
+                format!("({op} {} {})", sub(self), sub(self))
+            }
+            1 => format!("(if {} {} {})", sub(self), sub(self), sub(self)),
+            2 => format!("({} {} {})", self.random.pick(&["and", "or"]), sub(self), sub(self)),
+            3 => format!("(cond ({} {}) ({}) (else {}))", sub(self), sub(self), sub(self), sub(self)),
+            4 | 5 => {
+                let value = sub(self);
+                let name = self.var();
+                self.vars.push(name.clone());
+                let body = sub(self);
+                self.vars.pop();
+                match self.random.below(3) {
+                    0 => format!("(let (({name} {value})) {body})"),
+                    1 => format!("((lambda ({name}) {body}) {value})"),
+                    _ => format!("(let (({name} {value})) (set! {name} {}) {body})", sub(self)),
+                }
+            }
+            6 => format!("({} {})", self.random.pick(&["car", "cdr", "length", "not", "type-of", "second"]), sub(self)),
+            7 => format!("({} {} {})", self.random.pick(&["cons", "list", "append", "nth", "equal?"]), sub(self), sub(self)),
+            8 => format!("(begin {} {})", sub(self), sub(self)),
+            9 => format!("(try {} (catch err err))", sub(self)),
+            10 => format!("(str-concat {} {})", sub(self), sub(self)),
+            11 => format!("(twice {})", sub(self)),
+            12 => format!("(down {} {})", self.random.below(30), sub(self)),
+            13 => format!("({} (lambda (x) {}) {})", self.random.pick(&["map", "filter"]), self.with_var("x", &mut sub), sub(self)),
+            14 => format!("(reduce (lambda (acc x) {}) {} {})", self.with_vars(&["acc", "x"], &mut sub), sub(self), sub(self)),
+            // a loop that changes a local
+            _ => {
+                let (counter, acc) = (self.var(), self.var());
+                let start = sub(self);
+                let step = self.with_var(&acc, &mut sub);
+                format!("(let (({counter} 0) ({acc} {start})) (while (< {counter} 3) (set! {acc} {step}) (set! {counter} (+ {counter} 1))) {acc})")
+            }
+        }
+    }
+    fn with_var(&mut self, name: &str, sub: &mut impl FnMut(&mut Generator) -> String) -> String {
+        self.with_vars(&[name], sub)
+    }
+    fn with_vars(&mut self, names: &[&str], sub: &mut impl FnMut(&mut Generator) -> String) -> String {
+        self.vars.extend(names.iter().map(|name| name.to_string()));
+        let body = sub(self);
+        self.vars.truncate(self.vars.len() - names.len());
+        body
+    }
+    // an expression whose error is caught, so one failing doesn't hide
+    // what the others give
+    fn caught(&mut self) -> String {
+        format!("(try {} (catch err (list 'failed err)))", self.expr(DEPTH))
+    }
+    // some functions, then expressions using them and a global that changes
+    fn program(&mut self) -> String {
+        let mut program = vec![
+            "(defn twice (x) (* x 2))".to_owned(),
+            "(defn down (n acc) (if (<= n 0) acc (down (- n 1) (cons n acc))))".to_owned(),
+            format!("(define g (try {} (catch err nil)))", self.expr(2)),
+            format!("(set! g {})", self.caught()),
+        ];
+        let results: Vec<String> = (0..4).map(|_| self.caught()).collect();
+        program.push(format!("(list g {})", results.join(" ")));
+        program.join("\n")
+    }
+}
+
+// the program made from `seed`
+fn program(seed: u64) -> String {
+    Generator { random: Random(seed.wrapping_mul(0x9e3779b97f4a7c15)), vars: vec![], fresh: 0 }.program()
+}
+
+fn run(source: &str, vm: bool) -> String {
+    let mut lisp = Interpreter::new();
+    lisp.set_vm(vm);
+    match lisp.eval_str(source) {
+        Ok(vl) => vl.to_string(),
+        Err(err) => format!("error: {}", err.summary()),
+    }
+}
+
+#[test]
+fn engines_agree() {
+    let programs = std::env::var("SXPRS_PROGRAMS").ok().and_then(|n| n.parse().ok()).unwrap_or(PROGRAMS);
+    let mut failures = vec![];
+    for seed in 1..=programs {
+        let program = program(seed);
+        let (walked, compiled) = (run(&program, false), run(&program, true));
+        if walked != compiled {
+            failures.push(format!("seed {seed}:\n{program}\ntree-walker: {walked}\nvm:          {compiled}"));
+        }
+    }
+    assert!(failures.is_empty(), "{} of {programs} programs differ, the first:\n{}", failures.len(), failures[0]);
+}
+
+#[test]
+fn programs_vary() {
+    // the generator makes expressions that give values, not just errors
+    let results = (1..=200).map(|seed| run(&program(seed), false)).collect::<Vec<_>>().join(" ");
+    let failed = results.matches("( failed ").count();
+    assert!(failed < 700, "{failed} of the 1000 expressions in 200 programs fail");
+}
@@ -0,0 +1,23 @@
+// the game script `examples/embed_game.rs` runs, driven through the public
+// `ScriptHost` API the way the example drives it
+use std::cell::Cell;
+use std::rc::Rc;
+
+use sxprs::ScriptHost;
+
+#[test]
+fn the_ball_bounces() {
+    let mut host = ScriptHost::load(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/embed_game.lsp")).unwrap();
+    let drawn = Rc::new(Cell::new(-1));
+    host.register("width", || 10_i64);
+    host.register("draw", {
+        let drawn = drawn.clone();
+        move |x: i64, _text: String| drawn.set(x)
+    });
+    host.tick(0.5).unwrap();
+    assert_eq!(drawn.get(), 6);
+    // past the right wall at 9, back the other way
+    host.tick(0.5).unwrap();
+    assert_eq!(drawn.get(), 6);
+    assert_eq!(host.state().to_string(), "{ :dx -12 :x 6.0 }");
+}
@@ -0,0 +1,88 @@
+// the language's executable specification: every `tests/spec/name.lsp` is run
+// with `sxprs name.lsp`, from that directory, on the tree-walker and on the
+// VM, and what it prints has to be `name.out`. A program that fails has the
+// first line of its error in `name.err`, and no `.err` means it has to
+// succeed. A first line like `; flags: --opt` passes flags along, for the
+// modes that change how programs run. Modules the specs import are in
+// `support/`, which isn't run. SXPRS_BLESS=1 writes the files from what the
+// tree-walker does, for a new spec or a change that's meant
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// what a run printed, and the first line of its error if it failed
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    stdout: String,
+    error: Option<String>,
+}
+
+fn flags(source: &str) -> Vec<String> {
+    match source.lines().next().and_then(|line| line.strip_prefix("; flags:")) {
+        Some(flags) => flags.split_whitespace().map(str::to_owned).collect(),
+        None => vec![],
+    }
+}
+
+fn run(dir: &Path, file: &str, flags: &[String], vm: bool) -> Outcome {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_sxprs"));
+    command.current_dir(dir).env("NO_COLOR", "1").env("XDG_CONFIG_HOME", PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("no-config"));
+    command.args(flags);
+    if vm {
+        command.arg("--vm");
+    }
+    let output = command.arg(file).output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    Outcome {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        error: (!output.status.success()).then(|| stderr.lines().next().unwrap_or_default().to_owned()),
+    }
+}
+
+fn expected(path: &Path) -> Option<Outcome> {
+    let stdout = std::fs::read_to_string(path.with_extension("out")).ok()?;
+    let error = std::fs::read_to_string(path.with_extension("err")).ok().map(|error| error.trim_end().to_owned());
+    Some(Outcome { stdout, error })
+}
+
+fn bless(path: &Path, outcome: &Outcome) {
+    std::fs::write(path.with_extension("out"), &outcome.stdout).unwrap();
+    match &outcome.error {
+        Some(error) => std::fs::write(path.with_extension("err"), format!("{error}\n")).unwrap(),
+        None => {
+            let _ = std::fs::remove_file(path.with_extension("err"));
+        }
+    }
+}
+
+#[test]
+fn spec() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec");
+    let mut specs: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lsp"))
+        .collect();
+    specs.sort();
+    assert!(!specs.is_empty());
+    let blessing = std::env::var_os("SXPRS_BLESS").is_some();
+    let mut failures = vec![];
+    for path in &specs {
+        let file = path.file_name().unwrap().to_str().unwrap();
+        let flags = flags(&std::fs::read_to_string(path).unwrap());
+        if blessing {
+            bless(path, &run(&dir, file, &flags, false));
+        }
+        let Some(expected) = expected(path) else {
+            failures.push(format!("{file}: there's no {}", path.with_extension("out").file_name().unwrap().to_str().unwrap()));
+            continue;
+        };
+        for vm in [false, true] {
+            let outcome = run(&dir, file, &flags, vm);
+            if outcome != expected {
+                let engine = if vm { "the VM" } else { "the tree-walker" };
+                failures.push(format!("{file} on {engine}:\nexpected {expected:?}\nfound    {outcome:?}"));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{} of the specs fail:\n\n{}", failures.len(), failures.join("\n\n"));
+}
@@ -0,0 +1,4 @@
+; flags: --no-prelude
+; without the prelude, only the builtins are there
+(print (car '(1 2)))
+(second '(1 2))
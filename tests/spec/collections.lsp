@@ -0,0 +1,11 @@
+; vectors are shared, maps are values
+(define v [1 2 3])
+(define w v)
+(vec-set! w 0 10)
+(vec-push! v 4)
+(print v (vec-get v 0) (vec-length w))
+(define user {:name "ana" :langs ["pt" "en"]})
+(print (dict-get user :name) (dict-get user :age))
+(define older (dict-set user :age 31))
+(print (dict-keys older) (dict-keys user))
+(print (dict-remove older :langs))
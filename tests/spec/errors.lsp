@@ -0,0 +1,8 @@
+; try catches errors as their message, and error raises one
+(print (try (car 1) (catch e e)))
+(print (try (error "boom") (catch e (str-concat "caught " e))))
+(print (try 1 (catch e 2)))
+(defn safe-div (a b) (if (= b 0) (error "division by zero") (/ a b)))
+(print (try (safe-div 1 0) (catch e e)))
+(print (safe-div 6 3))
+(undefined-function 1)
@@ -0,0 +1,7 @@
+; flags: --opt
+; folding constant calls before running doesn't change what a program does
+(print (* 2 (+ 3 4)))
+(define x 5)
+(print (+ x (* 2 3)))
+(print (list (str-concat "a" "b") (length '(1 2))))
+(print (try (car 1) (catch e e)))
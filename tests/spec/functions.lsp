@@ -0,0 +1,17 @@
+; parameters, closures and tail calls
+(defn my-list (first &rest others) (cons first others))
+(print (my-list 1 2 3))
+(defn opt (a &optional b (c 10)) (list a b c))
+(print (opt 1) (opt 1 2 3))
+(defn greet (name :greeting "hello") (str-concat greeting ", " name))
+(print (greet "ana") (greet "ana" :greeting "oi"))
+(defn counter ()
+  (let ((n 0))
+    (lambda () (set! n (+ n 1)) n)))
+(define tick (counter))
+(tick)
+(tick)
+(print (tick))
+(defn count-down (n) (if (= n 0) 'done (count-down (- n 1))))
+(print (count-down 100000))
+(print ((lambda (x y) (* x y)) 6 7))
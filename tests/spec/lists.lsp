@@ -0,0 +1,10 @@
+; lists, pairs and the functions on them
+(define xs '(3 1 2))
+(print (car xs) (cdr xs) (cons 0 xs) (length xs))
+(print (nth xs 1) (append xs '(4)) (second xs) (last xs))
+(print (map (lambda (x) (* x x)) xs) (filter (lambda (x) (> x 1)) xs))
+(print (reduce + 0 xs))
+(print (cons 1 2) (cons 1 '(2)))
+(print (cdr (assoc 'rui '((ana . 31) (rui . 27)))))
+(print (range 0 5))
+(print (equal? '(1 (2)) (list 1 (list 2))) (eq? 'a 'a))
@@ -0,0 +1,25 @@
+; literals, comments and the reader's shorthands
+(print #t #f #nil)
+(print #\a #\space)
+#| a block comment
+   #| that nests |#
+|#
+(print "tab\there" 'sym :key)
+(print [1 (+ 1 1)] {:a 1})
+; `[..]` reads as a vector, so quoted or read it's still one
+(print '[1 (+ 1 1)] (type-of '[1 2]) (read "[1 2]") (type-of (read "[1 2]")) (equal? (read "[1 2]") [1 2]))
+; evaluating one makes a new vector every time, whatever `vector` is bound to
+(defn fresh () [0])
+(vec-set! (fresh) 0 9)
+(print (fresh) (let ((vector list)) [1 2]) `[1 ~(+ 1 1) ~@(list 3 4)])
+; `{..}` reads as a map in the same way
+(print '{:a (+ 1 1)} (type-of '{:a 1}) (read "{:a 1}") (type-of (read "{:a 1}")) (equal? (read "{:a 1}") {:a 1}))
+(define k :b)
+(print {:a (+ 1 1) k [k]} (let ((dict list)) {:a 1}) `{:a ~(+ 1 1)})
+(print '(a . b) '(a b . c))
+; what quoted data prints as reads back as an equal value of the same type
+(define data '(1 -2 1.0 -0.0 1e300 1/2 99999999999999999999 "a\"b\n" #\a #\space sym :key true nil () (1 . 2) [1 [2.0]] {:a 1 "s" [2.5]} 'x `(a ~b)))
+(define back (read (format "{}" data)))
+(print back)
+(print (equal? back data) (equal? (map type-of back) (map type-of data)))
+(print (map type-of (read "(1.0 [1] {:a 1})")))
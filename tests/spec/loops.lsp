@@ -0,0 +1,10 @@
+; while, for and dotimes
+(define i 0)
+(define total 0)
+(while (< i 5) (set! total (+ total i)) (set! i (+ i 1)))
+(print total)
+(for word in '("a" "b") (print word))
+(define squares '())
+(dotimes (n 4) (set! squares (cons (* n n) squares)))
+(print squares)
+(print (while false 1) (dotimes (n 0) n))
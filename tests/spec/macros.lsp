@@ -0,0 +1,10 @@
+; macros get their arguments unevaluated, and quasiquote builds code with ~ and ~@
+(defmacro unless (test &rest body) `(if ~test nil (begin ~@body)))
+(print (unless false 1 2) (unless true 1))
+(define xs '(2 3))
+(print `(1 ~@xs ~(+ 2 2)))
+(defmacro swap-vars! (a b) (let ((tmp (gensym))) `(let ((~tmp ~a)) (set! ~a ~b) (set! ~b ~tmp))))
+(define p 1)
+(define q 2)
+(swap-vars! p q)
+(print p q)
@@ -0,0 +1,10 @@
+; match picks the first pattern that fits
+(defn where (point)
+  (match point
+    ((0 0) "origin")
+    ((_ 0) "on the x axis")
+    ((x y &rest _) (+ x y))))
+(print (where '(0 0)) (where '(5 0)) (where '(1 2 3)))
+(print (match 'b ('a 1) ('b 2)))
+(print (match "s" (x (str-concat x "!"))))
+(match 5 ((a b) a))
@@ -0,0 +1,10 @@
+; import reads name.lsp beside the importing file, or in the include directories of sxprs.toml, and only what is exported is seen
+(defn area (r) (* 3 r r))
+(import geometry)
+(print (geometry/area 2 3))
+(print (try geometry/helper (catch e 'hidden)))
+; the program's own `area` is left as it was
+(print (area 2))
+; a name listed after the module is defined without the prefix, unless it's taken
+(print (try (import geometry area) (catch e e)))
+(print (try (import geometry perimeter) (catch e e)))
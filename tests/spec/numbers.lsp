@@ -0,0 +1,7 @@
+; ints grow into big ints, and dividing ints is exact
+(print (* 4611686018427387904 4))
+(print (pow 2 100))
+(print (/ 1 3) (/ 6 3) (+ 1/2 1/3))
+(print (+ 1/2 0.5) (* 2 1.5))
+(print (type-of 1) (type-of (pow 2 100)) (type-of 1/2) (type-of 1.5))
+(print (mod 7 3) (max 1 5 3) (min 2 -1))
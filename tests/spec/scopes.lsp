@@ -0,0 +1,15 @@
+; let binds in parallel, begin has a scope of its own
+(define x 1)
+(print (let ((x 2) (y x)) (list x y)))
+(print (begin (define x 5) x) x)
+(set! x 3)
+(print x)
+(print (let ((a 1)) (let ((b (+ a 1))) (+ a b))))
+; a parameter, local or definition named like a builtin is what's called
+(defn call-with (list) (list 1 2))
+(print (call-with +))
+(print (let ((abs (lambda (n) 'shadowed))) (abs -1)))
+(print (abs -1))
+(defn car (l) 'mine)
+(print (car '(1 2)))
+(print (try (defn if (a) a) (catch e e)))
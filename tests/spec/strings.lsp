@@ -0,0 +1,8 @@
+; strings and characters
+(print (str-concat "a" "b" "c") (str-length "héllo"))
+(print (substring "hello" 1 3) (str-split "a,b,c" ","))
+(print (upcase "abc") (downcase "ABC") (str-contains? "hello" "ell"))
+(print (string->number "42") (string->number "x") (number->string 1.5))
+(print (list->string (string->list "abc")))
+(print (char->int #\a) (int->char 98))
+(print (format "{} and {}" 1 "two"))
@@ -0,0 +1,7 @@
+; defstruct makes a constructor, accessors and a predicate
+(defstruct point x y)
+(define p (point 1 2))
+(print (point-x p) (point-y p) (point? p) (point? 1))
+(print (type-of p) p)
+(print (equal? p (point 1 2)))
+(point-x 5)
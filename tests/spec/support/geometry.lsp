@@ -0,0 +1,3 @@
+(module geometry (export area)
+  (defn helper (x) x)
+  (defn area (w h) (helper (* w h))))
@@ -0,0 +1,6 @@
+; false, nil, 0, 0.0 and () are false; everything else is true
+(print (map (lambda (x) (if x 'yes 'no)) (list false nil 0 0.0 '() "" 1 'a [])))
+(print (and 1 2 3) (and 1 nil 3) (or nil 0 'x) (or))
+(print (cond ((= 1 2) 'a) ((+ 1 1)) (else 'c)))
+(print (cond (false 1)))
+(print (not nil) (not 1))